@@ -0,0 +1,39 @@
+#![no_main]
+// --edit-plan reads back whatever was saved in the editor. An edited plan is either refused or
+// turned into a plan whose every move stays inside the organized folder, and no two files end up
+// at the same place.
+use directory_cleaner::edit_plan;
+use directory_cleaner::logic::Options;
+use directory_cleaner::plan::{self, PlannedAction};
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    // A made-up folder: nothing in it exists, so nothing is read either
+    let root = Path::new("/fuzz/root");
+    let files: Vec<PathBuf> = ["report.pdf", "a -> b.txt", "photos/beach.jpg", "~$notes.docx"]
+        .iter()
+        .map(|file| root.join(file))
+        .collect();
+    let actions = plan::plan(&files, root, &Options::default());
+
+    let Ok(edited) = edit_plan::apply(actions, text, root) else {
+        return;
+    };
+    let mut taken = HashSet::new();
+    for action in &edited {
+        if let PlannedAction::Move { from, to, .. } = action {
+            assert!(files.contains(from), "{:?} isn't a file of the plan", from);
+            assert!(
+                to.starts_with(root) && !to.components().any(|c| c.as_os_str() == ".."),
+                "{:?} is outside the folder",
+                to
+            );
+            assert!(taken.insert(to.clone()), "two files go to {:?}", to);
+        }
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+// Windows shortcuts (.lnk) are read to find the links that point at a file, and rewritten when
+// the file moves (see lnk.rs). Any file can be called .lnk, so the parser gets arbitrary bytes:
+// it finds a target or doesn't, and a shortcut it rewrites reads back with the new target.
+use directory_cleaner::lnk;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = lnk::target(data);
+    let new_target = r"C:\Users\me\Documents\pdf\report.pdf";
+    if let Some(rewritten) = lnk::retarget(data, new_target, Some(r"..\pdf\report.pdf")) {
+        assert_eq!(lnk::target(&rewritten).as_deref(), Some(new_target));
+    }
+});
@@ -0,0 +1,46 @@
+#![no_main]
+// File names are whatever the last program that wrote one liked. Whatever a name is, the planner
+// puts it into a folder inside the organized one, under a name the file system accepts (shortened
+// if need be), and the text output shows it without a single character that would do something
+// to the terminal.
+use directory_cleaner::display;
+use directory_cleaner::logic::Options;
+use directory_cleaner::plan::{self, PlannedAction};
+use libfuzzer_sys::fuzz_target;
+use std::path::{Path, PathBuf};
+
+fuzz_target!(|data: &[u8]| {
+    let name = String::from_utf8_lossy(data);
+    // A name is one path component: no separators, no NUL
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name == "." || name == ".." {
+        return;
+    }
+
+    let escaped = display::escape(&name);
+    assert!(
+        !escaped.chars().any(|c| c.is_control()),
+        "{:?} still has a control character in it",
+        escaped
+    );
+    let shown = display::fit(Path::new(&*name), Some(20));
+    assert!(shown.ends_with(&format!("{}\"", escaped)), "the name was cut: {}", shown);
+
+    let root = Path::new("/fuzz/root");
+    let files = vec![root.join("deep/down/in/here").join(&*name)];
+    for options in [
+        Options::default(),
+        Options {
+            preserve_structure: true,
+            ..Options::default()
+        },
+    ] {
+        for action in plan::plan(&files, root, &options) {
+            if let PlannedAction::Move { to, .. } = action {
+                let to: PathBuf = to;
+                assert!(to.starts_with(root), "{:?} is outside the folder", to);
+                let file_name = to.file_name().expect("a destination has a name");
+                assert!(file_name.len() <= 255, "{:?} is too long", file_name);
+            }
+        }
+    }
+});
@@ -0,0 +1,38 @@
+#![no_main]
+// The rule parsers of the CLI (--map, --rule, --category-folder, --path patterns, sizes, durations and
+// times): whatever is typed, or comes from a script, they answer with a rule or an error. Never a
+// panic, and a folder they accept is always inside the organized folder.
+use directory_cleaner::args::{
+    parse_category_folder, parse_duration, parse_map, parse_rule, parse_size, parse_time,
+};
+use directory_cleaner::target;
+use libfuzzer_sys::fuzz_target;
+use std::path::{Component, Path};
+
+fn inside(folder: &str) -> bool {
+    !folder.is_empty()
+        && Path::new(folder)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(mapping) = parse_map(s) {
+        assert!(inside(&mapping.folder), "{:?}", mapping);
+        assert!(mapping.exts.iter().all(|e| !e.is_empty()), "{:?}", mapping);
+    }
+    if let Ok(rule) = parse_rule(s) {
+        assert!(inside(&rule.folder), "{:?}", rule);
+        assert!(!rule.glob.is_empty(), "{:?}", rule);
+    }
+    if let Ok(rule) = parse_category_folder(s) {
+        assert!(inside(&rule.folder), "{:?}", rule);
+    }
+    let _ = parse_size(s);
+    let _ = parse_duration(s);
+    let _ = parse_time(s);
+    let _ = target::parse(s);
+});
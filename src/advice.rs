@@ -0,0 +1,183 @@
+use crate::display;
+use crate::plan::PlannedAction;
+use crate::report::format_size;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// --advise: suggestions printed after a run
+// =============================================================================
+//
+// Looks at the folders the run moved files into (and where it found them) and points out:
+//   - tiny folders: one or two files each, which could share a Misc/ folder (--map)
+//   - big folders: worth archiving, or moving to another drive
+//   - duplicate hotspots: folders where many names were already taken, which usually means copies
+// Nothing here changes anything, it only prints.
+
+// A folder with at most this many files is "tiny"
+const SMALL_FOLDER_FILES: u64 = 2;
+// A folder with at least this many bytes is "big" (1 GB)
+const BIG_FOLDER_BYTES: u64 = 1 << 30;
+// A folder where at least this many files were renamed or found to be identical is a "hotspot"
+const HOTSPOT_FILES: usize = 3;
+
+// Number of files and total size of one destination folder
+#[derive(Debug, Default)]
+struct FolderSize {
+    files: u64,
+    bytes: u64,
+}
+
+// The recommendations for a run that carried out (or, with `dry_run`, would carry out) `actions`
+// in `root`, one line each. Empty if there's nothing to suggest.
+pub fn advise(actions: &[PlannedAction], root: &Path, dry_run: bool) -> Vec<String> {
+    // Top-level destination folder ("pdf", "Documents") -> the extensions moved into it
+    let mut folders: BTreeMap<PathBuf, BTreeSet<&str>> = BTreeMap::new();
+    // Source folder -> files in it that were renamed (name taken) or found to be identical
+    let mut hotspots: BTreeMap<PathBuf, usize> = BTreeMap::new();
+
+    for action in actions {
+        match action {
+            PlannedAction::Move {
+                from,
+                to,
+                ext,
+                shortened,
+                ..
+            } => {
+                if let Some(folder) = top_folder(to, root) {
+                    folders.entry(folder).or_default().insert(ext);
+                }
+                if !shortened && from.file_name() != to.file_name() {
+                    *hotspots.entry(parent_of(from, root)).or_default() += 1;
+                }
+            }
+            PlannedAction::Trash {
+                path,
+                duplicate_of: Some(_),
+            } => *hotspots.entry(parent_of(path, root)).or_default() += 1,
+            PlannedAction::Skip { path, reason } if reason.starts_with("it's identical to") => {
+                *hotspots.entry(parent_of(path, root)).or_default() += 1
+            }
+            _ => {}
+        }
+    }
+
+    // How big the folders are now (in a dry run, plus what the run would move into them)
+    let mut sizes: BTreeMap<&Path, FolderSize> = BTreeMap::new();
+    for folder in folders.keys() {
+        let size = sizes.entry(folder).or_default();
+        add_folder(&root.join(folder), size);
+    }
+    if dry_run {
+        for action in actions {
+            if let PlannedAction::Move { from, to, .. } = action {
+                let size = top_folder(to, root).and_then(|f| sizes.get_mut(f.as_path()));
+                if let (Some(size), Ok(metadata)) = (size, fs::symlink_metadata(from)) {
+                    size.files += 1;
+                    size.bytes += metadata.len();
+                }
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+
+    // Tiny folders, merged with a --map rule for their extensions ("(no extension)" scripts can't be mapped)
+    let tiny: Vec<(&PathBuf, &BTreeSet<&str>)> = folders
+        .iter()
+        .filter(|(folder, _)| {
+            sizes
+                .get(folder.as_path())
+                .is_some_and(|s| s.files <= SMALL_FOLDER_FILES)
+        })
+        .collect();
+    if tiny.len() > 1 {
+        let names: Vec<String> = tiny
+            .iter()
+            .map(|(folder, _)| format!("{}/", shown(folder)))
+            .collect();
+        let exts: BTreeSet<&str> = tiny
+            .iter()
+            .flat_map(|(_, exts)| exts.iter().copied())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        let exts: Vec<&str> = exts.into_iter().collect();
+        lines.push(format!(
+            "{} hold only 1-2 files each: merge them into one folder with --map \"{}=Misc\"",
+            names.join(", "),
+            exts.join(",")
+        ));
+    }
+
+    for (folder, size) in &sizes {
+        if size.bytes >= BIG_FOLDER_BYTES {
+            lines.push(format!(
+                "{}/ holds {} in {} files: a candidate for archiving, or for another drive",
+                shown(folder),
+                format_size(size.bytes),
+                size.files
+            ));
+        }
+    }
+
+    for (folder, count) in &hotspots {
+        if *count >= HOTSPOT_FILES {
+            let place = if folder.as_os_str().is_empty() {
+                "the top folder".to_string()
+            } else {
+                format!("{}/", shown(folder))
+            };
+            lines.push(format!(
+                "{} had {} files whose name was taken or that were identical to another file: \
+                 look for copies with `directory-cleaner dedupe --path {}`",
+                place,
+                count,
+                shown(&root.join(folder))
+            ));
+        }
+    }
+
+    lines
+}
+
+// Folder names come from the files, so they're escaped like every path that's printed
+fn shown(folder: &Path) -> String {
+    display::escape(&folder.to_string_lossy())
+}
+
+// "./Downloads/pdf/sub/a.pdf" -> "pdf"
+fn top_folder(path: &Path, root: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut components = relative.components();
+    let first = components.next()?;
+    // A file directly in root has no folder
+    components.next()?;
+    Some(PathBuf::from(first.as_os_str()))
+}
+
+// "./Downloads/old/a.pdf" -> "old" ("" for files directly in root)
+fn parent_of(path: &Path, root: &Path) -> PathBuf {
+    path.parent()
+        .and_then(|p| p.strip_prefix(root).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+// Adds up the files below `dir` (symlinks are counted, not followed)
+fn add_folder(dir: &Path, size: &mut FolderSize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => add_folder(&entry.path(), size),
+            Ok(_) => {
+                size.files += 1;
+                size.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+            Err(_) => {}
+        }
+    }
+}
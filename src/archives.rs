@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// =============================================================================
+// stats --peek-archives: what's inside zip and tar files, without unpacking them
+// =============================================================================
+//
+// An archive is one file to the scan, so a Downloads folder full of .zip files is all "archives"
+// in the stats. Whether it's worth unpacking them depends on what's inside, and both formats
+// keep a list of that with every file's name, size and modification time:
+//
+//   .zip            - the "central directory" at the end of the file; only that list is read
+//   .tar            - a header in front of every file; the file contents are skipped over
+//   .tar.gz, .tgz   - the same, but the whole archive has to be decompressed on the way
+//
+// Nothing is ever written. Archives inside archives are counted as files (of the archives
+// category), they aren't opened in turn. Other compressed tars (.tar.xz, .tar.zst, ...) and
+// formats like .7z and .rar aren't looked into.
+
+// One file inside an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inner {
+    // Its path inside the archive ("photos/2024/beach.jpg")
+    pub path: PathBuf,
+    // Its size unpacked
+    pub size: u64,
+    // When it was last modified, if the archive says
+    pub modified: Option<SystemTime>,
+}
+
+// Whether peek() can look inside `path`, going by its name
+pub fn can_peek(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn format(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") {
+        Some(Format::Zip)
+    } else if name.ends_with(".tar") {
+        Some(Format::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Format::TarGz)
+    } else {
+        None
+    }
+}
+
+// The files inside the archive at `path` (folders and links left out)
+pub fn peek(path: &Path) -> Result<Vec<Inner>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let files = match format(path) {
+        Some(Format::Zip) => zip_files(file),
+        Some(Format::Tar) => tar_files(BufReader::new(file)),
+        Some(Format::TarGz) => tar_files(flate2::read::GzDecoder::new(BufReader::new(file))),
+        None => anyhow::bail!("not a zip or tar archive"),
+    };
+    files.with_context(|| format!("Failed to look inside {:?}", path))
+}
+
+fn zip_files(file: File) -> Result<Vec<Inner>> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        // "raw": the entry's header only, nothing is decompressed
+        let entry = archive.by_index_raw(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        files.push(Inner {
+            path: PathBuf::from(entry.name()?.as_ref()),
+            size: entry.size(),
+            // 1980-01-01 00:00, the earliest DOS time, is what zip tools write when they have none
+            modified: entry
+                .last_modified()
+                .filter(|time| *time != zip::DateTime::default())
+                .and_then(|time| {
+                    // DOS times have no time zone; taken as UTC, which is close enough for age groups
+                    let date = NaiveDate::from_ymd_opt(
+                        time.year().into(),
+                        time.month().into(),
+                        time.day().into(),
+                    )?;
+                    let time = date.and_hms_opt(
+                        time.hour().into(),
+                        time.minute().into(),
+                        time.second().into(),
+                    )?;
+                    Some(time.and_utc().into())
+                }),
+        });
+    }
+    Ok(files)
+}
+
+fn tar_files(reader: impl Read) -> Result<Vec<Inner>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        if !header.entry_type().is_file() {
+            continue;
+        }
+        files.push(Inner {
+            path: entry.path()?.into_owned(),
+            size: header.size()?,
+            modified: header
+                .mtime()
+                .ok()
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        });
+    }
+    Ok(files)
+}
@@ -1,12 +1,779 @@
-use clap::Parser;
+use crate::category;
+use crate::dedupe::Keep;
+use crate::events::Output;
+use crate::journal;
+use crate::links::LinkPolicy;
+use crate::locale::{self, Lang};
+use crate::paths;
+use crate::plan::{
+    CategoryFolder, ConflictStrategy, DuplicateStrategy, Grouping, JunkStrategy, Mapping, Rule,
+    SmallGroups,
+};
+use crate::throttle;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use clap::builder::BoolishValueParser;
+use clap::{Args, Parser, Subcommand};
+use globset::GlobBuilder;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 
+// Without a subcommand the tool organizes --path, like it always did.
+// Subcommands (e.g. `undo`) bring their own arguments.
+// Some arguments can also come from DIRCLEANER_* environment variables (e.g. DIRCLEANER_DRY_RUN=1
+// in a CI job); a flag on the command line wins over the variable.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    #[arg(short, long)]
-    pub path: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(short, long, required = true, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: Option<String>,
 
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, default_value_t = false, env = "DIRCLEANER_DRY_RUN", value_parser = BoolishValueParser::new())]
     pub dry_run: bool,
     // dry run = making all the calculations, then stopping before execution and printing what would have been done normally
+    #[arg(short, long, global = true, default_value_t = false, env = "DIRCLEANER_YES", value_parser = BoolishValueParser::new())]
+    pub yes: bool,
+    // yes = allow what can't be undone (moving files to the trash, replacing copies with links)
+    // without asking; without it the tool shows how many files and bytes it's about to destroy and
+    // asks first, and refuses when there's no terminal to ask on
+    #[arg(long, default_value_t = false)]
+    pub allow_system: bool,
+    // allow system = also organize OS/application folders like /usr, C:\Windows or AppData (dangerous!)
+    #[arg(long, default_value_t = false)]
+    pub same_fs: bool,
+    // same fs = stay on the file system of --path, skipping mounted drives and bind mounts (like `find -xdev`)
+    #[arg(long, value_name = "DIR", value_parser = parse_dir)]
+    pub skip_if_in: Option<PathBuf>,
+    // skip if in = leave the files that have an identical copy (same size and hash) somewhere in
+    // DIR alone, e.g. a backup or the NAS; for dump folders that are mostly backed up already
+    #[arg(long, default_value_t = false, requires = "skip_if_in")]
+    pub delete_if_duplicated: bool,
+    // delete if duplicated = with --skip-if-in, move those files to the trash instead (asks first,
+    // see --yes), so only what isn't backed up is left to sort
+    #[arg(long, default_value_t = false)]
+    pub fast_scan: bool,
+    // fast scan = for network drives (SMB, NFS) where every stat() is a round-trip: tell files from
+    // folders by the folder listings alone, and leave the permission checks to the moves (a move
+    // that isn't allowed is reported as an error instead of a skip up front)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub stop_if_free_below: Option<u64>,
+    // stop if free below = when a file has to be copied to another drive, stop the run before
+    // the destination's free space drops under SIZE (e.g. "500M", "2G")
+    #[arg(long, value_name = "N", value_parser = parse_limit)]
+    pub limit: Option<NonZeroU64>,
+    // limit = move at most N files this run (the first N of the plan, N random ones with --shuffle),
+    // the rest waits for the next run
+    #[arg(long, value_name = "SIZE", value_parser = parse_limit_bytes)]
+    pub limit_bytes: Option<NonZeroU64>,
+    // limit bytes = move at most SIZE this run ("10G"), stopping at the first file that doesn't fit
+    #[arg(long, default_value_t = false)]
+    pub shuffle: bool,
+    // shuffle = move the files in a random order instead of folder by folder, so a long run on
+    // shared storage doesn't keep one deep folder busy at a time; with --limit, the N files are a
+    // random sample of the plan
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_duration: Option<Duration>,
+    // max duration = stop starting new moves once the run has taken this long ("90s", "10m", "2h");
+    // the file being moved is finished, the rest waits for the next run
+    #[arg(long, default_value_t = false)]
+    pub only_when_idle: bool,
+    // only when idle = pause the moves while other programs keep the CPU busy, and go on when it's
+    // quiet again (Linux only; for scheduled runs on a machine someone is working on)
+    #[arg(long, value_name = "MB/s", value_parser = parse_rate)]
+    pub throttle: Option<f64>,
+    // throttle = limit copies to another drive (NAS, cloud-synced folder) to this many MB per second in total
+    #[arg(long, value_enum, default_value_t = ConflictStrategy::Rename)]
+    pub on_conflict: ConflictStrategy,
+    // on conflict = what to do when the destination name is taken (by an existing file, or by another
+    // file of this run): "rename" keeps both as "report (1).pdf", "skip" leaves the file where it is
+    #[arg(long, value_enum, default_value_t = DuplicateStrategy::KeepBoth)]
+    pub on_duplicate: DuplicateStrategy,
+    // on duplicate = what to do when the file that has the name is an identical copy (same bytes):
+    // "keep-both" treats it like any conflict (--on-conflict), "skip" leaves the copy where it is,
+    // "trash-source" moves the copy to the system trash
+    #[arg(long, value_enum, default_value_t = JunkStrategy::Temp)]
+    pub junk: JunkStrategy,
+    // junk = where temporary, backup and lock files ("*.tmp", "*.bak", "~$report.docx") and saved-mail
+    // leftovers ("ATT00001.htm", "winmail.dat") go: "temp" collects them in one Temp/ folder,
+    // "trash" moves them to the system trash, "organize" treats them like any other file
+    #[arg(long, value_enum, default_value_t = Grouping::Extension)]
+    pub by: Grouping,
+    // by = "extension" gives one folder per extension (pdf/, jpg/), "category" groups
+    // related extensions together (Documents/, Images/, Videos/, ...), "language" sorts source
+    // code by programming language (Python/, Rust/, Shell/, also scripts recognized by their "#!" line),
+    // "mime" goes by the first half of the MIME type (image/, video/, text/, application/, ...)
+    #[arg(long = "map", value_name = "EXT=FOLDER", value_parser = parse_map)]
+    pub map: Vec<Mapping>,
+    // map = send these extensions to FOLDER, whatever --by says (repeatable, the last one wins):
+    // --map pdf=Documents --map "jpg,png=Images" --map docx=Documents/Work
+    #[arg(long)]
+    pub since_last_run: bool,
+    // since last run = only look at the files added or changed since the last run on this folder
+    // that finished, so a run every few minutes on a huge folder only plans the new ones (files an
+    // earlier run skipped, or failed to move, are looked at again once they change)
+    #[arg(long)]
+    pub check_idempotent: bool,
+    // check idempotent = after planning, work out what a second run with the same options would do
+    // right after this one, and warn about every file it would move again (rules that send files
+    // on, or back and forth between two folders, on every run)
+    #[arg(long, value_name = "N", value_parser = parse_group_size)]
+    pub min_group_size: Option<NonZeroUsize>,
+    // min group size = only give a --by folder (pdf/, Documents/, ...) to groups of at least N files,
+    // counting the files already in the folder; smaller groups go by --small-groups instead
+    #[arg(long, value_enum, default_value_t = SmallGroups::Misc, requires = "min_group_size")]
+    pub small_groups: SmallGroups,
+    // small groups = where the files of groups under --min-group-size go: "misc" puts them all into
+    // one Misc/ folder, "stay" leaves them where they are
+    #[arg(long = "rule", value_name = "RULE", value_parser = parse_rule)]
+    pub rules: Vec<Rule>,
+    // rule = send the files whose name matches GLOB to FOLDER, before --map and --by look at them
+    // (repeatable): "[PRIORITY:]GLOB=FOLDER". The first rule that matches a file wins, trying the
+    // highest priority first and rules of the same priority in the order given, so
+    // --rule "*invoice*.pdf=Finance" --rule "*.pdf=Documents" sends invoices to Finance/ only
+    #[arg(long)]
+    pub check_rules: bool,
+    // check rules = scan and say which files every --rule takes, and fail if a rule can never win
+    // because the rules before it take all its files (nothing is moved)
+    #[arg(long = "compound-ext", value_name = "EXT", value_parser = parse_compound_ext)]
+    pub compound_extensions: Vec<String>,
+    // compound ext = treat this two-part extension as one (repeatable), like the built-in tar.gz,
+    // tar.zst, nii.gz, ...: "scan.dcm.gz" goes into dcm.gz/, not gz/ (--map and --ext take it too)
+    #[arg(long = "classifier", value_name = "NAME")]
+    pub classifiers: Vec<String>,
+    // classifier = ask the program dircleaner-classify-NAME (on PATH, in any language) where files
+    // go: it reads one JSON line per file on stdin and answers {"folder":"..."} or {} per line
+    // (repeatable, the first answer wins; --map and the junk patterns still come first)
+    #[arg(long = "category-folder", value_name = "CATEGORY=FOLDER", value_parser = parse_category_folder)]
+    pub category_folders: Vec<CategoryFolder>,
+    // category folder = put a built-in category (see --by category, or "temp" for junk) into FOLDER
+    // instead of its own folder (repeatable): --category-folder images=Pictures
+    #[arg(long, value_name = "LANG", value_parser = parse_lang)]
+    pub lang: Option<Lang>,
+    // lang = name the category folders (and Temp/) in this language: "de" makes Images/ into Bilder/,
+    // "auto" takes it from the locale (LC_ALL, LC_MESSAGES, LANG); --category-folder still wins
+    #[arg(long, default_value_t = false)]
+    pub adopt_existing: bool,
+    // adopt existing = use the folders that are already there: if --path has a "Pictures" folder,
+    // images go into it instead of a new jpg/ or Images/ next to it (works with --by extension and category)
+    #[arg(long, default_value_t = false)]
+    pub into_user_dirs: bool,
+    // into user dirs = put images, videos, music and documents into your own Pictures, Videos, Music
+    // and Documents folders (where the platform keeps them: XDG user dirs, Windows' Known Folders,
+    // ~/Movies on macOS) instead of new folders below --path; the other categories stay below --path
+    #[arg(long, default_value_t = false)]
+    pub preserve_structure: bool,
+    // preserve structure = keep the subfolders a file was found in below its new folder:
+    // "sub/dir/report.pdf" goes to "pdf/sub/dir/report.pdf" instead of "pdf/report.pdf"
+    #[arg(long, value_name = "USER")]
+    pub owner: Option<String>,
+    // owner = only organize the files of this user (name or ID, Unix only), leave everyone else's alone
+    #[arg(long, value_name = "GROUP")]
+    pub group: Option<String>,
+    // group = only organize the files of this group (name or ID, Unix only)
+    #[arg(long, default_value_t = false)]
+    pub preserve_dir_mtime: bool,
+    // preserve dir mtime = after the run, give the folders files were moved out of and into their old
+    // modification time back (some backup tools decide what to back up by the folder times)
+    #[arg(long, default_value_t = false)]
+    pub respect_gitignore: bool,
+    // respect gitignore = also leave alone the files .gitignore files exclude (files listed in a
+    // .dircleanerignore file, same syntax, are always left alone)
+    #[arg(long, default_value_t = false)]
+    pub stamp_xattr: bool,
+    // stamp xattr = tag every moved file with the run ID (extended attribute
+    // "user.directory-cleaner.run-id", Linux/macOS only), on top of the journal
+    #[arg(long, value_enum, default_value_t = LinkPolicy::Ignore)]
+    pub link_policy: LinkPolicy,
+    // link policy = what to do with files that symlinks point at (the links in --path and --link-dir):
+    // "ignore" them, "warn" about the links that will break, "skip" the files, or "update" the links
+    #[arg(long = "link-dir", value_name = "DIR", value_parser = parse_dir)]
+    pub link_dirs: Vec<PathBuf>,
+    // link dir = also look for links in DIR and below, e.g. ~/Desktop (repeatable)
+    #[arg(long, default_value_t = false)]
+    pub strip_exif_gps: bool,
+    // strip exif gps = remove the location (GPS data) from the JPEG photos that are moved, keeping
+    // the rest of their EXIF data; with --audit, list the photos that have one instead
+    #[arg(long, default_value_t = false)]
+    pub advise: bool,
+    // advise = after the summary, suggest what else could be tidied up: folders with only 1-2 files,
+    // folders big enough to archive, folders full of copies
+    #[arg(long, num_args = 2, value_names = ["FORMAT", "PATH"])]
+    pub report: Vec<String>,
+    // report = also write a report of the run to PATH; FORMAT "html" gives one self-contained page
+    // (totals, a chart of the size per category, sortable list of moves, skipped files and errors)
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+    // output = "text" for people, "jsonl" prints one JSON event per line instead (scan, plan, move,
+    // skip, warning, error, done), for GUIs and scripts that want to show live progress
+    #[arg(long, default_value_t = false)]
+    pub aggregate_moves: bool,
+    // aggregate moves = instead of a line per moved file, print one per destination folder when the
+    // moves are done ("Moved 500 files (1.2 GB) to "jpg""); for big runs nobody reads line by line
+    #[arg(long, global = true, default_value_t = false)]
+    pub full_paths: bool,
+    // full paths = print every path whole in the per-file lines, instead of relative to --path and
+    // cut to fit the terminal ("…/march/invoice.pdf"); control characters are escaped either way
+    #[arg(long, global = true, default_value_t = false)]
+    pub warnings_as_errors: bool,
+    // warnings as errors = fail the run (exit code 1) if anything gave a warning: a skipped file, a
+    // shortened name, a link that breaks, ... (errors fail it too); the files are still moved,
+    // this is for scripts that want to notice
+    #[arg(long, default_value_t = false)]
+    pub print_plan: bool,
+    // print plan = don't organize anything, print the plan as JSON instead (every folder to create,
+    // move, skip and trash), with a "schema_version" so other tools can depend on the format
+    #[arg(long, default_value_t = false, conflicts_with = "print_plan")]
+    pub edit_plan: bool,
+    // edit plan = open the plan in $VISUAL / $EDITOR first (one "move <file> -> <destination>" line
+    // per file), then run it as edited: remove a line to leave a file alone, change a destination
+    // to send it elsewhere
+    #[arg(long, value_name = "FILE")]
+    pub explain: Vec<PathBuf>,
+    // explain = don't organize anything, print which rules FILE goes through instead (--map, junk
+    // patterns, --by, ...) and where it would end up (repeatable)
+    #[arg(long, value_name = "N", value_parser = parse_sample)]
+    pub sample: Option<usize>,
+    // sample = don't organize anything, show what would happen to N randomly picked files instead
+    // (a quick check of the rules on a huge folder)
+    #[arg(long, default_value_t = false)]
+    pub audit: bool,
+    // audit = don't organize anything, list the files you aren't allowed to move instead (other
+    // users' files in a shared folder, read-only folders); a normal run skips those files
+    #[arg(long, global = true, value_name = "N", env = "DIRCLEANER_THREADS", value_parser = parse_threads)]
+    pub threads: Option<NonZeroUsize>,
+    // threads = how many threads scan, move and hash files at once (default: one per CPU core)
+    #[arg(long, global = true, value_name = "DIR", env = "DIRCLEANER_STATE_DIR")]
+    pub state_dir: Option<PathBuf>,
+    // state dir = where to keep the journals (default: ~/.local/state/directory-cleaner on Linux,
+    // ~/Library/Application Support/directory-cleaner on macOS, %APPDATA%\directory-cleaner on Windows)
+}
+
+// A folder, the way it's compared with the others (on Windows: one kind of separator, absolute
+// instead of "C:Downloads", see paths::normalize)
+fn parse_path(s: &str) -> Result<String, String> {
+    Ok(paths::normalize(s))
+}
+
+fn parse_dir(s: &str) -> Result<PathBuf, String> {
+    Ok(PathBuf::from(paths::normalize(s)))
+}
+
+// At least one thread
+fn parse_threads(s: &str) -> Result<NonZeroUsize, String> {
+    match s.trim().parse::<NonZeroUsize>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("invalid thread count {:?} (expected 1 or more)", s)),
+    }
+}
+
+// At least one file
+fn parse_limit(s: &str) -> Result<NonZeroU64, String> {
+    match s.trim().parse::<NonZeroU64>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("invalid limit {:?} (expected 1 or more files)", s)),
+    }
+}
+
+// A size, at least one byte
+fn parse_limit_bytes(s: &str) -> Result<NonZeroU64, String> {
+    NonZeroU64::new(parse_size(s)?)
+        .ok_or_else(|| format!("invalid limit {:?} (expected 1 byte or more)", s))
+}
+
+// At least one file
+fn parse_group_size(s: &str) -> Result<NonZeroUsize, String> {
+    match s.trim().parse::<NonZeroUsize>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!(
+            "invalid group size {:?} (expected 1 or more files)",
+            s
+        )),
+    }
+}
+
+// At least one file
+fn parse_sample(s: &str) -> Result<usize, String> {
+    match s.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid sample size {:?} (expected 1 or more files)",
+            s
+        )),
+    }
+}
+
+// Speeds must be a number of MB/s, e.g. "10" or "0.5", and at least throttle::MIN_MB_PER_SEC
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(rate) if throttle::is_valid_rate(rate) => Ok(rate),
+        _ => Err(format!(
+            "invalid speed {:?} (expected MB/s, e.g. 10 or 0.5, at least {})",
+            s,
+            throttle::MIN_MB_PER_SEC
+        )),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    // Put files moved by an earlier run back where they came from
+    Undo(UndoArgs),
+    // List files that are stored more than once (doesn't delete anything)
+    Dedupe(DedupeArgs),
+    // Fold one organized folder into another (e.g. the cleanups of two machines)
+    Merge(MergeArgs),
+    // Look at the journals of earlier runs
+    Journal(JournalArgs),
+    // Search the moves of earlier runs ("where did my file go?")
+    Query(QueryArgs),
+    // Follow one file through every run to where it is now
+    Locate(LocateArgs),
+    // Count the files per category and how old they are (doesn't change anything)
+    Stats(StatsArgs),
+    // Clean up after runs that were interrupted (killed, crashed, power cut)
+    Repair(RepairArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    #[arg(short, long, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: String,
+    // path = the folder that was organized
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+    // dry_run = only say what would be repaired
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    #[arg(short, long, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: String,
+    // path = the folder to look at
+    #[arg(long, default_value_t = false)]
+    pub peek_archives: bool,
+    // peek archives = also list what's inside the zip and tar(.gz) files, without unpacking them,
+    // and count those files per category and age too (to see whether unpacking is worth it)
+}
+
+#[derive(Args, Debug)]
+pub struct LocateArgs {
+    #[arg(short, long, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: String,
+    // path = the folder that was organized
+    pub name: String,
+    // name = the file's name (or path) before the runs moved it, e.g. "report.pdf" or "inbox/report.pdf"
+    #[arg(long, default_value_t = false)]
+    pub hash: bool,
+    // hash = NAME is the (start of the) blake3 hash of the file instead: read every file in the
+    // tree and list the ones with that content, for files that were moved by hand
+}
+
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    #[arg(short, long, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: String,
+    // path = the folder that was organized
+    #[arg(long, value_name = "RUN_ID")]
+    pub run: Option<String>,
+    // run = only search this run (the start of its ID is enough); default: every run
+    #[arg(long = "ext", value_name = "EXT")]
+    pub exts: Vec<String>,
+    // ext = only moves of files with this extension (repeatable: --ext pdf --ext docx)
+    #[arg(long = "match", value_name = "GLOB")]
+    pub patterns: Vec<String>,
+    // match = only files whose name or original path matches this glob (e.g. "*invoice*")
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<DateTime<Utc>>,
+    // since = only moves made at or after TIME ("2024-06-01", "2024-06-01T10:30", or "2h"/"3d" ago)
+}
+
+#[derive(Args, Debug)]
+pub struct JournalArgs {
+    #[command(subcommand)]
+    pub command: JournalCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum JournalCommand {
+    // Check the checksums of the journals: were they damaged (e.g. by a crash) or changed?
+    Verify(VerifyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    #[arg(short, long, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: String,
+    // path = the folder that was organized
+    #[arg(long, value_name = "RUN_ID")]
+    pub run: Option<String>,
+    // run = only check this run (the start of its ID is enough); default: every run
+}
+
+#[derive(Args, Debug)]
+pub struct MergeArgs {
+    #[arg(value_parser = parse_path)]
+    pub src: String,
+    // src = the folder whose files are moved (it's left empty, apart from files that stay)
+    #[arg(value_parser = parse_path)]
+    pub dst: String,
+    // dst = the folder they're moved into, each to the same relative place ("pdf/a.pdf")
+    #[arg(long, value_enum, default_value_t = ConflictStrategy::Rename)]
+    pub on_conflict: ConflictStrategy,
+    // on conflict = what to do when a name is already taken in dst (see the organize flag)
+    #[arg(long, value_enum, default_value_t = DuplicateStrategy::KeepBoth)]
+    pub on_duplicate: DuplicateStrategy,
+    // on duplicate = what to do when the file that has the name in dst is byte-for-byte the same
+    #[arg(short, long, default_value_t = false, env = "DIRCLEANER_DRY_RUN", value_parser = BoolishValueParser::new())]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct UndoArgs {
+    #[arg(short, long, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub path: String,
+    // path = the folder that was organized
+    #[arg(long, value_name = "RUN_ID")]
+    pub run: Option<String>,
+    // run = which run to undo (the start of its ID is enough); default: the latest run,
+    // or with --since every run
+    #[arg(long = "ext", value_name = "EXT")]
+    pub exts: Vec<String>,
+    // ext = only put back files with this extension (repeatable: --ext pdf --ext docx)
+    #[arg(long = "match", value_name = "GLOB")]
+    pub patterns: Vec<String>,
+    // match = only put back files whose name or original path matches this glob (e.g. "*invoice*")
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<DateTime<Utc>>,
+    // since = only put back files moved at or after TIME ("2024-06-01", "2024-06-01T10:30", or "2h"/"3d" ago)
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+    // force = also put back files that were edited or replaced after the run moved them, and undo
+    // runs whose journal is damaged (only the moves recorded before the damage)
+    #[arg(short, long, default_value_t = false, env = "DIRCLEANER_DRY_RUN", value_parser = BoolishValueParser::new())]
+    pub dry_run: bool,
+}
+
+// Like Cli: without a subcommand dedupe looks for duplicates in --path,
+// `dedupe apply` carries out an edited review file
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct DedupeArgs {
+    #[command(subcommand)]
+    pub command: Option<DedupeCommand>,
+
+    #[arg(short, long = "path", required = true, env = "DIRCLEANER_PATH", value_parser = parse_path)]
+    pub paths: Vec<String>,
+    // path = the folder to look for duplicates in (including its subfolders); repeat it to look in
+    // several folders at once and find the copies between them (--path ~/Downloads --path ~/Pictures)
+    #[arg(long, default_value_t = false)]
+    pub perceptual: bool,
+    // perceptual = also find images that LOOK the same (resized or re-encoded copies of a photo)
+    #[arg(long, default_value_t = false)]
+    pub quick_hash: bool,
+    // quick hash = first compare big files by their size and three samples (start, middle, end), and
+    // only read the ones that agree in full: much faster on video libraries, same results
+    #[arg(long, value_name = "FILE")]
+    pub review: Option<PathBuf>,
+    // review = write the findings to FILE, one "keep"/"delete" line per file, to edit and then `dedupe apply`
+    #[arg(long, value_name = "STRATEGY", value_parser = parse_keep)]
+    pub keep: Option<Keep>,
+    // keep = which copy of identical files stays: "newest", "oldest", "shortest-path" or "in-dir:PATH".
+    // Without --review the other copies are moved to the trash right away
+    #[arg(long, value_name = "PATH", conflicts_with = "keep")]
+    pub prefer_root: Option<PathBuf>,
+    // prefer root = keep the copies inside PATH (one of the --path folders, the curated one) and let
+    // the copies elsewhere go; files with no copy in PATH are all kept
+    #[arg(long, default_value_t = false)]
+    pub link: bool,
+    // link = replace the copies that aren't kept with links to the kept one instead of moving them
+    // to the trash (a hard link on the same drive, a symbolic link across drives)
+    #[arg(short, long, default_value_t = false, env = "DIRCLEANER_DRY_RUN", value_parser = BoolishValueParser::new())]
+    pub dry_run: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DedupeCommand {
+    // Carry out the keep/delete decisions of a review file written by `dedupe --review`
+    Apply(ApplyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ApplyArgs {
+    pub file: PathBuf,
+    // file = the review file (deleted files go to the system trash)
+    #[arg(short, long, default_value_t = false, env = "DIRCLEANER_DRY_RUN", value_parser = BoolishValueParser::new())]
+    pub dry_run: bool,
+}
+
+// "pdf=Documents" or "jpg,png=Images": extensions (with or without the dot), then a folder
+// inside the root (see parse_folder)
+pub fn parse_map(s: &str) -> Result<Mapping, String> {
+    let (exts, folder) = s.split_once('=').ok_or_else(|| {
+        format!(
+            "invalid mapping {:?} (expected EXT=FOLDER, e.g. pdf=Documents)",
+            s
+        )
+    })?;
+
+    let exts: Vec<String> = exts
+        .split(',')
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .collect();
+    if exts.iter().any(|e| e.is_empty()) {
+        return Err(format!("invalid mapping {:?}: empty extension", s));
+    }
+
+    Ok(Mapping {
+        exts,
+        folder: parse_folder(folder, s)?,
+    })
+}
+
+// "*invoice*.pdf=Finance" or "10:*invoice*.pdf=Finance": a priority (optional), a glob for the
+// file name (or, with a "/" in it, for the path below the root), then a folder (see parse_folder)
+pub fn parse_rule(s: &str) -> Result<Rule, String> {
+    let (glob, folder) = s.rsplit_once('=').ok_or_else(|| {
+        format!(
+            "invalid rule {:?} (expected [PRIORITY:]GLOB=FOLDER, e.g. *invoice*.pdf=Finance)",
+            s
+        )
+    })?;
+    let (priority, glob) = match glob.split_once(':') {
+        Some((priority, rest)) if priority.trim().parse::<i32>().is_ok() => {
+            (priority.trim().parse().unwrap_or_default(), rest)
+        }
+        _ => (0, glob),
+    };
+    let glob = glob.trim();
+    if glob.is_empty() {
+        return Err(format!("invalid rule {:?}: empty pattern", s));
+    }
+    let matcher = GlobBuilder::new(glob)
+        .case_insensitive(true)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| format!("invalid pattern {:?} in rule {:?}: {}", glob, s, e))?
+        .compile_matcher();
+    Ok(Rule {
+        priority,
+        glob: glob.to_string(),
+        matcher,
+        folder: parse_folder(folder, s)?,
+    })
+}
+
+// "tar.gz" or ".warc.gz": an extension of two (or more) parts, without the first dot
+pub fn parse_compound_ext(s: &str) -> Result<String, String> {
+    let ext = s.trim().trim_start_matches('.').to_lowercase();
+    let parts: Vec<&str> = ext.split('.').collect();
+    if parts.len() < 2 || parts.iter().any(|part| part.is_empty()) {
+        return Err(format!(
+            "invalid compound extension {:?} (expected two parts or more, e.g. tar.gz)",
+            s
+        ));
+    }
+    if ext.contains(['/', '\\']) {
+        return Err(format!(
+            "invalid compound extension {:?}: an extension has no folders",
+            s
+        ));
+    }
+    Ok(ext)
+}
+
+// "images=Pictures": a built-in category (or "temp", where junk goes), then a folder inside the
+// root, with the same rules as for --map
+pub fn parse_category_folder(s: &str) -> Result<CategoryFolder, String> {
+    let (name, folder) = s.split_once('=').ok_or_else(|| {
+        format!(
+            "invalid category folder {:?} (expected CATEGORY=FOLDER, e.g. images=Pictures)",
+            s
+        )
+    })?;
+
+    let name = name.trim().to_lowercase();
+    let known: Vec<&str> = category::CATEGORIES
+        .iter()
+        .map(|c| c.name)
+        .chain([category::JUNK_CATEGORY])
+        .collect();
+    if !known.contains(&name.as_str()) {
+        return Err(format!(
+            "unknown category {:?} in {:?} (expected one of {})",
+            name,
+            s,
+            known.join(", ")
+        ));
+    }
+
+    Ok(CategoryFolder {
+        category: name,
+        folder: parse_folder(folder, s)?,
+    })
+}
+
+// "auto", or a language we have folder names for ("de", also "de_DE.UTF-8")
+fn parse_lang(s: &str) -> Result<Lang, String> {
+    if s.trim().eq_ignore_ascii_case("auto") {
+        return Ok(Lang::Auto);
+    }
+    locale::find(s.trim()).map(Lang::Fixed).ok_or_else(|| {
+        format!(
+            "unknown language {:?} (expected auto or one of {})",
+            s,
+            locale::codes().join(", ")
+        )
+    })
+}
+
+// The FOLDER part of `rule`: inside the root (subfolders are fine, "..", absolute paths and our
+// own state folder aren't), trailing slashes removed
+fn parse_folder(folder: &str, rule: &str) -> Result<String, String> {
+    let folder = folder.trim().trim_end_matches(['/', '\\']);
+    let inside_root = !folder.is_empty()
+        && Path::new(folder)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if !inside_root {
+        return Err(format!(
+            "invalid folder {:?} in {:?} (expected a folder inside --path, e.g. Documents)",
+            folder, rule
+        ));
+    }
+    if Path::new(folder).starts_with(journal::STATE_DIR) {
+        return Err(format!(
+            "{:?} is where the journal is kept",
+            journal::STATE_DIR
+        ));
+    }
+    Ok(folder.to_string())
+}
+
+// "newest", "oldest", "shortest-path" or "in-dir:PATH"
+pub fn parse_keep(s: &str) -> Result<Keep, String> {
+    match s.trim() {
+        "newest" => Ok(Keep::Newest),
+        "oldest" => Ok(Keep::Oldest),
+        "shortest-path" => Ok(Keep::ShortestPath),
+        other => match other.split_once(':') {
+            Some(("in-dir", dir)) if !dir.is_empty() => Ok(Keep::InDir(PathBuf::from(dir))),
+            _ => Err(format!(
+                "unknown strategy {:?} (expected newest, oldest, shortest-path or in-dir:PATH)",
+                other
+            )),
+        },
+    }
+}
+
+// Turns "2024-06-01T10:33:00Z", "2024-06-01T10:33", "2024-06-01" (local time) or "2h" / "3d" (that long ago)
+// into a point in time
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let local = |naive: NaiveDateTime| {
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(|| format!("{:?} doesn't exist in the local time zone", s))
+    };
+    for format in [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+            return local(naive);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return local(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+
+    // "90m", "2h", "3d", "1w" ago
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    if let Ok(number) = number.parse::<i64>() {
+        let seconds = match unit {
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(60 * 60),
+            "d" => Some(24 * 60 * 60),
+            "w" => Some(7 * 24 * 60 * 60),
+            _ => None,
+        };
+        if let Some(seconds) = seconds {
+            // "99999999999999w" is from before the calendar starts
+            return number
+                .checked_mul(seconds)
+                .and_then(chrono::TimeDelta::try_seconds)
+                .and_then(|ago| Utc::now().checked_sub_signed(ago))
+                .ok_or_else(|| format!("{:?} ago is too long ago", s));
+        }
+    }
+
+    Err(format!(
+        "invalid time {:?} (expected e.g. 2024-06-01, 2024-06-01T10:30 or 2h)",
+        s
+    ))
+}
+
+// Turns "90s", "10m", "2h" or "1d" into a duration (at least a second)
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration {:?} (expected e.g. 90s, 10m or 2h)",
+                s
+            ))
+        }
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(Duration::from_secs(n.saturating_mul(seconds))),
+        _ => Err(format!(
+            "invalid duration {:?} (expected e.g. 90s, 10m or 2h)",
+            s
+        )),
+    }
+}
+
+// Turns a human size like "500M", "2GB" or "1024" into a number of bytes (1K = 1024 bytes)
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    // Split "500MB" into "500" and "MB"
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size {:?} (expected e.g. 500M or 2G)", s))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("unknown size unit {:?} (use K, M, G or T)", unit)),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {:?} is too large", s))
 }
@@ -0,0 +1,155 @@
+// Built-in categories for `--by category`: instead of one folder per extension
+// (pdf/, docx/, odt/, ...), related extensions share one folder (Documents/).
+// Also the built-in list of junk files (see JUNK_PATTERNS below).
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::ffi::OsStr;
+use std::sync::OnceLock;
+
+pub struct Category {
+    // Short id, used in the summary and by --category-folder
+    pub name: &'static str,
+    // Folder the files are moved into
+    pub folder: &'static str,
+    // Other names people give that folder, for --adopt-existing ("Pictures" for Images)
+    pub aliases: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+}
+
+pub const CATEGORIES: &[Category] = &[
+    Category {
+        name: "images",
+        folder: "Images",
+        aliases: &["Pictures", "Photos"],
+        extensions: &[
+            "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "heif", "svg",
+            "ico", "raw", "cr2", "nef", "arw", "dng", "psd",
+        ],
+    },
+    Category {
+        name: "videos",
+        folder: "Videos",
+        aliases: &["Movies"],
+        extensions: &[
+            "mp4", "mkv", "mov", "avi", "wmv", "flv", "webm", "m4v", "mpg", "mpeg", "3gp",
+        ],
+    },
+    Category {
+        name: "audio",
+        folder: "Audio",
+        aliases: &["Music"],
+        extensions: &[
+            "mp3", "wav", "flac", "aac", "ogg", "m4a", "wma", "opus", "aiff", "mid", "midi",
+        ],
+    },
+    Category {
+        name: "documents",
+        folder: "Documents",
+        aliases: &["Docs"],
+        extensions: &[
+            "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "tex", "epub", "mobi", "pages", "xls",
+            "xlsx", "ods", "csv", "numbers", "ppt", "pptx", "odp", "key",
+        ],
+    },
+    Category {
+        name: "archives",
+        folder: "Archives",
+        aliases: &[],
+        extensions: &[
+            "zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst", "iso", "dmg", "tar.gz",
+            "tar.bz2", "tar.xz", "tar.zst", "tar.lz", "tar.lz4", "tar.lzma", "tar.z",
+        ],
+    },
+    Category {
+        name: "code",
+        folder: "Code",
+        aliases: &["Source"],
+        extensions: &[
+            "rs", "py", "js", "ts", "java", "c", "h", "cpp", "hpp", "cs", "go", "rb", "php", "sh",
+            "ps1", "html", "css", "json", "yaml", "yml", "toml", "xml", "sql", "ipynb",
+        ],
+    },
+    Category {
+        name: "programs",
+        folder: "Programs",
+        aliases: &["Apps", "Applications", "Installers"],
+        extensions: &["exe", "msi", "deb", "rpm", "apk", "appimage", "pkg", "jar"],
+    },
+    Category {
+        name: "fonts",
+        folder: "Fonts",
+        aliases: &[],
+        extensions: &["ttf", "otf", "woff", "woff2"],
+    },
+];
+
+// --adopt-existing: (category, folder) for every category that already has a folder among
+// `folders` (the folders in the root), under its own name or one of its aliases, ignoring case.
+// The folder is returned the way it's spelled on disk ("pictures", not "Images").
+pub fn adopt(folders: &[String]) -> Vec<(&'static str, &str)> {
+    CATEGORIES
+        .iter()
+        .filter_map(|c| {
+            // Its own name first, then the aliases in order
+            std::iter::once(c.folder)
+                .chain(c.aliases.iter().copied())
+                .find_map(|name| folders.iter().find(|f| f.eq_ignore_ascii_case(name)))
+                .map(|folder| (c.name, folder.as_str()))
+        })
+        .collect()
+}
+
+// Extensions that are really two: "backup.tar.gz" is a compressed tar archive, not just some gzip
+// file, so it goes into tar.gz/ (or Archives/), not into gz/ with everything else that was ever
+// gzipped. The longest one a name ends in wins; --compound-ext adds more.
+pub const COMPOUND_EXTENSIONS: &[&str] = &[
+    "tar.gz", "tar.bz2", "tar.xz", "tar.zst", "tar.lz", "tar.lz4", "tar.lzma", "tar.z",
+    // Brain scans (NIfTI) and web archives
+    "nii.gz", "warc.gz",
+];
+
+// Finds the category of a (lowercase) extension, if it has one
+pub fn for_extension(ext: &str) -> Option<&'static Category> {
+    CATEGORIES.iter().find(|c| c.extensions.contains(&ext))
+}
+
+// Leftovers nobody wants sorted into folders of their own (tmp/, bak/, ...): temporary and
+// backup files, lock files of open documents, and what mail clients leave behind when
+// attachments are saved. Matched against the file name, ignoring case.
+pub const JUNK_PATTERNS: &[&str] = &[
+    // Microsoft Office lock file of an open document ("~$report.docx")
+    "~$*",
+    // LibreOffice lock file (".~lock.report.odt#")
+    ".~lock.*#",
+    "*.tmp",
+    "*.temp",
+    "*.bak",
+    // Outlook's attachment dumps ("ATT00001.htm", "ATT00002.txt") and wrapped attachments
+    "ATT[0-9][0-9][0-9][0-9][0-9].*",
+    "winmail.dat",
+    // Inline images of a saved email ("image001.png")
+    "image[0-9][0-9][0-9].{png,jpg,jpeg,gif}",
+];
+
+// Folder junk is collected in, instead of tmp/, bak/, ... (--junk temp)
+pub const JUNK_FOLDER: &str = "Temp";
+// The "category" of junk, in the summary and for --category-folder
+pub const JUNK_CATEGORY: &str = "temp";
+
+// True if a file with this name is junk (one of JUNK_PATTERNS)
+pub fn is_junk(file_name: &OsStr) -> bool {
+    // Compiled once, on first use
+    static JUNK: OnceLock<GlobSet> = OnceLock::new();
+    let junk = JUNK.get_or_init(|| {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in JUNK_PATTERNS {
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .expect("invalid junk pattern");
+            builder.add(glob);
+        }
+        builder.build().expect("invalid junk patterns")
+    });
+    junk.is_match(file_name)
+}
@@ -0,0 +1,305 @@
+use crate::plan::{self, Classification, Contents};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+// =============================================================================
+// --classifier: let programs of your own decide where files go
+// =============================================================================
+//
+// A classifier is any program on PATH named dircleaner-classify-<name>, written in whatever
+// language you like. `--classifier invoices` runs dircleaner-classify-invoices once per run; it
+// gets one JSON line per file on stdin and answers with one JSON line per file on stdout, in the
+// same order:
+//
+//   in:  {"path":"./Downloads/scan-0042.pdf","name":"scan-0042.pdf","ext":"pdf","size":52133,"modified":"2024-03-01T09:12:44Z"}
+//   out: {"folder":"Invoices/2024","category":"invoices"}
+//   out: {}                                    <- no opinion, the usual rules decide
+//
+// "folder" is relative to the organized folder (and has to stay inside it); "category" is
+// optional and is what the summary counts the file under. With several --classifier, the first
+// one with an answer wins. --map rules and the junk patterns still come first, the built-in --by
+// grouping last.
+//
+// A classifier that can't be started, exits with an error or answers something that isn't a line
+// of JSON per file stops the run before anything is moved.
+
+// The start of every classifier's program name
+pub const PREFIX: &str = "dircleaner-classify-";
+
+// A classifier found on PATH
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classifier {
+    // "invoices"
+    pub name: String,
+    // ".../bin/dircleaner-classify-invoices"
+    pub program: PathBuf,
+}
+
+// What a classifier is told about each file
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    path: &'a Path,
+    name: String,
+    ext: String,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<DateTime<Utc>>,
+}
+
+// What it answers ({} = nothing)
+#[derive(Debug, Default, Deserialize)]
+struct Answer {
+    folder: Option<String>,
+    category: Option<String>,
+}
+
+// Every classifier on PATH, by name (the first one found wins, like the shell does it)
+pub fn discover() -> Vec<Classifier> {
+    let mut found: Vec<Classifier> = Vec::new();
+    let Some(path) = env::var_os("PATH") else {
+        return found;
+    };
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut here: Vec<Classifier> = entries
+            .flatten()
+            .filter(|entry| is_executable(&entry.path()))
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let name = program_name(&file_name).strip_prefix(PREFIX)?.to_string();
+                (!name.is_empty()).then(|| Classifier {
+                    name,
+                    program: entry.path(),
+                })
+            })
+            .collect();
+        here.sort_by(|a, b| a.name.cmp(&b.name));
+        for classifier in here {
+            if !found.iter().any(|known| known.name == classifier.name) {
+                found.push(classifier);
+            }
+        }
+    }
+    found
+}
+
+// The classifier called `name` (dircleaner-classify-<name> on PATH)
+pub fn find(name: &str) -> Result<Classifier> {
+    let all = discover();
+    if let Some(classifier) = all.iter().find(|c| c.name == name) {
+        return Ok(classifier.clone());
+    }
+    let names: Vec<&str> = all.iter().map(|c| c.name.as_str()).collect();
+    bail!(
+        "No classifier {:?}: there's no program {}{} on PATH ({})",
+        name,
+        PREFIX,
+        name,
+        if names.is_empty() {
+            "no classifiers were found".to_string()
+        } else {
+            format!("found: {}", names.join(", "))
+        }
+    )
+}
+
+// "dircleaner-classify-x.exe" -> "dircleaner-classify-x" on Windows, where programs have an extension
+fn program_name(file_name: &str) -> &str {
+    if cfg!(windows) {
+        for ext in [".exe", ".bat", ".cmd"] {
+            if file_name.len() > ext.len() && file_name.to_lowercase().ends_with(ext) {
+                return &file_name[..file_name.len() - ext.len()];
+            }
+        }
+    }
+    file_name
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// The answers of `classifiers` for `files` (the first answer for a file wins)
+pub fn classify(classifiers: &[Classifier], files: &[PathBuf]) -> Result<Classified> {
+    let mut answers = HashMap::new();
+    for classifier in classifiers {
+        // The files no classifier before this one had an answer for
+        let open: Vec<&Path> = files
+            .iter()
+            .filter(|file| !answers.contains_key(*file))
+            .map(PathBuf::as_path)
+            .collect();
+        if open.is_empty() {
+            break;
+        }
+        let said = ask(classifier, &open)?;
+        for (file, answer) in open.into_iter().zip(said) {
+            if let Some(answer) = answer {
+                answers.insert(file.to_path_buf(), answer);
+            }
+        }
+    }
+    Ok(Classified { answers })
+}
+
+// Runs `classifier` on `files`: one answer per file, in the same order
+fn ask(classifier: &Classifier, files: &[&Path]) -> Result<Vec<Option<Classification>>> {
+    let failed = || {
+        format!(
+            "The classifier {:?} failed, nothing was moved",
+            classifier.name
+        )
+    };
+    let mut child = Command::new(&classifier.program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start the classifier {:?}", classifier.program))?;
+    let mut stdin = BufWriter::new(child.stdin.take().expect("stdin is piped"));
+    let stdout = child.stdout.take().expect("stdout is piped");
+
+    // Written on a thread of its own: a classifier that answers while it reads would otherwise
+    // fill the pipe back to us and wait forever
+    let answers = thread::scope(|s| {
+        s.spawn(move || {
+            for file in files {
+                let line =
+                    serde_json::to_string(&request(file)).expect("requests always serialize");
+                // A classifier that stops reading early is told about below
+                if writeln!(stdin, "{}", line).is_err() {
+                    break;
+                }
+            }
+            // Closing stdin tells it there are no more files
+            let _ = stdin.flush();
+        });
+        let read = || {
+            let mut answers = Vec::with_capacity(files.len());
+            for (line, file) in BufReader::new(stdout).lines().zip(files) {
+                let line = line.with_context(failed)?;
+                answers.push(answer(&line, &classifier.name).with_context(|| {
+                    format!("{}: its answer for {:?} was {:?}", failed(), file, line)
+                })?);
+            }
+            Ok(answers)
+        };
+        let answers: Result<Vec<_>> = read();
+        // Nobody reads what it says anymore, so it could wait forever for us (and the writer
+        // for it): stopped, both go on
+        if answers.is_err() {
+            let _ = child.kill();
+        }
+        answers
+    })?;
+
+    let status = child.wait().with_context(failed)?;
+    if !status.success() {
+        bail!("{}: it exited with {}", failed(), status);
+    }
+    if answers.len() < files.len() {
+        bail!(
+            "{}: it answered for {} of {} files",
+            failed(),
+            answers.len(),
+            files.len()
+        );
+    }
+    Ok(answers)
+}
+
+fn request(file: &Path) -> Request<'_> {
+    let metadata = fs::symlink_metadata(file).ok();
+    Request {
+        path: file,
+        name: file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        ext: file
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+        size: metadata.as_ref().map_or(0, |m| m.len()),
+        modified: metadata
+            .and_then(|m| m.modified().ok())
+            .map(DateTime::<Utc>::from),
+    }
+}
+
+// One line of a classifier's answers: None for "{}" (or an empty folder)
+fn answer(line: &str, by: &str) -> Result<Option<Classification>> {
+    let answer: Option<Answer> = serde_json::from_str(line).context("not a line of JSON")?;
+    let Some(Answer {
+        folder: Some(folder),
+        category,
+    }) = answer
+    else {
+        return Ok(None);
+    };
+    if folder.trim().is_empty() {
+        return Ok(None);
+    }
+    if !Path::new(&folder)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        bail!("the folder {:?} isn't inside the organized folder", folder);
+    }
+    if Path::new(&folder)
+        .components()
+        .find(|c| matches!(c, Component::Normal(_)))
+        .is_some_and(|first| first.as_os_str() == crate::journal::STATE_DIR)
+    {
+        bail!("the folder {:?} is where the journals are kept", folder);
+    }
+    Ok(Some(Classification {
+        folder,
+        category,
+        by: by.to_string(),
+    }))
+}
+
+// The classifiers' answers, for the planner (everything else is asked of the disk)
+#[derive(Debug, Default)]
+pub struct Classified {
+    answers: HashMap<PathBuf, Classification>,
+}
+
+impl Contents for Classified {
+    fn same(&self, a: &Path, b: &Path) -> bool {
+        plan::Disk.same(a, b)
+    }
+
+    fn interpreter(&self, file: &Path) -> Option<String> {
+        plan::Disk.interpreter(file)
+    }
+
+    fn classification(&self, file: &Path) -> Option<Classification> {
+        self.answers.get(file).cloned()
+    }
+
+    fn occupied(&self, path: &Path, file: &Path) -> bool {
+        plan::Disk.occupied(path, file)
+    }
+
+    fn files_in(&self, folder: &Path) -> usize {
+        plan::Disk.files_in(folder)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::report::format_size;
+use anyhow::{bail, Result};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::Mutex;
+
+// =============================================================================
+// Safe mode: nothing is destroyed without a yes
+// =============================================================================
+//
+// Moving a file can always be undone; sending it to the trash, replacing it with a link or
+// rewriting it (--strip-exif-gps) can't (not by undo, at least). So before any of that happens, the executors (the run in logic.rs,
+// `dedupe --keep` and `dedupe apply` in review.rs) add up what would go and ask once:
+//
+//   This moves 12 files (340.5 MB) to the trash. Continue? [y/N]
+//
+// --yes answers for you (for scripts and scheduled runs). Without a terminal to ask on and
+// without --yes, nothing is changed at all. Dry runs destroy nothing, so they never ask.
+//
+// Every question goes through ask(), one at a time. The moves run on many threads, and a question
+// asked from one of them would otherwise be cut up by the "Moved ..." lines of the others (and
+// two questions at once would fight over the answer). While a question waits for its answer, the
+// other threads wait for stdout and stderr.
+
+// Taken by whoever is asking right now
+static PROMPT: Mutex<()> = Mutex::new(());
+
+// Asks `question` on stderr and returns the answer (without the line break), None if there's no
+// terminal to ask on. Safe to call from any thread: one question at a time, with nothing else
+// printed until it's answered.
+pub fn ask(question: &str) -> io::Result<Option<String>> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Ok(None);
+    }
+    // Holding the stderr lock too keeps the other threads' eprintln! out of the question
+    ask_on(question, || (stdin.lock(), io::stderr().lock())).map(Some)
+}
+
+// ask() with somewhere else to ask: `open` gives the reader for the answer and the writer for
+// the question once it's our turn (so the tests can put two threads on a made-up terminal)
+pub fn ask_on<R: BufRead, W: Write>(
+    question: &str,
+    open: impl FnOnce() -> (R, W),
+) -> io::Result<String> {
+    // A thread that panicked while asking left nothing half done that matters here
+    let _turn = PROMPT.lock().unwrap_or_else(|e| e.into_inner());
+    // Holding the stdout lock keeps the other threads' println! out of the question
+    let _stdout = io::stdout().lock();
+    let (mut input, mut output) = open();
+    write!(output, "{} ", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// What an operation would destroy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Destruction {
+    // Files moved to the trash
+    pub trashed: u64,
+    // Files replaced by links to an identical copy
+    pub linked: u64,
+    // Photos whose GPS location is removed (--strip-exif-gps)
+    pub rewritten: u64,
+    // Their size together
+    pub bytes: u64,
+}
+
+impl Destruction {
+    pub fn is_empty(&self) -> bool {
+        self.trashed == 0 && self.linked == 0 && self.rewritten == 0
+    }
+
+    // "moves 12 files (340.5 MB) to the trash and replaces 3 files with links"
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.trashed > 0 {
+            parts.push(format!("moves {} files to the trash", self.trashed));
+        }
+        if self.linked > 0 {
+            parts.push(format!("replaces {} files with links", self.linked));
+        }
+        if self.rewritten > 0 {
+            parts.push(format!(
+                "removes the GPS location from {} photos",
+                self.rewritten
+            ));
+        }
+        format!("{} ({})", parts.join(" and "), format_size(self.bytes))
+    }
+}
+
+// Goes on if there's nothing to destroy, `yes` is set, or the user says yes; else an error
+// (and the caller changes nothing)
+pub fn confirm(destruction: &Destruction, yes: bool) -> Result<()> {
+    if destruction.is_empty() || yes {
+        return Ok(());
+    }
+    let Some(answer) = ask(&format!("This {}. Continue? [y/N]", destruction.describe()))? else {
+        bail!(
+            "This {}, which undo can't reverse. Pass --yes to allow it; nothing was changed",
+            destruction.describe()
+        );
+    };
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        bail!("Cancelled, nothing was changed");
+    }
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use crate::display::PathDisplay;
+use crate::events::{self, Event, Output};
+use crate::logic::Options;
+use crate::report::{format_size, Tally};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// =============================================================================
+// The console: one writer for everything the moves print
+// =============================================================================
+//
+// The moves run on every core (see execute() in logic.rs), and each of them used to println!()
+// its own line. That works, but every println!() takes the lock on stdout and writes (and, on a
+// terminal, flushes) right away, so on a big run the threads spend their time waiting for each
+// other and for the terminal, and a line on stderr can land in the middle of the others.
+//
+// So during the moves, the threads hand their lines to a Console instead, over a channel. One
+// writer thread takes them in the order they arrive and writes them into a buffer, which goes out
+// every FLUSH_EVERY (and before anything goes to stderr, so the two stay in order). The threads
+// never wait for the terminal.
+//
+// The writer also leaves out a message on stderr it has written before (a warning that's the same
+// for many files says it once, and the number of repeats comes at the end), and with
+// --aggregate-moves it counts the moves per destination folder instead of printing a line for
+// each, to print one line per folder when the moves are done:
+//
+//   Moved 500 files (1.2 GB) to "jpg"
+//   Moved 12 files (3.4 MB) to "pdf"
+
+// How often the buffered lines are written out, at most
+const FLUSH_EVERY: Duration = Duration::from_millis(100);
+
+// What the threads send to the writer
+enum Message {
+    // A line for stdout
+    Out(String),
+    // A line for stderr
+    Err(String),
+    // A file moved into `folder` (only sent with --aggregate-moves)
+    Moved { folder: PathBuf, bytes: u64 },
+}
+
+pub struct Console {
+    // Shared by the move threads (a Sender can be), taken when the Console is finished
+    sender: Option<Sender<Message>>,
+    writer: Option<JoinHandle<()>>,
+    output: Output,
+    display: PathDisplay,
+    dry_run: bool,
+    aggregate: bool,
+}
+
+impl Console {
+    // Starts the writer, on stdout and stderr
+    pub fn start(options: &Options) -> Console {
+        Console::with_writers(options, Box::new(io::stdout()), Box::new(io::stderr()))
+    }
+
+    // The same, writing into `out` and `err` (the tests read what was written)
+    pub fn with_writers(
+        options: &Options,
+        out: Box<dyn Write + Send>,
+        err: Box<dyn Write + Send>,
+    ) -> Console {
+        let (sender, receiver) = mpsc::channel();
+        let mut writer = Writer {
+            out: io::BufWriter::new(out),
+            err,
+            display: options.display.clone(),
+            dry_run: options.dry_run,
+            written: HashSet::new(),
+            repeated: 0,
+            folders: BTreeMap::new(),
+        };
+        let handle = thread::spawn(move || {
+            loop {
+                match receiver.recv_timeout(FLUSH_EVERY) {
+                    Ok(message) => writer.write(message),
+                    Err(RecvTimeoutError::Timeout) => writer.flush(),
+                    // Every sender is gone: the moves are done
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            writer.finish();
+        });
+        Console {
+            sender: Some(sender),
+            writer: Some(handle),
+            output: options.output,
+            display: options.display.clone(),
+            dry_run: options.dry_run,
+            aggregate: options.aggregate_moves,
+        }
+    }
+
+    fn send(&self, message: Message) {
+        if let Some(sender) = &self.sender {
+            // The writer only stops when the Console is finished
+            let _ = sender.send(message);
+        }
+    }
+
+    // A line of text for people (left out with --output jsonl, like say!() in logic.rs)
+    pub fn say(&self, line: String) {
+        if self.output == Output::Text {
+            self.send(Message::Out(line));
+        }
+    }
+
+    // A line on stderr (errors and warnings are printed with --output jsonl too)
+    pub fn error(&self, line: String) {
+        self.send(Message::Err(line));
+    }
+
+    // An event for --output jsonl (left out of the text output)
+    pub fn event(&self, event: &Event) {
+        if self.output == Output::Jsonl {
+            self.send(Message::Out(events::line(event)));
+        }
+    }
+
+    // A problem: on stderr, as an event with --output jsonl, and kept for the Outcome
+    pub fn problem(&self, problem: String, problems: &Mutex<Vec<String>>) {
+        self.error(problem.clone());
+        self.event(&Event::Error { message: &problem });
+        problems.lock().unwrap().push(problem);
+    }
+
+    // The same for a warning: something to look at, but nothing went wrong
+    pub fn warning(&self, warning: String, warnings: &Mutex<Vec<String>>) {
+        self.error(format!("Warning: {}", warning));
+        self.event(&Event::Warning { message: &warning });
+        warnings.lock().unwrap().push(warning);
+    }
+
+    // The line for a file that was moved (in a dry run: would be), or with --aggregate-moves
+    // its share of the line for its folder
+    pub fn moved(&self, from: &Path, to: &Path, bytes: u64, shortened: bool) {
+        if self.output != Output::Text {
+            return;
+        }
+        if self.aggregate {
+            let folder = to.parent().unwrap_or(to).to_path_buf();
+            self.send(Message::Moved { folder, bytes });
+            return;
+        }
+        // Point out conflict renames ("report.pdf" -> "report (1).pdf") and shortened names, they're easy to miss
+        let renamed = if shortened {
+            " (name shortened, the path was too long)"
+        } else if from.file_name() != to.file_name() {
+            " (renamed, name was taken)"
+        } else {
+            ""
+        };
+        let prefix = if self.dry_run {
+            "[DRY RUN] Would move "
+        } else {
+            "Moved "
+        };
+        self.say(self.display.pair(prefix, from, to, renamed));
+    }
+
+    // Writes out everything that's still waiting (and the lines per folder), and stops the
+    // writer. Called when the Console is dropped, too.
+    pub fn finish(mut self) {
+        self.close();
+    }
+
+    fn close(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.writer.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// The writer thread's end
+struct Writer {
+    out: io::BufWriter<Box<dyn Write + Send>>,
+    err: Box<dyn Write + Send>,
+    display: PathDisplay,
+    dry_run: bool,
+    // The messages written to stderr so far, and how many repeats of them were left out
+    written: HashSet<String>,
+    repeated: u64,
+    // --aggregate-moves: the files moved into each folder
+    folders: BTreeMap<PathBuf, Tally>,
+}
+
+impl Writer {
+    // Errors are ignored: a closed pipe (the reader went away) is no reason to stop moving files
+    fn write(&mut self, message: Message) {
+        match message {
+            Message::Out(line) => {
+                let _ = writeln!(self.out, "{}", line);
+            }
+            Message::Err(line) => {
+                if !self.written.insert(line.clone()) {
+                    self.repeated += 1;
+                    return;
+                }
+                // Whatever came before it goes out first
+                let _ = self.out.flush();
+                let _ = writeln!(self.err, "{}", line).and_then(|_| self.err.flush());
+            }
+            Message::Moved { folder, bytes } => self.folders.entry(folder).or_default().add(bytes),
+        }
+    }
+
+    fn flush(&mut self) {
+        let _ = self.out.flush();
+    }
+
+    fn finish(&mut self) {
+        let prefix = if self.dry_run {
+            "[DRY RUN] Would move"
+        } else {
+            "Moved"
+        };
+        for (folder, tally) in std::mem::take(&mut self.folders) {
+            let start = format!(
+                "{} {} {} ({}) to ",
+                prefix,
+                tally.files,
+                if tally.files == 1 { "file" } else { "files" },
+                format_size(tally.bytes)
+            );
+            let line = self.display.line(&start, &folder, "");
+            let _ = writeln!(self.out, "{}", line);
+        }
+        self.flush();
+        if self.repeated > 0 {
+            let _ = writeln!(
+                self.err,
+                "({} repeated {} left out)",
+                self.repeated,
+                if self.repeated == 1 {
+                    "message"
+                } else {
+                    "messages"
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use crate::category;
+use crate::hashing;
+use crate::logic::{self, Options};
+use crate::protected;
+use crate::report::format_size;
+use crate::review::{self, ApplyOptions, Decision};
+use anyhow::{bail, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageReader};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Dedupe: find files that are stored more than once
+// =============================================================================
+//
+// Identical files are found in two steps, so most files are never read:
+//   1. group files by size (a file with a unique size can't have a duplicate)
+//   2. hash the files that share a size with another one (BLAKE3), group by hash
+//
+// With --perceptual, images are also compared by how they LOOK, so a resized or
+// re-encoded copy of a photo is found too (see dhash() below).
+//
+// By default dedupe never deletes anything. With --review it writes its findings to a file
+// of keep/delete decisions you can edit, and `dedupe apply` carries them out (see review.rs).
+// With --keep it picks the copy to keep itself and moves the others to the trash right away.
+// With --link the other copies are replaced by links to the kept one instead.
+//
+// Several --path roots are searched as one, so a photo in both ~/Downloads and ~/Pictures is
+// found; --prefer-root ~/Pictures then keeps the copies in there (the curated place) and lets
+// the ones elsewhere go.
+
+// What to look for
+#[derive(Debug, Clone, Default)]
+pub struct DedupeOptions {
+    // Also group images that look the same, not only byte-identical files
+    pub perceptual: bool,
+    // Compare samples of big files first, and read only the ones that agree in full (see hashing.rs)
+    pub quick_hash: bool,
+    // Write the findings to this review file
+    pub review: Option<PathBuf>,
+    // Which copy of identical files to keep. Without --review the others are deleted right away.
+    pub keep: Option<Keep>,
+    // Replace the other copies with links to the kept one, instead of moving them to the trash
+    pub link: bool,
+    pub dry_run: bool,
+    // Delete without asking (see confirm.rs)
+    pub yes: bool,
+}
+
+// How --keep picks the one copy of a group of identical files that stays
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Keep {
+    // The most recently modified copy
+    Newest,
+    // The copy that was modified longest ago (usually the original)
+    Oldest,
+    // The copy with the shortest path ("Pictures/beach.jpg" over "Pictures/old/backup/beach (1).jpg")
+    ShortestPath,
+    // The copy inside this folder, e.g. the curated one; if no copy is in there, all are kept
+    InDir(PathBuf),
+    // Every copy inside this folder (--prefer-root); if no copy is in there, all are kept
+    PreferRoot(PathBuf),
+}
+
+impl Keep {
+    // True if `file` is kept no matter which file keeper() picks (for PreferRoot: every copy in
+    // the preferred root)
+    pub fn also_keeps(&self, file: &Path) -> bool {
+        match self {
+            Keep::PreferRoot(dir) => inside(file, dir),
+            _ => false,
+        }
+    }
+}
+
+// True if `file` is inside `dir`, compared as absolute paths ("./Pictures" and
+// "/home/me/Pictures" are the same folder)
+fn inside(file: &Path, dir: &Path) -> bool {
+    let Ok(dir) = protected::absolute_path(dir) else {
+        return false;
+    };
+    protected::absolute_path(file).is_ok_and(|file| file.starts_with(&dir))
+}
+
+// Files with exactly the same contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    // Size of each file
+    pub size: u64,
+    // Sorted, at least two
+    pub files: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    // Bytes freed by keeping only one of the files
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+// --quick-hash: the candidates still worth a full read, after comparing quick hashes (small
+// files always are, their quick hash would be the full one)
+fn quick_pass(sizes: Vec<u64>, candidates: Vec<PathBuf>) -> (Vec<u64>, Vec<PathBuf>) {
+    let mut small = Vec::new();
+    let (mut big_sizes, mut big_files) = (Vec::new(), Vec::new());
+    for (size, file) in sizes.into_iter().zip(candidates) {
+        if size <= hashing::QUICK_MIN {
+            small.push((size, file));
+        } else {
+            big_sizes.push(size);
+            big_files.push(file);
+        }
+    }
+    let quick = hashing::quick_hash_files(&big_files);
+
+    let mut by_quick: HashMap<(u64, blake3::Hash), Vec<PathBuf>> = HashMap::new();
+    for ((size, file), hash) in big_sizes.into_iter().zip(big_files).zip(quick) {
+        match hash {
+            Ok(hash) => by_quick.entry((size, hash)).or_default().push(file),
+            Err(e) => eprintln!("Failed to read {:?}: {}", file, e),
+        }
+    }
+    let still = by_quick
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .flat_map(|((size, _), files)| files.into_iter().map(move |file| (size, file)));
+    small.into_iter().chain(still).unzip()
+}
+
+// Index of the file `keep` picks out of `files` (ties go to the first file).
+// None if there's nothing to pick: no copy in the --keep in-dir folder, or no readable times.
+pub fn keeper(files: &[PathBuf], keep: &Keep) -> Option<usize> {
+    let modified = || {
+        files.iter().enumerate().filter_map(|(i, file)| {
+            let time = fs::symlink_metadata(file).ok()?.modified().ok()?;
+            Some((i, time))
+        })
+    };
+    match keep {
+        Keep::Newest => modified()
+            .min_by_key(|(_, time)| Reverse(*time))
+            .map(|(i, _)| i),
+        Keep::Oldest => modified().min_by_key(|(_, time)| *time).map(|(i, _)| i),
+        Keep::ShortestPath => files
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, file)| file.as_os_str().len())
+            .map(|(i, _)| i),
+        Keep::InDir(dir) | Keep::PreferRoot(dir) => files.iter().position(|file| inside(file, dir)),
+    }
+}
+
+// One image of a group of similar images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarImage {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64,
+}
+
+// Two images count as similar if their hashes differ in at most this many of their 64 bits.
+// Kept low on purpose: a false "duplicate" is much worse than a missed one.
+const MAX_DISTANCE: u32 = 4;
+
+// Groups the byte-identical files among `files`, biggest savings first. With `quick_hash`, big
+// files are compared by samples first (see hashing.rs).
+// Empty files are left out: they are all "identical", but removing them frees nothing.
+pub fn find_duplicates(files: &[PathBuf], quick_hash: bool) -> Vec<DuplicateGroup> {
+    // 1. By size (symlink_metadata: a symlink is not a copy of its target)
+    let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+    for file in files {
+        if let Ok(metadata) = fs::symlink_metadata(file) {
+            if metadata.is_file() && metadata.len() > 0 {
+                by_size.entry(metadata.len()).or_default().push(file);
+            }
+        }
+    }
+
+    // 2. By hash, only for sizes that occur more than once (read on a few threads, hashed on all
+    // cores, see hashing.rs)
+    let (sizes, candidates): (Vec<u64>, Vec<PathBuf>) = by_size
+        .iter()
+        .filter(|(_, files)| files.len() > 1)
+        .flat_map(|(size, files)| files.iter().map(move |file| (*size, (*file).clone())))
+        .unzip();
+    let (sizes, candidates) = if quick_hash {
+        quick_pass(sizes, candidates)
+    } else {
+        (sizes, candidates)
+    };
+    let hashes = hashing::hash_files(&candidates);
+
+    let mut by_hash: HashMap<(u64, blake3::Hash), Vec<PathBuf>> = HashMap::new();
+    for ((size, file), hash) in sizes.into_iter().zip(candidates).zip(hashes) {
+        match hash {
+            Ok(hash) => by_hash.entry((size, hash)).or_default().push(file),
+            Err(e) => eprintln!("Failed to read {:?}: {}", file, e),
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_hash
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|((size, _), mut files)| {
+            files.sort();
+            DuplicateGroup { size, files }
+        })
+        .collect();
+    // Ties sorted by path, so the output is the same on every run
+    groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.files.cmp(&b.files)));
+    groups
+}
+
+// Groups the images among `files` that look alike (each group sorted by path, at least two).
+// Files that aren't images, or that can't be decoded (RAW, HEIC, SVG, ...), are left out.
+pub fn find_similar_images(files: &[PathBuf]) -> Vec<Vec<SimilarImage>> {
+    let images: Vec<&PathBuf> = files
+        .iter()
+        .filter(|file| {
+            file.extension()
+                .and_then(|ext| category::for_extension(&ext.to_string_lossy().to_lowercase()))
+                .is_some_and(|c| c.name == "images")
+        })
+        .collect();
+
+    // Decoding is the slow part, so it runs on all cores
+    let mut hashed: Vec<(SimilarImage, u64)> = images
+        .par_iter()
+        .filter_map(|path| {
+            let size = fs::symlink_metadata(path).ok()?.len();
+            let image = ImageReader::open(path)
+                .ok()?
+                .with_guessed_format()
+                .ok()?
+                .decode()
+                .ok()?;
+            let info = SimilarImage {
+                path: path.to_path_buf(),
+                width: image.width(),
+                height: image.height(),
+                size,
+            };
+            Some((info, dhash(&image)))
+        })
+        .collect();
+    hashed.sort_by(|a, b| a.0.path.cmp(&b.0.path));
+
+    // Union-find: join every two images that are close enough, then collect the sets.
+    // This compares every pair, which is fine for the few thousand photos of a typical folder.
+    let mut parent: Vec<usize> = (0..hashed.len()).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..hashed.len() {
+        for j in i + 1..hashed.len() {
+            if (hashed[i].1 ^ hashed[j].1).count_ones() <= MAX_DISTANCE {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[b] = a;
+            }
+        }
+    }
+
+    let mut sets: HashMap<usize, Vec<SimilarImage>> = HashMap::new();
+    for (i, (image, _)) in hashed.into_iter().enumerate() {
+        let set = find(&mut parent, i);
+        sets.entry(set).or_default().push(image);
+    }
+    let mut groups: Vec<Vec<SimilarImage>> =
+        sets.into_values().filter(|set| set.len() > 1).collect();
+    groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+    groups
+}
+
+// "Difference hash": shrink the image to 9x8 gray pixels, then store for each pixel whether
+// it's darker than its right neighbour. That's 64 bits describing the rough shape of the
+// picture, which barely change when it's resized, re-compressed or slightly brightened.
+fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+// Reports the duplicates below the folders `roots`, taken together (nothing is changed on disk,
+// unless --keep says what to delete)
+pub fn dedupe(roots: &[&str], options: &DedupeOptions) -> Result<()> {
+    match &options.keep {
+        Some(Keep::InDir(dir)) if !dir.is_dir() => {
+            bail!("--keep in-dir: {:?} is not a folder", dir)
+        }
+        Some(Keep::PreferRoot(dir)) if !dir.is_dir() => {
+            bail!("--prefer-root: {:?} is not a folder", dir)
+        }
+        _ => {}
+    }
+
+    let mut scanned: Vec<(&Path, PathBuf)> = Vec::new();
+    for root in roots {
+        let root = Path::new(root);
+        let abs_root = logic::checked_root(root, false)?;
+        // A root inside another one would be scanned twice, and every file in it would be its own copy
+        if let Some((other, _)) = scanned.iter().find(|(_, abs_other)| {
+            abs_root.starts_with(abs_other) || abs_other.starts_with(&abs_root)
+        }) {
+            bail!(
+                "{:?} and {:?} overlap (one is inside the other), give only the outer one",
+                other,
+                root
+            );
+        }
+        scanned.push((root, abs_root));
+    }
+
+    let mut files = Vec::new();
+    for (root, abs_root) in &scanned {
+        println!("Scanning directory: {:?}", root);
+        files.extend(logic::scan(root, abs_root, &Options::default()));
+    }
+    println!("Found {} files", files.len());
+
+    let groups = find_duplicates(&files, options.quick_hash);
+    for (i, group) in groups.iter().enumerate() {
+        println!(
+            "Identical files #{} ({} files, {} each):",
+            i + 1,
+            group.files.len(),
+            format_size(group.size)
+        );
+        for file in &group.files {
+            println!("    {:?}", file);
+        }
+    }
+
+    let similar = if options.perceptual {
+        // Only one file of each identical group, or every copy would show up as "similar" too
+        let copies: HashSet<&PathBuf> = groups.iter().flat_map(|g| &g.files[1..]).collect();
+        let candidates: Vec<PathBuf> = files
+            .iter()
+            .filter(|f| !copies.contains(f))
+            .cloned()
+            .collect();
+        find_similar_images(&candidates)
+    } else {
+        Vec::new()
+    };
+    for (i, group) in similar.iter().enumerate() {
+        println!("Similar images #{} ({} files):", i + 1, group.len());
+        for image in group {
+            println!(
+                "    {:?} ({}x{}, {})",
+                image.path,
+                image.width,
+                image.height,
+                format_size(image.size)
+            );
+        }
+    }
+
+    println!("--- Dedupe Complete ---");
+    let wasted: u64 = groups.iter().map(|g| g.wasted()).sum();
+    println!(
+        "{} groups of identical files, {} could be freed",
+        groups.len(),
+        format_size(wasted)
+    );
+    if options.perceptual {
+        println!("{} groups of similar images", similar.len());
+    }
+
+    if let (Some(keep), None) = (&options.keep, &options.review) {
+        // No review step: delete right away (similar images are all kept, so only identical files go)
+        let decisions = review::groups_for(&groups, &[], Some(keep), copies(options));
+        review::apply_groups(
+            &decisions,
+            &ApplyOptions {
+                dry_run: options.dry_run,
+                yes: options.yes,
+            },
+        )?;
+        return Ok(());
+    }
+    println!("Nothing was deleted");
+
+    if let Some(review_path) = &options.review {
+        let mut review_groups =
+            review::groups_for(&groups, &similar, options.keep.as_ref(), copies(options));
+        // Absolute paths, so the file can be applied from any folder
+        for group in &mut review_groups {
+            for (_, file) in &mut group.files {
+                if let Some((root, abs_root)) =
+                    scanned.iter().find(|(root, _)| file.starts_with(root))
+                {
+                    *file = abs_root.join(file.strip_prefix(root).unwrap_or(file));
+                }
+            }
+        }
+        review::write(review_path, &review_groups)?;
+        println!(
+            "Review file written to {:?}: edit it, then run `directory-cleaner dedupe apply {}`",
+            review_path,
+            review_path.display()
+        );
+    }
+    Ok(())
+}
+
+// What the copies that aren't kept get: deleted, or (--link) replaced by links
+fn copies(options: &DedupeOptions) -> Decision {
+    if options.link {
+        Decision::Link
+    } else {
+        Decision::Delete
+    }
+}
@@ -0,0 +1,172 @@
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+
+// =============================================================================
+// Showing paths in the text output: short, fitting the terminal, and safe
+// =============================================================================
+//
+// A run prints a line per file, and with full paths those get long:
+//
+//   Moved "/home/me/Downloads/inbox/2024/invoices/march/invoice-0042.pdf" -> "/home/me/Downloa
+//   ds/pdf/inbox/2024/invoices/march/invoice-0042.pdf"
+//
+// So the per-file lines show the paths relative to the organized folder (which is printed at the
+// start), and when a line still wouldn't fit the terminal, the folders at the front of a path are
+// cut off ("…/march/invoice-0042.pdf"). The file name itself is never cut, so every line still says
+// which file it is. Output that doesn't go to a terminal (a log file, a pipe) has no width, so
+// only the root is left out there. --full-paths prints the paths as they are.
+//
+// File names can contain anything, control characters too. Printed as they are, a name with an
+// escape sequence in it can move the cursor, recolor or clear the terminal, or hide the lines
+// before it, and one with a right-to-left override shows "fdp.exe" as "exe.pdf". Those characters
+// are always printed escaped ("\n", "\u{1b}", "\u{202e}").
+
+// A path is never given less room than this, however narrow the terminal
+const MIN_ROOM: usize = 20;
+
+// How paths are shown (Options::display; the root is filled in by the run)
+#[derive(Debug, Clone, Default)]
+pub struct PathDisplay {
+    // Paths below it are shown relative to it
+    pub root: Option<PathBuf>,
+    // Columns of the terminal, None if the output doesn't go to one
+    pub width: Option<usize>,
+    // --full-paths: show every path whole
+    pub full: bool,
+}
+
+impl PathDisplay {
+    // `path` in quotes, shortened to fit `room` characters (None = as long as it is)
+    pub fn path(&self, path: &Path, room: Option<usize>) -> String {
+        if self.full {
+            return quoted(&escape(&path.to_string_lossy()));
+        }
+        let relative = self
+            .root
+            .as_deref()
+            .and_then(|root| path.strip_prefix(root).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or(path);
+        fit(relative, room)
+    }
+
+    // The room for each of `paths` paths on a line that has `used` characters besides them
+    pub fn room(&self, used: usize, paths: usize) -> Option<usize> {
+        if self.full {
+            return None;
+        }
+        self.width
+            .map(|width| (width.saturating_sub(used) / paths.max(1)).max(MIN_ROOM))
+    }
+
+    // A line with the path in it: "{prefix}{path}{suffix}"
+    pub fn line(&self, prefix: &str, path: &Path, suffix: &str) -> String {
+        let room = self.room(prefix.chars().count() + suffix.chars().count(), 1);
+        format!("{}{}{}", prefix, self.path(path, room), suffix)
+    }
+
+    // A line with the paths `from` and `to` in it: "{prefix}{from} -> {to}{suffix}"
+    pub fn pair(&self, prefix: &str, from: &Path, to: &Path, suffix: &str) -> String {
+        let room = self.room(prefix.chars().count() + 4 + suffix.chars().count(), 2);
+        format!(
+            "{}{} -> {}{}",
+            prefix,
+            self.path(from, room),
+            self.path(to, room),
+            suffix
+        )
+    }
+}
+
+// `path` in quotes, with the folders at its front left out until it fits `room` (quotes included)
+pub fn fit(path: &Path, room: Option<usize>) -> String {
+    let parts: Vec<String> = path
+        .components()
+        .map(|c| match c {
+            Component::RootDir => String::new(),
+            c => escape(&c.as_os_str().to_string_lossy()),
+        })
+        .collect();
+    let whole = escape(&path.to_string_lossy());
+    let Some(room) = room else {
+        return quoted(&whole);
+    };
+    if whole.chars().count() + 2 <= room || parts.len() < 2 {
+        return quoted(&whole);
+    }
+
+    // As many folders from the end as fit next to "…/" (the file name always)
+    let mut tail: Vec<&str> = vec![&parts[parts.len() - 1]];
+    let mut len = 2 + 2 + tail[0].chars().count();
+    for part in parts[..parts.len() - 1].iter().rev() {
+        let more = part.chars().count() + 1;
+        if len + more > room {
+            break;
+        }
+        len += more;
+        tail.push(part);
+    }
+    if tail.len() == parts.len() {
+        return quoted(&whole);
+    }
+    tail.reverse();
+    quoted(&format!(
+        "…{}{}",
+        MAIN_SEPARATOR,
+        tail.join(MAIN_SEPARATOR_STR)
+    ))
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text)
+}
+
+// `text` with the characters that would do something to the terminal escaped: control characters
+// ("\n", "\u{1b}") and the ones that change the direction of the text around them
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '"' => out.push_str("\\\""),
+            c if c.is_control() || is_bidi_control(c) => {
+                out.push_str(&format!("\\u{{{:x}}}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Marks, embeddings, overrides and isolates of the Unicode bidirectional algorithm
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+// How wide the terminal on stdout is, None if stdout isn't a terminal
+pub fn terminal_width() -> Option<usize> {
+    use std::io::IsTerminal;
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    width_of_stdout().or_else(|| {
+        std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .filter(|&columns| columns > 0)
+    })
+}
+
+#[cfg(unix)]
+fn width_of_stdout() -> Option<usize> {
+    rustix::termios::tcgetwinsize(std::io::stdout())
+        .ok()
+        .map(|size| size.ws_col as usize)
+        .filter(|&columns| columns > 0)
+}
+
+#[cfg(not(unix))]
+fn width_of_stdout() -> Option<usize> {
+    None
+}
@@ -0,0 +1,57 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+// =============================================================================
+// Duplicate detection for --on-duplicate
+// =============================================================================
+//
+// Two files count as duplicates only if they have exactly the same bytes. Sizes are
+// compared first (one metadata call each), so files are only read when the sizes match,
+// and reading stops at the first difference.
+
+// Buffer size for comparing contents
+const CHUNK: usize = 64 * 1024;
+
+// True if `a` and `b` are regular files with identical contents.
+// Anything that can't be read counts as "different", so the file is kept.
+pub fn same_content(a: &Path, b: &Path) -> bool {
+    compare(a, b).unwrap_or(false)
+}
+
+fn compare(a: &Path, b: &Path) -> io::Result<bool> {
+    // symlink_metadata: a symlink is never a duplicate of the file it points to
+    let (meta_a, meta_b) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+    if !meta_a.is_file() || !meta_b.is_file() || meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+
+    let (mut file_a, mut file_b) = (File::open(a)?, File::open(b)?);
+    let mut buf_a = vec![0u8; CHUNK];
+    let mut buf_b = vec![0u8; CHUNK];
+    loop {
+        let n = read_full(&mut file_a, &mut buf_a)?;
+        let m = read_full(&mut file_b, &mut buf_b)?;
+        if n != m || buf_a[..n] != buf_b[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// Like read(), but keeps going until `buf` is full or the file ends,
+// so both files are compared in the same sized pieces
+pub(crate) fn read_full(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
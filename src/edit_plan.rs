@@ -0,0 +1,285 @@
+use crate::journal;
+use crate::plan::{self, PlannedAction};
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use uuid::Uuid;
+
+// =============================================================================
+// --edit-plan: change the plan in your editor before it runs
+// =============================================================================
+//
+// The plan opens in $VISUAL (or $EDITOR) as one line per file, relative to the folder:
+//
+//   move  inbox/invoice.pdf -> pdf/invoice.pdf
+//   move  IMG_0042.jpg -> jpg/IMG_0042.jpg
+//   trash report (1).pdf
+//
+// Save and close the editor, and the edited plan runs:
+//   - a removed line leaves that file where it is (it shows up as skipped)
+//   - the part after "->" can be changed to send the file somewhere else
+// New lines can't be added (only the files of the plan are known to be safe to move). Every
+// changed destination is checked before anything moves: it has to stay inside the folder, can't
+// be a file that's already there and can't be used twice. If one isn't right, nothing moves.
+
+// The editor to use: $VISUAL, else $EDITOR, else vi (Notepad on Windows)
+pub fn editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string())
+}
+
+// Opens the plan in `editor` and returns the plan as edited
+pub fn edit(actions: Vec<PlannedAction>, root: &Path, editor: &str) -> Result<Vec<PlannedAction>> {
+    let path = env::temp_dir().join(format!("directory-cleaner-plan-{}.txt", Uuid::new_v4()));
+    fs::write(&path, plan_text(&actions, root))
+        .with_context(|| format!("Failed to write the plan to {:?}", path))?;
+
+    let edited = run_editor(editor, &path).and_then(|()| {
+        fs::read_to_string(&path).with_context(|| format!("Failed to read the plan {:?}", path))
+    });
+    let _ = fs::remove_file(&path);
+    apply(actions, &edited?, root)
+}
+
+// The editor command goes through the shell, so "code --wait" works as it does for git
+fn run_editor(editor: &str, file: &Path) -> Result<()> {
+    #[cfg(windows)]
+    let status = Command::new("cmd")
+        .arg("/C")
+        .arg(format!("{} \"{}\"", editor, file.display()))
+        .status();
+    #[cfg(not(windows))]
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(file)
+        .status();
+
+    let status = status.with_context(|| format!("Failed to start the editor {:?}", editor))?;
+    if !status.success() {
+        bail!(
+            "The editor {:?} exited with {}, nothing was moved",
+            editor,
+            status
+        );
+    }
+    Ok(())
+}
+
+// The plan as the editor shows it
+pub fn plan_text(actions: &[PlannedAction], root: &Path) -> String {
+    let mut out = String::new();
+    out.push_str("# The plan of this run. Save and close the editor to carry it out.\n");
+    out.push_str("# Remove a line to leave that file where it is, change the part after \"->\"\n");
+    out.push_str("# to send it somewhere else. Paths are relative to the organized folder.\n");
+    out.push('\n');
+    for action in actions {
+        match action {
+            PlannedAction::Move { from, to, .. } => out.push_str(&format!(
+                "move  {} -> {}\n",
+                relative(from, root).display(),
+                relative(to, root).display()
+            )),
+            PlannedAction::Trash { path, .. } => {
+                out.push_str(&format!("trash {}\n", relative(path, root).display()))
+            }
+            PlannedAction::Skip { path, reason } => out.push_str(&format!(
+                "# skip {} ({})\n",
+                relative(path, root).display(),
+                reason
+            )),
+            PlannedAction::CreateDir { .. } => {}
+        }
+    }
+    out
+}
+
+fn relative<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+// The plan `actions` changed the way the (edited) `text` says
+pub fn apply(actions: Vec<PlannedAction>, text: &str, root: &Path) -> Result<Vec<PlannedAction>> {
+    // The files of the plan, by how the editor shows them
+    let planned: HashMap<PathBuf, Option<&Path>> = actions
+        .iter()
+        .filter_map(plan::moved_file)
+        .map(|(file, to)| (relative(file, root).to_path_buf(), to))
+        .collect();
+
+    // File -> where it goes now (None for the trash)
+    let mut kept: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+    let mut taken: HashSet<PathBuf> = HashSet::new();
+    let mut problems = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let n = i + 1;
+        // "move some file -> somewhere else": the first word, then the rest of the line
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim_start();
+        let (file, to) = match word {
+            "trash" => (PathBuf::from(rest), None),
+            // File names can contain " -> " too, so the split is the one where the left side is
+            // a file of the plan
+            "move" => match rest
+                .match_indices(" -> ")
+                .map(|(at, _)| (Path::new(&rest[..at]), rest[at + 4..].trim()))
+                .find(|(file, _)| planned.contains_key(*file))
+            {
+                Some((file, to)) => (file.to_path_buf(), Some(PathBuf::from(to))),
+                None => {
+                    problems.push(format!(
+                        "line {}: expected \"move <file of the plan> -> <destination>\"",
+                        n
+                    ));
+                    continue;
+                }
+            },
+            _ => {
+                problems.push(format!(
+                    "line {}: expected \"move\" or \"trash\", found {:?}",
+                    n, word
+                ));
+                continue;
+            }
+        };
+
+        let Some(planned_to) = planned.get(&file) else {
+            problems.push(format!("line {}: {:?} isn't part of the plan", n, file));
+            continue;
+        };
+        if planned_to.is_some() != to.is_some() {
+            problems.push(format!(
+                "line {}: {:?} can't change between \"move\" and \"trash\"",
+                n, file
+            ));
+            continue;
+        }
+        if kept.contains_key(&file) {
+            problems.push(format!("line {}: {:?} is listed twice", n, file));
+            continue;
+        }
+        if let Some(to) = &to {
+            let unchanged = planned_to.is_some_and(|planned| relative(planned, root) == to);
+            if let Some(problem) = bad_destination(to, root, unchanged) {
+                problems.push(format!("line {}: {:?} {}", n, to, problem));
+                continue;
+            }
+            if !taken.insert(to.clone()) {
+                problems.push(format!(
+                    "line {}: {:?} is the destination of two files",
+                    n, to
+                ));
+                continue;
+            }
+        }
+        kept.insert(file, to);
+    }
+    if !problems.is_empty() {
+        bail!(
+            "The edited plan has problems, nothing was moved:\n  {}",
+            problems.join("\n  ")
+        );
+    }
+
+    // The new destinations, and the folders they need (CreateDirs come first)
+    let mut folders: Vec<PathBuf> = Vec::new();
+    let mut edited: Vec<PlannedAction> = actions
+        .into_iter()
+        .map(|action| match action {
+            PlannedAction::Move {
+                from,
+                to,
+                ext,
+                category,
+                shortened,
+            } => {
+                let new_to = kept
+                    .get(relative(&from, root))
+                    .cloned()
+                    .flatten()
+                    .map(|to| root.join(to));
+                match new_to {
+                    Some(new_to) if new_to != to => {
+                        folders.extend(new_to.parent().map(Path::to_path_buf));
+                        PlannedAction::Move {
+                            from,
+                            to: new_to,
+                            ext,
+                            category,
+                            shortened: false,
+                        }
+                    }
+                    _ => PlannedAction::Move {
+                        from,
+                        to,
+                        ext,
+                        category,
+                        shortened,
+                    },
+                }
+            }
+            action => action,
+        })
+        .collect();
+    let mut planned_folders: HashSet<PathBuf> = edited
+        .iter()
+        .filter_map(|action| match action {
+            PlannedAction::CreateDir { path } => Some(path.clone()),
+            _ => None,
+        })
+        .collect();
+    folders.retain(|folder| !folder.is_dir() && planned_folders.insert(folder.clone()));
+    edited.splice(
+        0..0,
+        folders
+            .into_iter()
+            .map(|path| PlannedAction::CreateDir { path }),
+    );
+
+    // Removed lines: the file stays
+    Ok(plan::skip_where(edited, |file, _| {
+        (!kept.contains_key(relative(file, root))).then(|| "left out in the editor".to_string())
+    }))
+}
+
+// Why `to` (relative to `root`) can't be a destination, None if it can. `unchanged` = it's what the
+// plan said, which was checked already.
+fn bad_destination(to: &Path, root: &Path, unchanged: bool) -> Option<&'static str> {
+    if unchanged {
+        return None;
+    }
+    if to.as_os_str().is_empty() {
+        return Some("is empty");
+    }
+    if !to
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Some("has to stay inside the organized folder");
+    }
+    if to
+        .components()
+        .find(|c| matches!(c, Component::Normal(_)))
+        .is_some_and(|first| first.as_os_str() == journal::STATE_DIR)
+    {
+        return Some("is where the journals are kept");
+    }
+    if to.file_name().is_none() {
+        return Some("has no file name");
+    }
+    if fs::symlink_metadata(root.join(to)).is_ok() {
+        return Some("already exists");
+    }
+    None
+}
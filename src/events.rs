@@ -0,0 +1,125 @@
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+// =============================================================================
+// --output jsonl: one JSON event per line on stdout, for GUIs and scripts
+// =============================================================================
+//
+// With --output jsonl the human text is left out of stdout (errors still go to stderr as text)
+// and every step is reported as it happens instead:
+//
+//   {"event":"scan","schema_version":1,"root":"./Downloads","files":3}
+//   {"event":"plan","moves":2,"skips":1,"trash":0}
+//   {"event":"skip","path":"./Downloads/pdf","reason":"..."}
+//   {"event":"move","from":"./Downloads/a.pdf","to":"./Downloads/pdf/a.pdf","bytes":52133,"dry_run":false}
+//   {"event":"warning","message":"The name of ... was shortened to ..."}
+//   {"event":"error","message":"Failed to move ..."}
+//   {"event":"reconciliation","planned":3,"planned_bytes":60877,"moved":2,"bytes":60211,"vanished":0,"late_conflicts":0,"in_place":0,"not_started":0,"failed":1,"resized":0}
+//   {"event":"done","run_id":"6f1c...","moved":2,"bytes":60211,"trashed":0,"problems":1,"warnings":1,"stopped_early":false,"left_out":0}
+//
+// Warnings are what's worth a look but didn't go wrong (a shortened name, a link that breaks);
+// errors are what failed. Skips count as warnings too, but keep their own "skip" event.
+//
+// Moves are reported from several threads at once, so their order can differ between runs.
+// (Those events go out through one writer, see console.rs, so each of them is one whole line.)
+
+// How a run reports what it does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    // Readable text (the default)
+    #[default]
+    Text,
+    // JSON Lines events, see above
+    Jsonl,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    // The scan is done (always the first event)
+    Scan {
+        // See schema.rs
+        schema_version: u32,
+        root: &'a Path,
+        files: usize,
+    },
+    // The plan is made: how many files will be moved, skipped, moved to the trash
+    Plan {
+        moves: usize,
+        skips: usize,
+        trash: usize,
+    },
+    // A file the planner leaves where it is, and why
+    Skip {
+        path: &'a Path,
+        reason: &'a str,
+    },
+    // A file was moved (in a dry run: would be)
+    Move {
+        from: &'a Path,
+        to: &'a Path,
+        bytes: u64,
+        dry_run: bool,
+    },
+    // A file was moved to the system trash (in a dry run: would be)
+    Trash {
+        path: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duplicate_of: Option<&'a Path>,
+        dry_run: bool,
+    },
+    // Worth a look, but nothing went wrong
+    Warning {
+        message: &'a str,
+    },
+    // Something went wrong (the run goes on)
+    Error {
+        message: &'a str,
+    },
+    // After a real run: how it went compared to the plan (see reconcile.rs). Every planned move
+    // is in exactly one of moved, vanished, late_conflicts, in_place, not_started and failed.
+    Reconciliation {
+        planned: u64,
+        planned_bytes: u64,
+        moved: u64,
+        bytes: u64,
+        vanished: u64,
+        late_conflicts: u64,
+        // Files that were at their destination already (under another name)
+        in_place: u64,
+        not_started: u64,
+        failed: u64,
+        // Moved files whose size changed since the plan
+        resized: u64,
+    },
+    // Last event of a run
+    Done {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<Uuid>,
+        moved: u64,
+        bytes: u64,
+        trashed: u64,
+        problems: usize,
+        // Skips included
+        warnings: usize,
+        stopped_early: bool,
+        // Moves left for the next run by --limit / --limit-bytes / --max-duration
+        left_out: usize,
+    },
+}
+
+// `event` as one line of JSON
+pub fn line(event: &Event) -> String {
+    serde_json::to_string(event).expect("events always serialize")
+}
+
+// Writes `event` as one line to stdout (the lock keeps lines from different threads whole;
+// during the moves, events go through the Console instead, see console.rs)
+pub fn emit(event: &Event) {
+    let line = line(event);
+    let mut stdout = io::stdout().lock();
+    // A closed pipe (the reader went away) is no reason to stop moving files
+    let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+}
@@ -0,0 +1,97 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// =============================================================================
+// --stop-if-free-below: leave room on the destination drive
+// =============================================================================
+//
+// Moves that cross drives are a copy and a delete, so while a file is copied it takes up space on
+// the destination too. Before each copy we look at how much is free and stop the run if the copy
+// would leave less than --stop-if-free-below.
+//
+// With several threads copying at once, each one looking on its own isn't enough: two threads can
+// both see 10 GB free, both decide their 6 GB file fits, and together leave nothing. So the bytes of
+// the copies still under way are counted as used already: a thread adds its file size to the shared
+// total *before* it looks, and takes it off again once its copy is done (or failed). The half of a
+// copy that's already written counts twice then, which errs on the side of stopping early.
+
+// Where the free space comes from: the real drive, or a made-up number in the tests
+pub type FreeSpaceOf = fn(&Path) -> io::Result<u64>;
+
+// Shared by all move threads of a run
+#[derive(Debug)]
+pub struct FreeSpace {
+    threshold: u64,
+    // Bytes of the copies that passed the check and aren't finished yet
+    in_flight: AtomicU64,
+    free_space_of: FreeSpaceOf,
+}
+
+// What a look at the free space found
+#[derive(Debug)]
+pub enum Room<'a> {
+    // There's room: keep the reservation until the copy is done
+    Enough(Reservation<'a>),
+    // Not enough: `free` bytes free, `in_flight` of them promised to other copies
+    TooLittle { free: u64, in_flight: u64 },
+    // The free space couldn't be read: the copy goes ahead (still counted for the others)
+    Unknown(Reservation<'a>, io::Error),
+}
+
+// A copy's share of the in-flight total, given back when it's dropped (so an early return or a
+// failed copy can't forget to)
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    space: &'a FreeSpace,
+    bytes: u64,
+}
+
+impl FreeSpace {
+    pub fn new(threshold: u64) -> Self {
+        Self::with_source(threshold, |path| fs4::available_space(path))
+    }
+
+    pub fn with_source(threshold: u64, free_space_of: FreeSpaceOf) -> Self {
+        FreeSpace {
+            threshold,
+            in_flight: AtomicU64::new(0),
+            free_space_of,
+        }
+    }
+
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    // Bytes being copied right now
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    // Reserves `bytes` on the drive of `dest_folder` if that still leaves the threshold free
+    pub fn reserve(&self, bytes: u64, dest_folder: &Path) -> Room<'_> {
+        // .fetch_add() returns the old total: everything promised before us, plus our own bytes
+        let in_flight = self.in_flight.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        let reservation = Reservation { space: self, bytes };
+
+        let free = match (self.free_space_of)(dest_folder) {
+            Ok(free) => free,
+            Err(e) => return Room::Unknown(reservation, e),
+        };
+        if free.saturating_sub(in_flight) >= self.threshold {
+            return Room::Enough(reservation);
+        }
+        // The reservation is dropped here, so a refused copy doesn't hold space for the others
+        Room::TooLittle {
+            free,
+            in_flight: in_flight - bytes,
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.space.in_flight.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
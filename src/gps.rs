@@ -0,0 +1,161 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+// =============================================================================
+// --strip-exif-gps: remove the location from photos
+// =============================================================================
+//
+// Phones write where a photo was taken into its EXIF data, and that travels along when the photo is
+// shared. Cleaning up is a natural moment to get rid of it. Only the location goes; the rest of
+// the EXIF data (camera, date, orientation, ...) and the picture itself stay as they are.
+//
+// How: EXIF is a little TIFF file inside the JPEG's APP1 segment. Its first folder of tags
+// (IFD0) points to a separate GPS folder. That folder's tags and the values they point to are
+// overwritten with zeros and the folder is marked empty, so every offset in the file stays valid
+// and the file keeps its size. Its modification time is put back afterwards.
+//
+// Only JPEG for now (what phones and most cameras write); PNG, WebP and HEIC are left alone.
+
+// Where the GPS data of a JPEG is, as byte ranges to clear (None = no GPS data)
+struct Gps {
+    // The GPS folder's entry count (2 bytes), set to 0
+    count_at: usize,
+    // Its entries and the values that don't fit in an entry
+    ranges: Vec<(usize, usize)>,
+    // Little-endian ("II") or big-endian ("MM") numbers
+    little_endian: bool,
+}
+
+// True if `path` is a JPEG with a GPS location in its EXIF data
+pub fn has_gps(path: &Path) -> io::Result<bool> {
+    Ok(find(&fs::read(path)?).is_some_and(|gps| !gps.ranges.is_empty()))
+}
+
+// Removes the GPS location from the JPEG `path`. Returns false if it has none (or isn't a JPEG).
+pub fn strip_gps(path: &Path) -> io::Result<bool> {
+    let mut data = fs::read(path)?;
+    let Some(gps) = find(&data) else {
+        return Ok(false);
+    };
+    if gps.ranges.is_empty() {
+        return Ok(false);
+    }
+    let modified = fs::metadata(path)?.modified()?;
+
+    for (start, end) in &gps.ranges {
+        data[*start..*end].fill(0);
+    }
+    let zero = if gps.little_endian {
+        0u16.to_le_bytes()
+    } else {
+        0u16.to_be_bytes()
+    };
+    data[gps.count_at..gps.count_at + 2].copy_from_slice(&zero);
+
+    // Same size, so written over the old bytes: no moment where the file is cut short
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    file.set_modified(modified)?;
+    Ok(true)
+}
+
+// Finds the GPS folder in a JPEG's EXIF data
+fn find(data: &[u8]) -> Option<Gps> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    // The segments before the picture data: FF <marker> <length, 2 bytes, counting itself> <data>
+    let mut at = 2;
+    while at + 4 <= data.len() && data[at] == 0xFF {
+        let marker = data[at + 1];
+        // Start of the picture data (or its end): no more metadata after this
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let length = u16::from_be_bytes([data[at + 2], data[at + 3]]) as usize;
+        let segment = data.get(at + 4..at + 2 + length)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return in_tiff(data, at + 4 + 6, at + 2 + length);
+        }
+        at += 2 + length;
+    }
+    None
+}
+
+// Finds the GPS folder in the TIFF data between `start` and `end` of `data`
+// (all TIFF offsets count from `start`)
+fn in_tiff(data: &[u8], start: usize, end: usize) -> Option<Gps> {
+    let tiff = data.get(start..end)?;
+    let little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |at: usize| {
+        let bytes: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        } as usize)
+    };
+
+    // IFD0: a count, then 12-byte entries (tag, type, count, value or offset of the value)
+    let ifd0 = u32_at(4)?;
+    let gps_ifd = (0..u16_at(ifd0)? as usize).find_map(|i| {
+        let entry = ifd0 + 2 + i * 12;
+        // 0x8825 = GPSInfo, the offset of the GPS folder
+        (u16_at(entry)? == 0x8825).then(|| u32_at(entry + 8))?
+    });
+    let Some(gps_ifd) = gps_ifd else {
+        return Some(Gps {
+            count_at: 0,
+            ranges: Vec::new(),
+            little_endian,
+        });
+    };
+
+    let count = u16_at(gps_ifd)? as usize;
+    let entries = gps_ifd + 2;
+    let mut ranges = vec![(start + entries, start + entries + count * 12)];
+    for i in 0..count {
+        let entry = entries + i * 12;
+        let size = match u16_at(entry + 2)? {
+            // BYTE, ASCII, SBYTE, UNDEFINED
+            1 | 2 | 6 | 7 => 1,
+            // SHORT, SSHORT
+            3 | 8 => 2,
+            // LONG, SLONG, FLOAT
+            4 | 9 | 11 => 4,
+            // RATIONAL, SRATIONAL, DOUBLE
+            5 | 10 | 12 => 8,
+            _ => continue,
+        };
+        let bytes = size * u32_at(entry + 4)?;
+        // Values of up to 4 bytes sit in the entry itself, longer ones elsewhere
+        if bytes > 4 {
+            let offset = u32_at(entry + 8)?;
+            if offset + bytes <= tiff.len() {
+                ranges.push((start + offset, start + offset + bytes));
+            }
+        }
+    }
+    if start + entries + count * 12 > end {
+        return None;
+    }
+    Some(Gps {
+        count_at: start + gps_ifd,
+        ranges: if count == 0 { Vec::new() } else { ranges },
+        little_endian,
+    })
+}
@@ -0,0 +1,137 @@
+use crate::duplicate;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// =============================================================================
+// Hashing many files: reading on IO threads, hashing on the CPU pool
+// =============================================================================
+//
+// Hashing a file is two kinds of work: waiting for the disk, and crunching the bytes. Done on one
+// pool, a few big files keep every thread waiting for the disk while the cores sit idle, or a
+// BLAKE3 hash of a 40 GB video runs on one core while the others have nothing to do. So the two
+// are split:
+//   - a few reader threads (READERS, not one per core: a disk doesn't get faster with more
+//     readers) each take the next file and read it in CHUNK-sized pieces
+//   - each piece is hashed on the rayon pool, spread over all cores (BLAKE3 can hash one piece
+//     in parallel), while the reader already reads the next piece
+// Every reader holds at most two pieces, so memory stays at READERS * 2 * CHUNK (8 MB) however
+// big the files are.
+//
+// --quick-hash adds a pass before that: a quick hash of three samples of a big file (its start,
+// middle and end) and its size. Files whose quick hashes differ can't be identical, so only the
+// ones that agree are read in full. Two videos of the same size almost never start, middle and
+// end the same, so most are told apart by reading 3 * SAMPLE bytes instead of gigabytes.
+
+// How many files are read at the same time
+const READERS: usize = 4;
+// How much of a file is read (and hashed) at a time
+const CHUNK: usize = 1024 * 1024;
+// How much the quick hash reads at each of its three places
+const SAMPLE: u64 = 64 * 1024;
+// Files up to this size are read whole by the quick hash too (the samples would cover them anyway)
+pub const QUICK_MIN: u64 = 3 * SAMPLE;
+
+// The blake3 hashes of `files`, in the same order (or why a file couldn't be read)
+pub fn hash_files(files: &[PathBuf]) -> Vec<io::Result<blake3::Hash>> {
+    on_readers(files, hash_file)
+}
+
+// The quick hashes of `files` (see quick_hash), in the same order
+pub fn quick_hash_files(files: &[PathBuf]) -> Vec<io::Result<blake3::Hash>> {
+    on_readers(files, quick_hash)
+}
+
+// `hash` of each of `files`, READERS files at a time
+fn on_readers(
+    files: &[PathBuf],
+    hash: fn(&Path) -> io::Result<blake3::Hash>,
+) -> Vec<io::Result<blake3::Hash>> {
+    let next = AtomicUsize::new(0);
+    let mut hashes: Vec<Option<io::Result<blake3::Hash>>> = Vec::new();
+    hashes.resize_with(files.len(), || None);
+
+    thread::scope(|s| {
+        let readers: Vec<_> = (0..READERS.min(files.len()))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(file) = files.get(i) else {
+                            return done;
+                        };
+                        done.push((i, hash(file)));
+                    }
+                })
+            })
+            .collect();
+        for reader in readers {
+            for (i, hash) in reader.join().expect("a reader thread panicked") {
+                hashes[i] = Some(hash);
+            }
+        }
+    });
+    hashes
+        .into_iter()
+        .map(|hash| hash.expect("every file is hashed"))
+        .collect()
+}
+
+// The blake3 hash of the contents of `path`
+pub fn hash_file(path: &Path) -> io::Result<blake3::Hash> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut piece = vec![0u8; CHUNK];
+    let mut len = duplicate::read_full(&mut file, &mut piece)?;
+    // A small file is hashed faster right here than by handing it to the pool
+    if len < CHUNK {
+        hasher.update(&piece[..len]);
+        return Ok(hasher.finalize());
+    }
+
+    let mut spare = vec![0u8; CHUNK];
+    loop {
+        // Hash this piece on the pool while the next one is read; the hasher and the piece come
+        // back when it's done
+        let (done, finished) = mpsc::sync_channel(1);
+        rayon::spawn(move || {
+            hasher.update_rayon(&piece[..len]);
+            let _ = done.send((hasher, piece));
+        });
+        let read = duplicate::read_full(&mut file, &mut spare);
+        (hasher, piece) = finished
+            .recv()
+            .map_err(|_| io::Error::other("hashing stopped halfway"))?;
+
+        len = read?;
+        if len == 0 {
+            return Ok(hasher.finalize());
+        }
+        mem::swap(&mut piece, &mut spare);
+    }
+}
+
+// A hash of the size and of SAMPLE bytes at the start, middle and end of `path` (the whole file if
+// it's no bigger than QUICK_MIN). Different quick hashes mean different contents; the same quick
+// hash only means the file is worth a full read.
+pub fn quick_hash(path: &Path) -> io::Result<blake3::Hash> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if size <= QUICK_MIN {
+        return hash_file(path);
+    }
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&size.to_le_bytes());
+    let mut sample = vec![0u8; SAMPLE as usize];
+    for at in [0, size / 2 - SAMPLE / 2, size - SAMPLE] {
+        file.seek(SeekFrom::Start(at))?;
+        let len = duplicate::read_full(&mut file, &mut sample)?;
+        hasher.update(&sample[..len]);
+    }
+    Ok(hasher.finalize())
+}
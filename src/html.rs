@@ -0,0 +1,234 @@
+use crate::logic::Outcome;
+use crate::plan::PlannedAction;
+use crate::report::{format_size, Tally};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+// =============================================================================
+// --report html: a self-contained HTML page about a run
+// =============================================================================
+//
+// One file, no external CSS, scripts or images, so it can be mailed around or archived as is:
+//   - the totals, and a bar chart of the size per category (or per extension)
+//   - every moved file, in a table you can sort by clicking on a column header
+//   - the files that were skipped, and everything that went wrong
+
+// What the report is about
+pub struct Run<'a> {
+    pub root: &'a Path,
+    // None for dry runs (they have no ID)
+    pub run_id: Option<Uuid>,
+    pub dry_run: bool,
+    pub actions: &'a [PlannedAction],
+    pub outcome: &'a Outcome,
+}
+
+// Writes the report about `run` to `path`
+pub fn write(path: &Path, run: &Run) -> Result<()> {
+    fs::write(path, render(run)).with_context(|| format!("Failed to write report {:?}", path))
+}
+
+// The whole page as a string
+pub fn render(run: &Run) -> String {
+    let outcome = run.outcome;
+    let relative = |p: &Path| escape(&p.strip_prefix(run.root).unwrap_or(p).to_string_lossy());
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Directory Cleaner report</title>\n");
+    html.push_str(STYLE);
+    html.push_str("</head>\n<body>\n");
+
+    // Header and totals
+    html.push_str(&format!(
+        "<h1>Directory Cleaner report{}</h1>\n",
+        if run.dry_run {
+            " <span class=\"badge\">dry run</span>"
+        } else {
+            ""
+        }
+    ));
+    html.push_str("<table class=\"facts\">\n");
+    let total = outcome.moved.total();
+    let skipped = run
+        .actions
+        .iter()
+        .filter(|a| matches!(a, PlannedAction::Skip { .. }))
+        .count();
+    let mut facts = vec![
+        ("Folder", escape(&run.root.to_string_lossy())),
+        ("Date", Utc::now().format("%Y-%m-%d %H:%M UTC").to_string()),
+        (
+            if run.dry_run { "Would move" } else { "Moved" },
+            format!("{} files, {}", total.files, format_size(total.bytes)),
+        ),
+        ("Moved to the trash", format!("{} files", outcome.trashed)),
+        ("Skipped", format!("{} files", skipped)),
+        ("Warnings", outcome.warnings.len().to_string()),
+        ("Problems", outcome.problems.len().to_string()),
+    ];
+    if let Some(run_id) = run.run_id {
+        facts.insert(1, ("Run ID", run_id.to_string()));
+    }
+    for (name, value) in facts {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, value));
+    }
+    html.push_str("</table>\n");
+
+    // Size per category, or per extension if the run didn't use categories
+    let mut groups: Vec<(String, Tally)> = if outcome.moved.by_category.is_empty() {
+        outcome
+            .moved
+            .by_ext
+            .iter()
+            .map(|(ext, tally)| (ext_label(ext), *tally))
+            .collect()
+    } else {
+        outcome
+            .moved
+            .by_category
+            .iter()
+            .map(|(name, group)| (name.clone(), group.total))
+            .collect()
+    };
+    groups.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(&b.0)));
+    if !groups.is_empty() {
+        html.push_str("<h2>Size per group</h2>\n<table class=\"chart\">\n");
+        let biggest = groups[0].1.bytes.max(1);
+        for (name, tally) in &groups {
+            html.push_str(&format!(
+                "<tr><th>{}</th><td><div class=\"bar\" style=\"width: {:.1}%\"></div></td><td>{} files, {}</td></tr>\n",
+                escape(name),
+                tally.bytes as f64 * 100.0 / biggest as f64,
+                tally.files,
+                format_size(tally.bytes)
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+
+    // Every moved file, sorted by destination to start with
+    let mut files: Vec<_> = outcome.files.iter().collect();
+    files.sort_by(|a, b| a.to.cmp(&b.to));
+    html.push_str(&format!("<h2>Moved files ({})</h2>\n", files.len()));
+    html.push_str("<table class=\"sortable\">\n<thead><tr><th>From</th><th>To</th><th>Group</th><th>Size</th></tr></thead>\n<tbody>\n");
+    for file in files {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td data-sort=\"{}\">{}</td></tr>\n",
+            relative(&file.from),
+            relative(&file.to),
+            escape(file.category.as_deref().unwrap_or("")),
+            file.bytes,
+            format_size(file.bytes)
+        ));
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    // Skipped by the planner, with the reason
+    let skips: Vec<(&Path, &str)> = run
+        .actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::Skip { path, reason } => Some((path.as_path(), reason.as_str())),
+            _ => None,
+        })
+        .collect();
+    if !skips.is_empty() {
+        html.push_str(&format!("<h2>Skipped ({})</h2>\n", skips.len()));
+        html.push_str("<table class=\"sortable\">\n<thead><tr><th>File</th><th>Reason</th></tr></thead>\n<tbody>\n");
+        for (path, reason) in skips {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                relative(path),
+                escape(reason)
+            ));
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    if !outcome.warnings.is_empty() {
+        html.push_str(&format!(
+            "<h2>Warnings ({})</h2>\n<ul class=\"warnings\">\n",
+            outcome.warnings.len()
+        ));
+        for warning in &outcome.warnings {
+            html.push_str(&format!("<li>{}</li>\n", escape(warning)));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    if !outcome.problems.is_empty() {
+        html.push_str(&format!(
+            "<h2>Problems ({})</h2>\n<ul class=\"problems\">\n",
+            outcome.problems.len()
+        ));
+        for problem in &outcome.problems {
+            html.push_str(&format!("<li>{}</li>\n", escape(problem)));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str(SCRIPT);
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// ".pdf", or "(no extension)" for scripts (--by language)
+fn ext_label(ext: &str) -> String {
+    if ext.is_empty() {
+        "(no extension)".to_string()
+    } else {
+        format!(".{}", ext)
+    }
+}
+
+// File names can contain anything, "<" and "&" included
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = r#"<style>
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 .badge { font-size: 0.5em; background: #e8a33d; color: white; padding: 0.2em 0.5em; border-radius: 0.3em; vertical-align: middle; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { text-align: left; padding: 0.25em 0.8em; border-bottom: 1px solid #ddd; }
+.facts th { color: #666; font-weight: normal; }
+.chart { width: 100%; max-width: 60em; }
+.chart td:nth-child(2) { width: 60%; }
+.bar { background: #4a90d9; height: 1em; min-width: 1px; }
+.sortable th { cursor: pointer; background: #f4f4f4; }
+.sortable th:hover { background: #e4e4e4; }
+.warnings li { color: #8a5a00; }
+.problems li { color: #b00020; }
+</style>
+"#;
+
+// Click a column header to sort by it, click again to reverse. Cells with a data-sort
+// attribute (sizes) are compared as numbers.
+const SCRIPT: &str = r#"<script>
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th").forEach(function (th, column) {
+    th.addEventListener("click", function () {
+      var body = table.tBodies[0];
+      var rows = Array.prototype.slice.call(body.rows);
+      var ascending = th.dataset.order !== "asc";
+      th.dataset.order = ascending ? "asc" : "desc";
+      rows.sort(function (a, b) {
+        var x = a.cells[column], y = b.cells[column];
+        var result = x.dataset.sort !== undefined
+          ? Number(x.dataset.sort) - Number(y.dataset.sort)
+          : x.textContent.localeCompare(y.textContent);
+        return ascending ? result : -result;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});
+</script>
+"#;
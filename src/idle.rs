@@ -0,0 +1,150 @@
+use std::fs;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// =============================================================================
+// --only-when-idle: pause the moves while the machine is busy
+// =============================================================================
+//
+// A scheduled run on a workstation shouldn't slow down whoever is using it. With --only-when-idle
+// the moves pause while other programs keep the CPU busy, and go on once it's quiet again. Combined
+// with --max-duration, the time spent waiting counts too: if the machine never gets quiet, the run
+// stops at the limit and the next one continues.
+//
+// "Busy" is measured, not guessed from the load average (which counts our own waiting for the disk):
+//   Linux: /proc/stat says how much CPU time the whole machine used, /proc/self/stat how much of
+//          that was us; the difference is everybody else
+//   elsewhere: not checked (the run goes on as usual)
+// Keyboard and mouse idle time would need the desktop's help (X11, Wayland, Windows), so it's not used.
+
+// How often to look (the moves go on between looks)
+const CHECK_EVERY: Duration = Duration::from_secs(5);
+// Other programs using more than this share of all CPU time = busy
+const BUSY_SHARE: f64 = 0.25;
+
+// CPU time so far, in clock ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Times {
+    // All CPUs together: time spent working, and in total (working + idle)
+    pub busy: u64,
+    pub total: u64,
+    // Our own process
+    pub own: u64,
+}
+
+// The times, and when they were read
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    times: Times,
+}
+
+// Shared by all move threads: whoever holds the lock looks, the others wait for the answer
+#[derive(Debug, Default)]
+pub struct Idle {
+    last: Mutex<Option<Sample>>,
+}
+
+impl Idle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns right away if the machine was quiet at the last look (or it's not time to look again),
+    // else waits until it's quiet or `deadline` passes. When it pauses and goes on, it tells `say`.
+    pub fn wait(&self, deadline: Option<Instant>, say: impl Fn(String)) {
+        let mut last = self.last.lock().unwrap();
+        if last.is_some_and(|sample| sample.at.elapsed() < CHECK_EVERY) {
+            return;
+        }
+        // The very first look needs something to compare with
+        let Some(mut before) = last.or_else(sample) else {
+            return;
+        };
+        if last.is_none() {
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        let mut said = false;
+        loop {
+            let Some(now) = sample() else {
+                return;
+            };
+            let share = others_share(&before.times, &now.times);
+            *last = Some(now);
+            before = now;
+            if share <= BUSY_SHARE || deadline.is_some_and(|d| Instant::now() >= d) {
+                if said {
+                    say("The machine is quiet again, going on".to_string());
+                }
+                return;
+            }
+            if !said {
+                say(format!(
+                    "The machine is busy (other programs use {:.0}% of the CPU), pausing",
+                    share * 100.0
+                ));
+                said = true;
+            }
+            thread::sleep(CHECK_EVERY);
+        }
+    }
+}
+
+// The share of all CPU time between two readings that other programs used
+pub fn others_share(before: &Times, after: &Times) -> f64 {
+    let total = after.total.saturating_sub(before.total);
+    if total == 0 {
+        return 0.0;
+    }
+    let busy = after.busy.saturating_sub(before.busy);
+    let own = after.own.saturating_sub(before.own);
+    busy.saturating_sub(own) as f64 / total as f64
+}
+
+// Reads the CPU times (None where there's no /proc)
+fn sample() -> Option<Sample> {
+    let stat = fs::read_to_string("/proc/stat").ok()?;
+    let own_stat = fs::read_to_string("/proc/self/stat").ok()?;
+    Some(Sample {
+        at: Instant::now(),
+        times: parse_times(&stat, &own_stat)?,
+    })
+}
+
+// Picks the times out of the text of /proc/stat and /proc/self/stat (None if it doesn't look right)
+pub fn parse_times(stat: &str, own_stat: &str) -> Option<Times> {
+    // "cpu  user nice system idle iowait irq softirq steal ..."
+    let times: Vec<u64> = stat
+        .lines()
+        .next()?
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|t| t.parse().ok())
+        .collect();
+    if times.len() < 8 {
+        return None;
+    }
+    let idle = times[3] + times[4];
+    let busy = times[0] + times[1] + times[2] + times[5] + times[6] + times[7];
+
+    // "pid (name) state ... utime stime ...": utime and stime are the 12th and 13th field after
+    // the name (which can contain spaces and parentheses, so split after the last ')')
+    let after_name = &own_stat[own_stat.rfind(')')? + 1..];
+    let fields: Vec<u64> = after_name
+        .split_whitespace()
+        .skip(11)
+        .take(2)
+        .filter_map(|t| t.parse().ok())
+        .collect();
+    if fields.len() < 2 {
+        return None;
+    }
+
+    Some(Times {
+        busy,
+        total: busy + idle,
+        own: fields.iter().sum(),
+    })
+}
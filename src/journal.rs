@@ -0,0 +1,614 @@
+use crate::paths;
+use crate::protected;
+use crate::schema;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// =============================================================================
+// The journal: a record of everything a run moved
+// =============================================================================
+//
+// Every real (non-dry) run gets a random ID and writes one journal file into the state folder
+// (see paths.rs), named after the start time and that ID.
+// It's a JSON Lines file (one JSON object per line), so it can be read with any tool:
+//
+//   {"type":"run","schema_version":1,"run_id":"6f1c...","started_at":"2024-06-01T10:33:12Z","root":"/home/me/Downloads"}
+//   {"type":"create_dir","path":"pdf","at":"2024-06-01T10:33:12Z"}
+//   {"type":"intent","from":"report.pdf","to":"pdf/report.pdf","size":52133,"at":"2024-06-01T10:33:12Z"}
+//   {"type":"move","from":"report.pdf","to":"pdf/report.pdf","size":52133,"at":"2024-06-01T10:33:12Z",
+//    "modified":"2024-05-28T08:01:44.310Z"}
+//   {"type":"done","finished_at":"2024-06-01T10:33:13Z","moved":1}
+//
+// The root is stored as an absolute path, the moves relative to it.
+//
+// The journal is written ahead: before a file is moved, an "intent" line says it's about to be,
+// and that line is on disk (fsync'ed) before the move starts. So even if the computer crashes in
+// the middle of a run, every move that happened is in the journal, and undo can look for the
+// file at both places. The "move" line after it adds what's only known afterwards. Threads that
+// want their intent on disk at the same time share one fsync, which keeps this fast.
+//
+// Every line also ends in a "checksum": a hash of the line itself, byte for byte as it's written
+// (without the checksum field), and of the checksum of the line before it. Changing, removing or
+// cutting off a line breaks the checksums of every line after it, so `journal verify` (and undo)
+// can tell a journal that was damaged, for example by a crash in the middle of a write, from one
+// that's whole. The checksum of the "done" line covers the whole run. Journals of older versions
+// have no checksums and aren't checked; the versions in between hashed the line with its keys in
+// alphabetical order instead, and are still checked that way.
+//
+// While a run is going, it holds a lock on a file next to its journal ("<journal>.lock"), so
+// `repair` can tell a run that's still busy from one that was interrupted (see repair.rs).
+
+// Folder (inside the organized root) where older versions kept the journals. The scan never looks inside it.
+pub const STATE_DIR: &str = ".directory-cleaner";
+
+// One line of a journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    // First line: which run this is
+    Run {
+        // See schema.rs (missing in journals written before it was recorded)
+        #[serde(default = "schema::first_version")]
+        schema_version: u32,
+        run_id: Uuid,
+        started_at: DateTime<Utc>,
+        root: PathBuf,
+    },
+    // A folder the run created for its moves (relative to the root), so what an interrupted run
+    // created can be found again
+    CreateDir {
+        path: PathBuf,
+        at: DateTime<Utc>,
+    },
+    // About to move a file (paths relative to the root). Written to disk before the move starts;
+    // if no "move" line follows, the run stopped before it could write one.
+    Intent {
+        from: PathBuf,
+        to: PathBuf,
+        size: u64,
+        at: DateTime<Utc>,
+    },
+    // A file that was moved (paths relative to the root)
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+        size: u64,
+        at: DateTime<Utc>,
+        // Last-modified time of the file at its new place, so undo can tell if it was changed since
+        // (missing in journals written before it was recorded)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        modified: Option<DateTime<Utc>>,
+        // The name was shortened because the full path would have been too long for the OS
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        shortened: bool,
+    },
+    // A file that was moved to the system trash: a duplicate (--on-duplicate trash-source)
+    // or junk (--junk trash). Undo can't bring it back, but the system trash can.
+    Trash {
+        path: PathBuf,
+        // Only set for duplicates
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duplicate_of: Option<PathBuf>,
+        at: DateTime<Utc>,
+    },
+    // A symlink that pointed at a moved file was pointed at its new place (--link-policy update).
+    // `old` and `new` are what the link said before and after; undo puts `old` back.
+    Relink {
+        link: PathBuf,
+        old: PathBuf,
+        new: PathBuf,
+        at: DateTime<Utc>,
+    },
+    // Appended by `undo`: the move from -> to was reverted
+    Undo {
+        from: PathBuf,
+        to: PathBuf,
+        at: DateTime<Utc>,
+    },
+    // Last line, only written if the run wasn't interrupted
+    Done {
+        finished_at: DateTime<Utc>,
+        moved: u64,
+        // The checkpoint of a run that stopped before the end of its plan (--limit, --max-duration):
+        // how many files it left for the next run
+        #[serde(default, skip_serializing_if = "is_zero")]
+        left: u64,
+    },
+}
+
+// The journal of the current run, shared by all threads of the executor
+pub struct Journal {
+    pub run_id: Uuid,
+    pub path: PathBuf,
+    // The root as the rest of the run sees it, to turn paths into relative ones
+    root: PathBuf,
+    file: Mutex<Writer>,
+    // A second handle on the file, for fsync, and how many lines are known to be on disk
+    sync: File,
+    synced: Mutex<u64>,
+    // Locked while the run is going (see lock_path())
+    lock: File,
+}
+
+// The writing end of a journal
+struct Writer {
+    file: BufWriter<File>,
+    // The checksum of the last line written
+    last: String,
+    // Lines written so far
+    lines: u64,
+}
+
+impl Journal {
+    // Starts a new journal for a run on `root` and writes its header line
+    // (`abs_root` is the same folder as an absolute path, `state_dir` the --state-dir, see paths.rs)
+    pub fn create(root: &Path, abs_root: &Path, state_dir: Option<&Path>) -> Result<Journal> {
+        let run_id = Uuid::new_v4();
+        let started_at = Utc::now();
+
+        let dir = paths::journal_dir(abs_root, state_dir);
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+        // e.g. "20240601T103312.045Z_6f1c....jsonl": sorts by time in a file browser (with the
+        // milliseconds, so two runs in the same second still sort in the order they ran)
+        let path = dir.join(format!(
+            "{}_{}.jsonl",
+            started_at.format("%Y%m%dT%H%M%S%.3fZ"),
+            run_id
+        ));
+        let lock = File::create(lock_path(&path))
+            .with_context(|| format!("Failed to create {:?}", lock_path(&path)))?;
+        lock.lock()
+            .with_context(|| format!("Failed to lock {:?}", lock_path(&path)))?;
+        let file = File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+        let sync = file.try_clone()?;
+
+        let journal = Journal {
+            run_id,
+            path,
+            root: root.to_path_buf(),
+            file: Mutex::new(Writer {
+                file: BufWriter::new(file),
+                last: String::new(),
+                lines: 0,
+            }),
+            sync,
+            synced: Mutex::new(0),
+            lock,
+        };
+        journal.write(&Entry::Run {
+            schema_version: schema::SCHEMA_VERSION,
+            run_id,
+            started_at,
+            root: abs_root.to_path_buf(),
+        })?;
+        Ok(journal)
+    }
+
+    // Writes one line, returns how many lines have been written now
+    fn write(&self, entry: &Entry) -> Result<u64> {
+        let mut writer = self.file.lock().unwrap();
+        let (line, checksum) = seal(entry, &writer.last)?;
+        writeln!(writer.file, "{}", line)?;
+        writer.last = checksum;
+        writer.lines += 1;
+        Ok(writer.lines)
+    }
+
+    // Makes sure the first `lines` lines are on disk. Whoever gets the lock first syncs everything
+    // written up to then, so threads that arrive while that fsync runs usually find their line
+    // already covered and return right away (a "group commit").
+    fn sync_up_to(&self, lines: u64) -> io::Result<()> {
+        let mut synced = self.synced.lock().unwrap();
+        if *synced >= lines {
+            return Ok(());
+        }
+        let written = {
+            let mut writer = self.file.lock().unwrap();
+            writer.file.flush()?;
+            writer.lines
+        };
+        self.sync.sync_data()?;
+        *synced = written;
+        Ok(())
+    }
+
+    // Records that `from` is about to be moved to `to`, and waits until that's on disk.
+    // If this fails the file must not be moved: the move couldn't be undone after a crash.
+    pub fn record_intent(&self, from: &Path, to: &Path, size: u64) -> Result<()> {
+        let lines = self.write(&Entry::Intent {
+            from: self.relative(from),
+            to: self.relative(to),
+            size,
+            at: Utc::now(),
+        })?;
+        self.sync_up_to(lines)?;
+        Ok(())
+    }
+
+    // "./Downloads/pdf/report.pdf" -> "pdf/report.pdf"
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.root).unwrap_or(path).to_path_buf()
+    }
+
+    // Records a finished move (called from many threads at once, the Mutex keeps lines whole).
+    // The record_* calls leave reporting a failure to the caller, which prints it where the run's
+    // other messages go.
+    pub fn record_move(&self, from: &Path, to: &Path, size: u64, shortened: bool) -> Result<()> {
+        let entry = Entry::Move {
+            from: self.relative(from),
+            to: self.relative(to),
+            size,
+            at: Utc::now(),
+            modified: modified_time(to),
+            shortened,
+        };
+        self.write(&entry)?;
+        Ok(())
+    }
+
+    // Records a folder the run created
+    pub fn record_create_dir(&self, path: &Path) -> Result<()> {
+        let entry = Entry::CreateDir {
+            path: self.relative(path),
+            at: Utc::now(),
+        };
+        self.write(&entry)?;
+        Ok(())
+    }
+
+    // Records a file that was moved to the trash
+    pub fn record_trash(&self, path: &Path, duplicate_of: Option<&Path>) -> Result<()> {
+        let entry = Entry::Trash {
+            path: self.relative(path),
+            duplicate_of: duplicate_of.map(|p| self.relative(p)),
+            at: Utc::now(),
+        };
+        self.write(&entry)?;
+        Ok(())
+    }
+
+    // Records that `link` now says `new` instead of `old`
+    pub fn record_relink(&self, link: &Path, old: &Path, new: &Path) -> Result<()> {
+        let entry = Entry::Relink {
+            link: self.relative(link),
+            old: old.to_path_buf(),
+            new: new.to_path_buf(),
+            at: Utc::now(),
+        };
+        self.write(&entry)?;
+        Ok(())
+    }
+
+    // Writes the closing line (with how many files were left for the next run) and flushes
+    // everything to disk
+    pub fn finish(self, moved: u64, left: u64) -> Result<()> {
+        self.write(&Entry::Done {
+            finished_at: Utc::now(),
+            moved,
+            left,
+        })?;
+        let mut file = self.file.into_inner().unwrap().file;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        // The run is over: unlocked, and the lock file isn't needed anymore
+        drop(self.lock);
+        let _ = fs::remove_file(lock_path(&self.path));
+        Ok(())
+    }
+}
+
+// The lock file of the journal at `path`: "<journal>.lock"
+pub fn lock_path(path: &Path) -> PathBuf {
+    path.with_extension("lock")
+}
+
+// True if the run of the journal at `path` is still going (some process holds its lock). A run
+// that ended without its lock file being removed (it failed, or was killed) holds no lock.
+pub fn is_running(path: &Path) -> bool {
+    match File::open(lock_path(path)) {
+        Ok(lock) => matches!(lock.try_lock(), Err(fs::TryLockError::WouldBlock)),
+        Err(_) => false,
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+// If the latest run on `root` stopped before the end of its plan: its ID and how many files it left
+pub fn checkpoint(root: &Path, state_dir: Option<&Path>) -> Option<(Uuid, u64)> {
+    let latest = list(root, state_dir).ok()?.pop()?;
+    let entries = read(&latest).ok()?;
+    let run_id = entries.iter().find_map(|entry| match entry {
+        Entry::Run { run_id, .. } => Some(*run_id),
+        _ => None,
+    })?;
+    match entries.last()? {
+        Entry::Done { left, .. } if *left > 0 => Some((run_id, *left)),
+        _ => None,
+    }
+}
+
+// When the latest run on `root` that finished its whole plan started (--since-last-run). Runs that
+// were interrupted, or stopped with files left for the next run, don't count: the files they
+// didn't get to are older than they are.
+pub fn last_complete_run(root: &Path, state_dir: Option<&Path>) -> Option<DateTime<Utc>> {
+    list(root, state_dir)
+        .ok()?
+        .iter()
+        .rev()
+        .filter_map(|path| read(path).ok())
+        .find_map(|entries| {
+            let Some(Entry::Done { left: 0, .. }) = entries.last() else {
+                return None;
+            };
+            entries.iter().find_map(|entry| match entry {
+                Entry::Run { started_at, .. } => Some(*started_at),
+                _ => None,
+            })
+        })
+}
+
+// When a file was last modified (not following symlinks), if the file system knows
+pub fn modified_time(path: &Path) -> Option<DateTime<Utc>> {
+    let modified = fs::symlink_metadata(path).ok()?.modified().ok()?;
+    Some(modified.into())
+}
+
+// All journals of `root`, oldest first (the file names start with the time), from the state
+// folder and from where older versions kept them
+pub fn list(root: &Path, state_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let abs_root = protected::absolute_path(root)
+        .with_context(|| format!("Failed to access directory {:?}", root))?;
+    let mut dirs = vec![paths::journal_dir(&abs_root, state_dir)];
+    let legacy = paths::legacy_journal_dir(root);
+    if !dirs.contains(&paths::legacy_journal_dir(&abs_root)) {
+        dirs.push(legacy);
+    }
+
+    let mut journals = Vec::new();
+    for dir in dirs.iter().filter(|d| d.is_dir()) {
+        for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "jsonl") {
+                journals.push(path);
+            }
+        }
+    }
+    journals.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(journals)
+}
+
+// Adds one entry to the end of an existing journal (continuing its checksums, if it has any)
+pub fn append(path: &Path, entry: &Entry) -> Result<()> {
+    let last = fs::read_to_string(path)
+        .with_context(|| format!("Failed to open journal {:?}", path))?
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .and_then(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .and_then(|value| value.get(CHECKSUM)?.as_str().map(str::to_string));
+    let line = match last {
+        Some(last) => seal(entry, &last)?.0,
+        // An old journal without checksums stays without them
+        None => serde_json::to_string(entry)?,
+    };
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open journal {:?}", path))?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+// Name of the checksum field of every line
+const CHECKSUM: &str = "checksum";
+// Hex digits of the checksum (64 bits is plenty to notice damage)
+const CHECKSUM_LEN: usize = 16;
+
+// `entry` as a journal line with its checksum, chained to `last` (the checksum of the line
+// before, "" for the first line). Returns the line and its checksum.
+fn seal(entry: &Entry, last: &str) -> Result<(String, String)> {
+    let body = serde_json::to_string(entry)?;
+    let checksum = checksum_of(body.as_bytes(), last);
+    // Every entry is an object with a "type", so the checksum goes in before its closing brace
+    let open = body.strip_suffix('}').unwrap_or(&body);
+    let line = format!("{},\"{}\":\"{}\"}}", open, CHECKSUM, checksum);
+    Ok((line, checksum))
+}
+
+// `line` without the checksum field seal() put at its end, and that checksum, or None if the line
+// doesn't end in one
+fn unseal(line: &str) -> Option<(String, &str)> {
+    let (open, rest) = line
+        .trim_end()
+        .rsplit_once(&format!(",\"{}\":\"", CHECKSUM))?;
+    let checksum = rest.strip_suffix("\"}")?;
+    let is_checksum =
+        checksum.len() == CHECKSUM_LEN && checksum.bytes().all(|b| b.is_ascii_hexdigit());
+    is_checksum.then(|| (format!("{}}}", open), checksum))
+}
+
+// The checksum of a line (the bytes of it, without its checksum) that follows the line with
+// checksum `last`
+fn checksum_of(body: &[u8], last: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(last.as_bytes());
+    hasher.update(body);
+    hasher.finalize().to_hex()[..CHECKSUM_LEN].to_string()
+}
+
+// True if `checksum` is right for `line`, the way this version writes it (the raw bytes), or the
+// way the versions before did (the line parsed, without the checksum, and written out again with
+// its keys in alphabetical order)
+fn checksum_matches(line: &str, value: &serde_json::Value, checksum: &str, last: &str) -> bool {
+    let raw = unseal(line).is_some_and(|(body, sealed)| {
+        sealed == checksum && checksum_of(body.as_bytes(), last) == checksum
+    });
+    raw || checksum_of(value.to_string().as_bytes(), last) == checksum
+}
+
+// What `journal verify` (and undo) found out about a journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    // Every checksum is right and the run finished
+    Intact,
+    // Every checksum is right, but the run never wrote its "done" line: it was interrupted
+    // (the moves that are recorded did happen)
+    Unfinished,
+    // Written by an older version, without checksums: nothing to check
+    Unchecked,
+    // A line is damaged, missing or was changed (1-based line number, and what's wrong)
+    Damaged { line: usize, problem: String },
+}
+
+// `journal verify`: checks the journals of `root` (or just those of runs starting with `run`)
+// and prints what it found, one line each. Fails if any of them is damaged.
+pub fn verify_runs(root: &Path, run: Option<&str>, state_dir: Option<&Path>) -> Result<()> {
+    let mut damaged = 0;
+    let mut checked = 0;
+    for path in list(root, state_dir)? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // "20240601T103312Z_6f1c....jsonl" -> "6f1c..."
+        let run_id = name
+            .trim_end_matches(".jsonl")
+            .split_once('_')
+            .map_or("", |(_, id)| id);
+        if run.is_some_and(|wanted| !run_id.starts_with(wanted)) {
+            continue;
+        }
+        checked += 1;
+        match verify(&path)? {
+            Integrity::Intact => println!("{}: intact", run_id),
+            Integrity::Unfinished => {
+                println!(
+                    "{}: intact, but the run didn't finish (interrupted)",
+                    run_id
+                )
+            }
+            Integrity::Unchecked => {
+                println!(
+                    "{}: written by an older version, no checksums to check",
+                    run_id
+                )
+            }
+            Integrity::Damaged { line, problem } => {
+                damaged += 1;
+                println!(
+                    "{}: DAMAGED on line {}: {} ({:?})",
+                    run_id, line, problem, path
+                );
+            }
+        }
+    }
+
+    match (checked, run) {
+        (0, Some(wanted)) => bail!("No run with ID {:?} in {:?}", wanted, root),
+        (0, None) => bail!("No runs recorded for {:?}", root),
+        _ if damaged > 0 => bail!("{} of {} journals are damaged", damaged, checked),
+        _ => Ok(()),
+    }
+}
+
+// Checks the checksums of the journal at `path`
+pub fn verify(path: &Path) -> Result<Integrity> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to open journal {:?}", path))?;
+    let mut last = String::new();
+    let mut finished = false;
+    let mut checked = false;
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let damaged = |problem: &str| {
+            Ok(Integrity::Damaged {
+                line: i + 1,
+                problem: problem.to_string(),
+            })
+        };
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(line) else {
+            return damaged("not valid JSON (cut off?)");
+        };
+        let checksum = match value.as_object_mut().and_then(|map| map.remove(CHECKSUM)) {
+            Some(serde_json::Value::String(checksum)) => checksum,
+            // No checksums at all: an old journal
+            None if i == 0 => return Ok(Integrity::Unchecked),
+            _ => return damaged("the checksum is missing"),
+        };
+        if !checksum_matches(line, &value, &checksum, &last) {
+            return damaged("the checksum doesn't match (the line, or one before it, was changed)");
+        }
+        if serde_json::from_value::<Entry>(value.clone()).is_err() {
+            return damaged("not a journal entry");
+        }
+        finished |= value.get("type").and_then(|t| t.as_str()) == Some("done");
+        checked = true;
+        last = checksum;
+    }
+
+    Ok(if !checked {
+        Integrity::Damaged {
+            line: 1,
+            problem: "the journal is empty".to_string(),
+        }
+    } else if finished {
+        Integrity::Intact
+    } else {
+        Integrity::Unfinished
+    })
+}
+
+// Name of the extended attribute set by --stamp-xattr
+pub const XATTR_NAME: &str = "user.directory-cleaner.run-id";
+
+// Stamps the run ID onto a moved file (e.g. `getfattr -n user.directory-cleaner.run-id file.pdf`)
+#[cfg(unix)]
+pub fn stamp(path: &Path, run_id: Uuid) -> io::Result<()> {
+    xattr::set(path, XATTR_NAME, run_id.to_string().as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn stamp(_path: &Path, _run_id: Uuid) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    ))
+}
+
+// Reads every entry of a journal file
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    read_before(path, usize::MAX)
+}
+
+// Cuts the journal at `path` off before `line` (1-based), e.g. a last line a crash left half written
+pub fn truncate_before(path: &Path, line: usize) -> Result<()> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to open journal {:?}", path))?;
+    let kept: String = text.split_inclusive('\n').take(line - 1).collect();
+    fs::write(path, kept).with_context(|| format!("Failed to write journal {:?}", path))
+}
+
+// Reads the entries on the lines before `line` (1-based), e.g. up to where verify() found damage
+pub fn read_before(path: &Path, line: usize) -> Result<Vec<Entry>> {
+    let file = File::open(path).with_context(|| format!("Failed to open journal {:?}", path))?;
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().take(line - 1).enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid journal entry on line {} of {:?}", i + 1, path))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,196 @@
+// Programming languages for `--by language`: source files go to Python/, Rust/, Shell/, ...
+// A file is recognized by its extension, or, if it has none, by its "#!" line
+// ("#!/usr/bin/env python3" makes an extensionless script a Python file).
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+pub struct Language {
+    // Short id, used in the summary
+    pub name: &'static str,
+    // Folder the files are moved into
+    pub folder: &'static str,
+    pub extensions: &'static [&'static str],
+    // Programs named in a "#!" line, without version numbers ("python3.11" counts as "python")
+    pub interpreters: &'static [&'static str],
+}
+
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        name: "python",
+        folder: "Python",
+        extensions: &["py", "pyw", "pyi", "ipynb"],
+        interpreters: &["python", "pypy"],
+    },
+    Language {
+        name: "rust",
+        folder: "Rust",
+        extensions: &["rs"],
+        interpreters: &[],
+    },
+    Language {
+        name: "shell",
+        folder: "Shell",
+        extensions: &["sh", "bash", "zsh", "ksh", "fish"],
+        interpreters: &["sh", "bash", "zsh", "ksh", "dash", "fish"],
+    },
+    Language {
+        name: "powershell",
+        folder: "PowerShell",
+        extensions: &["ps1", "psm1", "psd1"],
+        interpreters: &["pwsh"],
+    },
+    Language {
+        name: "batch",
+        folder: "Batch",
+        extensions: &["bat", "cmd"],
+        interpreters: &[],
+    },
+    Language {
+        name: "javascript",
+        folder: "JavaScript",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        interpreters: &["node"],
+    },
+    Language {
+        name: "typescript",
+        folder: "TypeScript",
+        extensions: &["ts", "mts", "cts", "tsx"],
+        interpreters: &["deno", "ts-node", "bun"],
+    },
+    Language {
+        name: "c",
+        folder: "C",
+        extensions: &["c", "h"],
+        interpreters: &[],
+    },
+    Language {
+        name: "cpp",
+        folder: "C++",
+        extensions: &["cpp", "cc", "cxx", "hpp", "hh", "hxx"],
+        interpreters: &[],
+    },
+    Language {
+        name: "csharp",
+        folder: "C#",
+        extensions: &["cs", "csx"],
+        interpreters: &[],
+    },
+    Language {
+        name: "java",
+        folder: "Java",
+        extensions: &["java"],
+        interpreters: &[],
+    },
+    Language {
+        name: "kotlin",
+        folder: "Kotlin",
+        extensions: &["kt", "kts"],
+        interpreters: &[],
+    },
+    Language {
+        name: "go",
+        folder: "Go",
+        extensions: &["go"],
+        interpreters: &[],
+    },
+    Language {
+        name: "ruby",
+        folder: "Ruby",
+        extensions: &["rb"],
+        interpreters: &["ruby"],
+    },
+    Language {
+        name: "php",
+        folder: "PHP",
+        extensions: &["php"],
+        interpreters: &["php"],
+    },
+    Language {
+        name: "perl",
+        folder: "Perl",
+        extensions: &["pl", "pm"],
+        interpreters: &["perl"],
+    },
+    Language {
+        name: "lua",
+        folder: "Lua",
+        extensions: &["lua"],
+        interpreters: &["lua", "luajit"],
+    },
+    Language {
+        name: "r",
+        folder: "R",
+        extensions: &["r"],
+        interpreters: &["Rscript"],
+    },
+    Language {
+        name: "swift",
+        folder: "Swift",
+        extensions: &["swift"],
+        interpreters: &["swift"],
+    },
+    Language {
+        name: "haskell",
+        folder: "Haskell",
+        extensions: &["hs"],
+        interpreters: &["runhaskell"],
+    },
+    Language {
+        name: "julia",
+        folder: "Julia",
+        extensions: &["jl"],
+        interpreters: &["julia"],
+    },
+    Language {
+        name: "sql",
+        folder: "SQL",
+        extensions: &["sql"],
+        interpreters: &[],
+    },
+    Language {
+        name: "html",
+        folder: "HTML",
+        extensions: &["html", "htm"],
+        interpreters: &[],
+    },
+    Language {
+        name: "css",
+        folder: "CSS",
+        extensions: &["css", "scss", "sass", "less"],
+        interpreters: &[],
+    },
+];
+
+// Finds the language of a (lowercase) extension, if it has one
+pub fn for_extension(ext: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|l| l.extensions.contains(&ext))
+}
+
+// Finds the language of an interpreter named in a "#!" line ("python3.11" -> Python)
+pub fn for_interpreter(program: &str) -> Option<&'static Language> {
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES.iter().find(|l| l.interpreters.contains(&name))
+}
+
+// The program named in the "#!" line of a file, if it starts with one:
+//   "#!/bin/bash"                  -> "bash"
+//   "#!/usr/bin/env -S python3 -u" -> "python3"
+// Only the first few hundred bytes are read.
+pub fn interpreter(file: &Path) -> Option<String> {
+    let mut head = [0u8; 256];
+    let n = File::open(file).ok()?.read(&mut head).ok()?;
+    let line = head[..n].split(|&b| b == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?.strip_prefix("#!")?;
+
+    let mut words = line.split_whitespace();
+    let program = Path::new(words.next()?).file_name()?.to_str()?;
+    if program == "env" {
+        // Skip env's own flags ("-S", "-i") and variables ("LANG=C")
+        return words
+            .find(|w| !w.starts_with('-') && !w.contains('='))
+            .map(|w| w.to_string());
+    }
+    Some(program.to_string())
+}
@@ -0,0 +1,45 @@
+// The library half of the crate: main.rs is just a thin CLI wrapper around these modules,
+// so the integration tests in tests/ (and anyone else) can call the organizer directly.
+
+pub mod advice;
+pub mod archives;
+pub mod args;
+pub mod category;
+pub mod classify;
+pub mod confirm;
+pub mod console;
+pub mod dedupe;
+pub mod display;
+pub mod duplicate;
+pub mod edit_plan;
+pub mod events;
+pub mod free_space;
+pub mod gps;
+pub mod hashing;
+pub mod html;
+pub mod idle;
+pub mod journal;
+pub mod language;
+pub mod links;
+pub mod lnk;
+pub mod locale;
+pub mod locate;
+pub mod logic;
+pub mod open_files;
+pub mod owners;
+pub mod paths;
+pub mod permissions;
+pub mod plan;
+pub mod protected;
+pub mod query;
+pub mod reconcile;
+pub mod reference;
+pub mod repair;
+pub mod report;
+pub mod review;
+pub mod schema;
+pub mod stats;
+pub mod target;
+pub mod throttle;
+pub mod undo;
+pub mod user_dirs;
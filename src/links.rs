@@ -0,0 +1,171 @@
+use crate::{lnk, protected};
+use ignore::WalkBuilder;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// =============================================================================
+// --link-policy: symlinks that point at the files we move
+// =============================================================================
+//
+// A link on the desktop (or anywhere in the organized folder) to ~/Downloads/report.pdf breaks
+// when report.pdf moves to ~/Downloads/pdf/. Before the run, the links in the tree (and in the
+// folders given with --link-dir) are looked up, and --link-policy says what happens to the files
+// they point at:
+//   ignore  don't look (the default, and the fastest)
+//   warn    move them anyway, but list the links that will break
+//   skip    leave them where they are
+//   update  move them and point the links at the new place (recorded in the journal, so undo
+//           points them back)
+//
+// On Windows, shortcuts (.lnk files) count as links too; lnk.rs reads and rewrites them.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinkPolicy {
+    #[default]
+    Ignore,
+    Warn,
+    Skip,
+    Update,
+}
+
+// File a link points at (absolute, symlink-free) -> the links pointing at it
+#[derive(Debug, Default)]
+pub struct Links {
+    by_target: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl Links {
+    // Collects the symlinks among `entries` (the scan) and in the folders `dirs` (and below)
+    pub fn find(entries: &[PathBuf], dirs: &[PathBuf]) -> Links {
+        let mut links = Links::default();
+        for entry in entries {
+            links.add(entry);
+        }
+        for dir in dirs {
+            let walk = WalkBuilder::new(dir).standard_filters(false).build();
+            for entry in walk.flatten() {
+                links.add(entry.path());
+            }
+        }
+        links
+    }
+
+    fn add(&mut self, path: &Path) {
+        let target = if is_symlink(path) {
+            protected::absolute_path(path)
+        } else if cfg!(windows) && lnk::is_shortcut(path) {
+            lnk::read_target(path).and_then(|target| protected::absolute_path(&target))
+        } else {
+            return;
+        };
+        // Links that lead nowhere (already broken) don't matter
+        if let Ok(target) = target {
+            self.by_target
+                .entry(target)
+                .or_default()
+                .push(path.to_path_buf());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_target.is_empty()
+    }
+
+    // The links pointing at `file` (empty if none)
+    pub fn to(&self, file: &Path) -> &[PathBuf] {
+        // A link that is moved itself isn't pointed at by what it points at
+        if is_symlink(file) {
+            return &[];
+        }
+        protected::absolute_path(file)
+            .ok()
+            .and_then(|abs| self.by_target.get(&abs))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+// What `link` says it points at (a symlink, or a shortcut)
+pub fn read_target(link: &Path) -> io::Result<PathBuf> {
+    if !is_symlink(link) && lnk::is_shortcut(link) {
+        return lnk::read_target(link);
+    }
+    fs::read_link(link)
+}
+
+// Points `link` (a symlink, or a shortcut) at `target`
+pub fn set_target(link: &Path, target: &Path) -> io::Result<()> {
+    if !is_symlink(link) && lnk::is_shortcut(link) {
+        return lnk::set_target(link, target);
+    }
+    repoint(link, target)
+}
+
+// What `link` should say to point at `new_file` (absolute): absolute if it was absolute before,
+// else relative to the folder the link is in
+pub fn new_target(link: &Path, old_target: &Path, new_file: &Path) -> io::Result<PathBuf> {
+    if old_target.is_absolute() {
+        return Ok(new_file.to_path_buf());
+    }
+    let folder = link.parent().unwrap_or(Path::new("."));
+    let folder = protected::absolute_path(if folder.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        folder
+    })?;
+    Ok(relative_path(new_file, &folder))
+}
+
+// `path` as seen from the folder `base` (both absolute): "/a/b/c.pdf" from "/a/d" is "../b/c.pdf"
+pub(crate) fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &path[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+// Points `link` at `target` instead, replacing the link in one step (a new link next to it is
+// renamed over the old one, so there's never a moment without it)
+pub fn repoint(link: &Path, target: &Path) -> io::Result<()> {
+    let name = link
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = link.with_file_name(format!(".{}.relink", name));
+    let _ = fs::remove_file(&temp);
+    make_link(target, &temp)?;
+    fs::rename(&temp, link).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+#[cfg(unix)]
+fn make_link(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn make_link(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn make_link(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks aren't supported here",
+    ))
+}
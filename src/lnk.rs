@@ -0,0 +1,285 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Windows shortcuts (.lnk files): where they point, and pointing them elsewhere
+// =============================================================================
+//
+// A shortcut is a small binary file (the "Shell Link" format). The parts that matter here:
+//   header       76 bytes, with flags that say which of the parts below are there
+//   ID list      the target as the Explorer sees it (optional)
+//   link info    the target as a plain path, "C:\Users\me\Downloads\report.pdf" (optional)
+//   strings      name, relative path, working folder, arguments, icon (each optional)
+//   extra blocks more about the target: environment variables, known folders, ... (optional)
+//
+// Windows tries the ID list first, and some extra blocks beat the link info too. So to point a
+// shortcut at a new place, the link info gets the new path, the relative path is updated, and the
+// ID list and the blocks that would still say the old place are left out. Everything else (icon,
+// arguments, working folder, hotkey) stays as it was. Windows fills the ID list in again the next
+// time it resolves the shortcut.
+//
+// Only shortcuts to local paths are understood; ones to network shares are left alone.
+
+const HEADER_SIZE: usize = 0x4C;
+
+// Header flags
+const HAS_ID_LIST: u32 = 1 << 0;
+const HAS_LINK_INFO: u32 = 1 << 1;
+const HAS_NAME: u32 = 1 << 2;
+const HAS_RELATIVE_PATH: u32 = 1 << 3;
+const HAS_WORKING_DIR: u32 = 1 << 4;
+const HAS_ARGUMENTS: u32 = 1 << 5;
+const HAS_ICON_LOCATION: u32 = 1 << 6;
+const IS_UNICODE: u32 = 1 << 7;
+const FORCE_NO_LINK_INFO: u32 = 1 << 8;
+const HAS_EXP_STRING: u32 = 1 << 9;
+const PREFER_ENVIRONMENT_PATH: u32 = 1 << 25;
+
+// Extra blocks that point at the target on their own (environment variables, special and known
+// folders, a second ID list): dropped when the target changes
+const STALE_BLOCKS: [u32; 4] = [0xA000_0001, 0xA000_0005, 0xA000_000B, 0xA000_000C];
+
+// True for a file named like a shortcut
+pub fn is_shortcut(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("lnk"))
+}
+
+// Where the shortcut file `path` points
+pub fn read_target(path: &Path) -> io::Result<PathBuf> {
+    let data = fs::read(path)?;
+    target(&data)
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a shortcut to a local path"))
+}
+
+// Points the shortcut file `path` at `new_target` (absolute), replacing the file in one step
+pub fn set_target(path: &Path, new_target: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let relative = path
+        .parent()
+        .map(|folder| crate::links::relative_path(new_target, folder))
+        .map(|relative| relative.to_string_lossy().replace('/', "\\"));
+    let new =
+        retarget(&data, &new_target.to_string_lossy(), relative.as_deref()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "not a shortcut to a local path")
+        })?;
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.relink", name));
+    fs::write(&temp, new)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+// The parts of a shortcut, as byte ranges of the file
+struct Parts {
+    flags: u32,
+    // Where the link info starts and ends (start == end if there is none)
+    link_info: (usize, usize),
+    // Each string that's there: which flag it belongs to, and its range (including its length)
+    strings: Vec<(u32, usize, usize)>,
+    // Where the extra blocks start
+    extra: usize,
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize)
+}
+
+fn parts(data: &[u8]) -> Option<Parts> {
+    if u32_at(data, 0)? != HEADER_SIZE {
+        return None;
+    }
+    let flags = u32_at(data, 0x14)? as u32;
+    let mut at = HEADER_SIZE;
+    if flags & HAS_ID_LIST != 0 {
+        at += 2 + u16_at(data, at)?;
+    }
+    let link_info_start = at;
+    if flags & HAS_LINK_INFO != 0 {
+        at += u32_at(data, at)?;
+    }
+    let link_info = (link_info_start, at);
+
+    let char_size = if flags & IS_UNICODE != 0 { 2 } else { 1 };
+    let mut strings = Vec::new();
+    for flag in [
+        HAS_NAME,
+        HAS_RELATIVE_PATH,
+        HAS_WORKING_DIR,
+        HAS_ARGUMENTS,
+        HAS_ICON_LOCATION,
+    ] {
+        if flags & flag != 0 {
+            let end = at + 2 + u16_at(data, at)? * char_size;
+            strings.push((flag, at, end));
+            at = end;
+        }
+    }
+    (at <= data.len()).then_some(Parts {
+        flags,
+        link_info,
+        strings,
+        extra: at,
+    })
+}
+
+// A NUL-terminated string at `at`: one byte per character, or UTF-16
+fn c_string(data: &[u8], at: usize, unicode: bool) -> Option<String> {
+    let rest = data.get(at..)?;
+    if unicode {
+        let units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|&unit| unit != 0)
+            .collect();
+        Some(String::from_utf16_lossy(&units))
+    } else {
+        let bytes: Vec<u8> = rest.iter().copied().take_while(|&b| b != 0).collect();
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+// The local path a shortcut points at ("C:\Users\me\report.pdf"), from its link info
+pub fn target(data: &[u8]) -> Option<String> {
+    let parts = parts(data)?;
+    let (start, end) = parts.link_info;
+    let info = data.get(start..end)?;
+    // Only local targets (the "volume ID and local base path" flag)
+    if start == end || u32_at(info, 8)? & 1 == 0 {
+        return None;
+    }
+    let header_size = u32_at(info, 4)?;
+    let (base, suffix) = if header_size >= 0x24 {
+        (
+            c_string(info, u32_at(info, 28)?, true)?,
+            c_string(info, u32_at(info, 32)?, true)?,
+        )
+    } else {
+        (
+            c_string(info, u32_at(info, 16)?, false)?,
+            c_string(info, u32_at(info, 24)?, false)?,
+        )
+    };
+    Some(base + &suffix)
+}
+
+// The shortcut `data` pointing at `new_target` instead (and with `relative` as its relative path,
+// if it has one). None if `data` isn't a shortcut.
+pub fn retarget(data: &[u8], new_target: &str, relative: Option<&str>) -> Option<Vec<u8>> {
+    let parts = parts(data)?;
+    let unicode = parts.flags & IS_UNICODE != 0;
+
+    let mut out = data[..HEADER_SIZE].to_vec();
+    let flags = (parts.flags | HAS_LINK_INFO)
+        & !(HAS_ID_LIST | FORCE_NO_LINK_INFO | HAS_EXP_STRING | PREFER_ENVIRONMENT_PATH);
+    out[0x14..0x18].copy_from_slice(&flags.to_le_bytes());
+
+    let (start, end) = parts.link_info;
+    out.extend(link_info(&data[start..end], new_target));
+
+    for (flag, start, end) in &parts.strings {
+        match relative {
+            Some(relative) if *flag == HAS_RELATIVE_PATH => {
+                let text: Vec<u8> = if unicode {
+                    relative
+                        .encode_utf16()
+                        .flat_map(|unit| unit.to_le_bytes())
+                        .collect()
+                } else {
+                    ansi(relative)
+                };
+                let chars = if unicode { text.len() / 2 } else { text.len() };
+                out.extend((chars as u16).to_le_bytes());
+                out.extend(text);
+            }
+            _ => out.extend(&data[*start..*end]),
+        }
+    }
+
+    // The extra blocks, without the ones that would still say the old place
+    let mut at = parts.extra;
+    while let Some(size) = u32_at(data, at).filter(|&size| size >= 8) {
+        let block = data.get(at..at + size)?;
+        let signature = u32_at(block, 4)? as u32;
+        if !STALE_BLOCKS.contains(&signature) {
+            out.extend(block);
+        }
+        at += size;
+    }
+    out.extend(0u32.to_le_bytes());
+    Some(out)
+}
+
+// `text` in one byte per character. Which bytes stand for letters beyond ASCII depends on the
+// language of the Windows that reads it, so those become "?" (the Unicode copies are exact).
+fn ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .collect()
+}
+
+// New link info for `target`, keeping the volume ID (drive type, serial number, label) of `old`
+fn link_info(old: &[u8], target: &str) -> Vec<u8> {
+    let volume_id = u32_at(old, 12)
+        .filter(|_| u32_at(old, 8).is_some_and(|flags| flags & 1 != 0))
+        .and_then(|at| old.get(at..at + u32_at(old, at)?))
+        .map(<[u8]>::to_vec)
+        // A fixed drive without a label
+        .unwrap_or_else(|| {
+            let mut id = Vec::new();
+            for field in [0x11u32, 3, 0, 0x10] {
+                id.extend(field.to_le_bytes());
+            }
+            id.push(0);
+            id
+        });
+
+    // The path once as bytes (for old readers) and once as UTF-16, each with an empty suffix
+    let mut ansi = ansi(target);
+    ansi.push(0);
+    let mut wide: Vec<u8> = target
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    wide.extend([0, 0]);
+
+    let header = 0x24;
+    let volume_at = header;
+    let base_at = volume_at + volume_id.len();
+    let suffix_at = base_at + ansi.len();
+    let wide_base_at = suffix_at + 1;
+    let wide_suffix_at = wide_base_at + wide.len();
+    let size = wide_suffix_at + 2;
+
+    let mut info = Vec::with_capacity(size);
+    for field in [
+        size,
+        header,
+        1,
+        volume_at,
+        base_at,
+        0,
+        suffix_at,
+        wide_base_at,
+        wide_suffix_at,
+    ] {
+        info.extend((field as u32).to_le_bytes());
+    }
+    info.extend(volume_id);
+    info.extend(ansi);
+    info.push(0);
+    info.extend(wide);
+    info.extend([0, 0]);
+    info
+}
@@ -0,0 +1,172 @@
+use crate::plan::CategoryFolder;
+
+// =============================================================================
+// --lang: the built-in category folders in another language
+// =============================================================================
+//
+// `--by category` puts images into Images/, documents into Documents/, ... On a German desktop
+// the folders next to them are called Bilder and Dokumente, so `--lang de` names the category
+// folders that way too (and the Temp/ folder for junk). It's the same mapping as
+// --category-folder, just filled in for you: --category-folder rules and the folders found by
+// --adopt-existing and --into-user-dirs still win.
+//
+// `--lang auto` takes the language from the locale the way other programs do (LC_ALL, then
+// LC_MESSAGES, then LANG: "de_DE.UTF-8" is German). Without --lang the folders keep their English
+// names, so an existing setup doesn't suddenly start a second set of folders next to its old ones.
+//
+// Only folder names are translated; the messages the program prints are in English.
+
+// A language, and what the category folders are called in it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Language {
+    // "de", "fr", ... (the start of a locale like "de_DE.UTF-8")
+    pub code: &'static str,
+    // (category, folder) for the built-in categories and "temp"; English has none (the defaults)
+    pub folders: &'static [(&'static str, &'static str)],
+}
+
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        code: "en",
+        folders: &[],
+    },
+    Language {
+        code: "de",
+        folders: &[
+            ("images", "Bilder"),
+            ("videos", "Videos"),
+            ("audio", "Audio"),
+            ("documents", "Dokumente"),
+            ("archives", "Archive"),
+            ("code", "Code"),
+            ("programs", "Programme"),
+            ("fonts", "Schriftarten"),
+            ("temp", "Temporär"),
+        ],
+    },
+    Language {
+        code: "es",
+        folders: &[
+            ("images", "Imágenes"),
+            ("videos", "Vídeos"),
+            ("audio", "Audio"),
+            ("documents", "Documentos"),
+            ("archives", "Comprimidos"),
+            ("code", "Código"),
+            ("programs", "Programas"),
+            ("fonts", "Fuentes"),
+            ("temp", "Temporales"),
+        ],
+    },
+    Language {
+        code: "fr",
+        folders: &[
+            ("images", "Images"),
+            ("videos", "Vidéos"),
+            ("audio", "Audio"),
+            ("documents", "Documents"),
+            ("archives", "Archives"),
+            ("code", "Code"),
+            ("programs", "Programmes"),
+            ("fonts", "Polices"),
+            ("temp", "Temporaire"),
+        ],
+    },
+    Language {
+        code: "it",
+        folders: &[
+            ("images", "Immagini"),
+            ("videos", "Video"),
+            ("audio", "Audio"),
+            ("documents", "Documenti"),
+            ("archives", "Archivi"),
+            ("code", "Codice"),
+            ("programs", "Programmi"),
+            ("fonts", "Caratteri"),
+            ("temp", "Temporanei"),
+        ],
+    },
+    Language {
+        code: "nl",
+        folders: &[
+            ("images", "Afbeeldingen"),
+            ("videos", "Video's"),
+            ("audio", "Audio"),
+            ("documents", "Documenten"),
+            ("archives", "Archieven"),
+            ("code", "Code"),
+            ("programs", "Programma's"),
+            ("fonts", "Lettertypen"),
+            ("temp", "Tijdelijk"),
+        ],
+    },
+    Language {
+        code: "pt",
+        folders: &[
+            ("images", "Imagens"),
+            ("videos", "Vídeos"),
+            ("audio", "Áudio"),
+            ("documents", "Documentos"),
+            ("archives", "Compactados"),
+            ("code", "Código"),
+            ("programs", "Programas"),
+            ("fonts", "Fontes"),
+            ("temp", "Temporários"),
+        ],
+    },
+];
+
+// What --lang asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    // Whatever the locale says
+    Auto,
+    Fixed(&'static Language),
+}
+
+impl Lang {
+    // The language to use, with `var` to read the environment (None = the English defaults)
+    pub fn resolve(self, var: impl Fn(&str) -> Option<String>) -> Option<&'static Language> {
+        match self {
+            Lang::Fixed(language) => Some(language),
+            Lang::Auto => from_locale(var),
+        }
+    }
+}
+
+// "de", "DE", "de_DE.UTF-8", "pt-BR": the language part, if we have folder names for it
+pub fn find(name: &str) -> Option<&'static Language> {
+    let code = name
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    LANGUAGES.iter().find(|language| language.code == code)
+}
+
+// The language of the locale: the first of LC_ALL, LC_MESSAGES and LANG that is set wins, like
+// for every other program ("C" and "POSIX" mean no language in particular)
+pub fn from_locale(var: impl Fn(&str) -> Option<String>) -> Option<&'static Language> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| var(name))
+        .find(|value| !value.is_empty())?;
+    find(&locale)
+}
+
+// The folders of `language` as --category-folder rules (see Options::lang_folders)
+pub fn category_folders(language: &Language) -> Vec<CategoryFolder> {
+    language
+        .folders
+        .iter()
+        .map(|(category, folder)| CategoryFolder {
+            category: category.to_string(),
+            folder: folder.to_string(),
+        })
+        .collect()
+}
+
+// The codes --lang takes, for its help and error message
+pub fn codes() -> Vec<&'static str> {
+    LANGUAGES.iter().map(|language| language.code).collect()
+}
@@ -0,0 +1,210 @@
+use crate::hashing;
+use crate::journal::{self, Entry, Integrity};
+use crate::logic::{self, Options};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Utc};
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Locate: where did this file end up?
+// =============================================================================
+//
+// A file can be moved by more than one run: organized into pdf/, undone, organized again, merged
+// into another folder... `locate --path ~/Downloads report.pdf` follows it through the journals
+// of every run, oldest first, from the place it was found to where it is now.
+//
+// A file that was renamed or moved by hand isn't in any journal. If its hash is known (from
+// `b3sum`, or an earlier `dedupe`), `locate --hash` reads every file in the tree instead and
+// lists the ones with that content.
+
+// One move of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub run_id: String,
+    pub at: DateTime<Utc>,
+    // Where it went, None = the system trash
+    pub to: Option<PathBuf>,
+    // This step was an undo, putting the file back
+    pub undo: bool,
+}
+
+// The moves of one file, from where a run first found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trail {
+    pub original: PathBuf,
+    pub steps: Vec<Step>,
+}
+
+impl Trail {
+    // Where the file should be now (relative to the root, like the journal), None = the trash
+    pub fn now(&self) -> Option<&Path> {
+        match self.steps.last() {
+            Some(step) => step.to.as_deref(),
+            None => Some(&self.original),
+        }
+    }
+}
+
+// A move, trash or undo of some file, in the order the runs did them
+struct Event {
+    run_id: String,
+    at: DateTime<Utc>,
+    from: PathBuf,
+    to: Option<PathBuf>,
+    undo: bool,
+}
+
+// The trails of the files called `name` (or found at the path `name`) when a run first moved them
+pub fn trails(root: &Path, name: &str, state_dir: Option<&Path>) -> Result<Vec<Trail>> {
+    let events = events(root, state_dir)?;
+    let wanted = Path::new(name);
+    let matches = |path: &Path| path == wanted || path.file_name() == Some(wanted.as_os_str());
+
+    let mut trails = Vec::new();
+    for (i, start) in events.iter().enumerate() {
+        // Only where the file was before any run touched it: if an earlier move brought it there,
+        // this move is part of that earlier trail
+        if !matches(&start.from)
+            || events[..i]
+                .iter()
+                .any(|e| e.to.as_deref() == Some(start.from.as_path()))
+        {
+            continue;
+        }
+
+        let mut steps = Vec::new();
+        let mut current = Some(start.from.clone());
+        for event in &events[i..] {
+            if current.as_ref() == Some(&event.from) {
+                current = event.to.clone();
+                steps.push(Step {
+                    run_id: event.run_id.clone(),
+                    at: event.at,
+                    to: event.to.clone(),
+                    undo: event.undo,
+                });
+            }
+        }
+        trails.push(Trail {
+            original: start.from.clone(),
+            steps,
+        });
+    }
+    Ok(trails)
+}
+
+// Every move, trash and undo in the journals of `root`, in the order they happened
+fn events(root: &Path, state_dir: Option<&Path>) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for path in journal::list(root, state_dir)? {
+        // A damaged journal is only read up to the damage, like undo does
+        let entries = match journal::verify(&path)? {
+            Integrity::Damaged { line, .. } => journal::read_before(&path, line)?,
+            _ => journal::read(&path)?,
+        };
+        let mut run_id = String::new();
+        for entry in entries {
+            let (from, to, at, undo) = match entry {
+                Entry::Run { run_id: id, .. } => {
+                    run_id = id.to_string();
+                    continue;
+                }
+                Entry::Move { from, to, at, .. } => (from, Some(to), at, false),
+                Entry::Trash { path, at, .. } => (path, None, at, false),
+                // An undo moves the file from where the run put it back to where it was
+                Entry::Undo { from, to, at } => (to, Some(from), at, true),
+                _ => continue,
+            };
+            events.push(Event {
+                run_id: run_id.clone(),
+                at,
+                from,
+                to,
+                undo,
+            });
+        }
+    }
+    // Undos are appended to the journal of the run they undo, and two runs can start in the same
+    // second (the journals' names sort by second), so the journals alone don't give the order
+    events.sort_by_key(|event| event.at);
+    Ok(events)
+}
+
+// The files below `root` whose blake3 hash starts with `hash` (hex, any case)
+pub fn with_hash(root: &Path, hash: &str) -> Result<Vec<PathBuf>> {
+    let hash = hash.to_lowercase();
+    if hash.len() < 8 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!(
+            "{:?} isn't a hash (expected at least 8 hex digits of a blake3 hash)",
+            hash
+        );
+    }
+    let abs_root = logic::checked_root(root, false)?;
+    let files = logic::scan(root, &abs_root, &Options::default());
+    let hashes = hashing::hash_files(&files);
+    let mut found: Vec<PathBuf> = files
+        .into_iter()
+        .zip(hashes)
+        .filter(|(_, h)| {
+            h.as_ref()
+                .is_ok_and(|h| h.to_hex().starts_with(hash.as_str()))
+        })
+        .map(|(file, _)| file)
+        .collect();
+    found.sort();
+    Ok(found)
+}
+
+// `locate`: prints where the file `name` went (or, with `by_hash`, which files have the hash `name`)
+pub fn locate(
+    target_path: &str,
+    name: &str,
+    by_hash: bool,
+    state_dir: Option<&Path>,
+) -> Result<()> {
+    let root = Path::new(target_path);
+
+    if by_hash {
+        println!("Hashing every file in {:?}...", root);
+        let found = with_hash(root, name)?;
+        if found.is_empty() {
+            println!("No file with hash {} in {:?}", name, root);
+        }
+        for file in found {
+            println!("    {:?}", file);
+        }
+        return Ok(());
+    }
+
+    let trails = trails(root, name, state_dir)?;
+    if trails.is_empty() {
+        println!(
+            "No run moved a file called {:?} (try --hash if it was moved by hand)",
+            name
+        );
+        return Ok(());
+    }
+    for trail in trails {
+        println!("{:?}:", trail.original);
+        for step in &trail.steps {
+            let at = step.at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+            let short_id = &step.run_id[..step.run_id.len().min(8)];
+            let verb = if step.undo { "undo of" } else { "run" };
+            match &step.to {
+                Some(to) => println!("    {}  {} {}  -> {:?}", at, verb, short_id, to),
+                None => println!("    {}  {} {}  -> the trash", at, verb, short_id),
+            }
+        }
+        match trail.now() {
+            Some(now) if root.join(now).exists() => {
+                println!("    now at {:?}", now)
+            }
+            Some(now) => println!(
+                "    last moved to {:?}, but it isn't there anymore (moved by hand?)",
+                now
+            ),
+            None => println!("    now in the system trash"),
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,49 @@
+use crate::advice;
+use crate::category;
+use crate::classify::{self, Classified, Classifier};
+use crate::confirm::{self, Destruction};
+use crate::console::Console;
+use crate::display::{self, PathDisplay};
+use crate::edit_plan;
+use crate::events::{self, Event, Output};
+use crate::free_space::{FreeSpace, Reservation, Room};
+use crate::gps;
+use crate::html;
+use crate::idle::Idle;
+use crate::journal::{self, Journal};
+use crate::links::{self, LinkPolicy, Links};
+use crate::open_files;
+use crate::owners::Owners;
+use crate::paths;
+use crate::permissions::Permissions;
+use crate::plan::{
+    self, CategoryFolder, ConflictStrategy, DuplicateStrategy, Grouping, JunkStrategy, Mapping,
+    PlannedAction, Rule, SmallGroups,
+};
+use crate::protected;
+use crate::reconcile::{Estimate, Reconciliation};
+use crate::reference;
+use crate::report::{self, Stats};
+use crate::schema;
+use crate::target;
+use crate::throttle::{self, Throttle};
+use crate::user_dirs;
+use anyhow::{bail, Context, Result};
+use globset::GlobMatcher;
+use ignore::{WalkBuilder, WalkState};
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::io;
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use walkdir::WalkDir;
+use std::time::{Instant, SystemTime};
+use uuid::Uuid;
 
 // =============================================================================
 // EDUCATIONAL COMMENTS: Common Rust Concepts
@@ -52,95 +91,2008 @@ use walkdir::WalkDir;
 //
 // =============================================================================
 
+// println!(), except with --output jsonl, where stdout is kept for the events
+macro_rules! say {
+    ($options:expr, $($arg:tt)*) => {
+        if $options.output == Output::Text {
+            println!($($arg)*);
+        }
+    };
+}
+
+// All the switches that change how a run behaves (filled in from the CLI args in main.rs)
+// Options::default() is a plain run: no dry run, conflicts renamed, no limits
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub dry_run: bool,
+    // Allow what can't be undone (moving files to the trash) without asking (see confirm.rs)
+    pub yes: bool,
+    // A run that had warnings (or errors) fails at the end, for scripts that want to know
+    pub warnings_as_errors: bool,
+    pub allow_system: bool,
+    pub same_fs: bool,
+    // Ask the file system as little as possible, for network drives (see scan())
+    pub fast_scan: bool,
+    // Leave the files that have an identical copy in this folder alone (--skip-if-in)...
+    pub skip_if_in: Option<PathBuf>,
+    // ...or send them to the trash (--delete-if-duplicated)
+    pub delete_if_duplicated: bool,
+    // Stop copying once the destination has fewer than this many bytes free
+    pub stop_if_free_below: Option<u64>,
+    // Move at most this many files / bytes (the rest of the plan is left for the next run)
+    pub limit: Option<NonZeroU64>,
+    pub limit_bytes: Option<NonZeroU64>,
+    // Go through the plan in a random order (--shuffle)
+    pub shuffle: bool,
+    // --max-duration: no new moves are started after this moment
+    pub deadline: Option<Instant>,
+    // Pause the moves while other programs keep the machine busy (see idle.rs)
+    pub only_when_idle: bool,
+    // Remove the GPS location from the photos that are moved (see gps.rs)
+    pub strip_exif_gps: bool,
+    // What to do with files that symlinks point at (see links.rs), and where else to look for links
+    pub link_policy: LinkPolicy,
+    pub link_dirs: Vec<PathBuf>,
+    // Speed limit for copies in MB/s
+    pub throttle: Option<f64>,
+    // What to do when a destination is already taken
+    pub on_conflict: ConflictStrategy,
+    // What to do when the destination is taken by an identical copy
+    pub on_duplicate: DuplicateStrategy,
+    // Where temporary/backup/lock files go
+    pub junk: JunkStrategy,
+    // Keep the subfolders a file was in: "sub/dir/a.pdf" -> "pdf/sub/dir/a.pdf"
+    pub preserve_structure: bool,
+    // One folder per extension, or per category
+    pub by: Grouping,
+    // Only the files added or changed since the last run that finished (see journal::last_complete_run)
+    pub since_last_run: bool,
+    // After planning, check that a second run would have nothing left to do
+    pub check_idempotent: bool,
+    // --min-group-size: a --by folder needs this many files, or they go by --small-groups
+    pub min_group_size: Option<NonZeroUsize>,
+    pub small_groups: SmallGroups,
+    // --rule rules, in the order they're tried (see plan::by_priority)
+    pub rules: Vec<Rule>,
+    // --map rules, in the order given
+    pub map: Vec<Mapping>,
+    // --compound-ext: extensions of several parts, on top of category::COMPOUND_EXTENSIONS
+    pub compound_extensions: Vec<String>,
+    // --category-folder rules, in the order given
+    pub category_folders: Vec<CategoryFolder>,
+    // --lang: the category folders' names in another language (see locale.rs), rules like
+    // category_folders that lose to every one of them
+    pub lang_folders: Vec<CategoryFolder>,
+    // Put categories into folders that already exist in root ("Pictures" for images)
+    pub adopt_existing: bool,
+    // Put categories into the platform's user folders (~/Pictures for images, see user_dirs.rs)
+    pub into_user_dirs: bool,
+    // Give the folders the run touched their old modification time back
+    pub preserve_dir_mtime: bool,
+    // Only the files of this user and/or group (--owner, --group)
+    pub owners: Option<Owners>,
+    // Also write the run ID into an extended attribute of every moved file
+    pub stamp_xattr: bool,
+    // Threads for the scan (None = the walker's default)
+    pub threads: Option<NonZeroUsize>,
+    // Also leave alone what .gitignore files exclude
+    pub respect_gitignore: bool,
+    // Print recommendations after the summary
+    pub advise: bool,
+    // Write an HTML report of the run to this file
+    pub html_report: Option<PathBuf>,
+    // Programs asked where files go, in this order (--classifier, see classify.rs)
+    pub classifiers: Vec<Classifier>,
+    // Readable text, or JSON events on stdout
+    pub output: Output,
+    // How the per-file lines show paths (relative to the root, fitting the terminal, see display.rs)
+    pub display: PathDisplay,
+    // One line per destination folder instead of one per moved file (see console.rs)
+    pub aggregate_moves: bool,
+    // Open the plan in this editor before the run, and run it as edited (see edit_plan.rs)
+    pub edit_plan: Option<String>,
+    // Where journals go (None = the platform's state folder, see paths.rs)
+    pub state_dir: Option<PathBuf>,
+    // Only organize the files below the root that match this (the pattern part of
+    // --path "~/Downloads/*.pdf", see target.rs)
+    pub matching: Option<GlobMatcher>,
+}
+
+// The combinations of options that make no sense, caught before anything is scanned instead of
+// having them ignored or failing halfway through the moves. Counts that must be at least 1
+// (--limit, --threads, ...) are NonZero in Options, so a 0 can't get this far.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    // --delete-if-duplicated without --skip-if-in: without a reference folder, nothing is a duplicate
+    DeleteWithoutReference,
+    // --into-user-dirs with a grouping that has no user folders (languages, MIME types)
+    NoUserDirsFor(Grouping),
+    // A --throttle slower than throttle::MIN_MB_PER_SEC, or not a number
+    InvalidThrottle(f64),
+    // An option `merge` would ignore, named the way it's typed on the command line
+    IgnoredByMerge(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::DeleteWithoutReference => write!(
+                f,
+                "--delete-if-duplicated needs --skip-if-in: without a reference folder, nothing is a duplicate"
+            ),
+            ConfigError::NoUserDirsFor(_) => write!(
+                f,
+                "--into-user-dirs works with --by extension and --by category: languages and MIME types have no user folders"
+            ),
+            ConfigError::InvalidThrottle(rate) => write!(
+                f,
+                "Invalid --throttle {} (expected MB/s, e.g. 10 or 0.5, at least {})",
+                rate,
+                throttle::MIN_MB_PER_SEC
+            ),
+            ConfigError::IgnoredByMerge(option) => write!(
+                f,
+                "{} can't be used with merge: a merge keeps every file's place",
+                option
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Options {
+    // Options::builder().dry_run(true).by(Grouping::Category).build()?: checked on the way out.
+    // (Filling in the fields directly works too; every entry point checks them again.)
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if self.delete_if_duplicated && self.skip_if_in.is_none() {
+            return Err(ConfigError::DeleteWithoutReference);
+        }
+        if self.into_user_dirs && matches!(self.by, Grouping::Language | Grouping::Mime) {
+            return Err(ConfigError::NoUserDirsFor(self.by));
+        }
+        if let Some(rate) = self.throttle {
+            if !throttle::is_valid_rate(rate) {
+                return Err(ConfigError::InvalidThrottle(rate));
+            }
+        }
+        Ok(())
+    }
+
+    // check(), for `merge`. A merge keeps every file at its place relative to the folder it's in,
+    // so the options that decide where files go, or which of them are moved, would be ignored.
+    pub fn check_merge(&self) -> Result<(), ConfigError> {
+        self.check()?;
+        let ignored = [
+            ("--limit", self.limit.is_some()),
+            ("--limit-bytes", self.limit_bytes.is_some()),
+            ("--edit-plan", self.edit_plan.is_some()),
+            ("--classifier", !self.classifiers.is_empty()),
+            ("--skip-if-in", self.skip_if_in.is_some()),
+            ("--rule", !self.rules.is_empty()),
+            ("--min-group-size", self.min_group_size.is_some()),
+            ("--since-last-run", self.since_last_run),
+            ("--map", !self.map.is_empty()),
+            ("--category-folder", !self.category_folders.is_empty()),
+            ("--lang", !self.lang_folders.is_empty()),
+            ("--into-user-dirs", self.into_user_dirs),
+            ("--report html", self.html_report.is_some()),
+        ];
+        match ignored.iter().find(|(_, set)| *set) {
+            Some((option, _)) => Err(ConfigError::IgnoredByMerge(option)),
+            None => Ok(()),
+        }
+    }
+}
+
+// One setter per field, each taking and returning the builder
+macro_rules! setters {
+    ($($field:ident: $type:ty),* $(,)?) => {
+        $(
+            pub fn $field(mut self, $field: $type) -> Self {
+                self.options.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+// Options, one field at a time (see Options::builder)
+#[derive(Debug, Clone, Default)]
+pub struct OptionsBuilder {
+    options: Options,
+}
+
+impl OptionsBuilder {
+    setters! {
+        dry_run: bool,
+        yes: bool,
+        warnings_as_errors: bool,
+        allow_system: bool,
+        same_fs: bool,
+        fast_scan: bool,
+        skip_if_in: Option<PathBuf>,
+        delete_if_duplicated: bool,
+        stop_if_free_below: Option<u64>,
+        limit: Option<NonZeroU64>,
+        limit_bytes: Option<NonZeroU64>,
+        shuffle: bool,
+        deadline: Option<Instant>,
+        only_when_idle: bool,
+        strip_exif_gps: bool,
+        link_policy: LinkPolicy,
+        link_dirs: Vec<PathBuf>,
+        throttle: Option<f64>,
+        on_conflict: ConflictStrategy,
+        on_duplicate: DuplicateStrategy,
+        junk: JunkStrategy,
+        preserve_structure: bool,
+        by: Grouping,
+        since_last_run: bool,
+        check_idempotent: bool,
+        min_group_size: Option<NonZeroUsize>,
+        small_groups: SmallGroups,
+        rules: Vec<Rule>,
+        map: Vec<Mapping>,
+        compound_extensions: Vec<String>,
+        category_folders: Vec<CategoryFolder>,
+        lang_folders: Vec<CategoryFolder>,
+        adopt_existing: bool,
+        into_user_dirs: bool,
+        preserve_dir_mtime: bool,
+        owners: Option<Owners>,
+        stamp_xattr: bool,
+        threads: Option<NonZeroUsize>,
+        respect_gitignore: bool,
+        advise: bool,
+        html_report: Option<PathBuf>,
+        classifiers: Vec<Classifier>,
+        output: Output,
+        display: PathDisplay,
+        aggregate_moves: bool,
+        edit_plan: Option<String>,
+        state_dir: Option<PathBuf>,
+        matching: Option<GlobMatcher>,
+    }
+
+    pub fn build(self) -> Result<Options, ConfigError> {
+        self.options.check()?;
+        Ok(self.options)
+    }
+
+    // build(), for `merge` (see Options::check_merge)
+    pub fn build_merge(self) -> Result<Options, ConfigError> {
+        self.options.check_merge()?;
+        Ok(self.options)
+    }
+}
+
 // This is the function we will call from main.rs
-pub fn process_directory(target_path: &str, dry_run: bool) -> Result<(), ()> {
+pub fn process_directory(target_path: &str, options: &Options) -> Result<()> {
+    options.check()?;
     let root = Path::new(target_path);
+    let abs_root = checked_root(root, options.allow_system)?;
+    let (mut options, adopted_folders) = adopted(root, options);
+    options.display.root = Some(root.to_path_buf());
+    let options = &options;
+
+    say!(options, "Scanning directory: {:?}", root);
+    for (category, folder) in &adopted_folders {
+        say!(
+            options,
+            "Using the existing folder {:?} for {}",
+            folder,
+            category
+        );
+    }
 
-    println!("Scanning directory: {:?}", root);
+    if let Some((run_id, left)) = journal::checkpoint(root, options.state_dir.as_deref()) {
+        say!(
+            options,
+            "Continuing where run {} stopped ({} files were left for this run)",
+            run_id,
+            left
+        );
+    }
+    // The warnings from before and after the moves (the ones during them are in the Outcome)
+    let warnings: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let since = if options.since_last_run {
+        let since = journal::last_complete_run(root, options.state_dir.as_deref());
+        match since {
+            Some(time) => say!(
+                options,
+                "Only looking at files added or changed since the last run ({})",
+                time.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+            ),
+            None => say!(
+                options,
+                "No earlier run finished here, looking at every file"
+            ),
+        }
+        since.map(SystemTime::from)
+    } else {
+        None
+    };
+    let (entries, partials) = scan_with_partials(root, &abs_root, options, since);
+    remove_partials(&partials, options, &warnings);
+
+    say!(options, "Found {} files", entries.len());
+
+    if options.output == Output::Jsonl {
+        events::emit(&Event::Scan {
+            schema_version: schema::SCHEMA_VERSION,
+            root,
+            files: entries.len(),
+        });
+    }
+
+    // Phase 1: decide what to do (no changes on disk yet)
+    let contents = classify::classify(&options.classifiers, &entries)?;
+    let actions = checked_plan(&entries, root, &abs_root, options, &contents)?;
+    if options.check_idempotent {
+        let unsettled = unsettled(&entries, root, options, &contents, &actions);
+        if unsettled.is_empty() {
+            say!(options, "Checked: a second run would have nothing to do");
+        }
+        for warning in unsettled {
+            report_warning(warning, &warnings, options);
+        }
+    }
+    let (actions, left_out) = limited(actions, options);
+    let actions = match &options.edit_plan {
+        Some(editor) => edit_plan::edit(actions, root, editor)?,
+        None => actions,
+    };
+    if options.output == Output::Jsonl {
+        let count = |f: fn(&PlannedAction) -> bool| actions.iter().filter(|a| f(a)).count();
+        events::emit(&Event::Plan {
+            moves: count(|a| matches!(a, PlannedAction::Move { .. })),
+            skips: count(|a| matches!(a, PlannedAction::Skip { .. })),
+            trash: count(|a| matches!(a, PlannedAction::Trash { .. })),
+        });
+    }
+
+    let estimate = Estimate::of(&actions);
+    confirmed(&actions, options)?;
+
+    // Every real run gets an ID and a journal of what it moved (a dry run, or a run
+    // with nothing to do, moves nothing, so it needs neither)
+    let has_moves = actions
+        .iter()
+        .any(|a| matches!(a, PlannedAction::Move { .. } | PlannedAction::Trash { .. }));
+    let journal = if options.dry_run || !has_moves {
+        None
+    } else {
+        let journal = Journal::create(root, &abs_root, options.state_dir.as_deref())?;
+        say!(options, "Run ID: {}", journal.run_id);
+        Some(journal)
+    };
+
+    let linked = linked_files(&actions, &entries, options);
+    if options.link_policy == LinkPolicy::Warn {
+        for (file, file_links) in &linked {
+            for link in file_links {
+                let warning = format!(
+                    "The link {:?} points to {:?}, it breaks when the file is moved",
+                    link, file
+                );
+                report_warning(warning, &warnings, options);
+            }
+        }
+    }
+
+    // Phase 2: do it (or, in a dry run, print what would be done)
+    let dir_times = if options.preserve_dir_mtime && !options.dry_run {
+        dir_mtimes(&actions, root)
+    } else {
+        Vec::new()
+    };
+    let mut outcome = execute(&actions, options, journal.as_ref());
+    if options.link_policy == LinkPolicy::Update {
+        update_links(&linked, &outcome, options, journal.as_ref(), &warnings);
+    }
+    for (dir, time) in dir_times {
+        if let Err(e) = set_dir_mtime(&dir, time) {
+            let warning = format!(
+                "Failed to restore the modification time of {:?}: {}",
+                dir, e
+            );
+            report_warning(warning, &warnings, options);
+        }
+    }
+    outcome.warnings.extend(warnings.into_inner().unwrap());
+
+    say!(options, "--- Organization Complete ---");
+    if options.output == Output::Text {
+        report::print_summary(&outcome.moved, options.by != Grouping::Extension);
+    }
+    if outcome.trashed > 0 {
+        say!(options, "Moved to the trash : {} files", outcome.trashed);
+    }
+    stopped_early(&outcome, options);
+    reconciled(&estimate, &outcome, options);
+    warnings_and_problems(&outcome, options);
+    if left_out > 0 {
+        say!(
+            options,
+            "Stopped at the limit: {} more files are left for the next run",
+            left_out
+        );
+    }
+
+    if options.advise {
+        let advice = advice::advise(&actions, root, options.dry_run);
+        if !advice.is_empty() {
+            say!(options, "--- Recommendations ---");
+            for line in advice {
+                say!(options, "* {}", line);
+            }
+        }
+    }
+
+    if let Some(path) = &options.html_report {
+        let run = html::Run {
+            root,
+            run_id: journal.as_ref().map(|j| j.run_id),
+            dry_run: options.dry_run,
+            actions: &actions,
+            outcome: &outcome,
+        };
+        html::write(path, &run)?;
+        say!(options, "Report written to {:?}", path);
+    }
+
+    if options.output == Output::Jsonl {
+        let total = outcome.moved.total();
+        events::emit(&Event::Done {
+            run_id: journal.as_ref().map(|j| j.run_id),
+            moved: total.files,
+            bytes: total.bytes,
+            trashed: outcome.trashed,
+            problems: outcome.problems.len(),
+            warnings: outcome.warnings.len(),
+            stopped_early: outcome.stopped_early,
+            left_out: left_out + outcome.not_started as usize,
+        });
+    }
 
-    let entries: Vec<PathBuf> = WalkDir::new(root)
-        .into_iter()
-        .filter_map(|x| x.ok()) // Ignore errors (like permission denied)
-        .filter(|x| x.path().is_file()) // Ignore folders, only look at files
-        .map(|x| x.path().to_owned()) // Convert to PathBuf (owns the data)
+    if let Some(journal) = journal {
+        let path = journal.path.clone();
+        say!(options, "Run {} recorded in {:?}", journal.run_id, path);
+        journal
+            .finish(
+                outcome.moved.total().files,
+                left_out as u64 + outcome.not_started,
+            )
+            .with_context(|| format!("Failed to write journal {:?}", path))?;
+    }
+
+    strict(&outcome, options)
+}
+
+// `options` with --adopt-existing worked out: every category whose folder (or a folder with one of
+// its other names, see category::adopt) is already in root goes there, instead of into a new folder
+// next to it. With --by category that's a --category-folder rule, with --by extension a --map rule
+// for the category's extensions. --into-user-dirs works the same way, with the platform's folders
+// for pictures, music, ... (see user_dirs.rs), which win over the adopted ones. Rules given on the
+// command line come later, so they still win. Also returns which folders were adopted, as
+// (category, folder).
+fn adopted(root: &Path, options: &Options) -> (Options, Vec<(&'static str, String)>) {
+    let mut options = options.clone();
+    let mut adopted: Vec<(&'static str, String)> = Vec::new();
+    if options.adopt_existing {
+        let folders: Vec<String> = fs::read_dir(root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name != journal::STATE_DIR)
+            .collect();
+        adopted.extend(
+            category::adopt(&folders)
+                .into_iter()
+                .map(|(name, folder)| (name, folder.to_string())),
+        );
+    }
+    if options.into_user_dirs {
+        // Rules hold folders as text, so a folder whose path isn't valid UTF-8 can't be one
+        adopted.extend(
+            user_dirs::find()
+                .into_iter()
+                .filter_map(|(name, folder)| Some((name, folder.to_str()?.to_string()))),
+        );
+    }
+    if adopted.is_empty() {
+        return (options, adopted);
+    }
+    let mut category_folders = Vec::new();
+    let mut map = Vec::new();
+    for (name, folder) in &adopted {
+        // Always true, adopt() only returns built-in categories
+        let Some(c) = category::CATEGORIES.iter().find(|c| c.name == *name) else {
+            continue;
+        };
+        match options.by {
+            Grouping::Category => category_folders.push(CategoryFolder {
+                category: name.to_string(),
+                folder: folder.clone(),
+            }),
+            Grouping::Extension => map.push(Mapping {
+                exts: c.extensions.iter().map(|e| e.to_string()).collect(),
+                folder: folder.clone(),
+            }),
+            // Languages and MIME types have no folders to adopt (check() doesn't let
+            // --into-user-dirs get here with them)
+            Grouping::Language | Grouping::Mime => {}
+        }
+    }
+    category_folders.append(&mut options.category_folders);
+    map.append(&mut options.map);
+    options.category_folders = category_folders;
+    options.map = map;
+    (options, adopted)
+}
+
+// --preserve-dir-mtime: the folders whose modification time the run will change (every folder a
+// file leaves or enters, and the folders new folders are created in), with their current time.
+// Folders the run creates are new anyway, so they aren't included.
+fn dir_mtimes(actions: &[PlannedAction], root: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let mut dirs: BTreeSet<&Path> = BTreeSet::new();
+    for action in actions {
+        match action {
+            PlannedAction::Move { from, to, .. } => {
+                dirs.extend(from.parent());
+                if let Some(folder) = to.parent() {
+                    // "pdf/sub" (new) is created in "pdf" (maybe new), which is created in root
+                    for dir in folder.ancestors() {
+                        dirs.insert(dir);
+                        if dir == root {
+                            break;
+                        }
+                    }
+                }
+            }
+            PlannedAction::Trash { path, .. } => dirs.extend(path.parent()),
+            PlannedAction::CreateDir { .. } | PlannedAction::Skip { .. } => {}
+        }
+    }
+    dirs.into_iter()
+        .filter_map(|dir| {
+            let time = fs::metadata(dir).ok()?.modified().ok()?;
+            Some((dir.to_path_buf(), time))
+        })
+        .collect()
+}
+
+// Sets the modification time of a folder
+fn set_dir_mtime(dir: &Path, time: SystemTime) -> io::Result<()> {
+    // Windows only opens folders with FILE_FLAG_BACKUP_SEMANTICS, and needs write access to set times
+    #[cfg(windows)]
+    let folder = {
+        use std::os::windows::fs::OpenOptionsExt;
+        fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(0x0200_0000)
+            .open(dir)?
+    };
+    #[cfg(not(windows))]
+    let folder = fs::File::open(dir)?;
+    folder.set_modified(time)
+}
+
+// The plan for the scanned `entries` (with the answers of the classifiers in `contents`), without
+// the files of other users (--owner, --group) and with the files other programs have open left
+// where they are
+fn checked_plan(
+    entries: &[PathBuf],
+    root: &Path,
+    abs_root: &Path,
+    options: &Options,
+    contents: &Classified,
+) -> Result<Vec<PlannedAction>> {
+    let mut actions = plan::plan_with(entries, root, options, contents);
+    if let Some(owners) = &options.owners {
+        actions = plan::only(actions, |file| owners.matches(file));
+    }
+    let actions = without_open_files(actions, root, abs_root);
+    // --fast-scan leaves the permissions to the moves: one that isn't allowed fails and is reported
+    // like any failed move, instead of a look at every file's folder before the run
+    let actions = if options.fast_scan {
+        actions
+    } else {
+        without_unmovable(actions)
+    };
+    let actions = without_backed_up(actions, root, options)?;
+    if options.link_policy != LinkPolicy::Skip {
+        return Ok(actions);
+    }
+    let links = Links::find(entries, &options.link_dirs);
+    Ok(plan::skip_where(actions, |file, _| {
+        links
+            .to(file)
+            .first()
+            .map(|link| format!("the link {:?} points to it", link))
+    }))
+}
+
+// --skip-if-in: the files with an identical copy in the reference folder stay where they are (or,
+// with --delete-if-duplicated, go to the trash), see reference.rs
+fn without_backed_up(
+    actions: Vec<PlannedAction>,
+    root: &Path,
+    options: &Options,
+) -> Result<Vec<PlannedAction>> {
+    let Some(reference) = &options.skip_if_in else {
+        return Ok(actions);
+    };
+    let moved: Vec<&Path> = actions
+        .iter()
+        .filter_map(|action| match action {
+            PlannedAction::Move { from, .. } => Some(from.as_path()),
+            _ => None,
+        })
+        .collect();
+    let copies = reference::copies_in(reference, root, &moved)?;
+    if options.delete_if_duplicated {
+        return Ok(plan::trash_where(actions, |file| copies.get(file).cloned()));
+    }
+    Ok(plan::skip_where(actions, |file, _| {
+        copies
+            .get(file)
+            .map(|copy| format!("an identical copy is already in {:?}", copy))
+    }))
+}
+
+// The files of the plan that links point at (--link-policy warn / update), with the links.
+// Found before the run, afterwards the files aren't where the links point anymore.
+fn linked_files(
+    actions: &[PlannedAction],
+    entries: &[PathBuf],
+    options: &Options,
+) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    if !matches!(options.link_policy, LinkPolicy::Warn | LinkPolicy::Update) {
+        return Vec::new();
+    }
+    let links = Links::find(entries, &options.link_dirs);
+    if links.is_empty() {
+        return Vec::new();
+    }
+    actions
+        .iter()
+        .filter_map(plan::moved_file)
+        .map(|(file, _)| (file.to_path_buf(), links.to(file).to_vec()))
+        .filter(|(_, links)| !links.is_empty())
+        .collect()
+}
+
+// --link-policy update: points the links at where their files went, and journals it
+fn update_links(
+    linked: &[(PathBuf, Vec<PathBuf>)],
+    outcome: &Outcome,
+    options: &Options,
+    journal: Option<&Journal>,
+    warnings: &Mutex<Vec<String>>,
+) {
+    for moved in &outcome.files {
+        let Some((_, file_links)) = linked.iter().find(|(file, _)| *file == moved.from) else {
+            continue;
+        };
+        for link in file_links {
+            // The link may have been moved by the run too
+            let link = outcome
+                .files
+                .iter()
+                .find(|other| other.from == *link)
+                .map_or(link, |other| &other.to);
+            if options.dry_run {
+                say!(
+                    options,
+                    "[DRY RUN] Would point the link {:?} at {:?}",
+                    link,
+                    moved.to
+                );
+                continue;
+            }
+            let updated = links::read_target(link).and_then(|old| {
+                let new_file = protected::absolute_path(&moved.to)?;
+                let new = links::new_target(link, &old, &new_file)?;
+                links::set_target(link, &new)?;
+                Ok((old, new))
+            });
+            match updated {
+                Ok((old, new)) => {
+                    say!(options, "Updated the link {:?} -> {:?}", link, new);
+                    if let Some(Err(e)) = journal.map(|j| j.record_relink(link, &old, &new)) {
+                        let warning =
+                            format!("Failed to write journal entry for {:?}: {}", link, e);
+                        report_warning(warning, warnings, options);
+                    }
+                }
+                Err(e) => {
+                    let warning = format!("Failed to update the link {:?}: {}", link, e);
+                    report_warning(warning, warnings, options);
+                }
+            }
+        }
+    }
+}
+
+// Safe mode: the files the plan sends to the trash, and the photos --strip-exif-gps rewrites, need
+// a yes before the run starts (see confirm.rs)
+fn confirmed(actions: &[PlannedAction], options: &Options) -> Result<()> {
+    if options.dry_run {
+        return Ok(());
+    }
+    let mut destruction = Destruction::default();
+    for action in actions {
+        let path = match action {
+            PlannedAction::Trash { path, .. } => {
+                destruction.trashed += 1;
+                path
+            }
+            // Only JPEGs are rewritten (see gps.rs), so the other files aren't read
+            PlannedAction::Move { from, ext, .. }
+                if options.strip_exif_gps
+                    && matches!(ext.as_str(), "jpg" | "jpeg")
+                    && gps::has_gps(from).unwrap_or(false) =>
+            {
+                destruction.rewritten += 1;
+                from
+            }
+            _ => continue,
+        };
+        destruction.bytes += fs::symlink_metadata(path).map(|m| m.len()).unwrap_or(0);
+    }
+    confirm::confirm(&destruction, options.yes)
+}
+
+// The plan with the files we aren't allowed to move left where they are (see permissions.rs)
+fn without_unmovable(actions: Vec<PlannedAction>) -> Vec<PlannedAction> {
+    let mut permissions = Permissions::new();
+    plan::skip_unmovable(actions, |file, to| permissions.why_not(file, to))
+}
+
+// The plan with the files other programs have open left where they are (see open_files.rs)
+fn without_open_files(
+    actions: Vec<PlannedAction>,
+    root: &Path,
+    abs_root: &Path,
+) -> Vec<PlannedAction> {
+    let open = open_files::open_files();
+    if open.is_empty() {
+        return actions;
+    }
+    plan::skip_open(actions, |file| {
+        open_files::opened_by(&open, file, root, abs_root).map(str::to_string)
+    })
+}
+
+// The plan cut down to --limit / --limit-bytes (see plan::limit), and how many moves were left out
+fn limited(actions: Vec<PlannedAction>, options: &Options) -> (Vec<PlannedAction>, usize) {
+    // Shuffled first, so --limit takes a random sample of the plan instead of its first folders
+    let actions = shuffled(actions, options);
+    if options.limit.is_none() && options.limit_bytes.is_none() {
+        return (actions, 0);
+    }
+    let max_files = options.limit.map(NonZeroU64::get);
+    let max_bytes = options.limit_bytes.map(NonZeroU64::get);
+    plan::limit(actions, max_files, max_bytes, |file| {
+        fs::symlink_metadata(file).map(|m| m.len()).unwrap_or(0)
+    })
+}
+
+// --shuffle: the plan in a random order, so a long run on shared storage (a NAS) spreads its
+// moves over all folders instead of working through one deep folder after another
+fn shuffled(actions: Vec<PlannedAction>, options: &Options) -> Vec<PlannedAction> {
+    if !options.shuffle {
+        return actions;
+    }
+    plan::shuffle(actions, &mut fastrand::Rng::new())
+}
+
+// The whole plan of a run, as --print-plan prints it
+#[derive(Debug, Serialize)]
+pub struct PlanDocument<'a> {
+    // See schema.rs
+    pub schema_version: u32,
+    pub root: &'a Path,
+    pub actions: &'a [PlannedAction],
+}
+
+// --print-plan: prints the plan as JSON instead of carrying it out
+pub fn print_plan(target_path: &str, options: &Options) -> Result<()> {
+    options.check()?;
+    let root = Path::new(target_path);
+    let abs_root = checked_root(root, options.allow_system)?;
+    let (options, _) = adopted(root, options);
+    let options = &options;
+    let entries = scan(root, &abs_root, options);
+    let contents = classify::classify(&options.classifiers, &entries)?;
+    let actions = checked_plan(&entries, root, &abs_root, options, &contents)?;
+    let (actions, _) = limited(actions, options);
+
+    let document = PlanDocument {
+        schema_version: schema::SCHEMA_VERSION,
+        root,
+        actions: &actions,
+    };
+    println!("{}", serde_json::to_string_pretty(&document)?);
+    Ok(())
+}
+
+// --explain: prints, for each of `files`, the rules the planner went through and what it decided.
+// Nothing is moved (not even a dry run is printed), so this is safe to run on any folder.
+pub fn explain(target_path: &str, files: &[PathBuf], options: &Options) -> Result<()> {
+    options.check()?;
+    let root = Path::new(target_path);
+    let abs_root = checked_root(root, options.allow_system)?;
+    let (options, _) = adopted(root, options);
+    let options = &options;
+    let entries = scan(root, &abs_root, options);
+    let contents = classify::classify(&options.classifiers, &entries)?;
+    let actions = checked_plan(&entries, root, &abs_root, options, &contents)?;
+
+    for file in files {
+        println!("Explaining {:?}", file);
+        // The scan has the file under a different spelling ("./Downloads/a.pdf" vs "/home/me/Downloads/a.pdf")
+        let wanted = protected::absolute_path(file).ok();
+        let found = entries.iter().find(|entry| {
+            entry.file_name() == file.file_name()
+                && wanted.is_some()
+                && protected::absolute_path(entry).ok() == wanted
+        });
+        let Some(entry) = found else {
+            println!("    not found by the scan: it doesn't exist, isn't inside {:?}, is excluded by {}{}, or is in a folder that's never touched (system folders, {})", root, IGNORE_FILE, if options.respect_gitignore { " or .gitignore" } else { "" }, journal::STATE_DIR);
+            continue;
+        };
+
+        for line in plan::explain(entry, root, options, &contents) {
+            println!("    {}", line);
+        }
+        println!("    decision: {}", decision(&actions, entry));
+    }
+    Ok(())
+}
+
+// --check-idempotent: the files a second run with the same options would move (again), in words
+fn unsettled(
+    entries: &[PathBuf],
+    root: &Path,
+    options: &Options,
+    contents: &impl plan::Contents,
+    actions: &[PlannedAction],
+) -> Vec<String> {
+    // Where each file the plan moves goes -> where it is now
+    let first: HashMap<&Path, &Path> = actions
+        .iter()
+        .filter_map(|action| match action {
+            PlannedAction::Move { from, to, .. } => Some((to.as_path(), from.as_path())),
+            _ => None,
+        })
         .collect();
+    plan::second_run(entries, root, options, contents, actions)
+        .iter()
+        .filter_map(|action| {
+            let PlannedAction::Move { from, to, .. } = action else {
+                return None;
+            };
+            Some(match first.get(from.as_path()) {
+                Some(original) if paths::key(original) == paths::key(to) => format!(
+                    "{:?} goes to {:?}, and a second run would move it back: the options send it back and forth on every run",
+                    original, from
+                ),
+                Some(original) => format!(
+                    "{:?} goes to {:?}, and a second run would move it on to {:?}",
+                    original, from, to
+                ),
+                None => format!(
+                    "{:?} stays, but a second run would move it to {:?}",
+                    from, to
+                ),
+            })
+        })
+        .collect()
+}
 
-    println!("Found {} files", entries.len());
+// What the plan does with `entry`, in words ("move to ...", "skip: ...")
+fn decision(actions: &[PlannedAction], entry: &Path) -> String {
+    actions
+        .iter()
+        .find_map(|action| match action {
+            PlannedAction::Move {
+                from,
+                to,
+                shortened,
+                ..
+            } if from == entry => Some(if *shortened {
+                format!("move to {:?} (name shortened, the path was too long)", to)
+            } else if to.file_name() != entry.file_name() {
+                format!("move to {:?} (renamed, the name is taken)", to)
+            } else {
+                format!("move to {:?}", to)
+            }),
+            PlannedAction::Skip { path, reason } if path == entry => {
+                Some(format!("skip: {}", reason))
+            }
+            PlannedAction::Trash { path, duplicate_of } if path == entry => {
+                Some(match duplicate_of {
+                    Some(original) => {
+                        format!("move to the trash, it's identical to {:?}", original)
+                    }
+                    None => "move to the trash".to_string(),
+                })
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| "leave it where it is".to_string())
+}
 
-    // Arc<Mutex<HashMap>> explained:
-    // - HashMap tracks how many files of each extension we moved
-    // - Mutex ensures only one thread updates the map at a time (prevents data corruption)
-    // - Arc allows multiple threads to share ownership of the Mutex<HashMap>
-    let stats: Arc<Mutex<HashMap<String, i32>>> = Arc::new(Mutex::new(HashMap::new()));
+// --sample: prints what the plan does with `count` randomly picked files, then stops.
+// The plan is made for every file (where a file goes can depend on the others), but only the
+// picked ones are shown, and nothing is moved.
+pub fn sample(target_path: &str, count: usize, options: &Options) -> Result<()> {
+    options.check()?;
+    let root = Path::new(target_path);
+    let abs_root = checked_root(root, options.allow_system)?;
+    let (options, _) = adopted(root, options);
+    let options = &options;
+    let entries = scan(root, &abs_root, options);
+    let contents = classify::classify(&options.classifiers, &entries)?;
+    let actions = checked_plan(&entries, root, &abs_root, options, &contents)?;
+
+    let mut picked: Vec<&PathBuf> = entries.iter().collect();
+    fastrand::shuffle(&mut picked);
+    picked.truncate(count);
+    // Shown in the scan's order, which is easier to read than a random one
+    picked.sort();
 
+    println!("Sample of {} of {} files:", picked.len(), entries.len());
+    for entry in picked {
+        println!("    {:?}: {}", entry, decision(&actions, entry));
+    }
+    Ok(())
+}
+
+// --audit: lists the files the plan would move or trash but the current user isn't allowed to
+// (other people's files, read-only folders), then stops. Nothing is moved.
+pub fn audit(target_path: &str, options: &Options) -> Result<()> {
+    options.check()?;
+    let root = Path::new(target_path);
+    let abs_root = checked_root(root, options.allow_system)?;
+    let (options, _) = adopted(root, options);
+    let options = &options;
+    let entries = scan(root, &abs_root, options);
+    let mut actions = plan::plan(&entries, root, options);
+    if let Some(owners) = &options.owners {
+        actions = plan::only(actions, |file| owners.matches(file));
+    }
+
+    let mut permissions = Permissions::new();
+    let planned: Vec<(&Path, Option<&Path>)> =
+        actions.iter().filter_map(plan::moved_file).collect();
+    let unmovable: Vec<(&Path, String)> = planned
+        .iter()
+        .filter_map(|&(file, to)| permissions.why_not(file, to).map(|why| (file, why)))
+        .collect();
+
+    if unmovable.is_empty() {
+        println!(
+            "You can move all {} files the plan would move",
+            planned.len()
+        );
+    } else {
+        println!(
+            "{} of the {} files the plan would move can't be moved by you:",
+            unmovable.len(),
+            planned.len()
+        );
+        for (file, why) in unmovable {
+            println!("    {:?}: {}", file, why);
+        }
+    }
+
+    // With --strip-exif-gps: which photos would lose their location
+    if options.strip_exif_gps {
+        let located: Vec<&Path> = planned
+            .iter()
+            .map(|&(file, _)| file)
+            .filter(|file| gps::has_gps(file).unwrap_or(false))
+            .collect();
+        println!(
+            "{} photos have a GPS location in them (--strip-exif-gps removes it as they're organized):",
+            located.len()
+        );
+        for file in located {
+            println!("    {:?}", file);
+        }
+    }
+    Ok(())
+}
+
+// --check-rules: says for every --rule, in the order they're tried, how many of the files below
+// the root it takes and how many more it matches that a rule before it takes, then stops. A rule
+// that matches files but takes none of them is shadowed ("*.pdf" before "*invoice*.pdf", or
+// with the lower priority the other way round): it never does anything, which is an error.
+pub fn check_rules(target_path: &str, options: &Options) -> Result<()> {
+    options.check()?;
+    if options.rules.is_empty() {
+        bail!("--check-rules needs --rule: there are no rules to check");
+    }
+    let root = Path::new(target_path);
+    let abs_root = checked_root(root, options.allow_system)?;
+    let entries = scan(root, &abs_root, options);
+
+    let mut taken = vec![0usize; options.rules.len()];
+    let mut shadowed = vec![0usize; options.rules.len()];
+    for entry in &entries {
+        let Some((winner, _)) = plan::rule_for(entry, root, options) else {
+            continue;
+        };
+        taken[winner] += 1;
+        for (i, rule) in options.rules.iter().enumerate().skip(winner + 1) {
+            if rule.matches(entry, root) {
+                shadowed[i] += 1;
+            }
+        }
+    }
+
+    println!(
+        "{} rules, in the order they're tried, on {} files:",
+        options.rules.len(),
+        entries.len()
+    );
+    let mut never = Vec::new();
+    for (i, rule) in options.rules.iter().enumerate() {
+        let rule_text = format!("{}:{}={}", rule.priority, rule.glob, rule.folder);
+        let note = match (taken[i], shadowed[i]) {
+            (0, 0) => " (matches no file here)".to_string(),
+            (0, more) => {
+                never.push(rule_text.clone());
+                format!(
+                    " - SHADOWED: the rules before it take all {} of its files",
+                    more
+                )
+            }
+            (_, 0) => String::new(),
+            (_, more) => format!(
+                ", {} more files it matches are taken by rules before it",
+                more
+            ),
+        };
+        println!(
+            "    {}. {}: takes {} files{}",
+            i + 1,
+            rule_text,
+            taken[i],
+            note
+        );
+    }
+    if !never.is_empty() {
+        bail!(
+            "{} never applies: move it before the rules that take its files, or give it a higher priority ({}:...)",
+            never.join(", "),
+            options.rules[0].priority + 1
+        );
+    }
+    Ok(())
+}
+
+// `merge`: folds the (organized) tree `src_path` into `dst_path`, every file to the same relative
+// place ("src/pdf/a.pdf" -> "dst/pdf/a.pdf"). Names that are taken in `dst_path` are handled by
+// --on-duplicate and --on-conflict. The run is journaled in `dst_path`, so `undo --path DST` puts
+// the files back into `src_path`.
+pub fn merge(src_path: &str, dst_path: &str, options: &Options) -> Result<()> {
+    options.check_merge()?;
+    // Absolute paths, so the journal (which lives with dst) can find the files in src again
+    let src = checked_root(Path::new(src_path), options.allow_system)?;
+    let dst = checked_root(Path::new(dst_path), options.allow_system)?;
+    if src.starts_with(&dst) || dst.starts_with(&src) {
+        bail!(
+            "Can't merge {:?} into {:?}: one is inside the other",
+            src_path,
+            dst_path
+        );
+    }
+
+    say!(options, "Merging {:?} into {:?}", src, dst);
+    let warnings: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let (files, mut partials) = scan_with_partials(&src, &src, options, None);
+    let (existing, in_dst) = scan_with_partials(&dst, &dst, options, None);
+    partials.extend(in_dst);
+    remove_partials(&partials, options, &warnings);
+    say!(
+        options,
+        "Found {} files to merge, {} already in the destination",
+        files.len(),
+        existing.len()
+    );
+
+    let actions = plan::plan_merge(&files, &src, &existing, &dst, options, &plan::Disk);
+    let actions = shuffled(actions, options);
+    let actions = without_open_files(actions, &src, &src);
+    let actions = without_unmovable(actions);
+    let estimate = Estimate::of(&actions);
+    confirmed(&actions, options)?;
+
+    let has_moves = actions
+        .iter()
+        .any(|a| matches!(a, PlannedAction::Move { .. } | PlannedAction::Trash { .. }));
+    let journal = if options.dry_run || !has_moves {
+        None
+    } else {
+        let journal = Journal::create(&dst, &dst, options.state_dir.as_deref())?;
+        say!(options, "Run ID: {}", journal.run_id);
+        Some(journal)
+    };
+
+    let mut outcome = execute(&actions, options, journal.as_ref());
+    outcome.warnings.extend(warnings.into_inner().unwrap());
+
+    say!(options, "--- Merge Complete ---");
+    if options.output == Output::Text {
+        report::print_summary(&outcome.moved, false);
+    }
+    if outcome.trashed > 0 {
+        say!(options, "Moved to the trash : {} files", outcome.trashed);
+    }
+    stopped_early(&outcome, options);
+    reconciled(&estimate, &outcome, options);
+    warnings_and_problems(&outcome, options);
+
+    if let Some(journal) = journal {
+        let path = journal.path.clone();
+        say!(options, "Run {} recorded in {:?}", journal.run_id, path);
+        journal
+            .finish(outcome.moved.total().files, outcome.not_started)
+            .with_context(|| format!("Failed to write journal {:?}", path))?;
+    }
+    strict(&outcome, options)
+}
+
+// The absolute, symlink-free version of root, so "./link-to-etc" can't sneak past the denylist.
+// Fails if root is a protected system folder (unless allow_system).
+pub(crate) fn checked_root(root: &Path, allow_system: bool) -> Result<PathBuf> {
+    let abs_root = protected::absolute_path(root)
+        .with_context(|| format!("Failed to access directory {:?}", root))?;
+
+    if !allow_system && protected::is_protected(&abs_root) {
+        bail!(
+            "Refusing to organize protected system directory {:?} (use --allow-system to override)",
+            abs_root
+        );
+    }
+    Ok(abs_root)
+}
+
+// What actually happened while executing a plan
+pub struct Outcome {
+    // How many files (and bytes) of each extension/category were moved
+    pub moved: Stats,
+    // Files moved to the trash (--on-duplicate trash-source, --junk trash)
+    pub trashed: u64,
+    // Every file that was moved (in a dry run: would be moved), in no particular order
+    pub files: Vec<MovedFile>,
+    // What went wrong, one message each (folders that couldn't be created, failed moves, ...)
+    pub problems: Vec<String>,
+    // What's worth a look but didn't stop a file from being moved (or was left alone on purpose):
+    // skipped files, shortened names, links that break, ...
+    pub warnings: Vec<String>,
+    // True if the run was stopped before every action was carried out
+    pub stopped_early: bool,
+    // ...because --max-duration ran out (otherwise the disk ran low on space)
+    pub out_of_time: bool,
+    // Moves that weren't started because the run stopped early
+    pub not_started: u64,
+    // Moves of files that were gone by the time it was their turn (see reconcile.rs)
+    pub vanished: u64,
+    // Moves whose destination was taken by then
+    pub late_conflicts: u64,
+    // Moves whose destination turned out to be the file itself
+    pub in_place: u64,
+}
+
+// One file of Outcome::files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovedFile {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub bytes: u64,
+    // Category (or language) the file was counted under, if any
+    pub category: Option<String>,
+}
+
+// Carries out a plan made by plan::plan()
+// Every move is recorded in `journal` (if there is one)
+pub fn execute(actions: &[PlannedAction], options: &Options, journal: Option<&Journal>) -> Outcome {
+    // 1. Create every destination folder once, up front, instead of having all threads race
+    // to call create_dir_all on the same folders. Folders that can't be created are reported here, once.
+    let mut blocked: HashSet<&Path> = HashSet::new();
+    // Shared with the move threads below, so it's behind a Mutex from the start
+    let problems: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let warnings: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    // Everything the run prints from here on goes through one writer (see console.rs)
+    let console = Console::start(options);
+    for action in actions {
+        if let PlannedAction::CreateDir { path } = action {
+            // Already there, nothing to do (and nothing to print)
+            if path.is_dir() {
+                continue;
+            }
+
+            // The folders create_dir_all() is about to make, deepest first
+            let new: Vec<&Path> = path.ancestors().take_while(|dir| !dir.exists()).collect();
+            // A dry run can't create the folder, but it can tell whether creating it would fail
+            let result = if options.dry_run {
+                check_can_create_dir(path)
+            } else {
+                fs::create_dir_all(path)
+            };
+
+            match result {
+                Ok(()) if options.dry_run => console.say(options.display.line(
+                    "[DRY RUN] Would create directory ",
+                    path,
+                    "",
+                )),
+                // Recorded outermost first, so `repair` can remove them again deepest first
+                Ok(()) => {
+                    if let Some(journal) = journal {
+                        for dir in new.iter().rev() {
+                            if let Err(e) = journal.record_create_dir(dir) {
+                                let warning =
+                                    format!("Failed to write journal entry for {:?}: {}", dir, e);
+                                console.warning(warning, &warnings);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    let skipped = actions
+                        .iter()
+                        .filter(|a| matches!(a, PlannedAction::Move { to, .. } if to.parent() == Some(path.as_path())))
+                        .count();
+                    let problem = format!(
+                        "{}Failed to create directory {:?}: {} (skipping {} files)",
+                        if options.dry_run { "[DRY RUN] " } else { "" },
+                        path,
+                        e,
+                        skipped
+                    );
+                    console.problem(problem, &problems);
+                    blocked.insert(path);
+                }
+            }
+        }
+    }
+
+    // 2. Report the files the planner already knows it can't move
+    for action in actions {
+        if let PlannedAction::Skip { path, reason } = action {
+            // A warning of its own kind: the skip event says it already
+            let warning = format!("Skipping {:?}: {}", path, reason);
+            let why = format!(": {}", display::escape(reason));
+            console.error(options.display.line("Warning: Skipping ", path, &why));
+            console.event(&Event::Skip { path, reason });
+            warnings.lock().unwrap().push(warning);
+        }
+    }
+
+    // 3. Duplicates and junk go to the trash, one by one (the copy a duplicate duplicates is never touched)
+    let mut trashed = 0;
+    for action in actions {
+        if let PlannedAction::Trash { path, duplicate_of } = action {
+            let why = match duplicate_of {
+                Some(original) => format!("identical to {:?}", original),
+                None => "temporary or leftover file".to_string(),
+            };
+            let event = Event::Trash {
+                path,
+                duplicate_of: duplicate_of.as_deref(),
+                dry_run: options.dry_run,
+            };
+            if options.dry_run {
+                console.say(options.display.line(
+                    "[DRY RUN] Would move ",
+                    path,
+                    &format!(" to the trash ({})", why),
+                ));
+                console.event(&event);
+                trashed += 1;
+                continue;
+            }
+            match trash::delete(path) {
+                Ok(()) => {
+                    console.say(options.display.line(
+                        "Moved ",
+                        path,
+                        &format!(" to the trash ({})", why),
+                    ));
+                    console.event(&event);
+                    trashed += 1;
+                    if let Some(journal) = journal {
+                        if let Err(e) = journal.record_trash(path, duplicate_of.as_deref()) {
+                            let warning =
+                                format!("Failed to write journal entry for {:?}: {}", path, e);
+                            console.warning(warning, &warnings);
+                        }
+                    }
+                }
+                Err(e) => {
+                    let problem = format!("Failed to move {:?} to the trash: {}", path, e);
+                    console.problem(problem, &problems);
+                }
+            }
+        }
+    }
+
+    // Arc<Mutex<Stats>> explained:
+    // - Stats tracks how many files (and bytes) of each extension/category we moved
+    // - Mutex ensures only one thread updates it at a time (prevents data corruption)
+    // - Arc allows multiple threads to share ownership of the Mutex<Stats>
+    let stats: Arc<Mutex<Stats>> = Arc::new(Mutex::new(Stats::default()));
+
+    // AtomicBool = a bool that many threads can read/write safely without a Mutex.
+    // Once one thread sets it (e.g. the disk is almost full), all the others stop picking up new files.
+    let stop = AtomicBool::new(false);
+    let out_of_time = AtomicBool::new(false);
+    let idle = options.only_when_idle.then(Idle::new);
+    let not_started = AtomicU64::new(0);
+    let vanished = AtomicU64::new(0);
+    let late_conflicts = AtomicU64::new(0);
+    let in_place = AtomicU64::new(0);
+
+    // One limiter shared by every thread, so the total copy speed stays under --throttle
+    let throttle = options.throttle.map(Throttle::new);
+    // Also shared: the copies under way, for --stop-if-free-below
+    let free_space = options.stop_if_free_below.map(FreeSpace::new);
+
+    // --stamp-xattr failures (file system without xattr support) are reported only once
+    let stamp_failed = AtomicBool::new(false);
+
+    let files: Mutex<Vec<MovedFile>> = Mutex::new(Vec::new());
+
+    // 4. The moves themselves
     // .par_iter() distributes the work across all your CPU cores automatically (parallel processing of files)
-    entries.par_iter().for_each(|file_path| {
+    actions.par_iter().for_each(|action| {
+        let (file_path, dest_path, ext, category, shortened) = match action {
+            PlannedAction::Move {
+                from,
+                to,
+                ext,
+                category,
+                shortened,
+            } => (from, to, ext, category, *shortened),
+            _ => return,
+        };
+
         // Arc::clone() creates another pointer to the SAME data (cheap, just increments counter)
         // We need this because each thread needs its own Arc handle to access the shared stats
         let stats_clone = Arc::clone(&stats);
 
-        // organize_file returns Option<String> - the extension if file was moved, None otherwise
-        if let Some(ext) = organize_file(file_path, root, dry_run) {
+        if let Some(idle) = &idle {
+            idle.wait(options.deadline, |line| console.error(line));
+        }
+        if options
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            out_of_time.store(true, Ordering::Relaxed);
+            // Only the first thread to notice prints it
+            if !stop.swap(true, Ordering::Relaxed) {
+                console.say("Time limit reached, stopping".to_string());
+            }
+        }
+        if stop.load(Ordering::Relaxed) {
+            not_started.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if dest_path.parent().is_some_and(|p| blocked.contains(p)) {
+            return;
+        }
+
+        // Measured before the move, the file isn't at file_path anymore afterwards
+        let size = match fs::symlink_metadata(file_path) {
+            Ok(metadata) => metadata.len(),
+            // Deleted or moved by someone else since the scan: nothing to move, nothing wrong
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                vanished.fetch_add(1, Ordering::Relaxed);
+                let warning = format!(
+                    "Skipping {:?}: it's gone (deleted or moved since the scan)",
+                    file_path
+                );
+                console.warning(warning, &warnings);
+                return;
+            }
+            Err(_) => 0,
+        };
+
+        // The planner already steered around every file it scanned, so if something is there now it
+        // appeared behind our back. rename() would silently replace it on Unix, so never go through with that.
+        // (Checked in dry runs too, so the preview shows the same skips as the real run.)
+        if fs::symlink_metadata(dest_path).is_ok() {
+            // ...unless it's the file itself, under another name (a symlinked folder, "PDF" on a
+            // file system that doesn't tell it from "pdf"): then it's where it belongs already
+            if paths::same_file(file_path, dest_path) {
+                in_place.fetch_add(1, Ordering::Relaxed);
+                let warning = format!(
+                    "Skipping {:?}: it's already where it belongs, as {:?}",
+                    file_path, dest_path
+                );
+                console.warning(warning, &warnings);
+                return;
+            }
+            late_conflicts.fetch_add(1, Ordering::Relaxed);
+            let problem = format!(
+                "{}Skipping {:?}: {:?} already exists",
+                if options.dry_run { "[DRY RUN] " } else { "" },
+                file_path,
+                dest_path
+            );
+            console.problem(problem, &problems);
+            return;
+        }
+
+        // Write-ahead: the journal knows about the move before it happens (see journal.rs)
+        if let Some(journal) = journal {
+            if let Err(e) = journal.record_intent(file_path, dest_path, size) {
+                let problem = format!(
+                    "Not moving {:?}: failed to write the journal: {}",
+                    file_path, e
+                );
+                console.problem(problem, &problems);
+                return;
+            }
+        }
+
+        // organize_file returns true if the file was moved, or why it couldn't be
+        let moved = match organize_file(
+            file_path,
+            dest_path,
+            options,
+            &stop,
+            throttle.as_ref(),
+            free_space.as_ref(),
+            &console,
+        ) {
+            // Not moved without an error: the disk ran low, the file is left for the next run
+            Ok(false) => {
+                not_started.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Ok(true) => true,
+            Err(problem) => {
+                console.problem(problem, &problems);
+                false
+            }
+        };
+        if moved {
+            console.moved(file_path, dest_path, size, shortened);
+            console.event(&Event::Move {
+                from: file_path,
+                to: dest_path,
+                bytes: size,
+                dry_run: options.dry_run,
+            });
+
             // .lock() acquires the mutex lock - blocks until we get exclusive access
             // .unwrap() extracts the MutexGuard or panics if the lock is poisoned
-            let mut map = stats_clone.lock().unwrap();
-            *map.entry(ext).or_insert(0) += 1;
+            let mut stats = stats_clone.lock().unwrap();
+            stats.add(ext, category.as_deref(), size);
+            drop(stats); // release the lock before the (slower) journal write
+            files.lock().unwrap().push(MovedFile {
+                from: file_path.clone(),
+                to: dest_path.clone(),
+                bytes: size,
+                category: category.clone(),
+            });
+            if shortened {
+                let warning = format!(
+                    "The name of {:?} was shortened to {:?}, the full path was too long",
+                    file_path,
+                    dest_path.file_name().unwrap_or_default()
+                );
+                console.warning(warning, &warnings);
+            }
+
+            // Before the journal records the file's modification time (which strip_gps keeps anyway)
+            if options.strip_exif_gps && !options.dry_run {
+                match gps::strip_gps(dest_path) {
+                    Ok(true) => {
+                        console.say(format!("Removed the GPS location from {:?}", dest_path))
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        let warning = format!(
+                            "Failed to remove the GPS location from {:?}: {}",
+                            dest_path, e
+                        );
+                        console.warning(warning, &warnings);
+                    }
+                }
+            }
+
+            if let Some(journal) = journal {
+                if let Err(e) = journal.record_move(file_path, dest_path, size, shortened) {
+                    let warning =
+                        format!("Failed to write journal entry for {:?}: {}", file_path, e);
+                    console.warning(warning, &warnings);
+                }
+
+                if options.stamp_xattr {
+                    if let Err(e) = journal::stamp(dest_path, journal.run_id) {
+                        if !stamp_failed.swap(true, Ordering::Relaxed) {
+                            let warning =
+                                format!("Failed to stamp run ID onto {:?}: {}", dest_path, e);
+                            console.warning(warning, &warnings);
+                        }
+                    }
+                }
+            }
         }
     });
 
-    // .lock().unwrap() - acquire the lock to read the final stats
-    let final_stats = stats.lock().unwrap();
-    println!("--- Organization Complete ---");
-    for (ext, count) in final_stats.iter() {
-        println!("[.{}] : {} files", ext, count);
+    console.finish();
+
+    // Nobody else holds the Arc anymore, so we can take the Stats out of it
+    let moved = Arc::try_unwrap(stats).unwrap().into_inner().unwrap();
+    Outcome {
+        moved,
+        trashed,
+        files: files.into_inner().unwrap(),
+        problems: problems.into_inner().unwrap(),
+        warnings: warnings.into_inner().unwrap(),
+        stopped_early: stop.load(Ordering::Relaxed),
+        out_of_time: out_of_time.load(Ordering::Relaxed),
+        not_started: not_started.into_inner(),
+        vanished: vanished.into_inner(),
+        late_conflicts: late_conflicts.into_inner(),
+        in_place: in_place.into_inner(),
     }
+}
 
+// Says why the run stopped before the end of the plan, if it did
+fn stopped_early(outcome: &Outcome, options: &Options) {
+    if outcome.out_of_time {
+        say!(
+            options,
+            "Stopped at the time limit: {} more files are left for the next run",
+            outcome.not_started
+        );
+    } else if outcome.stopped_early {
+        say!(options, "Stopped early because the destination ran low on free space (remaining files were left untouched)");
+    }
+}
+
+// Plan vs. result (see reconcile.rs). Dry runs move nothing, so there's nothing to compare.
+fn reconciled(estimate: &Estimate, outcome: &Outcome, options: &Options) {
+    if options.dry_run || (estimate.moves.files == 0 && estimate.trash == 0) {
+        return;
+    }
+    let reconciliation = Reconciliation::new(estimate, outcome);
+    if options.output == Output::Jsonl {
+        events::emit(&Event::Reconciliation {
+            planned: reconciliation.planned.files,
+            planned_bytes: reconciliation.planned.bytes,
+            moved: reconciliation.moved.files,
+            bytes: reconciliation.moved.bytes,
+            vanished: reconciliation.vanished,
+            late_conflicts: reconciliation.late_conflicts,
+            in_place: reconciliation.in_place,
+            not_started: reconciliation.not_started,
+            failed: reconciliation.failed,
+            resized: reconciliation.resized,
+        });
+    }
+    if reconciliation.as_planned() {
+        say!(options, "Everything went as planned");
+        return;
+    }
+    for line in reconciliation.lines() {
+        say!(options, "{}", line);
+    }
+}
+
+// Prints a warning to stderr (and with --output jsonl as an event too), and keeps it for the
+// Outcome. During the moves, Console::warning() does the same (see console.rs).
+fn report_warning(warning: String, warnings: &Mutex<Vec<String>>, options: &Options) {
+    eprintln!("Warning: {}", warning);
+    if options.output == Output::Jsonl {
+        events::emit(&Event::Warning { message: &warning });
+    }
+    warnings.lock().unwrap().push(warning);
+}
+
+// "2 warnings, 1 error" under the summary (nothing if there were neither)
+fn warnings_and_problems(outcome: &Outcome, options: &Options) {
+    if outcome.warnings.is_empty() && outcome.problems.is_empty() {
+        return;
+    }
+    say!(
+        options,
+        "{}, {}",
+        count(outcome.warnings.len(), "warning"),
+        count(outcome.problems.len(), "error")
+    );
+}
+
+fn count(n: usize, what: &str) -> String {
+    format!("{} {}{}", n, what, if n == 1 { "" } else { "s" })
+}
+
+// --warnings-as-errors: a run with warnings (or errors) fails, after it's done and recorded
+fn strict(outcome: &Outcome, options: &Options) -> Result<()> {
+    if options.warnings_as_errors && !(outcome.warnings.is_empty() && outcome.problems.is_empty()) {
+        bail!(
+            "The run had {} and {} (--warnings-as-errors)",
+            count(outcome.warnings.len(), "warning"),
+            count(outcome.problems.len(), "error")
+        );
+    }
     Ok(())
 }
 
-// Logic for a single file
-// Returns Some(extension) if file was moved, None if skipped
-pub fn organize_file(file_path: &Path, root: &Path, dry_run: bool) -> Option<String> {
-    // 1. Get the file extension
-    // If no extension -> We just skip it (return None)
-    let extension = match file_path.extension() {
-        Some(ext) => ext.to_string_lossy().to_lowercase(),
-        None => return None,
+// Name of the ignore files the scan honors (gitignore syntax), in root or any folder below it
+pub const IGNORE_FILE: &str = ".dircleanerignore";
+
+// Collects every file below root.
+// The walk itself runs on several threads (one directory listing per thread at a time), which
+// matters on huge trees and network drives where waiting for the file system dominates the runtime.
+// With --fast-scan the listings are all it reads: whether an entry is a file comes from the folder
+// listing instead of a stat() per entry, which on SMB/NFS is a round-trip to the server each.
+// Files matching the patterns in an ignore file are left alone, and so are the files in a folder
+// that matches. They use .gitignore syntax ("*.iso", "/keep-here.pdf", "Projects/", "!Projects/todo.txt")
+// and apply to the folder they're in and everything below it.
+pub(crate) fn scan(root: &Path, abs_root: &Path, options: &Options) -> Vec<PathBuf> {
+    scan_with_partials(root, abs_root, options, None).0
+}
+
+// scan(), and the unfinished copies of earlier runs it found (never organized, see is_partial()).
+// With `since`, only the files added or changed after that time (--since-last-run).
+fn scan_with_partials(
+    root: &Path,
+    abs_root: &Path,
+    options: &Options,
+    since: Option<SystemTime>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        // The `ignore` crate skips hidden and .gitignore'd files by default, we want everything
+        .standard_filters(false)
+        // ...except what our own ignore files exclude
+        .add_custom_ignore_filename(IGNORE_FILE)
+        // --respect-gitignore: also .gitignore, .git/info/exclude and the global git excludes,
+        // in git repositories and outside of them
+        .git_ignore(options.respect_gitignore)
+        .git_exclude(options.respect_gitignore)
+        .git_global(options.respect_gitignore)
+        .require_git(false)
+        // Like `find -xdev`: don't wander into mounted drives below root
+        .same_file_system(options.same_fs)
+        // 0 = let the walker pick
+        .threads(options.threads.map_or(0, NonZeroUsize::get))
+        // --path "dir/*.pdf" only needs the files right in dir, not the whole tree below it
+        .max_depth(options.matching.as_ref().and_then(target::depth));
+
+    // The filter runs on the walker's threads after this function returns, so it needs its own copies
+    let root_copy = root.to_path_buf();
+    let abs_root = abs_root.to_path_buf();
+    let allow_system = options.allow_system;
+    let output = options.output;
+    // The journals, when root is above where they're kept (organizing ~ or a --state-dir inside
+    // root): moving them would lose the runs they're the record of
+    let state_dir = paths::state_dir(options.state_dir.as_deref())
+        .map(|dir| protected::absolute_path(&dir).unwrap_or(dir));
+    builder.filter_entry(move |x| {
+        let root = &root_copy;
+        if !x.file_type().is_some_and(|t| t.is_dir()) {
+            return true;
+        }
+        // Our own journal folder is not something to organize
+        if x.depth() == 1 && x.file_name() == journal::STATE_DIR {
+            return false;
+        }
+        let abs_path = match x.path().strip_prefix(root) {
+            Ok(rel) => abs_root.join(rel),
+            Err(_) => return true,
+        };
+        if state_dir
+            .as_deref()
+            .is_some_and(|state| paths::starts_with(&abs_path, state))
+        {
+            return false;
+        }
+        // Don't descend into protected folders when pointed at a parent (~ has AppData or .config)
+        if !allow_system && protected::is_protected(&abs_path) {
+            if output == Output::Text {
+                println!("Skipping protected directory {:?}", x.path());
+            }
+            return false;
+        }
+        true
+    });
+
+    let files: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+    let partials: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+    // Files that match the pattern of --path, if it has one
+    let wanted = |file: &Path| match (&options.matching, file.strip_prefix(root)) {
+        (Some(matching), Ok(relative)) => matching.is_match(relative),
+        _ => true,
+    };
+
+    // run() calls the outer closure once per walker thread, and the returned visitor once per entry
+    builder.build_parallel().run(|| {
+        Box::new(|result| {
+            // Ignore errors (like permission denied), and folders: only look at files
+            if let Ok(x) = result {
+                let is_file = if options.fast_scan {
+                    // What the folder listing says; only a symlink needs a look at what it points to
+                    x.file_type()
+                        .is_some_and(|t| t.is_file() || (t.is_symlink() && x.path().is_file()))
+                } else {
+                    x.path().is_file()
+                };
+                if is_file && is_partial(x.path()) {
+                    partials.lock().unwrap().push(x.into_path());
+                } else if is_file && wanted(x.path()) && since.is_none_or(|t| changed_since(&x, t))
+                {
+                    files.lock().unwrap().push(x.into_path());
+                }
+            }
+            WalkState::Continue
+        })
+    });
+
+    // Threads finish in random order, sort so every run sees the files in the same order
+    let mut files = files.into_inner().unwrap();
+    files.sort();
+    let mut partials = partials.into_inner().unwrap();
+    partials.sort();
+    (files, partials)
+}
+
+// Was the file added to the folder, or changed, after `since`? A file moved in keeps its modification
+// time, but on Unix its inode change time (ctime) is the time of the move; elsewhere, the time a
+// copy was made is when it was created. Symlinks are asked about themselves.
+fn changed_since(entry: &ignore::DirEntry, since: SystemTime) -> bool {
+    let Ok(metadata) = entry.metadata() else {
+        // Better organized once too often than never
+        return true;
     };
+    let after = |time: io::Result<SystemTime>| time.is_ok_and(|time| time > since);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let changed = SystemTime::UNIX_EPOCH
+            + std::time::Duration::new(
+                metadata.ctime().max(0) as u64,
+                metadata.ctime_nsec() as u32,
+            );
+        if changed > since {
+            return true;
+        }
+    }
+    after(metadata.modified()) || after(metadata.created())
+}
 
-    // 2. Create the destination folder (e.g. "./Downloads/pdf")
-    let dest_folder = root.join(&extension);
+// Moves a single file to its destination (the destination folder already exists, and nothing is
+// at the destination itself: execute() checks that right before)
+// Returns true if the file was moved, false if the run was stopped, or an error message
+// (for the caller to print) if the file couldn't be moved
+pub fn organize_file(
+    file_path: &Path,
+    dest_path: &Path,
+    options: &Options,
+    stop: &AtomicBool,
+    throttle: Option<&Throttle>,
+    free_space: Option<&FreeSpace>,
+    console: &Console,
+) -> Result<bool, String> {
+    // A dry run only says what it would do (execute() prints the line)
+    if options.dry_run {
+        return Ok(true);
+    }
 
-    // 3. Create the full destination file path (e.g. "./Downloads/pdf/document.pdf")
-    // .file_name() returns Option<&OsStr>, we use ? to return None if it fails
-    let file_name = file_path.file_name()?;
-    let dest_path = dest_folder.join(file_name);
+    // Move the file (fixed: was using dest_folder instead of dest_path)
+    match fs::rename(file_path, dest_path) {
+        Ok(()) => {}
+        // rename() can't cross drives/mount points, so fall back to copy + delete
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let dest_folder = dest_path.parent().unwrap_or(dest_path);
+            // Held until the copy is done, so the other threads count these bytes as taken
+            let reservation = match free_space {
+                Some(free_space) => {
+                    match has_room_for(file_path, dest_folder, free_space, stop, console) {
+                        Some(reservation) => Some(reservation),
+                        None => return Ok(false),
+                    }
+                }
+                None => None,
+            };
+            let copied = copy_then_remove(file_path, dest_path, throttle);
+            drop(reservation);
+            if let Err(e) = copied {
+                return Err(format!("Failed to move {:?}: {}", file_path, e));
+            }
+        }
+        Err(e) => return Err(format!("Failed to move {:?}: {}", file_path, e)),
+    }
+    Ok(true)
+}
 
-    // 4. Don't move the file if it's already in the right place
-    if dest_path == file_path {
-        return None;
+// What create_dir_all would run into, without creating anything:
+// the nearest part of the path that already exists has to be a folder
+fn check_can_create_dir(path: &Path) -> io::Result<()> {
+    for ancestor in path.ancestors() {
+        // symlink_metadata, so a broken symlink counts as "something's there"
+        if fs::symlink_metadata(ancestor).is_ok() {
+            if ancestor.is_dir() {
+                return Ok(());
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} exists and is not a directory", ancestor),
+            ));
+        }
     }
+    Ok(())
+}
 
-    // 5. The Moving logic
-    if dry_run {
-        println!("[DRY RUN] Would move {:?} -> {:?}", file_path, dest_path);
-    } else {
-        // A. Create the directory if it doesn't exist
-        if let Err(e) = fs::create_dir_all(&dest_folder) {
-            eprintln!("Failed to create directory {:?}: {}", dest_folder, e);
-            return None;
+// Checks that copying `file_path` into `dest_folder` keeps at least --stop-if-free-below bytes free,
+// counting the copies other threads are still making. If not, it raises the stop flag so the run
+// ends gracefully instead of failing halfway through a copy.
+// Returns the file's reservation to hold during the copy, or None to stop
+fn has_room_for<'a>(
+    file_path: &Path,
+    dest_folder: &Path,
+    free_space: &'a FreeSpace,
+    stop: &AtomicBool,
+    console: &Console,
+) -> Option<Reservation<'a>> {
+    let file_size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+    let (free, in_flight) = match free_space.reserve(file_size, dest_folder) {
+        Room::Enough(reservation) => return Some(reservation),
+        Room::TooLittle { free, in_flight } => (free, in_flight),
+        Room::Unknown(reservation, e) => {
+            console.error(format!(
+                "Failed to check free space on {:?}: {}",
+                dest_folder, e
+            ));
+            return Some(reservation);
         }
+    };
 
-        // B. Move the file (fixed: was using dest_folder instead of dest_path)
-        if let Err(e) = fs::rename(file_path, &dest_path) {
-            eprintln!("Failed to move {:?}: {}", file_path, e);
-            return None;
+    // .swap() returns the old value, so only the first thread to notice prints the message
+    if !stop.swap(true, Ordering::Relaxed) {
+        let promised = match in_flight {
+            0 => String::new(),
+            n => format!(", {} of them for copies under way", n),
+        };
+        console.error(format!(
+            "Only {} bytes free on {:?}{} (threshold: {} bytes), stopping",
+            free,
+            dest_folder,
+            promised,
+            free_space.threshold()
+        ));
+    }
+    None
+}
+
+// The start of the temporary name a copy is written under until it's complete
+pub const PARTIAL_PREFIX: &str = ".dircleaner-partial-";
+
+// True for a copy that was never finished (a run was killed in the middle of it)
+pub fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with(PARTIAL_PREFIX))
+}
+
+// Cross-device move: copy the data, then delete the original.
+// The copy is written under a temporary name next to the destination (".dircleaner-partial-<id>")
+// and only gets the real name once all of it is on disk, so a crash or a pulled plug in the middle
+// of a copy can't leave a half file behind that passes for the real one. The next run removes
+// such leftovers (see remove_partials()). If the copy fails, the temporary file is removed at once.
+pub(crate) fn copy_then_remove(
+    file_path: &Path,
+    dest_path: &Path,
+    throttle: Option<&Throttle>,
+) -> io::Result<()> {
+    let partial =
+        dest_path.with_file_name(format!("{}{}", PARTIAL_PREFIX, Uuid::new_v4().simple()));
+    let copied = match throttle {
+        Some(throttle) => throttle.copy(file_path, &partial),
+        None => fs::copy(file_path, &partial),
+    }
+    // Otherwise the file system may write the rename out before the data
+    .and_then(|_| sync(&partial))
+    .and_then(|_| give_name(&partial, dest_path));
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::remove_file(file_path)
+}
+
+// Gives the finished copy its real name, but never over something that appeared there during the
+// copy (see execute()). rename() would replace it, so the name is added as a hard link, which
+// fails if it's taken, and the temporary one removed afterwards. File systems without hard links
+// (FAT, exFAT, some network shares) get a check right before the rename instead, which leaves a
+// moment for something to slip in between.
+fn give_name(partial: &Path, dest_path: &Path) -> io::Result<()> {
+    let appeared = || {
+        io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} appeared while the file was copied", dest_path),
+        )
+    };
+    match fs::hard_link(partial, dest_path) {
+        Ok(()) => {
+            // If this fails, the next run removes the extra name with the other leftovers
+            let _ = fs::remove_file(partial);
+            Ok(())
         }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(appeared()),
+        Err(_) => {
+            if fs::symlink_metadata(dest_path).is_ok() {
+                return Err(appeared());
+            }
+            fs::rename(partial, dest_path)
+        }
+    }
+}
 
-        println!("Moved {:?} -> {:?}", file_path, dest_path);
+// Waits until `path` is on disk
+fn sync(path: &Path) -> io::Result<()> {
+    match fs::OpenOptions::new().write(true).open(path) {
+        Ok(file) => file.sync_all(),
+        // A read-only copy (fs::copy carries the permission bits over): Unix syncs it through a
+        // read handle too, Windows can't, so there it's left to the OS
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            if cfg!(windows) {
+                Ok(())
+            } else {
+                fs::File::open(path)?.sync_all()
+            }
+        }
+        Err(e) => Err(e),
     }
+}
 
-    // .clone() creates a copy of the extension String so we can return it
-    // (the original `extension` would be dropped at end of function)
-    Some(extension.clone())
+// Removes the unfinished copies (see copy_then_remove()) the scan found; in a dry run it only
+// says so. A copy that can't be removed is a warning, the run goes on without it.
+fn remove_partials(partials: &[PathBuf], options: &Options, warnings: &Mutex<Vec<String>>) {
+    if partials.is_empty() {
+        return;
+    }
+    if options.dry_run {
+        say!(
+            options,
+            "[DRY RUN] Would remove {} unfinished copies an interrupted run left behind",
+            partials.len()
+        );
+        return;
+    }
+    for partial in partials {
+        match fs::remove_file(partial) {
+            Ok(()) => say!(
+                options,
+                "{}",
+                options.display.line(
+                    "Removed ",
+                    partial,
+                    ", an unfinished copy an interrupted run left behind"
+                )
+            ),
+            Err(e) => {
+                let warning = format!("Failed to remove the unfinished copy {:?}: {}", partial, e);
+                report_warning(warning, warnings, options);
+            }
+        }
+    }
 }
@@ -1,16 +1,235 @@
-mod args;
-mod logic;
-
-use anyhow::Result;
-use args::Cli;
+use anyhow::{bail, Result};
 use clap::Parser;
+use directory_cleaner::args::{Cli, Command, DedupeCommand, JournalCommand};
+use directory_cleaner::classify;
+use directory_cleaner::dedupe::{self, DedupeOptions, Keep};
+use directory_cleaner::display::{self, PathDisplay};
+use directory_cleaner::edit_plan;
+use directory_cleaner::journal;
+use directory_cleaner::locale;
+use directory_cleaner::locate;
+use directory_cleaner::logic;
+use directory_cleaner::owners::Owners;
+use directory_cleaner::plan;
+use directory_cleaner::query::{self, QueryOptions};
+use directory_cleaner::repair::{self, RepairOptions};
+use directory_cleaner::review::{self, ApplyOptions};
+use directory_cleaner::stats;
+use directory_cleaner::target;
+use directory_cleaner::undo::{self, UndoOptions};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
-    // Handle the Result - if it fails, convert error and propagate with ?
-    logic::process_directory(&args.path, args.dry_run)
-        .map_err(|_| anyhow::anyhow!("Failed to process directory"))?;
+    // Every parallel loop (moves, hashing, image decoding) runs on rayon's global pool
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.get())
+            .build_global()?;
+    }
+
+    if let Some(Command::Dedupe(dedupe_args)) = args.command {
+        if let Some(DedupeCommand::Apply(apply_args)) = dedupe_args.command {
+            let options = ApplyOptions {
+                dry_run: apply_args.dry_run,
+                yes: args.yes,
+            };
+            return review::apply(&apply_args.file, &options);
+        }
+
+        let options = DedupeOptions {
+            perceptual: dedupe_args.perceptual,
+            quick_hash: dedupe_args.quick_hash,
+            review: dedupe_args.review,
+            keep: dedupe_args
+                .prefer_root
+                .map(Keep::PreferRoot)
+                .or(dedupe_args.keep),
+            link: dedupe_args.link,
+            dry_run: dedupe_args.dry_run,
+            yes: args.yes,
+        };
+        let roots: Vec<&str> = dedupe_args.paths.iter().map(String::as_str).collect();
+        return dedupe::dedupe(&roots, &options);
+    }
+
+    if let Some(Command::Journal(journal_args)) = args.command {
+        let JournalCommand::Verify(verify_args) = journal_args.command;
+        return journal::verify_runs(
+            Path::new(&verify_args.path),
+            verify_args.run.as_deref(),
+            args.state_dir.as_deref(),
+        );
+    }
+
+    if let Some(Command::Merge(merge_args)) = args.command {
+        let options = logic::Options::builder()
+            .dry_run(merge_args.dry_run)
+            .yes(args.yes)
+            .warnings_as_errors(args.warnings_as_errors)
+            .display(PathDisplay {
+                width: display::terminal_width(),
+                full: args.full_paths,
+                ..PathDisplay::default()
+            })
+            .on_conflict(merge_args.on_conflict)
+            .on_duplicate(merge_args.on_duplicate)
+            .threads(args.threads)
+            .state_dir(args.state_dir)
+            .build_merge()?;
+        return logic::merge(&merge_args.src, &merge_args.dst, &options);
+    }
+
+    if let Some(Command::Query(query_args)) = args.command {
+        let options = QueryOptions {
+            run: query_args.run,
+            exts: query_args.exts,
+            patterns: query_args.patterns,
+            since: query_args.since,
+            state_dir: args.state_dir,
+        };
+        return query::query(&query_args.path, &options);
+    }
+
+    if let Some(Command::Locate(locate_args)) = args.command {
+        return locate::locate(
+            &locate_args.path,
+            &locate_args.name,
+            locate_args.hash,
+            args.state_dir.as_deref(),
+        );
+    }
+
+    if let Some(Command::Stats(stats_args)) = args.command {
+        return stats::stats(&stats_args.path, stats_args.peek_archives);
+    }
+
+    if let Some(Command::Repair(repair_args)) = args.command {
+        let options = RepairOptions {
+            dry_run: repair_args.dry_run,
+            state_dir: args.state_dir,
+        };
+        return repair::repair(&repair_args.path, &options).map(|_| ());
+    }
+
+    if let Some(Command::Undo(undo_args)) = args.command {
+        let options = UndoOptions {
+            run: undo_args.run,
+            exts: undo_args.exts,
+            patterns: undo_args.patterns,
+            since: undo_args.since,
+            force: undo_args.force,
+            dry_run: undo_args.dry_run,
+            state_dir: args.state_dir,
+        };
+        return undo::undo(&undo_args.path, &options);
+    }
+
+    // --report FORMAT PATH (clap makes sure there are two values)
+    let html_report = match args.report.as_slice() {
+        [] => None,
+        [format, path] if format == "html" => Some(PathBuf::from(path)),
+        [format, _] => bail!("Unknown report format {:?} (expected html)", format),
+        _ => unreachable!("--report takes two values"),
+    };
+
+    // clap makes sure --path is there when no subcommand is given
+    let target = target::parse(&args.path.expect("--path is required"))?;
+    let path = target.root;
+
+    let options = logic::Options::builder()
+        .dry_run(args.dry_run)
+        .yes(args.yes)
+        .warnings_as_errors(args.warnings_as_errors)
+        .display(PathDisplay {
+            width: display::terminal_width(),
+            full: args.full_paths,
+            ..PathDisplay::default()
+        })
+        .allow_system(args.allow_system)
+        .same_fs(args.same_fs)
+        .fast_scan(args.fast_scan)
+        .skip_if_in(args.skip_if_in)
+        .delete_if_duplicated(args.delete_if_duplicated)
+        .stop_if_free_below(args.stop_if_free_below)
+        .limit(args.limit)
+        .limit_bytes(args.limit_bytes)
+        .shuffle(args.shuffle)
+        // From now on: the time the scan and the plan take counts too. A duration too long for the
+        // clock is no deadline at all
+        .deadline(
+            args.max_duration
+                .and_then(|d| Instant::now().checked_add(d)),
+        )
+        .only_when_idle(args.only_when_idle)
+        .strip_exif_gps(args.strip_exif_gps)
+        .link_policy(args.link_policy)
+        .link_dirs(args.link_dirs)
+        .throttle(args.throttle)
+        .on_conflict(args.on_conflict)
+        .on_duplicate(args.on_duplicate)
+        .junk(args.junk)
+        .preserve_structure(args.preserve_structure)
+        .by(args.by)
+        .since_last_run(args.since_last_run)
+        .check_idempotent(args.check_idempotent)
+        .min_group_size(args.min_group_size)
+        .small_groups(args.small_groups)
+        .rules(plan::by_priority(args.rules))
+        .map(args.map)
+        .compound_extensions(args.compound_extensions)
+        .classifiers(
+            args.classifiers
+                .iter()
+                .map(|name| classify::find(name))
+                .collect::<Result<_>>()?,
+        )
+        .category_folders(args.category_folders)
+        .lang_folders(
+            args.lang
+                .and_then(|lang| lang.resolve(|name| std::env::var(name).ok()))
+                .map(locale::category_folders)
+                .unwrap_or_default(),
+        )
+        .adopt_existing(args.adopt_existing)
+        .into_user_dirs(args.into_user_dirs)
+        .preserve_dir_mtime(args.preserve_dir_mtime)
+        .owners(Owners::lookup(
+            args.owner.as_deref(),
+            args.group.as_deref(),
+        )?)
+        .stamp_xattr(args.stamp_xattr)
+        .threads(args.threads)
+        .respect_gitignore(args.respect_gitignore)
+        .advise(args.advise)
+        .html_report(html_report)
+        .output(args.output)
+        .aggregate_moves(args.aggregate_moves)
+        .edit_plan(args.edit_plan.then(edit_plan::editor))
+        .state_dir(args.state_dir)
+        .matching(target.matching)
+        .build()?;
+
+    if args.print_plan {
+        return logic::print_plan(&path, &options);
+    }
+    if !args.explain.is_empty() {
+        return logic::explain(&path, &args.explain, &options);
+    }
+    if let Some(count) = args.sample {
+        return logic::sample(&path, count, &options);
+    }
+    if args.audit {
+        return logic::audit(&path, &options);
+    }
+    if args.check_rules {
+        return logic::check_rules(&path, &options);
+    }
+
+    // Handle the Result - if it fails, the error is propagated with ?
+    logic::process_directory(&path, &options)?;
 
     Ok(())
 }
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Files that other programs have open right now
+// =============================================================================
+//
+// Moving a file out from under a running program (a download that's still being written, a
+// document open in an editor) can lose data, so the plan leaves such files where they are.
+//
+// Finding out is best effort:
+//   Linux:   every process's open files are listed in /proc/<pid>/fd (processes of other users
+//            can only be seen when running as root)
+//   Windows: an open file can't be moved anyway, the move fails with a sharing violation and is
+//            reported like any other failed move
+//   macOS and others: not checked
+
+// Open file (absolute, symlink-free path) -> the program that has it open, "firefox (pid 1234)"
+pub fn open_files() -> HashMap<PathBuf, String> {
+    let mut open = HashMap::new();
+    if !cfg!(target_os = "linux") {
+        return open;
+    }
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return open;
+    };
+
+    let own_pid = std::process::id().to_string();
+    for process in processes.flatten() {
+        let pid = process.file_name().to_string_lossy().into_owned();
+        // Only the numbered entries are processes, and our own open files don't count
+        if !pid.bytes().all(|b| b.is_ascii_digit()) || pid == own_pid {
+            continue;
+        }
+        // Fails for processes of other users, or ones that just ended
+        let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        let name = fs::read_to_string(process.path().join("comm"))
+            .map(|comm| comm.trim().to_string())
+            .unwrap_or_else(|_| "a program".to_string());
+        for fd in fds.flatten() {
+            // Each fd is a symlink to what it points to: a file, or "socket:[1234]", "pipe:[5678]"
+            if let Ok(target) = fs::read_link(fd.path()) {
+                if target.is_absolute() {
+                    open.entry(target)
+                        .or_insert_with(|| format!("{} (pid {})", name, pid));
+                }
+            }
+        }
+    }
+    open
+}
+
+// Who has `file` open, if anyone. `file` is what the scan found below `root`, `abs_root` the
+// absolute, symlink-free `root` (the paths in /proc are absolute and symlink-free too).
+pub fn opened_by<'a>(
+    open: &'a HashMap<PathBuf, String>,
+    file: &Path,
+    root: &Path,
+    abs_root: &Path,
+) -> Option<&'a str> {
+    let relative = file.strip_prefix(root).ok()?;
+    open.get(&abs_root.join(relative)).map(String::as_str)
+}
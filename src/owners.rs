@@ -0,0 +1,70 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+// =============================================================================
+// --owner and --group: only organize the files of one user or group (Unix)
+// =============================================================================
+//
+// On a shared server everyone's files can sit in the same folder. With --owner alice only
+// alice's files are moved; the others stay where they are, without a "permission denied" for
+// each of them. Names are looked up in /etc/passwd and /etc/group; numeric IDs work too
+// (for accounts that only exist in LDAP or similar).
+
+// Which files to organize: the ones whose owner and group match (None = any)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Owners {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl Owners {
+    // Looks up --owner and --group, None if neither was given
+    pub fn lookup(owner: Option<&str>, group: Option<&str>) -> Result<Option<Owners>> {
+        if owner.is_none() && group.is_none() {
+            return Ok(None);
+        }
+        if !cfg!(unix) {
+            bail!("--owner and --group only work on Unix");
+        }
+        Ok(Some(Owners {
+            uid: owner.map(|name| id_of(name, "/etc/passwd")).transpose()?,
+            gid: group.map(|name| id_of(name, "/etc/group")).transpose()?,
+        }))
+    }
+
+    // True if `file` belongs to the owner and group we're looking for (symlinks themselves, not
+    // what they point to). Files we can't look at don't.
+    #[cfg(unix)]
+    pub fn matches(&self, file: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let Ok(metadata) = fs::symlink_metadata(file) else {
+            return false;
+        };
+        self.uid.is_none_or(|uid| metadata.uid() == uid)
+            && self.gid.is_none_or(|gid| metadata.gid() == gid)
+    }
+
+    #[cfg(not(unix))]
+    pub fn matches(&self, _file: &Path) -> bool {
+        true
+    }
+}
+
+// The ID of user or group `name` ("alice" or "1001"), from `database` ("name:x:ID:...")
+fn id_of(name: &str, database: &str) -> Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    let text =
+        fs::read_to_string(database).with_context(|| format!("Failed to read {}", database))?;
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let mut fields = line.split(':');
+            let found = fields.next()? == name;
+            let id = fields.nth(1)?.parse().ok()?;
+            found.then_some(id)
+        })
+        .with_context(|| format!("Unknown user or group {:?} (not in {})", name, database))
+}
@@ -0,0 +1,184 @@
+use crate::journal::STATE_DIR;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf, Prefix, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+
+// =============================================================================
+// Where the tool keeps its own files, and how paths are compared
+// =============================================================================
+//
+// Journals live in the platform's folder for application state, not in the organized folder:
+//   Linux:   $XDG_STATE_HOME/directory-cleaner   (default ~/.local/state/directory-cleaner)
+//   macOS:   ~/Library/Application Support/directory-cleaner
+//   Windows: %APPDATA%\directory-cleaner
+// --state-dir (or DIRCLEANER_STATE_DIR) picks another folder. Each organized folder gets its own
+// subfolder there, named after it plus a hash of its full path ("Downloads-1b2c3d4e5f60").
+//
+// Older versions wrote journals to <root>/.directory-cleaner/journal. Those are still read
+// (undo finds them), but new runs don't add to them.
+//
+// Windows has more ways to write the same place than other systems: "C:/Users/me" and
+// "C:\Users\me", "c:\users\ME", "\\?\C:\Users\me", and shares as "\\server\share\folder" or
+// "//server/share/folder". Paths from the command line are put into one form up front
+// (normalize()), and whenever two paths have to be the same place, key() is compared instead of
+// the paths' text.
+
+const APP_NAME: &str = "directory-cleaner";
+
+// The state folder: `chosen` if given, else the platform's (None if it can't be found,
+// e.g. no home folder)
+pub fn state_dir(chosen: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = chosen {
+        return Some(dir.to_path_buf());
+    }
+    // Only absolute paths count, like the XDG spec says; a relative one would depend on the working directory
+    let absolute = |var: &str| {
+        env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+    };
+
+    let base = if cfg!(windows) {
+        absolute("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        absolute("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        absolute("XDG_STATE_HOME")
+            .or_else(|| absolute("HOME").map(|home| home.join(".local").join("state")))
+    };
+    base.map(|base| base.join(APP_NAME))
+}
+
+// Where the journals of the folder `abs_root` go
+// (inside the folder itself, the old place, if there's no state folder)
+pub fn journal_dir(abs_root: &Path, chosen: Option<&Path>) -> PathBuf {
+    match state_dir(chosen) {
+        Some(dir) => dir.join("journals").join(folder_key(abs_root)),
+        None => legacy_journal_dir(abs_root),
+    }
+}
+
+// Where older versions put the journals of `root`
+pub fn legacy_journal_dir(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join("journal")
+}
+
+// "/home/me/Downloads" -> "Downloads-1b2c3d4e5f60": readable, and different for every folder
+fn folder_key(abs_root: &Path) -> String {
+    let name = abs_root
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        // "/" or "C:\"
+        .unwrap_or_else(|| "root".to_string());
+    let hash = blake3::hash(abs_root.to_string_lossy().as_bytes()).to_hex();
+    format!("{}-{}", name, &hash[..12])
+}
+
+// A path given on the command line, in the form the rest of the tool works with. On Windows:
+//   "//server/share/inbox", "C:/Users/me"  -> "\\server\share\inbox", "C:\Users\me"
+//   "C:Downloads" (relative to the current folder of drive C:, not to C:\) -> "C:\Users\me\Downloads"
+// Everywhere else the path stays as it is.
+pub fn normalize(path: &str) -> String {
+    if !cfg!(windows) {
+        return path.to_string();
+    }
+    let path = path.replace('/', "\\");
+    if is_drive_relative(&path) {
+        if let Ok(absolute) = std::path::absolute(&path) {
+            return absolute.to_string_lossy().into_owned();
+        }
+    }
+    path
+}
+
+// "C:" or "C:Downloads": a drive letter without a backslash after it
+fn is_drive_relative(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && bytes.get(2) != Some(&b'\\')
+}
+
+// `path` the way the file system compares it: one kind of separator, "\\?\C:\" and "c:\" the
+// same as "C:\" (and "\\?\UNC\server\share" as "\\server\share"), and on the case-insensitive
+// file systems of Windows and macOS "PDF" the same as "pdf". "." and trailing separators are left
+// out and "a/b/../c" is "a/c", going by the text alone (without looking at the disk, so a ".."
+// after a symlinked folder isn't followed). Two paths with the same key are the same place.
+pub fn key(path: &Path) -> String {
+    let mut start = String::new();
+    let mut names: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => start.push_str(&prefix_key(prefix.kind())),
+            Component::RootDir => start.push(MAIN_SEPARATOR),
+            Component::CurDir => {}
+            // Above the root is the root ("/.." is "/"); at the start of a relative path it stays
+            Component::ParentDir => match names.last().map(String::as_str) {
+                Some(name) if name != ".." => {
+                    names.pop();
+                }
+                _ if start.ends_with(MAIN_SEPARATOR) => {}
+                _ => names.push("..".to_string()),
+            },
+            Component::Normal(name) => names.push(name.to_string_lossy().into_owned()),
+        }
+    }
+    // No separator between a prefix and a name: "C:foo" isn't "C:\foo"
+    let key = start + &names.join(MAIN_SEPARATOR_STR);
+    if cfg!(any(windows, target_os = "macos")) {
+        key.to_lowercase()
+    } else {
+        key
+    }
+}
+
+fn prefix_key(prefix: Prefix) -> String {
+    match prefix {
+        Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => {
+            format!("{}:", (drive as char).to_ascii_uppercase())
+        }
+        Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => format!(
+            r"\\{}\{}",
+            server.to_string_lossy(),
+            share.to_string_lossy()
+        ),
+        Prefix::DeviceNS(device) => format!(r"\\.\{}", device.to_string_lossy()),
+        Prefix::Verbatim(name) => format!(r"\\?\{}", name.to_string_lossy()),
+    }
+}
+
+// True if `a` and `b` are the same place (see key())
+pub fn same(a: &Path, b: &Path) -> bool {
+    key(a) == key(b)
+}
+
+// Path::starts_with, comparing the parts the way key() does
+pub fn starts_with(path: &Path, prefix: &Path) -> bool {
+    let mut parts = path.components();
+    prefix.components().all(|p| {
+        parts
+            .next()
+            .is_some_and(|c| same(Path::new(c.as_os_str()), Path::new(p.as_os_str())))
+    })
+}
+
+// True if `a` and `b` are the same file on disk, however they're written (through a symlinked
+// folder, with other case, ...). False if either isn't there.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => same(&a, &b),
+            _ => false,
+        }
+    }
+}
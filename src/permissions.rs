@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Files the current user isn't allowed to move
+// =============================================================================
+//
+// Moving a file takes away its name from one folder and gives it a name in another, so what the
+// file's own permissions say doesn't matter. What matters is the folders:
+//   - we have to be allowed to change the folder the file is in (write + enter it)
+//   - in a "sticky" folder (like /tmp, mode 1777) only the owner of a file can move it away
+//   - we have to be allowed to change the destination folder (or, if it doesn't exist yet, the
+//     folder it will be created in)
+// On a shared server that rules out other people's files, and checking up front turns them into
+// skipped files in the plan instead of one "permission denied" after another mid-run.
+//
+// Only checked on Unix; elsewhere every file counts as movable and a failed move is reported as usual.
+//
+// On a network drive every question to the system is a round-trip to the server, so each folder
+// is asked about once, and a file itself is only looked at in a sticky folder (the only place
+// where its owner matters).
+
+// Asks the system about each folder once, however many files are in it
+#[derive(Debug, Default)]
+pub struct Permissions {
+    writable: HashMap<PathBuf, bool>,
+    // Folder -> its owner if it's sticky (None if it isn't)
+    sticky: HashMap<PathBuf, Option<u32>>,
+    // Destination folder -> the closest folder above it (or itself) that exists
+    existing: HashMap<PathBuf, PathBuf>,
+}
+
+impl Permissions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Why we can't move `file` to `to` (None = we can). `to` is None for the trash, which is
+    // the system's business.
+    #[cfg(unix)]
+    pub fn why_not(&mut self, file: &Path, to: Option<&Path>) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let folder = folder_of(file);
+        if !self.can_write(folder) {
+            return Some(format!("you can't change its folder {:?}", folder));
+        }
+        if let Some(folder_owner) = self.sticky_owner(folder) {
+            let me = rustix::process::geteuid().as_raw();
+            // root may move anything, even in a sticky folder
+            if me != 0 && folder_owner != me {
+                if let Ok(file_meta) = std::fs::symlink_metadata(file) {
+                    if file_meta.uid() != me {
+                        return Some(format!(
+                            "it belongs to user {} and its folder {:?} is sticky",
+                            file_meta.uid(),
+                            folder
+                        ));
+                    }
+                }
+            }
+        }
+        let destination = self.existing_ancestor(folder_of(to?));
+        if !self.can_write(&destination) {
+            return Some(format!(
+                "you can't change the destination {:?}",
+                destination
+            ));
+        }
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn why_not(&mut self, _file: &Path, _to: Option<&Path>) -> Option<String> {
+        None
+    }
+
+    // The owner of `folder` if it's sticky
+    #[cfg(unix)]
+    fn sticky_owner(&mut self, folder: &Path) -> Option<u32> {
+        use std::os::unix::fs::MetadataExt;
+        *self.sticky.entry(folder.to_path_buf()).or_insert_with(|| {
+            std::fs::metadata(folder)
+                .ok()
+                .filter(|meta| meta.mode() & 0o1000 != 0)
+                .map(|meta| meta.uid())
+        })
+    }
+
+    // `folder` itself if it exists, else the closest folder above it that does (where the missing
+    // folders will be created)
+    #[cfg(unix)]
+    fn existing_ancestor(&mut self, folder: &Path) -> PathBuf {
+        self.existing
+            .entry(folder.to_path_buf())
+            .or_insert_with(|| {
+                folder
+                    .ancestors()
+                    .find(|dir| dir.is_dir())
+                    // "pdf" relative to the working directory, and it doesn't exist yet
+                    .unwrap_or(Path::new("."))
+                    .to_path_buf()
+            })
+            .clone()
+    }
+
+    // May we add and remove names in `folder`?
+    #[cfg(unix)]
+    fn can_write(&mut self, folder: &Path) -> bool {
+        use rustix::fs::Access;
+        *self
+            .writable
+            .entry(folder.to_path_buf())
+            .or_insert_with(|| {
+                // access() asks the kernel, so ACLs, read-only mounts and root count too
+                rustix::fs::access(folder, Access::WRITE_OK | Access::EXEC_OK).is_ok()
+            })
+    }
+}
+
+// The folder `path` is in ("a.pdf" -> "." rather than "")
+#[cfg(unix)]
+fn folder_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
@@ -0,0 +1,1254 @@
+use crate::category;
+use crate::duplicate;
+use crate::language;
+use crate::logic::Options;
+use crate::paths;
+use globset::GlobMatcher;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// The planner: decides WHAT should happen, without touching the file system.
+// =============================================================================
+//
+// Every run is split into two phases:
+//   1. plan()    - turns the list of scanned files into a list of PlannedActions (pure, no IO)
+//   2. execute() - in logic.rs, carries those actions out (or just prints them in a dry run)
+//
+// Because plan() only looks at its inputs, it can be tested with made-up paths
+// that don't exist on disk. The exceptions are --on-duplicate, which has to compare file
+// contents, --by language, which reads the "#!" line of scripts without an extension, and
+// --classifier, whose programs are asked where files go (see classify.rs), and destinations that
+// aren't in the scan, which are looked up on disk (an ignored file, a folder outside the root can
+// be there already). plan_with() asks those questions through the Contents trait, so tests can
+// fake the answers.
+
+// What to do when a file's destination is already taken, either by a file that's
+// already there or by another file of this run (two "report.pdf"s from different subfolders)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConflictStrategy {
+    // Keep both: "report.pdf" becomes "report (1).pdf", "report (2).pdf", ...
+    #[default]
+    Rename,
+    // Leave the file where it is
+    Skip,
+}
+
+// What to do when the file that already has the destination name is byte-for-byte the same file
+// (checked before ConflictStrategy, and only if this isn't KeepBoth)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuplicateStrategy {
+    // Don't look at contents, handle it like any other conflict
+    #[default]
+    KeepBoth,
+    // Leave the duplicate where it is
+    Skip,
+    // Move the duplicate to the system trash (the copy at the destination stays)
+    TrashSource,
+}
+
+// What to do with junk: temporary, backup and lock files, mail attachment leftovers
+// (category::JUNK_PATTERNS)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum JunkStrategy {
+    // All of it goes into one Temp/ folder, instead of tmp/, bak/, ...
+    #[default]
+    Temp,
+    // Move it to the system trash
+    Trash,
+    // No special treatment, organize it like any other file
+    Organize,
+}
+
+// Where the files of a --by folder that's too small for --min-group-size go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SmallGroups {
+    // Together into one Misc/ folder
+    #[default]
+    Misc,
+    // Nowhere, they're left where they are
+    Stay,
+}
+
+// The folder SmallGroups::Misc collects small groups in
+pub const MISC_FOLDER: &str = "Misc";
+
+// How files are grouped into folders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Grouping {
+    // One folder per extension: pdf/, jpg/, ...
+    #[default]
+    Extension,
+    // Built-in categories: Documents/, Images/, ... (extensions without one keep their own folder)
+    Category,
+    // Source code by programming language: Python/, Rust/, Shell/, ... (other files by extension)
+    Language,
+    // The first half of the file's MIME type: image/, video/, text/, application/, ...
+    // (from the extension; unknown extensions keep their own folder)
+    Mime,
+}
+
+// A --map rule: files with these (lowercase) extensions go into `folder`, whatever --by says
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub exts: Vec<String>,
+    // Relative to the root, may have subfolders ("Documents/Work")
+    pub folder: String,
+}
+
+// A --rule: files whose name matches `glob` (or their path below the root, for a pattern with a
+// "/" in it) go into `folder`. The first rule that matches wins, so more specific rules go first:
+//   --rule "*invoice*.pdf=Finance" --rule "*.pdf=Documents"
+// The CLI puts them in the order of their priorities ("10:*invoice*.pdf=Finance"), highest first.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    // The higher, the earlier it's tried (0 if none was given)
+    pub priority: i32,
+    // The pattern as it was given, for messages
+    pub glob: String,
+    pub matcher: GlobMatcher,
+    // Relative to the root, like Mapping::folder
+    pub folder: String,
+}
+
+impl Rule {
+    pub fn matches(&self, file_path: &Path, root: &Path) -> bool {
+        if self.glob.contains('/') {
+            file_path
+                .strip_prefix(root)
+                .is_ok_and(|relative| self.matcher.is_match(relative))
+        } else {
+            file_path
+                .file_name()
+                .is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+}
+
+// `rules` in the order they're tried: by priority, highest first, and in the order given otherwise
+pub fn by_priority(mut rules: Vec<Rule>) -> Vec<Rule> {
+    // A stable sort, so rules of the same priority keep their order
+    rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+    rules
+}
+
+// The first --rule of options.rules that matches the file, and its number (0-based)
+pub fn rule_for<'a>(
+    file_path: &Path,
+    root: &Path,
+    options: &'a Options,
+) -> Option<(usize, &'a Rule)> {
+    options
+        .rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.matches(file_path, root))
+}
+
+// A --category-folder rule: files of a built-in category (or junk, "temp") go into `folder` instead
+// of the category's own folder. The category itself doesn't change, only where it's put.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryFolder {
+    // "images", "documents", ... or "temp"
+    pub category: String,
+    // Relative to the root, like Mapping::folder
+    pub folder: String,
+}
+
+// What the planner may need to know about the contents of files
+pub trait Contents {
+    // Are these two files byte-for-byte the same? (only asked for --on-duplicate)
+    fn same(&self, a: &Path, b: &Path) -> bool;
+    // The program named in the file's "#!" line, e.g. "python3" (only asked for --by language,
+    // and only about files without an extension)
+    fn interpreter(&self, file: &Path) -> Option<String>;
+    // Where an external classifier (--classifier) sends the file, if one has an answer for it
+    fn classification(&self, _file: &Path) -> Option<Classification> {
+        None
+    }
+    // Is there something else than `file` at `path` already? Only asked about destinations the
+    // scan didn't see (files left out by an ignore file or a --path pattern, folders outside the
+    // root), so a preview shows the same renames and duplicates as the run will
+    fn occupied(&self, _path: &Path, _file: &Path) -> bool {
+        false
+    }
+    // How many files are right in `folder` already (only asked for --min-group-size, about folders
+    // --by would make), so a group counts the ones the scan left out too
+    fn files_in(&self, _folder: &Path) -> usize {
+        0
+    }
+}
+
+// The answer of an external classifier for one file (see classify.rs)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    // Relative to the root ("Invoices", "Invoices/2024"), checked to stay inside it
+    pub folder: String,
+    // Counted under this in the summary, if given
+    pub category: Option<String>,
+    // Which classifier said it ("invoices" for dircleaner-classify-invoices)
+    pub by: String,
+}
+
+// The real answers, from the files on disk
+pub struct Disk;
+
+impl Contents for Disk {
+    fn same(&self, a: &Path, b: &Path) -> bool {
+        duplicate::same_content(a, b)
+    }
+
+    fn interpreter(&self, file: &Path) -> Option<String> {
+        language::interpreter(file)
+    }
+
+    // symlink_metadata, so a broken symlink counts as "something's there". The file itself, under
+    // another name (a symlinked folder), doesn't: it's where it belongs already (see execute()).
+    fn occupied(&self, path: &Path, file: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok() && !paths::same_file(file, path)
+    }
+
+    fn files_in(&self, folder: &Path) -> usize {
+        fs::read_dir(folder).map_or(0, |entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|t| !t.is_dir()))
+                .count()
+        })
+    }
+}
+
+// A single step of a run (--print-plan prints them as JSON, tagged with "action": "move", ...)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    // Make sure this destination folder exists (e.g. "./Downloads/pdf")
+    CreateDir {
+        path: PathBuf,
+    },
+    // Move a file into its destination folder
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+        ext: String,
+        // Set when grouping by category or language (and the file has one)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        category: Option<String>,
+        // The file name was shortened to fit the path length limits (see fit())
+        shortened: bool,
+    },
+    // A file we would have moved, but can't (and why)
+    Skip {
+        path: PathBuf,
+        reason: String,
+    },
+    // A file to move to the system trash: a duplicate (--on-duplicate trash-source) or junk (--junk trash)
+    Trash {
+        path: PathBuf,
+        // For a duplicate, the identical file that is kept
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duplicate_of: Option<PathBuf>,
+    },
+}
+
+// Where a single file should go
+pub struct Destination {
+    pub ext: String,
+    pub category: Option<String>,
+    pub path: PathBuf,
+}
+
+// Works out where a single file belongs
+// Returns None if the file is skipped (no extension, or already in the right place)
+pub fn destination_for(file_path: &Path, root: &Path, options: &Options) -> Option<Destination> {
+    // 1. Get the file extension
+    // If no extension -> We just skip it (return None)
+    let extension = extension_of(file_path, options)?;
+
+    // --map wins over --by
+    if let Some(folder) = mapped_folder(file_path, options) {
+        return Some(Destination {
+            ext: extension,
+            category: (options.by != Grouping::Extension).then(|| folder.to_string()),
+            path: place(file_path, root, folder, options.preserve_structure)?,
+        });
+    }
+
+    // 2. The folder --by gives it (e.g. "pdf", or "Documents" by category)
+    let group = group_of(file_path, options)?;
+
+    // 3. The full destination file path (e.g. "./Downloads/pdf/document.pdf"),
+    // None if the file is already in the right place
+    let dest_path = place(file_path, root, &group.folder, options.preserve_structure)?;
+
+    Some(Destination {
+        ext: group.ext,
+        category: group.category,
+        path: dest_path,
+    })
+}
+
+// The folder --by gives a file, before --min-group-size looks at how many files it gets
+pub struct Group {
+    pub ext: String,
+    // The category or language, if the file has one (like Destination::category)
+    pub category: Option<String>,
+    // "pdf", or "Documents" by category
+    pub folder: String,
+}
+
+// The --by folder of a file (--map isn't looked at). None if it has no extension.
+pub fn group_of(file_path: &Path, options: &Options) -> Option<Group> {
+    let extension = extension_of(file_path, options)?;
+
+    // (name, folder) of the file's category or language, if it has one
+    let group = match options.by {
+        Grouping::Extension => None,
+        Grouping::Category => category::for_extension(&extension)
+            .map(|c| (c.name, category_folder(c.name, c.folder, options))),
+        Grouping::Language => language::for_extension(&extension).map(|l| (l.name, l.folder)),
+        // "image/png" -> "image"
+        Grouping::Mime => mime_guess::from_ext(&extension)
+            .first_raw()
+            .and_then(|mime| mime.split('/').next())
+            .map(|supertype| (supertype, supertype)),
+    };
+
+    let folder = match group {
+        Some((_, folder)) => folder,
+        None => &extension,
+    };
+
+    Some(Group {
+        folder: folder.to_string(),
+        category: group.map(|(name, _)| name.to_string()),
+        ext: extension,
+    })
+}
+
+// The folder a --map rule sends this file to, if one does (the last matching --map wins)
+fn mapped_folder<'a>(file_path: &Path, options: &'a Options) -> Option<&'a str> {
+    let extension = extension_of(file_path, options)?;
+    options
+        .map
+        .iter()
+        .rev()
+        .find(|mapping| mapping.exts.contains(&extension))
+        .map(|mapping| mapping.folder.as_str())
+}
+
+// The (lowercase) extension of a file: "pdf" for "report.pdf", and both parts for a name that ends
+// in a compound extension (category::COMPOUND_EXTENSIONS, --compound-ext): "tar.gz" for
+// "backup.tar.gz". None if the name has no extension.
+pub fn extension_of(file_path: &Path, options: &Options) -> Option<String> {
+    let extension = file_path.extension()?.to_string_lossy().to_lowercase();
+    let name = file_path.file_name()?.to_string_lossy().to_lowercase();
+    let compound = category::COMPOUND_EXTENSIONS
+        .iter()
+        .copied()
+        .chain(options.compound_extensions.iter().map(String::as_str))
+        // Something has to come before it: ".tar.gz" alone is a hidden "gz" file
+        .filter(|compound| {
+            name.strip_suffix(compound)
+                .and_then(|stem| stem.strip_suffix('.'))
+                .is_some_and(|stem| !stem.is_empty())
+        })
+        .max_by_key(|compound| compound.len());
+    Some(compound.map_or(extension, str::to_string))
+}
+
+// The folder of category `name`: `default` unless a --category-folder rule renames it (the last one
+// wins), or --lang has another name for it
+fn category_folder<'a>(name: &str, default: &'a str, options: &'a Options) -> &'a str {
+    options
+        .category_folders
+        .iter()
+        .rev()
+        .chain(&options.lang_folders)
+        .find(|rule| rule.category == name)
+        .map_or(default, |rule| rule.folder.as_str())
+}
+
+// Where a file that belongs in `folder` ends up, or None if it's already there:
+//   "./Downloads/sub/dir/report.pdf" -> "./Downloads/pdf/report.pdf"
+//   with --preserve-structure        -> "./Downloads/pdf/sub/dir/report.pdf"
+fn place(file_path: &Path, root: &Path, folder: &str, preserve_structure: bool) -> Option<PathBuf> {
+    let dest_folder = root.join(folder);
+    // .file_name() returns Option<&OsStr>, we use ? to return None if it fails
+    let file_name = file_path.file_name()?;
+
+    let dest_path = if preserve_structure {
+        let relative = file_path.strip_prefix(root).ok()?;
+        // Organized by an earlier run: "pdf/sub/dir/report.pdf" is where it belongs
+        if paths::starts_with(relative, Path::new(folder)) {
+            return None;
+        }
+        dest_folder.join(relative)
+    } else {
+        dest_folder.join(file_name)
+    };
+
+    // Not just the same text: on Windows and macOS "PDF\report.pdf" is already in "pdf"
+    if paths::same(&dest_path, file_path) {
+        return None;
+    }
+    Some(dest_path)
+}
+
+// Where a file goes that a --rule sends somewhere
+fn ruled_destination(
+    file_path: &Path,
+    root: &Path,
+    options: &Options,
+    rule: &Rule,
+) -> Option<Destination> {
+    Some(Destination {
+        ext: extension_of(file_path, options).unwrap_or_default(),
+        category: (options.by != Grouping::Extension).then(|| rule.folder.clone()),
+        path: place(file_path, root, &rule.folder, options.preserve_structure)?,
+    })
+}
+
+// Where a file goes that a classifier has an answer for
+fn classified_destination(
+    file_path: &Path,
+    root: &Path,
+    options: &Options,
+    classification: Classification,
+) -> Option<Destination> {
+    let dest_path = place(
+        file_path,
+        root,
+        &classification.folder,
+        options.preserve_structure,
+    )?;
+    Some(Destination {
+        ext: extension_of(file_path, options).unwrap_or_default(),
+        category: classification.category,
+        path: dest_path,
+    })
+}
+
+// Where a script without an extension goes with --by language, going by its "#!" line
+// ("./Downloads/backup" starting with "#!/bin/bash" -> "./Downloads/Shell/backup")
+fn script_destination(
+    file_path: &Path,
+    root: &Path,
+    options: &Options,
+    contents: &impl Contents,
+) -> Option<Destination> {
+    let language = language::for_interpreter(&contents.interpreter(file_path)?)?;
+    let dest_path = place(file_path, root, language.folder, options.preserve_structure)?;
+    Some(Destination {
+        ext: String::new(),
+        category: Some(language.name.to_string()),
+        path: dest_path,
+    })
+}
+
+// Where junk goes with --junk temp: "./Downloads/Temp/~$report.docx"
+// Returns None if the file is already there
+fn junk_destination(file_path: &Path, root: &Path, options: &Options) -> Option<Destination> {
+    let dest_path = place(
+        file_path,
+        root,
+        category_folder(category::JUNK_CATEGORY, category::JUNK_FOLDER, options),
+        options.preserve_structure,
+    )?;
+    Some(Destination {
+        ext: extension_of(file_path, options).unwrap_or_default(),
+        category: (options.by != Grouping::Extension).then(|| category::JUNK_CATEGORY.to_string()),
+        path: dest_path,
+    })
+}
+
+// The rules plan() goes through for one file, in order, one readable line each (for --explain).
+// Whether the destination is free depends on the other files too, so that part is left to the plan.
+pub fn explain(
+    file_path: &Path,
+    root: &Path,
+    options: &Options,
+    contents: &impl Contents,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let extension = extension_of(file_path, options);
+    lines.push(match &extension {
+        Some(ext) => format!("extension: {:?}", ext),
+        None => "no extension".to_string(),
+    });
+
+    // 1. --rule, the first matching one wins (and then nothing else is looked at)
+    let ruled = rule_for(file_path, root, options);
+    for (i, rule) in options.rules.iter().enumerate() {
+        let state = match ruled {
+            Some((winner, _)) if i == winner => "matches",
+            Some((winner, _)) if i > winner => "not tried, an earlier rule matched",
+            _ => "no match",
+        };
+        lines.push(format!(
+            "--rule {}:{}={}: {}",
+            rule.priority, rule.glob, rule.folder, state
+        ));
+    }
+    if let Some((_, rule)) = ruled {
+        lines.push(format!("decision by --rule: folder {}/", rule.folder));
+        match ruled_destination(file_path, root, options, rule) {
+            Some(dest) => lines.push(format!("destination: {:?}", dest.path)),
+            None => lines.push("destination: none, it's already in the right place".to_string()),
+        }
+        return lines;
+    }
+
+    // 2. --map rules, the last matching one wins
+    for mapping in &options.map {
+        let matches = extension
+            .as_ref()
+            .is_some_and(|ext| mapping.exts.contains(ext));
+        lines.push(format!(
+            "--map {}={}: {}",
+            mapping.exts.join(","),
+            mapping.folder,
+            if matches { "matches" } else { "no match" }
+        ));
+    }
+    let mapped = mapped_folder(file_path, options);
+
+    // 3. Junk patterns
+    let is_junk = file_path.file_name().is_some_and(category::is_junk);
+    let junk = match options.junk {
+        JunkStrategy::Organize => {
+            lines.push("junk patterns: not checked (--junk organize)".to_string());
+            false
+        }
+        _ if is_junk && mapped.is_some() => {
+            lines.push("junk patterns: match, but a --map rule comes first".to_string());
+            false
+        }
+        strategy => {
+            let name = match strategy {
+                JunkStrategy::Trash => "trash",
+                _ => "temp",
+            };
+            lines.push(format!(
+                "junk patterns (--junk {}): {}",
+                name,
+                if is_junk { "match" } else { "no match" }
+            ));
+            is_junk
+        }
+    };
+
+    // 4. External classifiers, in the order of --classifier
+    let classified = if mapped.is_none() && !junk {
+        contents.classification(file_path)
+    } else {
+        None
+    };
+    if !options.classifiers.is_empty() && mapped.is_none() && !junk {
+        lines.push(match &classified {
+            Some(c) => format!("--classifier {}: {}/", c.by, c.folder),
+            None => "--classifier: no answer".to_string(),
+        });
+    }
+
+    // 5. --by (only if nothing above decided already)
+    if mapped.is_none() && !junk && classified.is_none() {
+        lines.push(match (options.by, &extension) {
+            (Grouping::Extension, Some(ext)) => format!("--by extension: folder {}/", ext),
+            (Grouping::Category, Some(ext)) => match category::for_extension(ext) {
+                Some(c) => format!(
+                    "--by category: {} -> {}/",
+                    c.name,
+                    category_folder(c.name, c.folder, options)
+                ),
+                None => format!("--by category: no category, folder {}/", ext),
+            },
+            (Grouping::Language, Some(ext)) => match language::for_extension(ext) {
+                Some(l) => format!("--by language: {} -> {}/", l.name, l.folder),
+                None => format!("--by language: not source code, folder {}/", ext),
+            },
+            (Grouping::Language, None) => match contents.interpreter(file_path) {
+                Some(program) => match language::for_interpreter(&program) {
+                    Some(l) => format!(
+                        "--by language: \"#!\" line runs {} -> {}/",
+                        program, l.folder
+                    ),
+                    None => format!(
+                        "--by language: \"#!\" line runs {}, not a known language",
+                        program
+                    ),
+                },
+                None => "--by language: no \"#!\" line, the file stays".to_string(),
+            },
+            (Grouping::Mime, Some(ext)) => match mime_guess::from_ext(ext).first_raw() {
+                Some(mime) => format!(
+                    "--by mime: {} -> {}/",
+                    mime,
+                    mime.split('/').next().unwrap_or(mime)
+                ),
+                None => format!("--by mime: unknown MIME type, folder {}/", ext),
+            },
+            (_, None) => "no extension: the file stays".to_string(),
+        });
+    }
+    if let Some(min) = options.min_group_size {
+        if mapped.is_none() && !junk && classified.is_none() && extension.is_some() {
+            lines.push(format!(
+                "--min-group-size {}: {} if fewer files get that folder (see the decision)",
+                min,
+                match options.small_groups {
+                    SmallGroups::Misc => format!("into {}/ instead", MISC_FOLDER),
+                    SmallGroups::Stay => "the file stays".to_string(),
+                }
+            ));
+        }
+    }
+    if options.preserve_structure {
+        lines.push("--preserve-structure: subfolders are kept below the new folder".to_string());
+    }
+
+    // The destination, worked out exactly like plan_with() does
+    let dest = match (junk, options.junk) {
+        (true, JunkStrategy::Trash) => {
+            lines.push("destination: the system trash".to_string());
+            return lines;
+        }
+        (true, _) => junk_destination(file_path, root, options),
+        (false, _) if classified.is_some() => {
+            classified.and_then(|c| classified_destination(file_path, root, options, c))
+        }
+        (false, _) if options.by == Grouping::Language && extension.is_none() => {
+            script_destination(file_path, root, options, contents)
+        }
+        (false, _) => destination_for(file_path, root, options),
+    };
+    match dest {
+        Some(dest) => lines.push(format!("destination: {:?}", dest.path)),
+        None if extension.is_some() || junk => {
+            lines.push("destination: none, it's already in the right place".to_string())
+        }
+        None => {}
+    }
+    lines
+}
+
+// "report.pdf" -> "report (1).pdf" (n = 1), and "backup.tar.gz" -> "backup (1).tar.gz"
+fn numbered(dest_path: &Path, n: usize, options: &Options) -> PathBuf {
+    let name = dest_path.file_name().unwrap_or_default().to_string_lossy();
+    // The last dot, or for a compound extension the one before all of its parts
+    let dots = extension_of(dest_path, options).map_or(0, |ext| ext.matches('.').count() + 1);
+    let name = match dots
+        .checked_sub(1)
+        .and_then(|skip| name.rmatch_indices('.').nth(skip))
+    {
+        Some((at, _)) => format!("{} ({}){}", &name[..at], n, &name[at..]),
+        None => format!("{} ({})", name, n),
+    };
+    dest_path.with_file_name(name)
+}
+
+// How long a path and a single file name may get. Windows counts UTF-16 units and most other
+// systems bytes; counting UTF-8 bytes everywhere errs on the safe side.
+// (Windows' MAX_PATH is 260 and Unix' PATH_MAX 4096, both including a terminating NUL.)
+const MAX_PATH_LEN: usize = if cfg!(windows) { 259 } else { 4095 };
+const MAX_NAME_LEN: usize = 255;
+
+// Length of the hash fit() puts into shortened names: "~" + 8 hex digits
+const HASH_SUFFIX_LEN: usize = 9;
+
+// Makes `dest_path` short enough for the file system, or None if even its folder is too long.
+// --preserve-structure (and "report (1).pdf" counting) can make a destination longer than the OS
+// allows, which would only fail at rename time. Instead the name's stem is cut off and a short hash
+// of the full name is added, so names that are cut the same way still come out different:
+//   "a very very ... long report.pdf" -> "a very very ... lo~1b2c3d4e.pdf"
+// Returns the path and whether it was shortened.
+fn fit(dest_path: &Path) -> Option<(PathBuf, bool)> {
+    let name = dest_path.file_name()?.to_string_lossy();
+    let path_len = dest_path.as_os_str().to_string_lossy().len();
+    if name.len() <= MAX_NAME_LEN && path_len <= MAX_PATH_LEN {
+        return Some((dest_path.to_path_buf(), false));
+    }
+
+    // How much of the path is left for the name
+    let budget = MAX_NAME_LEN.min(MAX_PATH_LEN.checked_sub(path_len - name.len())?);
+    // Keep a short extension, so the file still opens with the right program
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() <= 16 => (stem, format!(".{}", ext)),
+        _ => (name.as_ref(), String::new()),
+    };
+    let room = budget.checked_sub(HASH_SUFFIX_LEN + ext.len())?;
+    if room == 0 {
+        return None;
+    }
+    // Cut at a character boundary, not in the middle of "é"
+    let mut cut = room.min(stem.len());
+    while !stem.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let hash = blake3::hash(name.as_bytes()).to_hex();
+    let short = format!("{}~{}{}", &stem[..cut], &hash[..HASH_SUFFIX_LEN - 1], ext);
+    Some((dest_path.with_file_name(short), true))
+}
+
+// Builds the full plan for a run: first every CreateDir, then the Moves (and Skips)
+// `files` is the output of the scan (sorted), `root` the folder being organized.
+// Files are handled in the order given, so when two of them want the same destination
+// the first one gets it, and the result is the same on every run.
+pub fn plan(files: &[PathBuf], root: &Path, options: &Options) -> Vec<PlannedAction> {
+    plan_with(files, root, options, &Disk)
+}
+
+// plan(), asking `contents` whenever it needs to know what's inside a file
+pub fn plan_with(
+    files: &[PathBuf],
+    root: &Path,
+    options: &Options,
+    contents: &impl Contents,
+) -> Vec<PlannedAction> {
+    // Junk for the trash (--junk trash), it gets no destination
+    let mut junk_for_trash = Vec::new();
+    // The files that go by --by, with their folder. Where they go is only known once every file was
+    // looked at: with --min-group-size, that depends on how many others get the same folder.
+    let mut grouped: Vec<(&PathBuf, Group)> = Vec::new();
+    let mut moves: Vec<(&PathBuf, Destination)> = files
+        .iter()
+        .filter_map(|file_path| {
+            // A --rule names the files it's for, so it comes before everything else
+            if let Some((_, rule)) = rule_for(file_path, root, options) {
+                return Some((
+                    file_path,
+                    ruled_destination(file_path, root, options, rule)?,
+                ));
+            }
+            // A --map rule for the extension ("bak=Backups") is more specific than the junk patterns
+            let mapped = mapped_folder(file_path, options).is_some();
+            let is_junk = options.junk != JunkStrategy::Organize
+                && file_path.file_name().is_some_and(category::is_junk)
+                && !mapped;
+            // ...and both are more specific than a classifier, which comes before --by
+            let classified = (!mapped && !is_junk)
+                .then(|| contents.classification(file_path))
+                .flatten();
+            let dest = match (is_junk, options.junk) {
+                (true, JunkStrategy::Trash) => {
+                    junk_for_trash.push(file_path);
+                    return None;
+                }
+                (true, _) => junk_destination(file_path, root, options)?,
+                (false, _) if classified.is_some() => {
+                    classified_destination(file_path, root, options, classified?)?
+                }
+                (false, _)
+                    if options.by == Grouping::Language && file_path.extension().is_none() =>
+                {
+                    script_destination(file_path, root, options, contents)?
+                }
+                (false, _) if mapped => destination_for(file_path, root, options)?,
+                (false, _) => {
+                    grouped.push((file_path, group_of(file_path, options)?));
+                    return None;
+                }
+            };
+            Some((file_path, dest))
+        })
+        .collect();
+    moves.extend(grouped_destinations(grouped, root, options, contents));
+    // Back in the order of the scan, which the renames of colliding names go by
+    let order: HashMap<&Path, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (file.as_path(), i))
+        .collect();
+    moves.sort_by_key(|(file_path, _)| order.get(file_path.as_path()).copied());
+
+    let mut actions = resolve(&moves, files, root, options, contents);
+    actions.extend(junk_for_trash.into_iter().map(|path| PlannedAction::Trash {
+        path: path.to_path_buf(),
+        duplicate_of: None,
+    }));
+    actions
+}
+
+// Where the files that go by --by end up. With --min-group-size, a folder that would get fewer files
+// than that (counting the ones already in it) isn't made: its files go into Misc/ instead, or stay
+// where they are (--small-groups). That keeps a folder of 60 different kinds of files from
+// turning into 60 folders with one file each.
+fn grouped_destinations<'a>(
+    grouped: Vec<(&'a PathBuf, Group)>,
+    root: &Path,
+    options: &Options,
+    contents: &impl Contents,
+) -> Vec<(&'a PathBuf, Destination)> {
+    // Per folder: the files that would move into it, and the ones of the scan already in it
+    let mut sizes: HashMap<String, (usize, usize)> = HashMap::new();
+    for (file_path, group) in &grouped {
+        let size = sizes.entry(group.folder.clone()).or_default();
+        if file_path.starts_with(root.join(&group.folder)) {
+            size.1 += 1;
+        } else {
+            size.0 += 1;
+        }
+    }
+    // The scan can leave out files already in the folder (--since-last-run, a --path pattern), so
+    // those are counted on disk too
+    let sizes: HashMap<String, usize> = match options.min_group_size {
+        Some(_) => sizes
+            .into_iter()
+            .map(|(folder, (moving, there))| {
+                let on_disk = contents.files_in(&root.join(&folder));
+                (folder, moving + there.max(on_disk))
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+    let small = |group: &Group| {
+        options
+            .min_group_size
+            .is_some_and(|min| sizes[&group.folder] < min.get())
+    };
+
+    grouped
+        .into_iter()
+        .filter_map(|(file_path, group)| {
+            let folder = match (small(&group), options.small_groups) {
+                (false, _) => group.folder.as_str(),
+                (true, SmallGroups::Misc) => MISC_FOLDER,
+                (true, SmallGroups::Stay) => return None,
+            };
+            let path = place(file_path, root, folder, options.preserve_structure)?;
+            Some((
+                file_path,
+                Destination {
+                    ext: group.ext,
+                    category: group.category,
+                    path,
+                },
+            ))
+        })
+        .collect()
+}
+
+// --check-idempotent: the plan a second run with the same options would make, right after this
+// one carried out `actions`. For a folder that's organized, that's nothing; whatever it would
+// still move is something the options don't agree with themselves about (a --rule for "inbox/*"
+// that sends files out of inbox/, and something else that sends them on from there).
+pub fn second_run(
+    files: &[PathBuf],
+    root: &Path,
+    options: &Options,
+    contents: &impl Contents,
+    actions: &[PlannedAction],
+) -> Vec<PlannedAction> {
+    let mut was: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut gone: HashSet<&Path> = HashSet::new();
+    for action in actions {
+        match action {
+            PlannedAction::Move { from, to, .. } => {
+                was.insert(to.clone(), from);
+                gone.insert(from);
+            }
+            PlannedAction::Trash { path, .. } => {
+                gone.insert(path);
+            }
+            _ => {}
+        }
+    }
+    let mut after: Vec<PathBuf> = files
+        .iter()
+        .filter(|file| !gone.contains(file.as_path()))
+        .cloned()
+        .chain(was.keys().cloned())
+        .collect();
+    // Sorted, like the scan
+    after.sort();
+
+    let contents = AfterRun {
+        contents,
+        was,
+        gone,
+    };
+    plan_with(&after, root, options, &contents)
+}
+
+// The files as the first run leaves them. Nothing has moved yet, so what's inside a file is asked
+// of it where it still is.
+struct AfterRun<'a, C> {
+    contents: &'a C,
+    // Where each moved file is now -> where it was
+    was: HashMap<PathBuf, &'a Path>,
+    // The places the first run moved files away from
+    gone: HashSet<&'a Path>,
+}
+
+impl<C: Contents> AfterRun<'_, C> {
+    fn on_disk<'p>(&'p self, file: &'p Path) -> &'p Path {
+        self.was.get(file).copied().unwrap_or(file)
+    }
+}
+
+impl<C: Contents> Contents for AfterRun<'_, C> {
+    fn same(&self, a: &Path, b: &Path) -> bool {
+        self.contents.same(self.on_disk(a), self.on_disk(b))
+    }
+
+    fn interpreter(&self, file: &Path) -> Option<String> {
+        self.contents.interpreter(self.on_disk(file))
+    }
+
+    fn classification(&self, file: &Path) -> Option<Classification> {
+        self.contents.classification(self.on_disk(file))
+    }
+
+    fn occupied(&self, path: &Path, file: &Path) -> bool {
+        !self.gone.contains(path) && self.contents.occupied(path, self.on_disk(file))
+    }
+
+    // The files moved into the folder are in the scan of the second run, so they count anyway
+    fn files_in(&self, folder: &Path) -> usize {
+        self.contents.files_in(folder)
+    }
+}
+
+// The plan for `merge`: every file of `files` (the scan of `src`) goes to the same place below
+// `dst`, "src/pdf/a.pdf" -> "dst/pdf/a.pdf". `existing` are the files already in `dst`; when a
+// name is taken, --on-duplicate and --on-conflict decide, like for any other run.
+pub fn plan_merge(
+    files: &[PathBuf],
+    src: &Path,
+    existing: &[PathBuf],
+    dst: &Path,
+    options: &Options,
+    contents: &impl Contents,
+) -> Vec<PlannedAction> {
+    let moves: Vec<(&PathBuf, Destination)> = files
+        .iter()
+        .filter_map(|file_path| {
+            let relative = file_path.strip_prefix(src).ok()?;
+            Some((
+                file_path,
+                Destination {
+                    ext: extension_of(file_path, options).unwrap_or_default(),
+                    category: None,
+                    path: dst.join(relative),
+                },
+            ))
+        })
+        .collect();
+    resolve(&moves, existing, dst, options, contents)
+}
+
+// The second half of plan_with() (and of plan_merge()): gives every file of `moves` its final
+// destination below `root`, with a CreateDir for each folder needed. `occupied` are the files that
+// are already there (the names they have are taken).
+fn resolve<'a>(
+    moves: &[(&'a PathBuf, Destination)],
+    occupied: &'a [PathBuf],
+    root: &Path,
+    options: &Options,
+    contents: &impl Contents,
+) -> Vec<PlannedAction> {
+    // BTreeSet = a sorted set, so folders are created (and errors shown) in a stable order
+    let folders: BTreeSet<&Path> = moves
+        .iter()
+        .filter(|(_, dest)| fit(&dest.path).is_some())
+        .filter_map(|(_, dest)| dest.path.parent())
+        .collect();
+
+    // A file called "pdf" (no extension, so it's never moved) sitting where the "pdf" folder
+    // should go means that folder can't be created, so nothing can be moved into it
+    // (with --preserve-structure also "pdf/sub", the file is in the way of one of its parents)
+    let scanned: HashSet<&Path> = occupied.iter().map(|f| f.as_path()).collect();
+    let blocked: HashSet<&Path> = folders
+        .iter()
+        .copied()
+        .filter(|folder| {
+            folder
+                .ancestors()
+                .take_while(|a| *a != root)
+                .any(|a| scanned.contains(a))
+        })
+        .collect();
+
+    let mut actions: Vec<PlannedAction> = folders
+        .iter()
+        .filter(|folder| !blocked.contains(*folder))
+        .map(|folder| PlannedAction::CreateDir {
+            path: folder.to_path_buf(),
+        })
+        .collect();
+
+    // Every path that is (or will be) occupied: the scanned files themselves, plus each destination we hand out.
+    // The value is where the file that ends up there is right now (the file itself, or the source of the move).
+    let mut taken: HashMap<String, &Path> = occupied
+        .iter()
+        .map(|f| (paths::key(f), f.as_path()))
+        .collect();
+
+    for (file_path, dest) in moves {
+        let dest_path = &dest.path;
+        if let Some(folder) = dest_path.parent().filter(|f| blocked.contains(f)) {
+            actions.push(PlannedAction::Skip {
+                path: file_path.to_path_buf(),
+                reason: format!("a file is in the way of folder {:?}", folder),
+            });
+            continue;
+        }
+
+        // The destination and its numbered variants ("report (1).pdf", ...), each made to fit the
+        // path length limits. fit() only fails if the folder alone is too long, which is the same
+        // for every n, so that's checked once up front.
+        let candidate = |n: usize| {
+            let path = if n == 0 {
+                dest_path.clone()
+            } else {
+                numbered(dest_path, n, options)
+            };
+            fit(&path).unwrap_or((path, false))
+        };
+        if fit(dest_path).is_none() {
+            actions.push(PlannedAction::Skip {
+                path: file_path.to_path_buf(),
+                reason: format!(
+                    "the path of folder {:?} is too long",
+                    dest_path.parent().unwrap_or(dest_path)
+                ),
+            });
+            continue;
+        }
+
+        // Who has the name `path` now, if anyone: a file of the scan or of this run, or (unless
+        // --fast-scan leaves that to the moves) something on disk the scan didn't see
+        let holder =
+            |taken: &HashMap<String, &Path>, path: &Path| match taken.get(&paths::key(path)) {
+                Some(holder) => Some(holder.to_path_buf()),
+                None if !options.fast_scan && contents.occupied(path, file_path) => {
+                    Some(path.to_path_buf())
+                }
+                None => None,
+            };
+
+        let (dest_path, shortened) = if holder(&taken, &candidate(0).0).is_none() {
+            let (path, shortened) = candidate(0);
+            taken.insert(paths::key(&path), file_path);
+            (path, shortened)
+        } else {
+            // The name is taken: by the same file? Look at "report.pdf", "report (1).pdf", ...
+            if options.on_duplicate != DuplicateStrategy::KeepBoth {
+                let original = (0..)
+                    .map_while(|n| holder(&taken, &candidate(n).0))
+                    .find(|other| contents.same(file_path, other));
+
+                if let Some(original) = original {
+                    actions.push(match options.on_duplicate {
+                        DuplicateStrategy::TrashSource => PlannedAction::Trash {
+                            path: file_path.to_path_buf(),
+                            duplicate_of: Some(original.clone()),
+                        },
+                        _ => PlannedAction::Skip {
+                            path: file_path.to_path_buf(),
+                            reason: format!("it's identical to {:?}", original),
+                        },
+                    });
+                    continue;
+                }
+            }
+
+            match options.on_conflict {
+                ConflictStrategy::Rename => {
+                    // Count up until we find a free name
+                    let mut n = 1;
+                    while holder(&taken, &candidate(n).0).is_some() {
+                        n += 1;
+                    }
+                    let (path, shortened) = candidate(n);
+                    taken.insert(paths::key(&path), file_path);
+                    (path, shortened)
+                }
+                ConflictStrategy::Skip => {
+                    actions.push(PlannedAction::Skip {
+                        path: file_path.to_path_buf(),
+                        reason: format!("{:?} is already taken", dest_path),
+                    });
+                    continue;
+                }
+            }
+        };
+
+        actions.push(PlannedAction::Move {
+            from: file_path.to_path_buf(),
+            to: dest_path,
+            ext: dest.ext.clone(),
+            category: dest.category.clone(),
+            shortened,
+        });
+    }
+
+    actions
+}
+
+// --limit and --limit-bytes: keeps the Moves of `actions` (in plan order) until `max_files` files or
+// `max_bytes` bytes are reached, and drops the rest, together with the folders only they needed.
+// The first file that doesn't fit in `max_bytes` ends the run, so the files kept are always the
+// first ones of the plan. `size` tells how big a file is. Skips and Trash are kept as they are.
+// Returns the shorter plan and how many moves were left out.
+pub fn limit(
+    actions: Vec<PlannedAction>,
+    max_files: Option<u64>,
+    max_bytes: Option<u64>,
+    size: impl Fn(&Path) -> u64,
+) -> (Vec<PlannedAction>, usize) {
+    let mut files = 0;
+    let mut bytes = 0;
+    let mut full = false;
+    let mut left_out = 0;
+    let mut kept: Vec<PlannedAction> = Vec::with_capacity(actions.len());
+    for action in actions {
+        if let PlannedAction::Move { from, .. } = &action {
+            if !full {
+                let file_size = size(from);
+                full = max_files.is_some_and(|max| files >= max)
+                    || max_bytes.is_some_and(|max| bytes + file_size > max);
+                files += 1;
+                bytes += file_size;
+            }
+            if full {
+                left_out += 1;
+                continue;
+            }
+        }
+        kept.push(action);
+    }
+    drop_unused_folders(&mut kept);
+    (kept, left_out)
+}
+
+// --shuffle: the same plan in a random order. The CreateDirs stay in front (the moves need their
+// folders), everything after them is shuffled. No move depends on another one having happened
+// (every destination is a name nobody has), so any order gives the same result.
+pub fn shuffle(mut actions: Vec<PlannedAction>, rng: &mut fastrand::Rng) -> Vec<PlannedAction> {
+    let folders = actions
+        .iter()
+        .take_while(|a| matches!(a, PlannedAction::CreateDir { .. }))
+        .count();
+    rng.shuffle(&mut actions[folders..]);
+    actions
+}
+
+// Turns the Moves and Trashes of files that another program has open into Skips, so nothing is
+// moved out from under a running program. `opened_by` tells who has a file open ("firefox (pid 1234)").
+pub fn skip_open(
+    actions: Vec<PlannedAction>,
+    opened_by: impl Fn(&Path) -> Option<String>,
+) -> Vec<PlannedAction> {
+    skip_where(actions, |path, _| {
+        opened_by(path).map(|program| format!("it's open in {}", program))
+    })
+}
+
+// Turns the Moves and Trashes the current user isn't allowed to do into Skips, so they show up
+// in the plan and not as failed moves halfway through the run. `why_not` gets the file and where
+// it goes (None for the trash) and tells why it can't be moved (see permissions.rs).
+pub fn skip_unmovable(
+    actions: Vec<PlannedAction>,
+    why_not: impl FnMut(&Path, Option<&Path>) -> Option<String>,
+) -> Vec<PlannedAction> {
+    skip_where(actions, why_not)
+}
+
+// The file a Move or Trash takes away, and where it goes (None for the trash)
+pub fn moved_file(action: &PlannedAction) -> Option<(&Path, Option<&Path>)> {
+    match action {
+        PlannedAction::Move { from, to, .. } => Some((from, Some(to))),
+        PlannedAction::Trash { path, .. } => Some((path, None)),
+        _ => None,
+    }
+}
+
+// The Moves and Trashes `reason` has a reason for become Skips with that reason
+pub fn skip_where(
+    actions: Vec<PlannedAction>,
+    mut reason: impl FnMut(&Path, Option<&Path>) -> Option<String>,
+) -> Vec<PlannedAction> {
+    let mut actions: Vec<PlannedAction> = actions
+        .into_iter()
+        .map(|action| {
+            let Some((path, to)) = moved_file(&action) else {
+                return action;
+            };
+            match reason(path, to) {
+                Some(reason) => PlannedAction::Skip {
+                    path: path.to_path_buf(),
+                    reason,
+                },
+                None => action,
+            }
+        })
+        .collect();
+    drop_unused_folders(&mut actions);
+    actions
+}
+
+// Leaves only the actions for files `keep` says yes to (the others aren't even reported as
+// skipped, they're none of this run's business), and the folders those still need
+pub fn only(actions: Vec<PlannedAction>, keep: impl Fn(&Path) -> bool) -> Vec<PlannedAction> {
+    let mut actions: Vec<PlannedAction> = actions
+        .into_iter()
+        .filter(|action| match action {
+            PlannedAction::Move { from: path, .. }
+            | PlannedAction::Trash { path, .. }
+            | PlannedAction::Skip { path, .. } => keep(path),
+            PlannedAction::CreateDir { .. } => true,
+        })
+        .collect();
+    drop_unused_folders(&mut actions);
+    actions
+}
+
+// The plan with the moves of the files `duplicate_of` has a copy for sent to the trash instead
+// (--skip-if-in with --delete-if-duplicated)
+pub fn trash_where(
+    actions: Vec<PlannedAction>,
+    mut duplicate_of: impl FnMut(&Path) -> Option<PathBuf>,
+) -> Vec<PlannedAction> {
+    let mut actions: Vec<PlannedAction> = actions
+        .into_iter()
+        .map(|action| match action {
+            PlannedAction::Move {
+                from,
+                to,
+                ext,
+                category,
+                shortened,
+            } => match duplicate_of(&from) {
+                Some(original) => PlannedAction::Trash {
+                    path: from,
+                    duplicate_of: Some(original),
+                },
+                None => PlannedAction::Move {
+                    from,
+                    to,
+                    ext,
+                    category,
+                    shortened,
+                },
+            },
+            action => action,
+        })
+        .collect();
+    drop_unused_folders(&mut actions);
+    actions
+}
+
+// Removes the CreateDirs no Move needs anymore (CreateDirs come first, so this needs a second pass)
+fn drop_unused_folders(actions: &mut Vec<PlannedAction>) {
+    let needed: HashSet<PathBuf> = actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::Move { to, .. } => to.parent().map(Path::to_path_buf),
+            _ => None,
+        })
+        .collect();
+    actions.retain(|a| !matches!(a, PlannedAction::CreateDir { path } if !needed.contains(path)));
+}
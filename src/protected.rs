@@ -0,0 +1,165 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+// Directories that belong to the operating system or to installed applications.
+// Moving files out of these breaks programs (or the whole OS), so the organizer
+// refuses to touch them unless the user passes --allow-system.
+
+#[cfg(unix)]
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/libx32",
+    "/opt",
+    "/proc",
+    "/run",
+    "/sbin",
+    "/snap",
+    "/sys",
+    "/usr",
+    "/var/cache",
+    "/var/db",
+    "/var/lib",
+    "/var/log",
+    "/var/spool",
+    // macOS
+    "/Applications",
+    "/Library",
+    "/System",
+    "/private/etc",
+    "/private/var/db",
+];
+
+#[cfg(windows)]
+const SYSTEM_DIRS: &[&str] = &[
+    r"C:\Windows",
+    r"C:\Program Files",
+    r"C:\Program Files (x86)",
+    r"C:\ProgramData",
+];
+
+// Relative to the user's home directory
+const HOME_DIRS: &[&str] = &["Library"];
+
+// The XDG folders where programs keep their settings, data, state and caches (the Unix version of
+// AppData), with their default below the home directory. Both count: a variable that points
+// elsewhere doesn't mean nothing is left in the default place.
+#[cfg(unix)]
+const XDG_DIRS: &[(&str, &str)] = &[
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+    ("XDG_CACHE_HOME", ".cache"),
+];
+#[cfg(not(unix))]
+const XDG_DIRS: &[(&str, &str)] = &[];
+
+// Any path containing one of these folder names is protected, wherever it is
+const PROTECTED_NAMES: &[&str] = &["AppData"];
+
+// Builds the full denylist of absolute paths for this machine (only once per run)
+fn protected_roots() -> &'static [PathBuf] {
+    static ROOTS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        let mut roots: Vec<PathBuf> = SYSTEM_DIRS.iter().map(PathBuf::from).collect();
+
+        // Windows lets these live on other drives, so ask the environment as well
+        for var in [
+            "SystemRoot",
+            "ProgramFiles",
+            "ProgramFiles(x86)",
+            "ProgramData",
+        ] {
+            if let Some(dir) = env::var_os(var) {
+                roots.push(PathBuf::from(dir));
+            }
+        }
+
+        if let Some(home) = home_dir() {
+            for dir in HOME_DIRS {
+                roots.push(home.join(dir));
+            }
+            for (_, default) in XDG_DIRS {
+                roots.push(home.join(default));
+            }
+        }
+        // Only absolute paths count, like the XDG spec says
+        for (var, _) in XDG_DIRS {
+            if let Some(dir) = env::var_os(var).map(PathBuf::from) {
+                if dir.is_absolute() {
+                    roots.push(dir);
+                }
+            }
+        }
+
+        roots
+    })
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+// Windows and macOS file systems are case-insensitive by default,
+// so "c:\windows" must match "C:\Windows"
+fn same_name(a: &str, b: &str) -> bool {
+    if cfg!(any(windows, target_os = "macos")) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+fn starts_with(path: &Path, prefix: &Path) -> bool {
+    let mut path_parts = path.components();
+    prefix.components().all(|p| match path_parts.next() {
+        Some(c) => same_name(
+            &c.as_os_str().to_string_lossy(),
+            &p.as_os_str().to_string_lossy(),
+        ),
+        None => false,
+    })
+}
+
+// fs::canonicalize resolves symlinks and "..", but on Windows it returns "\\?\C:\..."
+// paths, which don't compare equal to plain "C:\..." ones, so strip that prefix again
+pub fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let canonical = fs::canonicalize(path)?;
+    #[cfg(windows)]
+    {
+        let s = canonical.to_string_lossy();
+        if let Some(stripped) = s.strip_prefix(r"\\?\") {
+            if !stripped.starts_with("UNC") {
+                return Ok(PathBuf::from(stripped));
+            }
+        }
+    }
+    Ok(canonical)
+}
+
+// Returns true if `path` (absolute) is, or is inside, a protected directory
+pub fn is_protected(path: &Path) -> bool {
+    // "/" or "C:\": everything is below it, the system too
+    if path.parent().is_none() {
+        return true;
+    }
+
+    let has_protected_name = path.components().any(|c| match c {
+        Component::Normal(name) => PROTECTED_NAMES
+            .iter()
+            .any(|n| same_name(&name.to_string_lossy(), n)),
+        _ => false,
+    });
+
+    has_protected_name || protected_roots().iter().any(|r| starts_with(path, r))
+}
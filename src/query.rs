@@ -0,0 +1,123 @@
+use crate::journal::{self, Entry, Integrity};
+use crate::undo::Filter;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Query: search the journals of earlier runs
+// =============================================================================
+//
+// "Where did my invoices go?" Every run journals its moves (see journal.rs), so the answer is
+// already on disk: `query --path ~/Downloads --ext pdf --since 2024-01-01` lists the matching
+// moves of every run, oldest first, with the same filters undo has. Nothing is moved.
+
+// Which moves to list
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    // Start of a run ID; None = every run
+    pub run: Option<String>,
+    // Only these extensions, empty = all
+    pub exts: Vec<String>,
+    // Only files whose name or original path matches one of these globs, empty = all
+    pub patterns: Vec<String>,
+    // Only moves made at or after this time
+    pub since: Option<DateTime<Utc>>,
+    // Where journals are kept (None = the platform's state folder, see paths.rs)
+    pub state_dir: Option<PathBuf>,
+}
+
+// One journaled move that matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    pub run_id: String,
+    pub at: DateTime<Utc>,
+    // Where the file was before the run (relative to the root, like in the journal)
+    pub from: PathBuf,
+    // Where the run put it, None = the system trash
+    pub to: Option<PathBuf>,
+    // An undo put it back since
+    pub undone: bool,
+}
+
+// The moves of the runs on `root` that pass the filters, oldest first
+pub fn find(root: &Path, options: &QueryOptions) -> Result<Vec<Found>> {
+    let filter = Filter::new(&options.exts, &options.patterns, options.since)?;
+    let mut found = Vec::new();
+
+    for path in journal::list(root, options.state_dir.as_deref())? {
+        // A damaged journal is only read up to the damage, like undo does
+        let entries = match journal::verify(&path)? {
+            Integrity::Damaged { line, .. } => journal::read_before(&path, line)?,
+            _ => journal::read(&path)?,
+        };
+        let run_id = entries
+            .iter()
+            .find_map(|entry| match entry {
+                Entry::Run { run_id, .. } => Some(run_id.to_string()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        if options
+            .run
+            .as_ref()
+            .is_some_and(|wanted| !run_id.starts_with(wanted.as_str()))
+        {
+            continue;
+        }
+
+        let undone: HashSet<(&Path, &Path)> = entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Undo { from, to, .. } => Some((from.as_path(), to.as_path())),
+                _ => None,
+            })
+            .collect();
+        for entry in &entries {
+            let (from, to, at) = match entry {
+                Entry::Move { from, to, at, .. } => (from, Some(to), at),
+                Entry::Trash { path, at, .. } => (path, None, at),
+                _ => continue,
+            };
+            if !filter.matches(from, at) {
+                continue;
+            }
+            found.push(Found {
+                run_id: run_id.clone(),
+                at: *at,
+                from: from.clone(),
+                to: to.cloned(),
+                undone: to.is_some_and(|to| undone.contains(&(from.as_path(), to.as_path()))),
+            });
+        }
+    }
+    Ok(found)
+}
+
+// `query`: prints the moves find() finds, grouped by run
+pub fn query(target_path: &str, options: &QueryOptions) -> Result<()> {
+    let root = Path::new(target_path);
+    if journal::list(root, options.state_dir.as_deref())?.is_empty() {
+        bail!("No runs recorded for {:?}", root);
+    }
+    let found = find(root, options)?;
+
+    let mut run = None;
+    for item in &found {
+        if run != Some(&item.run_id) {
+            println!("Run {}:", item.run_id);
+            run = Some(&item.run_id);
+        }
+        let at = item.at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+        match &item.to {
+            Some(to) if item.undone => {
+                println!("    {}  {:?} -> {:?} (undone since)", at, item.from, to)
+            }
+            Some(to) => println!("    {}  {:?} -> {:?}", at, item.from, to),
+            None => println!("    {}  {:?} -> the trash", at, item.from),
+        }
+    }
+    println!("{} moves found", found.len());
+    Ok(())
+}
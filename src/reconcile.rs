@@ -0,0 +1,186 @@
+use crate::logic::Outcome;
+use crate::plan::PlannedAction;
+use crate::report::{format_size, Tally};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+// =============================================================================
+// Plan vs. result: did the run do what it said it would?
+// =============================================================================
+//
+// Between the scan and the moves, the folder keeps living: files get deleted, downloads finish
+// (and grow), another program drops a file right where one of ours was going. The summary counts
+// what was actually moved, which is then less (or more bytes) than the plan said, and for a run
+// nobody watches (a scheduled one, a script) it's hard to tell why. So after a real run the
+// summary reconciles the two:
+//
+//   Planned : 120 files, 3.4 GB
+//   Moved   : 116 files, 3.3 GB
+//       2 files were gone before their turn (deleted or moved since the scan)
+//       1 file was skipped: something appeared at its destination during the run
+//       1 file failed to move (see the errors above)
+//       3 files had changed size since the plan (-12.0 MB)
+//
+// Every planned file ends up in exactly one of: moved, gone, skipped, already in place, not
+// started (the run stopped early) or failed, so the lines always add up to the plan.
+
+// What the plan expected, measured right before the moves start
+#[derive(Debug, Clone, Default)]
+pub struct Estimate {
+    // The files to move and their size
+    pub moves: Tally,
+    // The files to move to the trash
+    pub trash: u64,
+    // The size of each file to move (files that were already gone aren't in here)
+    sizes: HashMap<PathBuf, u64>,
+}
+
+impl Estimate {
+    pub fn of(actions: &[PlannedAction]) -> Estimate {
+        let mut estimate = Estimate::default();
+        for action in actions {
+            match action {
+                PlannedAction::Move { from, .. } => {
+                    let size = fs::symlink_metadata(from).map(|m| m.len()).ok();
+                    estimate.moves.add(size.unwrap_or(0));
+                    if let Some(size) = size {
+                        estimate.sizes.insert(from.clone(), size);
+                    }
+                }
+                PlannedAction::Trash { .. } => estimate.trash += 1,
+                _ => {}
+            }
+        }
+        estimate
+    }
+}
+
+// How the run went compared to its Estimate
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    pub planned: Tally,
+    pub moved: Tally,
+    // Files that weren't there anymore when it was their turn
+    pub vanished: u64,
+    // Files whose destination was taken by the time it was their turn
+    pub late_conflicts: u64,
+    // Files that turned out to be at their destination already (under another name)
+    pub in_place: u64,
+    // Files the run didn't get to (--max-duration, low disk space)
+    pub not_started: u64,
+    // Files that couldn't be moved for any other reason (they're in the errors)
+    pub failed: u64,
+    // Moved files that had a different size than when the plan was made, and by how much together
+    pub resized: u64,
+    pub resized_bytes: i64,
+    pub planned_trash: u64,
+    pub trashed: u64,
+}
+
+impl Reconciliation {
+    pub fn new(estimate: &Estimate, outcome: &Outcome) -> Reconciliation {
+        let moved = outcome.moved.total();
+        let mut resized = 0;
+        let mut resized_bytes = 0;
+        for file in &outcome.files {
+            if let Some(&planned) = estimate.sizes.get(&file.from) {
+                if planned != file.bytes {
+                    resized += 1;
+                    resized_bytes += file.bytes as i64 - planned as i64;
+                }
+            }
+        }
+        let accounted = moved.files
+            + outcome.vanished
+            + outcome.late_conflicts
+            + outcome.in_place
+            + outcome.not_started;
+        Reconciliation {
+            planned: estimate.moves,
+            moved,
+            vanished: outcome.vanished,
+            late_conflicts: outcome.late_conflicts,
+            in_place: outcome.in_place,
+            not_started: outcome.not_started,
+            failed: estimate.moves.files.saturating_sub(accounted),
+            resized,
+            resized_bytes,
+            planned_trash: estimate.trash,
+            trashed: outcome.trashed,
+        }
+    }
+
+    // True if everything went exactly as planned
+    pub fn as_planned(&self) -> bool {
+        self.planned == self.moved && self.resized == 0 && self.planned_trash == self.trashed
+    }
+
+    // The lines of the summary (see the top of this file)
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Planned : {}", describe(&self.planned)),
+            format!("Moved   : {}", describe(&self.moved)),
+        ];
+        let mut why = |n: u64, singular: &str, plural: &str| {
+            if n > 0 {
+                lines.push(format!(
+                    "    {} {} {}",
+                    n,
+                    if n == 1 { "file" } else { "files" },
+                    if n == 1 { singular } else { plural }
+                ));
+            }
+        };
+        why(
+            self.vanished,
+            "was gone before its turn (deleted or moved since the scan)",
+            "were gone before their turn (deleted or moved since the scan)",
+        );
+        why(
+            self.late_conflicts,
+            "was skipped: something appeared at its destination during the run",
+            "were skipped: something appeared at their destinations during the run",
+        );
+        why(
+            self.in_place,
+            "was already where it belongs (under another name of the same folder)",
+            "were already where they belong (under another name of the same folder)",
+        );
+        why(
+            self.not_started,
+            "wasn't started (the run stopped early)",
+            "weren't started (the run stopped early)",
+        );
+        why(
+            self.failed,
+            "failed to move (see the errors above)",
+            "failed to move (see the errors above)",
+        );
+        let trash_failed = self.planned_trash.saturating_sub(self.trashed);
+        why(
+            trash_failed,
+            "couldn't be moved to the trash (see the errors above)",
+            "couldn't be moved to the trash (see the errors above)",
+        );
+        if self.resized > 0 {
+            let sign = if self.resized_bytes < 0 { "-" } else { "+" };
+            lines.push(format!(
+                "    {} {} changed size since the plan ({}{})",
+                self.resized,
+                if self.resized == 1 {
+                    "file had"
+                } else {
+                    "files had"
+                },
+                sign,
+                format_size(self.resized_bytes.unsigned_abs())
+            ));
+        }
+        lines
+    }
+}
+
+fn describe(tally: &Tally) -> String {
+    format!("{} files, {}", tally.files, format_size(tally.bytes))
+}
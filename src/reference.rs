@@ -0,0 +1,95 @@
+use crate::hashing;
+use crate::protected;
+use anyhow::{bail, Context, Result};
+use ignore::WalkBuilder;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// --skip-if-in: files that are safe somewhere else already
+// =============================================================================
+//
+// A dump folder ("I'll sort this later") is often mostly files that are in the backup already.
+// `--skip-if-in /mnt/nas/backup` looks for an identical copy of every file the plan would move in
+// that folder (and below it), and leaves the ones that have one where they are. With
+// --delete-if-duplicated they go to the trash instead, so only what's not backed up is left to
+// sort (that needs a yes, like every other trashing, see confirm.rs).
+//
+// Identical means the same size and the same BLAKE3 hash. Only files whose size turns up in the
+// reference folder are read at all, so a reference folder full of other things costs one look at
+// each file's size. Empty files are never counted as copies: one empty file is like any other.
+
+// The files of `files` that have an identical copy below `reference`, with the copy
+pub fn copies_in(
+    reference: &Path,
+    root: &Path,
+    files: &[&Path],
+) -> Result<HashMap<PathBuf, PathBuf>> {
+    let abs_reference = protected::absolute_path(reference)
+        .with_context(|| format!("Failed to access the reference folder {:?}", reference))?;
+    if !abs_reference.is_dir() {
+        bail!("The reference folder {:?} isn't a folder", reference);
+    }
+    let abs_root = protected::absolute_path(root)
+        .with_context(|| format!("Failed to access directory {:?}", root))?;
+    if abs_reference.starts_with(&abs_root) || abs_root.starts_with(&abs_reference) {
+        bail!(
+            "The reference folder {:?} and {:?} overlap (one is inside the other): every file would be its own copy",
+            reference,
+            root
+        );
+    }
+
+    // The reference folder's files, by size
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for entry in WalkBuilder::new(reference)
+        .standard_filters(false)
+        .build()
+        .flatten()
+    {
+        if let Ok(meta) = fs::symlink_metadata(entry.path()) {
+            if meta.is_file() && meta.len() > 0 {
+                by_size
+                    .entry(meta.len())
+                    .or_default()
+                    .push(entry.into_path());
+            }
+        }
+    }
+
+    // The files that have a reference file of the same size, and those reference files
+    let candidates: Vec<(&Path, u64)> = files
+        .iter()
+        .filter_map(|file| {
+            let meta = fs::symlink_metadata(file).ok()?;
+            (meta.is_file() && by_size.contains_key(&meta.len())).then_some((*file, meta.len()))
+        })
+        .collect();
+    let sizes: HashSet<u64> = candidates.iter().map(|&(_, size)| size).collect();
+    let references: Vec<PathBuf> = sizes
+        .iter()
+        .flat_map(|size| by_size[size].iter().cloned())
+        .collect();
+
+    // Both hashed in one go (see hashing.rs); a file that can't be read has no copy
+    let mut to_hash: Vec<PathBuf> = candidates.iter().map(|&(f, _)| f.to_path_buf()).collect();
+    to_hash.extend(references.iter().cloned());
+    let hashes = hashing::hash_files(&to_hash);
+    let (file_hashes, reference_hashes) = hashes.split_at(candidates.len());
+
+    let mut known: HashMap<blake3::Hash, &Path> = HashMap::new();
+    for (path, hash) in references.iter().zip(reference_hashes) {
+        if let Ok(hash) = hash {
+            known.entry(*hash).or_insert(path);
+        }
+    }
+    Ok(candidates
+        .iter()
+        .zip(file_hashes)
+        .filter_map(|(&(file, _), hash)| {
+            let copy = known.get(hash.as_ref().ok()?)?;
+            Some((file.to_path_buf(), copy.to_path_buf()))
+        })
+        .collect())
+}
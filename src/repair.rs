@@ -0,0 +1,294 @@
+use crate::duplicate;
+use crate::journal::{self, Entry, Integrity};
+use crate::logic;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use ignore::WalkBuilder;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Repair: clean up after runs that were interrupted
+// =============================================================================
+//
+// A run that's killed, crashes or loses its power in the middle can leave things half done. The
+// journal (written ahead, see journal.rs) says which, and `repair` sorts it out:
+//
+//   - moves that happened after their "intent" line but before their "move" line are recorded,
+//     so undo knows about them for sure. A copy to another drive that got its real name while the
+//     original is still there is finished: the original is removed, if the two are identical.
+//   - unfinished copies (".dircleaner-partial-...", see logic.rs) are removed
+//   - the folders the run created for its moves are removed again, if they're still empty
+//   - a last journal line the crash left half written is cut off, so the journal is whole again
+//   - the lock files of runs that ended without removing theirs are removed
+//
+// Runs that finished are left alone, and so is everything if a run on the folder is still going
+// (it holds the lock of its journal): its copies aren't unfinished, they're busy. A journal that
+// is damaged anywhere else than on its last line was changed, not cut off; it's reported (see
+// `journal verify`) and not touched. Running repair twice does nothing the second time.
+
+#[derive(Debug, Clone, Default)]
+pub struct RepairOptions {
+    // Only say what would be done
+    pub dry_run: bool,
+    // Where journals are kept (None = the platform's state folder, see paths.rs)
+    pub state_dir: Option<PathBuf>,
+}
+
+// What a repair did (or, in a dry run, would do)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Repaired {
+    // Moves that happened but weren't recorded as done
+    pub completed: u64,
+    // Unfinished copies removed
+    pub partials: u64,
+    // Empty folders of interrupted runs removed
+    pub folders: u64,
+    // Journals whose half-written last line was cut off
+    pub truncated: u64,
+    // Things repair can't decide about, left for a human (they were printed)
+    pub left_alone: u64,
+}
+
+// Repairs what interrupted runs left behind in `target_path`
+pub fn repair(target_path: &str, options: &RepairOptions) -> Result<Repaired> {
+    let root = Path::new(target_path);
+    let prefix = if options.dry_run { "[DRY RUN] " } else { "" };
+    let journals = journal::list(root, options.state_dir.as_deref())?;
+    if let Some(running) = journals.iter().find(|path| journal::is_running(path)) {
+        bail!(
+            "A run on {:?} is still going (journal {:?}), repair it once it has stopped",
+            root,
+            running
+        );
+    }
+
+    let mut repaired = Repaired::default();
+    // Folders outside root the interrupted runs moved files into, to look for unfinished copies
+    let mut outside: BTreeSet<PathBuf> = BTreeSet::new();
+    for path in &journals {
+        let lock = journal::lock_path(path);
+        if lock.exists() && !options.dry_run {
+            fs::remove_file(&lock).with_context(|| format!("Failed to remove {:?}", lock))?;
+        }
+
+        let entries = match journal::verify(path)? {
+            Integrity::Intact => continue,
+            Integrity::Unfinished | Integrity::Unchecked => journal::read(path)?,
+            Integrity::Damaged { line, problem }
+                if problem.starts_with("not valid JSON") && line == last_line(path)? =>
+            {
+                println!(
+                    "{}Cutting off the half-written last line of {:?} ({})",
+                    prefix, path, problem
+                );
+                repaired.truncated += 1;
+                if !options.dry_run {
+                    journal::truncate_before(path, line)?;
+                }
+                journal::read_before(path, line)?
+            }
+            Integrity::Damaged { line, problem } => {
+                eprintln!(
+                    "{}Leaving {:?} alone: it's damaged on line {} ({}), see `journal verify`",
+                    prefix, path, line, problem
+                );
+                repaired.left_alone += 1;
+                continue;
+            }
+        };
+        // Old journals without checksums have no "done" line in them either if they were cut off
+        if entries.iter().any(|e| matches!(e, Entry::Done { .. })) {
+            continue;
+        }
+
+        complete_moves(root, path, &entries, options, &mut repaired)?;
+        remove_empty_folders(root, &entries, options, &mut repaired);
+        for entry in &entries {
+            if let Entry::Intent { to, .. } = entry {
+                let to = root.join(to);
+                if let Some(folder) = to.parent().filter(|f| !f.starts_with(root)) {
+                    outside.insert(folder.to_path_buf());
+                }
+            }
+        }
+    }
+
+    // Unfinished copies, below root and in the folders outside of it
+    let mut partials = partials_below(root);
+    for folder in &outside {
+        partials.extend(
+            fs::read_dir(folder)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| logic::is_partial(path)),
+        );
+    }
+    for partial in partials {
+        println!("{}Removing the unfinished copy {:?}", prefix, partial);
+        if options.dry_run {
+            repaired.partials += 1;
+            continue;
+        }
+        match fs::remove_file(&partial) {
+            Ok(()) => repaired.partials += 1,
+            Err(e) => eprintln!("Failed to remove {:?}: {}", partial, e),
+        }
+    }
+
+    if repaired == Repaired::default() {
+        println!("Nothing to repair in {:?}", root);
+    } else {
+        println!(
+            "{}{} moves completed, {} unfinished copies and {} empty folders removed, {} journals cut off, {} things left alone",
+            prefix,
+            repaired.completed,
+            repaired.partials,
+            repaired.folders,
+            repaired.truncated,
+            repaired.left_alone
+        );
+    }
+    Ok(repaired)
+}
+
+// The number of the last line of the journal that isn't empty (1-based)
+fn last_line(path: &Path) -> Result<usize> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to open journal {:?}", path))?;
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .last()
+        .map_or(0, |(i, _)| i + 1))
+}
+
+// The intents of an interrupted run that have no "move" line (and weren't undone): did the move
+// happen? Then it's recorded, and a copy whose original is still there is finished first.
+fn complete_moves(
+    root: &Path,
+    path: &Path,
+    entries: &[Entry],
+    options: &RepairOptions,
+    repaired: &mut Repaired,
+) -> Result<()> {
+    let prefix = if options.dry_run { "[DRY RUN] " } else { "" };
+    let done: HashSet<(&Path, &Path)> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Move { from, to, .. } | Entry::Undo { from, to, .. } => {
+                Some((from.as_path(), to.as_path()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    for entry in entries {
+        let Entry::Intent { from, to, size, .. } = entry else {
+            continue;
+        };
+        if done.contains(&(from.as_path(), to.as_path())) {
+            continue;
+        }
+        let (source, dest) = (root.join(from), root.join(to));
+        match (
+            fs::symlink_metadata(&source).is_ok(),
+            fs::symlink_metadata(&dest).is_ok(),
+        ) {
+            // Never started: the file is still where it was
+            (true, false) => continue,
+            (false, false) => {
+                eprintln!(
+                    "{}{:?} is neither at {:?} nor at {:?} anymore",
+                    prefix, from, source, dest
+                );
+                repaired.left_alone += 1;
+                continue;
+            }
+            // Copied to another drive, but the original wasn't removed yet
+            (true, true) if duplicate::same_content(&source, &dest) => {
+                println!(
+                    "{}Finishing the move {:?} -> {:?} (removing the original, the copy is complete)",
+                    prefix, source, dest
+                );
+                if !options.dry_run {
+                    fs::remove_file(&source)
+                        .with_context(|| format!("Failed to remove {:?}", source))?;
+                }
+            }
+            (true, true) => {
+                eprintln!(
+                    "{}Leaving {:?} alone: {:?} is there too, with other contents",
+                    prefix, source, dest
+                );
+                repaired.left_alone += 1;
+                continue;
+            }
+            (false, true) => {
+                println!("{}Recording the move {:?} -> {:?}", prefix, source, dest)
+            }
+        }
+        repaired.completed += 1;
+        if !options.dry_run {
+            journal::append(
+                path,
+                &Entry::Move {
+                    from: from.clone(),
+                    to: to.clone(),
+                    size: *size,
+                    at: Utc::now(),
+                    modified: journal::modified_time(&dest),
+                    shortened: false,
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The folders an interrupted run created that are still empty, deepest first
+fn remove_empty_folders(
+    root: &Path,
+    entries: &[Entry],
+    options: &RepairOptions,
+    repaired: &mut Repaired,
+) {
+    let prefix = if options.dry_run { "[DRY RUN] " } else { "" };
+    // In a dry run nothing is removed, so a folder whose only content is an empty folder the
+    // run created counts as empty too
+    let mut removed: HashSet<PathBuf> = HashSet::new();
+    for entry in entries.iter().rev() {
+        let Entry::CreateDir { path, .. } = entry else {
+            continue;
+        };
+        let folder = root.join(path);
+        let empty = fs::read_dir(&folder).is_ok_and(|mut children| {
+            children.all(|child| child.is_ok_and(|c| removed.contains(&c.path())))
+        });
+        if !empty {
+            continue;
+        }
+        println!("{}Removing the empty folder {:?}", prefix, folder);
+        if options.dry_run || fs::remove_dir(&folder).is_ok() {
+            repaired.folders += 1;
+            removed.insert(folder);
+        }
+    }
+}
+
+// The unfinished copies anywhere below root (but not in our own state folder)
+fn partials_below(root: &Path) -> Vec<PathBuf> {
+    WalkBuilder::new(root)
+        .standard_filters(false)
+        .filter_entry(|entry| entry.file_name() != journal::STATE_DIR)
+        .build()
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .filter(|path| logic::is_partial(path))
+        .collect()
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+// =============================================================================
+// The summary printed at the end of a run
+// =============================================================================
+
+// Number of files and their total size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl Tally {
+    pub fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+// A category's total, broken down by extension
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    pub total: Tally,
+    pub by_ext: HashMap<String, Tally>,
+}
+
+// What was moved, per extension and per category
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub by_ext: HashMap<String, Tally>,
+    // Only filled in for files that have a category
+    pub by_category: HashMap<String, Group>,
+}
+
+impl Stats {
+    // Counts one moved file
+    pub fn add(&mut self, ext: &str, category: Option<&str>, bytes: u64) {
+        self.by_ext.entry(ext.to_string()).or_default().add(bytes);
+
+        if let Some(category) = category {
+            let group = self.by_category.entry(category.to_string()).or_default();
+            group.total.add(bytes);
+            group.by_ext.entry(ext.to_string()).or_default().add(bytes);
+        }
+    }
+
+    pub fn total(&self) -> Tally {
+        sum(self.by_ext.values())
+    }
+}
+
+fn sum<'a>(tallies: impl Iterator<Item = &'a Tally>) -> Tally {
+    let mut total = Tally::default();
+    for tally in tallies {
+        total.files += tally.files;
+        total.bytes += tally.bytes;
+    }
+    total
+}
+
+// Biggest groups first (by file count), ties sorted by name so the output is stable
+fn sorted(map: &HashMap<String, Tally>) -> Vec<(&String, &Tally)> {
+    let mut rows: Vec<(&String, &Tally)> = map.iter().collect();
+    rows.sort_by(|a, b| b.1.files.cmp(&a.1.files).then(a.0.cmp(b.0)));
+    rows
+}
+
+// Prints the per-extension lines, or, when categories (or languages) are in use, each
+// category with its extensions underneath (files without a category are listed under "other")
+pub fn print_summary(stats: &Stats, by_category: bool) {
+    if by_category {
+        print_categories(stats);
+    } else {
+        for (ext, tally) in sorted(&stats.by_ext) {
+            println!("{} : {}", label(ext), describe(tally));
+        }
+    }
+    println!("Total : {}", describe(&stats.total()));
+}
+
+fn print_categories(stats: &Stats) {
+    let mut categories: Vec<(&String, &Group)> = stats.by_category.iter().collect();
+    categories.sort_by(|a, b| b.1.total.files.cmp(&a.1.total.files).then(a.0.cmp(b.0)));
+
+    for (name, group) in categories {
+        println!("{} : {}", name, describe(&group.total));
+        for (ext, tally) in sorted(&group.by_ext) {
+            println!("    {} : {}", label(ext), describe(tally));
+        }
+    }
+
+    // Extensions that didn't fall into any category
+    let other: HashMap<String, Tally> = stats
+        .by_ext
+        .iter()
+        .filter(|(ext, _)| {
+            !stats
+                .by_category
+                .values()
+                .any(|group| group.by_ext.contains_key(*ext))
+        })
+        .map(|(ext, tally)| (ext.clone(), *tally))
+        .collect();
+    if !other.is_empty() {
+        println!("other : {}", describe(&sum(other.values())));
+        for (ext, tally) in sorted(&other) {
+            println!("    {} : {}", label(ext), describe(tally));
+        }
+    }
+}
+
+// "[.pdf]", or for scripts without an extension (--by language) "(no extension)"
+fn label(ext: &str) -> String {
+    if ext.is_empty() {
+        "(no extension)".to_string()
+    } else {
+        format!("[.{}]", ext)
+    }
+}
+
+// "50 files, 12.3 MB"
+fn describe(tally: &Tally) -> String {
+    format!("{} files, {}", tally.files, format_size(tally.bytes))
+}
+
+// 8804682957 -> "8.2 GB" (1 KB = 1024 bytes, like --stop-if-free-below)
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,365 @@
+use crate::confirm::{self, Destruction};
+use crate::dedupe::{self, DuplicateGroup, Keep, SimilarImage};
+use crate::duplicate;
+use crate::links;
+use crate::protected;
+use crate::report::format_size;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// The review file: dedupe's findings, as decisions you can edit
+// =============================================================================
+//
+// `dedupe --review dupes.txt` writes one line per file, saying whether to keep or delete it:
+//
+//   == identical (3 files, 2.1 MB each)
+//   keep   /home/me/Pictures/beach.jpg
+//   delete /home/me/Downloads/beach.jpg
+//   delete /home/me/Downloads/beach (1).jpg
+//
+// Change the words as you like, then `dedupe apply dupes.txt` carries the decisions out.
+// Deleted files go to the system trash. "link" instead of "delete" replaces the file with a link
+// to a kept copy (a hard link, or a symbolic link if the copy is on another drive), so it's still
+// there for whatever uses it, without taking up space twice. Two rules keep this safe:
+//   - a group where every file says "delete" is skipped (at least one copy stays)
+//   - in an "identical" group, a file is only deleted if it's still identical to a file that's kept
+
+// What kind of group the lines below a "==" header belong to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupKind {
+    Identical,
+    Similar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Keep,
+    Delete,
+    // Replace with a link to a kept copy (identical groups only)
+    Link,
+}
+
+// One group of the review file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewGroup {
+    pub kind: GroupKind,
+    pub files: Vec<(Decision, PathBuf)>,
+}
+
+// Switches for `dedupe apply`
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    pub dry_run: bool,
+    // Delete without asking (see confirm.rs)
+    pub yes: bool,
+}
+
+// Turns dedupe's findings into review groups: in identical groups one file is kept (the one
+// `keep` picks, or the first; with --prefer-root, every copy in that root) and the others get
+// `copies` (Delete or Link); if `keep` finds no file to keep, the whole group is kept. Similar
+// images are all kept (they are only deleted if you say so).
+pub fn groups_for(
+    identical: &[DuplicateGroup],
+    similar: &[Vec<SimilarImage>],
+    keep: Option<&Keep>,
+    copies: Decision,
+) -> Vec<ReviewGroup> {
+    let identical = identical.iter().map(|group| {
+        let keeper = match keep {
+            Some(keep) => dedupe::keeper(&group.files, keep),
+            None => Some(0),
+        };
+        ReviewGroup {
+            kind: GroupKind::Identical,
+            files: group
+                .files
+                .iter()
+                .enumerate()
+                .map(|(i, file)| {
+                    let kept = keeper.is_none()
+                        || keeper == Some(i)
+                        || keep.is_some_and(|keep| keep.also_keeps(file));
+                    let decision = if kept { Decision::Keep } else { copies };
+                    (decision, file.clone())
+                })
+                .collect(),
+        }
+    });
+    let similar = similar.iter().map(|group| ReviewGroup {
+        kind: GroupKind::Similar,
+        files: group
+            .iter()
+            .map(|image| (Decision::Keep, image.path.clone()))
+            .collect(),
+    });
+    identical.chain(similar).collect()
+}
+
+// Writes `groups` to `path` (file paths should be absolute, so the file works from any folder)
+pub fn write(path: &Path, groups: &[ReviewGroup]) -> Result<()> {
+    let mut out = String::new();
+    out.push_str("# Review file written by `directory-cleaner dedupe`\n");
+    out.push_str("# Change \"keep\" / \"delete\" as you like, then run:\n");
+    out.push_str(&format!(
+        "#   directory-cleaner dedupe apply {}\n",
+        path.display()
+    ));
+    out.push_str("# Deleted files go to the system trash. Removing a line keeps that file.\n");
+    out.push_str("# \"link\" replaces an identical copy with a link to a kept one.\n");
+
+    for group in groups {
+        out.push('\n');
+        let header = match group.kind {
+            GroupKind::Identical => {
+                let size = fs::symlink_metadata(&group.files[0].1)
+                    .map(|m| m.len())
+                    .unwrap_or(0);
+                format!(
+                    "identical ({} files, {} each)",
+                    group.files.len(),
+                    format_size(size)
+                )
+            }
+            GroupKind::Similar => format!("similar ({} images)", group.files.len()),
+        };
+        out.push_str(&format!("== {}\n", header));
+        for (decision, file) in &group.files {
+            let word = match decision {
+                Decision::Keep => "keep  ",
+                Decision::Delete => "delete",
+                Decision::Link => "link  ",
+            };
+            out.push_str(&format!("{} {}\n", word, file.display()));
+        }
+    }
+
+    let mut file =
+        fs::File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+    file.write_all(out.as_bytes())?;
+    Ok(())
+}
+
+// Reads a (possibly edited) review file
+pub fn parse(text: &str) -> Result<Vec<ReviewGroup>> {
+    let mut groups: Vec<ReviewGroup> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix("==") {
+            let kind = match header.split_whitespace().next() {
+                Some("identical") => GroupKind::Identical,
+                Some("similar") => GroupKind::Similar,
+                _ => bail!(
+                    "Line {}: expected \"== identical\" or \"== similar\", found {:?}",
+                    i + 1,
+                    line
+                ),
+            };
+            groups.push(ReviewGroup {
+                kind,
+                files: Vec::new(),
+            });
+            continue;
+        }
+
+        // "delete /some/path with spaces.jpg": the first word, then the rest of the line
+        let (word, path) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let decision = match word {
+            "keep" => Decision::Keep,
+            "delete" => Decision::Delete,
+            "link" => Decision::Link,
+            _ => bail!(
+                "Line {}: expected \"keep\", \"delete\" or \"link\", found {:?}",
+                i + 1,
+                word
+            ),
+        };
+        let path = path.trim_start();
+        if path.is_empty() {
+            bail!("Line {}: missing file path", i + 1);
+        }
+        match groups.last_mut() {
+            Some(group) => group.files.push((decision, PathBuf::from(path))),
+            None => bail!("Line {}: file listed before the first \"==\" header", i + 1),
+        }
+    }
+    Ok(groups)
+}
+
+// Carries out the decisions of the review file at `path`
+pub fn apply(path: &Path, options: &ApplyOptions) -> Result<()> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read review file {:?}", path))?;
+    let groups = parse(&text).with_context(|| format!("Invalid review file {:?}", path))?;
+    apply_groups(&groups, options)
+}
+
+// Carries out the decisions of `groups` (also used by `dedupe --keep`, which skips the review file),
+// once the user agrees to what they destroy (see confirm.rs)
+pub fn apply_groups(groups: &[ReviewGroup], options: &ApplyOptions) -> Result<()> {
+    let prefix = if options.dry_run { "[DRY RUN] " } else { "" };
+    if !options.dry_run {
+        confirm::confirm(&destruction(groups), options.yes)?;
+    }
+    let mut deleted = 0;
+    let mut linked = 0;
+    let mut freed = 0;
+    let mut skipped = 0;
+
+    for (n, group) in groups.iter().enumerate() {
+        let doomed: Vec<(Decision, &Path)> = group
+            .files
+            .iter()
+            .filter(|(decision, _)| *decision != Decision::Keep)
+            .map(|(decision, file)| (*decision, file.as_path()))
+            .collect();
+        // Files listed twice (once "keep", once "delete") don't count as kept
+        let kept: Vec<&Path> = group
+            .files
+            .iter()
+            .filter(|(decision, file)| {
+                *decision == Decision::Keep
+                    && !doomed.iter().any(|(_, doomed)| doomed == file)
+                    && fs::symlink_metadata(file).is_ok()
+            })
+            .map(|(_, file)| file.as_path())
+            .collect();
+        if doomed.is_empty() {
+            continue;
+        }
+        if kept.is_empty() {
+            eprintln!(
+                "{}Skipping group {}: no file in it is kept (mark at least one \"keep\")",
+                prefix,
+                n + 1
+            );
+            skipped += doomed.len();
+            continue;
+        }
+
+        for (decision, file) in doomed {
+            let size = match fs::symlink_metadata(file) {
+                Ok(metadata) => metadata.len(),
+                Err(_) => {
+                    eprintln!("{}Skipping {:?}: it's no longer there", prefix, file);
+                    skipped += 1;
+                    continue;
+                }
+            };
+            if decision == Decision::Link && group.kind != GroupKind::Identical {
+                eprintln!(
+                    "{}Skipping {:?}: only identical files can be replaced by links",
+                    prefix, file
+                );
+                skipped += 1;
+                continue;
+            }
+            // The files may have changed since dedupe looked at them
+            let same_as = kept.iter().find(|k| duplicate::same_content(file, k));
+            if group.kind == GroupKind::Identical && same_as.is_none() {
+                eprintln!(
+                    "{}Skipping {:?}: it's no longer identical to the files you kept",
+                    prefix, file
+                );
+                skipped += 1;
+                continue;
+            }
+
+            match (decision, same_as) {
+                (Decision::Link, Some(original)) => {
+                    if options.dry_run {
+                        println!(
+                            "{}Would replace {:?} with a link to {:?}",
+                            prefix, file, original
+                        );
+                    } else {
+                        match replace_with_link(file, original) {
+                            Ok(kind) => {
+                                println!("Replaced {:?} with a {} to {:?}", file, kind, original)
+                            }
+                            Err(e) => {
+                                eprintln!("Failed to replace {:?} with a link: {}", file, e);
+                                skipped += 1;
+                                continue;
+                            }
+                        }
+                    }
+                    linked += 1;
+                }
+                _ => {
+                    if options.dry_run {
+                        println!("{}Would move {:?} to the trash", prefix, file);
+                    } else if let Err(e) = trash::delete(file) {
+                        eprintln!("Failed to move {:?} to the trash: {}", file, e);
+                        skipped += 1;
+                        continue;
+                    } else {
+                        println!("Moved {:?} to the trash", file);
+                    }
+                    deleted += 1;
+                }
+            }
+            freed += size;
+        }
+    }
+
+    println!("--- Apply Complete ---");
+    let links = if linked > 0 {
+        format!(", replaced {} with links", linked)
+    } else {
+        String::new()
+    };
+    println!(
+        "{}Moved {} files to the trash{} ({}), skipped {}",
+        prefix,
+        deleted,
+        links,
+        format_size(freed),
+        skipped
+    );
+    Ok(())
+}
+
+// What carrying out `groups` destroys, at most (files that changed since are skipped then)
+fn destruction(groups: &[ReviewGroup]) -> Destruction {
+    let mut destruction = Destruction::default();
+    for (decision, file) in groups.iter().flat_map(|group| &group.files) {
+        match decision {
+            Decision::Keep => continue,
+            Decision::Delete => destruction.trashed += 1,
+            Decision::Link => destruction.linked += 1,
+        }
+        destruction.bytes += fs::symlink_metadata(file).map(|m| m.len()).unwrap_or(0);
+    }
+    destruction
+}
+
+// Replaces `file` with a link to its identical copy `original`: a hard link if both are on the
+// same drive, else a symbolic link. The link is made next to `file` and renamed over it, so
+// `file` is never missing. Returns which kind of link it is.
+fn replace_with_link(file: &Path, original: &Path) -> io::Result<&'static str> {
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = file.with_file_name(format!(".{}.dedupe-link", name));
+    let _ = fs::remove_file(&temp);
+    match fs::hard_link(original, &temp) {
+        Ok(()) => {
+            fs::rename(&temp, file).inspect_err(|_| {
+                let _ = fs::remove_file(&temp);
+            })?;
+            Ok("hard link")
+        }
+        Err(_) => {
+            links::repoint(file, &protected::absolute_path(original)?)?;
+            Ok("symbolic link")
+        }
+    }
+}
@@ -0,0 +1,17 @@
+// =============================================================================
+// The version of every machine-readable format the tool writes
+// =============================================================================
+//
+// --print-plan, the journals and the --output jsonl events all carry a "schema_version".
+// Tools that read them can rely on these rules:
+//   - within one version, fields are only ever ADDED (readers should ignore fields they don't know)
+//   - a field is never removed, renamed or given a different meaning without a new version
+//   - optional fields may be left out when they have no value ("duplicate_of", "modified", ...)
+// Journals written before the version was recorded count as version 1.
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Journals written before "schema_version" existed
+pub(crate) fn first_version() -> u32 {
+    1
+}
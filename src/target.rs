@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+// =============================================================================
+// What --path points at: a folder, or a pattern of files in one
+// =============================================================================
+//
+// `--path "~/Downloads/*.pdf"` organizes only the PDFs in ~/Downloads (into ~/Downloads/pdf, like
+// a run on the whole folder would). Unix shells expand patterns before we see them, which makes
+// many arguments out of one, so the pattern goes in quotes; the Windows shell doesn't expand them
+// at all. Either way the tool does it itself:
+//   - the folders before the first part with * ? [ or { are the root (unless a folder with that
+//     name exists: then it's the folder, "Photos [2024]" is no pattern)
+//   - the rest is a glob below the root (a * stays inside one folder, ** crosses folders)
+//   - a leading ~ is the home folder, which quoted paths don't get from the shell either
+
+#[derive(Debug)]
+pub struct Target {
+    // The folder to organize
+    pub root: String,
+    // Only the files below root whose path matches this (None = all of them)
+    pub matching: Option<GlobMatcher>,
+}
+
+// Splits --path into the folder and the pattern, if it has one
+pub fn parse(path: &str) -> Result<Target> {
+    let path = expand_home(path);
+    let components: Vec<Component> = Path::new(&path).components().collect();
+    // A folder that really is called "Photos [2024]" is that folder, not a pattern
+    let Some(first_glob) = (0..components.len()).find(|&i| {
+        matches!(components[i], Component::Normal(part) if part.to_string_lossy().contains(['*', '?', '[', '{']))
+            && !components[..=i].iter().collect::<PathBuf>().exists()
+    }) else {
+        return Ok(Target {
+            root: path,
+            matching: None,
+        });
+    };
+
+    let root: PathBuf = components[..first_glob].iter().collect();
+    let pattern: Vec<String> = components[first_glob..]
+        .iter()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let pattern = pattern.join("/");
+    let glob = GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid pattern {:?} in --path", pattern))?;
+
+    Ok(Target {
+        root: if root.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            root.to_string_lossy().into_owned()
+        },
+        matching: Some(glob.compile_matcher()),
+    })
+}
+
+// How deep below the root the scan has to look for files that can match (None = all the way,
+// the pattern has a **). "*.pdf" only matches files right in the root, "*/*.pdf" one folder down.
+pub fn depth(matching: &GlobMatcher) -> Option<usize> {
+    let pattern = matching.glob().glob();
+    if pattern.contains("**") {
+        return None;
+    }
+    Some(pattern.split('/').count())
+}
+
+// "~/Downloads" -> "/home/me/Downloads" ("~" alone too; "~alice/..." is left alone)
+fn expand_home(path: &str) -> String {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return path.to_string(),
+    };
+    let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    match env::var(home) {
+        Ok(home) => format!("{}{}", home, rest),
+        Err(_) => path.to_string(),
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Copy in 256 KiB pieces, small enough that pacing stays smooth
+const CHUNK_SIZE: usize = 256 * 1024;
+
+// The slowest --throttle there is, in MB/s (about 1 KB/s): below that, one piece takes hours
+pub const MIN_MB_PER_SEC: f64 = 0.001;
+
+// A --throttle we can pace copies at (checked by args::parse_rate and Options::check)
+pub fn is_valid_rate(mb_per_sec: f64) -> bool {
+    mb_per_sec >= MIN_MB_PER_SEC && mb_per_sec.is_finite()
+}
+
+// A shared speed limit for all copies of a run (so 8 threads copying at once still add up to --throttle).
+// It remembers when we started and how many bytes went out since then; if we're ahead of
+// schedule, the thread that just wrote a chunk sleeps until the average speed is back under the limit.
+pub struct Throttle {
+    bytes_per_sec: f64,
+    // (start time, bytes copied since then)
+    state: Mutex<(Instant, u64)>,
+}
+
+impl Throttle {
+    pub fn new(mb_per_sec: f64) -> Throttle {
+        Throttle {
+            bytes_per_sec: mb_per_sec * 1024.0 * 1024.0,
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    // Record `bytes` as sent and sleep if that puts us over the limit
+    fn pace(&self, bytes: u64) {
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+            state.1 += bytes;
+            // Too long for a Duration is as long as it gets
+            let expected = Duration::try_from_secs_f64(state.1 as f64 / self.bytes_per_sec)
+                .unwrap_or(Duration::MAX);
+            expected.saturating_sub(state.0.elapsed())
+        }; // lock released here, so other threads aren't blocked while we sleep
+
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    // Like fs::copy, but in chunks with pacing in between
+    pub fn copy(&self, src: &Path, dest: &Path) -> io::Result<u64> {
+        let mut reader = File::open(src)?;
+        let mut writer = File::create(dest)?;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut total = 0;
+
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buf[..n])?;
+            total += n as u64;
+            self.pace(n as u64);
+        }
+
+        writer.flush()?;
+        // fs::copy also carries over the permission bits, so do the same
+        fs::set_permissions(dest, reader.metadata()?.permissions())?;
+        Ok(total)
+    }
+}
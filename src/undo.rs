@@ -0,0 +1,406 @@
+use crate::journal::{self, Entry, Integrity};
+use crate::links;
+use crate::logic;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// Undo: put files back where an earlier run found them, using its journal
+// =============================================================================
+//
+// Each reverted move is appended to the journal as an "undo" entry, so running
+// undo again never tries to revert the same move twice, and a partial undo
+// (e.g. only --ext pdf) can be followed by another one for the rest.
+
+// Which runs to look at and which of their moves to revert
+#[derive(Debug, Clone, Default)]
+pub struct UndoOptions {
+    // Start of a run ID; None = the latest run (or every run with --since)
+    pub run: Option<String>,
+    // Only these (lowercase) extensions, empty = all
+    pub exts: Vec<String>,
+    // Only files whose name or original path matches one of these globs, empty = all
+    pub patterns: Vec<String>,
+    // Only moves made at or after this time
+    pub since: Option<DateTime<Utc>>,
+    // Move files back even if they were changed after the run moved them
+    pub force: bool,
+    pub dry_run: bool,
+    // Where journals are kept (None = the platform's state folder, see paths.rs)
+    pub state_dir: Option<PathBuf>,
+}
+
+// The filters of UndoOptions (and `query`), ready to test moves against
+pub(crate) struct Filter {
+    exts: Vec<String>,
+    globs: Option<GlobSet>,
+    since: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    // `exts` without dots, any case; `patterns` globs on the name or original path
+    pub(crate) fn new(
+        exts: &[String],
+        patterns: &[String],
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Filter> {
+        let globs = if patterns.is_empty() {
+            None
+        } else {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(
+                    Glob::new(pattern).with_context(|| format!("Invalid glob {:?}", pattern))?,
+                );
+            }
+            Some(builder.build()?)
+        };
+
+        Ok(Filter {
+            exts: exts
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+            globs,
+            since,
+        })
+    }
+
+    // Does the move of `from` (its original path) at `at` pass every filter?
+    pub(crate) fn matches(&self, from: &Path, at: &DateTime<Utc>) -> bool {
+        if let Some(since) = self.since {
+            if *at < since {
+                return false;
+            }
+        }
+
+        // "pdf" for "report.pdf", and "gz" as well as "tar.gz" for "backup.tar.gz"
+        if !self.exts.is_empty() {
+            let name = from
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let has = |ext: &String| {
+                name.strip_suffix(ext.as_str())
+                    .and_then(|stem| stem.strip_suffix('.'))
+                    .is_some_and(|stem| !stem.is_empty())
+            };
+            if !self.exts.iter().any(has) {
+                return false;
+            }
+        }
+
+        if let Some(globs) = &self.globs {
+            let name_matches = from.file_name().is_some_and(|n| globs.is_match(n));
+            if !name_matches && !globs.is_match(from) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// A journaled move that hasn't been reverted yet
+struct Pending {
+    from: PathBuf,
+    to: PathBuf,
+    size: u64,
+    at: DateTime<Utc>,
+    modified: Option<DateTime<Utc>>,
+    // False if only the intent was recorded: the run stopped (crashed) before it could write the
+    // "move" line, so the file may or may not have been moved
+    confirmed: bool,
+}
+
+impl Pending {
+    // Why the file at `current` doesn't look like the one the run moved there, if it doesn't:
+    // edited since, or replaced by a different file with the same name
+    fn changed(&self, current: &Path) -> Option<String> {
+        let metadata = fs::symlink_metadata(current).ok()?;
+        if metadata.len() != self.size {
+            return Some(format!(
+                "its size changed from {} to {} bytes",
+                self.size,
+                metadata.len()
+            ));
+        }
+        let now = journal::modified_time(current);
+        match (self.modified, now) {
+            (Some(then), Some(now)) if then != now => Some(format!(
+                "it was modified at {} (after the run)",
+                now.format("%Y-%m-%d %H:%M:%S UTC")
+            )),
+            _ => None,
+        }
+    }
+}
+
+// The run ID and the moves of one journal that haven't been undone yet (in the order they happened)
+fn pending_moves(entries: &[Entry]) -> (Option<String>, Vec<Pending>) {
+    let mut run_id = None;
+    let mut undone: HashSet<(&Path, &Path)> = HashSet::new();
+    let mut moved: HashSet<(&Path, &Path)> = HashSet::new();
+    for entry in entries {
+        match entry {
+            Entry::Run { run_id: id, .. } => run_id = Some(id.to_string()),
+            Entry::Undo { from, to, .. } => {
+                undone.insert((from, to));
+            }
+            Entry::Move { from, to, .. } => {
+                moved.insert((from, to));
+            }
+            _ => {}
+        }
+    }
+
+    let moves = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Move {
+                from,
+                to,
+                size,
+                at,
+                modified,
+                ..
+            } if !undone.contains(&(from.as_path(), to.as_path())) => Some(Pending {
+                from: from.clone(),
+                to: to.clone(),
+                size: *size,
+                at: *at,
+                modified: *modified,
+                confirmed: true,
+            }),
+            // An intent without its move line: the run was cut off around this move
+            Entry::Intent { from, to, size, at }
+                if !moved.contains(&(from.as_path(), to.as_path()))
+                    && !undone.contains(&(from.as_path(), to.as_path())) =>
+            {
+                Some(Pending {
+                    from: from.clone(),
+                    to: to.clone(),
+                    size: *size,
+                    at: *at,
+                    modified: None,
+                    confirmed: false,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    (run_id, moves)
+}
+
+// Undoes (parts of) earlier runs on `target_path`
+pub fn undo(target_path: &str, options: &UndoOptions) -> Result<()> {
+    let root = Path::new(target_path);
+    let filter = Filter::new(&options.exts, &options.patterns, options.since)?;
+
+    let journals = journal::list(root, options.state_dir.as_deref())?;
+    if journals.is_empty() {
+        bail!("No runs recorded for {:?}, nothing to undo", root);
+    }
+
+    // Newest first: later runs may have moved files that earlier runs put in place
+    let mut selected = Vec::new();
+    for path in journals.iter().rev() {
+        // A damaged journal is only read up to the damage (see journal::verify)
+        let integrity = journal::verify(path)?;
+        let entries = match &integrity {
+            Integrity::Damaged { line, .. } => journal::read_before(path, *line)?,
+            _ => journal::read(path)?,
+        };
+        let (run_id, moves) = pending_moves(&entries);
+        let run_id = run_id.unwrap_or_default();
+
+        match &options.run {
+            Some(wanted) if !run_id.starts_with(wanted.as_str()) => continue,
+            Some(_) => {}
+            // Default: the latest run that still has something to undo (or all of them with --since)
+            None if moves.is_empty() => continue,
+            None => {}
+        }
+
+        match integrity {
+            Integrity::Damaged { line, problem } if !options.force => bail!(
+                "The journal of run {} is damaged on line {} ({}), see `journal verify`. \
+                 Add --force to undo only the moves recorded before that line",
+                run_id,
+                line,
+                problem
+            ),
+            Integrity::Damaged { line, .. } => eprintln!(
+                "The journal of run {} is damaged on line {}, undoing only the moves recorded before it",
+                run_id, line
+            ),
+            Integrity::Unfinished => eprintln!(
+                "Run {} didn't finish (it was interrupted), undoing the moves it recorded",
+                run_id
+            ),
+            Integrity::Intact | Integrity::Unchecked => {}
+        }
+
+        let created: HashSet<PathBuf> = entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::CreateDir { path, .. } => Some(root.join(path)),
+                _ => None,
+            })
+            .collect();
+        selected.push((path.clone(), run_id, moves, created));
+        if options.run.is_some() || options.since.is_none() {
+            break;
+        }
+    }
+
+    if selected.is_empty() {
+        match &options.run {
+            Some(wanted) => bail!("No run with ID {:?} in {:?}", wanted, root),
+            None => {
+                println!("Every recorded run has already been undone");
+                return Ok(());
+            }
+        }
+    }
+
+    let prefix = if options.dry_run { "[DRY RUN] " } else { "" };
+    let mut restored = 0;
+    let mut skipped = 0;
+
+    for (journal_path, run_id, moves, created) in selected {
+        println!("Undoing run {}", run_id);
+
+        // Last move first, the exact reverse of the run
+        for pending in moves
+            .iter()
+            .rev()
+            .filter(|p| filter.matches(&p.from, &p.at))
+        {
+            let current = root.join(&pending.to);
+            let original = root.join(&pending.from);
+
+            if fs::symlink_metadata(&current).is_err() {
+                // The move this intent announced never happened, the file is still where it was
+                if !pending.confirmed && fs::symlink_metadata(&original).is_ok() {
+                    continue;
+                }
+                eprintln!("{}Skipping {:?}: it's no longer there", prefix, current);
+                skipped += 1;
+                continue;
+            }
+            if fs::symlink_metadata(&original).is_ok() {
+                eprintln!(
+                    "{}Skipping {:?}: something else is at {:?} now",
+                    prefix, current, original
+                );
+                skipped += 1;
+                continue;
+            }
+            // Moving back a file that changed since could bury newer data in an old folder layout
+            if let Some(change) = pending.changed(&current) {
+                if !options.force {
+                    eprintln!(
+                        "{}Skipping {:?}: {} (use --force to move it back anyway)",
+                        prefix, current, change
+                    );
+                    skipped += 1;
+                    continue;
+                }
+                eprintln!("{}{:?} changed since the run: {}", prefix, current, change);
+            }
+
+            if options.dry_run {
+                println!("{}Would move {:?} -> {:?}", prefix, current, original);
+                restored += 1;
+                continue;
+            }
+
+            if let Err(e) = move_back(&current, &original) {
+                eprintln!("Failed to move {:?} back: {}", current, e);
+                skipped += 1;
+                continue;
+            }
+            println!("Moved {:?} -> {:?}", current, original);
+            restored += 1;
+
+            journal::append(
+                &journal_path,
+                &Entry::Undo {
+                    from: pending.from.clone(),
+                    to: pending.to.clone(),
+                    at: Utc::now(),
+                },
+            )?;
+
+            // Remove the folders the run created, once they're empty again ("pdf/sub/dir", then
+            // "pdf/sub", then "pdf" with --preserve-structure). Only the ones its journal recorded
+            // creating: an empty pdf/ that was there before the run stays. remove_dir fails on a
+            // folder that isn't empty, which ends the loop. Only folders below root: a destination
+            // outside of it (--into-user-dirs) went into a folder that was there before, like
+            // ~/Pictures. (Journals written before folders were recorded leave them all.)
+            for folder in current
+                .ancestors()
+                .skip(1)
+                .take_while(|f| f.starts_with(root) && *f != root)
+            {
+                if !created.contains(folder) || fs::remove_dir(folder).is_err() {
+                    break;
+                }
+            }
+        }
+
+        if !options.dry_run {
+            relink_back(root, &journal_path)?;
+        }
+    }
+
+    println!("--- Undo Complete ---");
+    println!("{}Restored {} files, skipped {}", prefix, restored, skipped);
+    Ok(())
+}
+
+// Points the links that the run updated (--link-policy update) back at their old targets, once
+// those are there again. A link that was changed since, or whose file wasn't put back (e.g. undo
+// --ext for another type), is left alone; so running this twice changes nothing.
+fn relink_back(root: &Path, journal_path: &Path) -> Result<()> {
+    // Only up to the damage, like the moves (undo --force on a damaged journal)
+    let entries = match journal::verify(journal_path)? {
+        Integrity::Damaged { line, .. } => journal::read_before(journal_path, line)?,
+        _ => journal::read(journal_path)?,
+    };
+    for entry in entries.iter().rev() {
+        let Entry::Relink { link, old, new, .. } = entry else {
+            continue;
+        };
+        let link = root.join(link);
+        let points_at_new = links::read_target(&link).is_ok_and(|current| current == *new);
+        let folder = link.parent().unwrap_or(root);
+        if points_at_new && folder.join(old).exists() {
+            match links::set_target(&link, old) {
+                Ok(()) => println!("Pointed the link {:?} back at {:?}", link, old),
+                Err(e) => eprintln!("Failed to point the link {:?} back: {}", link, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+// rename(), or copy + delete if the original location is on another drive
+fn move_back(current: &Path, original: &Path) -> io::Result<()> {
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(current, original) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            logic::copy_then_remove(current, original, None)
+        }
+        result => result,
+    }
+}
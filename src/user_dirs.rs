@@ -0,0 +1,174 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// =============================================================================
+// --into-user-dirs: the platform's own folders for pictures, music, ...
+// =============================================================================
+//
+// Every desktop already has folders for these: ~/Pictures, ~/Music, ~/Videos (~/Movies on macOS)
+// and ~/Documents. `--into-user-dirs` sends those categories there, instead of into new Images/,
+// Audio/, ... folders below the organized folder, so sorting ~/Downloads files the photos where the
+// photo apps look for them. Categories without such a folder (archives, code, programs, fonts,
+// junk) are organized below the root as usual.
+//
+// Where the folders are is asked of the platform, because people move (or translate) them:
+//   Linux:   ~/.config/user-dirs.dirs, XDG_PICTURES_DIR="$HOME/Bilder" (xdg-user-dirs), else ~/Pictures
+//   macOS:   ~/Pictures, ~/Music, ~/Movies, ~/Documents
+//   Windows: the Known Folders (SHGetKnownFolderPath), which also know about folders moved to OneDrive
+// Only folders that exist are used; the run never creates a user folder of its own.
+
+// A category that has a user folder, and the names of that folder
+struct UserDir {
+    // The built-in category (see category.rs)
+    category: &'static str,
+    // The name in user-dirs.dirs, without XDG_ and _DIR
+    xdg: &'static str,
+    // The folder in the home folder, if the platform doesn't say otherwise
+    name: &'static str,
+    // The same on macOS, where videos go to ~/Movies
+    mac_name: &'static str,
+}
+
+const USER_DIRS: &[UserDir] = &[
+    UserDir {
+        category: "images",
+        xdg: "PICTURES",
+        name: "Pictures",
+        mac_name: "Pictures",
+    },
+    UserDir {
+        category: "videos",
+        xdg: "VIDEOS",
+        name: "Videos",
+        mac_name: "Movies",
+    },
+    UserDir {
+        category: "audio",
+        xdg: "MUSIC",
+        name: "Music",
+        mac_name: "Music",
+    },
+    UserDir {
+        category: "documents",
+        xdg: "DOCUMENTS",
+        name: "Documents",
+        mac_name: "Documents",
+    },
+];
+
+// (category, folder) for every category whose user folder exists on this machine
+pub fn find() -> Vec<(&'static str, PathBuf)> {
+    USER_DIRS
+        .iter()
+        .filter_map(|dir| Some((dir.category, folder(dir)?)))
+        .filter(|(_, folder)| folder.is_dir())
+        .collect()
+}
+
+fn home() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|home| home.is_absolute())
+}
+
+// Where the user folder of `dir` is on this platform
+fn folder(dir: &UserDir) -> Option<PathBuf> {
+    let home = home()?;
+    #[cfg(windows)]
+    if let Some(folder) = known_folder(dir.category) {
+        return Some(folder);
+    }
+    if cfg!(not(any(windows, target_os = "macos"))) {
+        match configured(dir.xdg, &home) {
+            Some(Configured::Folder(folder)) => return Some(folder),
+            Some(Configured::Off) => return None,
+            None => {}
+        }
+    }
+    let name = if cfg!(target_os = "macos") {
+        dir.mac_name
+    } else {
+        dir.name
+    };
+    Some(home.join(name))
+}
+
+// Where Windows keeps the known folder of `category` right now
+#[cfg(windows)]
+fn known_folder(category: &str) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use windows_sys::Win32::System::Com::CoTaskMemFree;
+    use windows_sys::Win32::UI::Shell::{
+        FOLDERID_Documents, FOLDERID_Music, FOLDERID_Pictures, FOLDERID_Videos,
+        SHGetKnownFolderPath, KF_FLAG_DEFAULT,
+    };
+
+    let id = match category {
+        "images" => &FOLDERID_Pictures,
+        "videos" => &FOLDERID_Videos,
+        "audio" => &FOLDERID_Music,
+        _ => &FOLDERID_Documents,
+    };
+    let mut path: windows_sys::core::PWSTR = std::ptr::null_mut();
+    // SAFETY: `id` is a valid GUID and `path` a place for the answer. A null token means the
+    // current user. The answer is a NUL-terminated string we have to free, even on failure.
+    let result = unsafe {
+        SHGetKnownFolderPath(id, KF_FLAG_DEFAULT as u32, std::ptr::null_mut(), &mut path)
+    };
+    let folder = (result == 0 && !path.is_null()).then(|| {
+        // SAFETY: on success `path` points to a NUL-terminated UTF-16 string
+        let wide = unsafe {
+            let len = (0..).take_while(|&i| *path.add(i) != 0).count();
+            std::slice::from_raw_parts(path, len)
+        };
+        PathBuf::from(OsString::from_wide(wide))
+    });
+    // SAFETY: `path` came from SHGetKnownFolderPath (or is null, which is allowed)
+    unsafe { CoTaskMemFree(path as *const _) };
+    folder
+}
+
+// What ~/.config/user-dirs.dirs (or the one in $XDG_CONFIG_HOME) says about the folder `name`
+fn configured(name: &str, home: &Path) -> Option<Configured> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|config| config.is_absolute())
+        .unwrap_or_else(|| home.join(".config"));
+    let text = fs::read_to_string(config.join("user-dirs.dirs")).ok()?;
+    from_user_dirs(&text, name, home)
+}
+
+// What user-dirs.dirs says about one folder
+#[derive(Debug, PartialEq, Eq)]
+pub enum Configured {
+    Folder(PathBuf),
+    // Set to the home folder itself, which is how the folder is turned off
+    Off,
+}
+
+// The line for `name` ("PICTURES") in the text of a user-dirs.dirs file, which looks like
+//   XDG_PICTURES_DIR="$HOME/Bilder"
+//   XDG_MUSIC_DIR="/mnt/music"
+// Only "$HOME/..." and absolute paths are allowed there, anything else is left out.
+pub fn from_user_dirs(text: &str, name: &str, home: &Path) -> Option<Configured> {
+    let key = format!("XDG_{}_DIR", name);
+    let value = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(k, _)| k.trim() == key)
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        // The last one wins, like in the shell that reads the file
+        .next_back()?;
+    let folder = match value.strip_prefix("$HOME") {
+        Some(rest) if rest.is_empty() || rest == "/" => return Some(Configured::Off),
+        Some(rest) => home.join(rest.strip_prefix('/')?),
+        None if Path::new(value).is_absolute() => PathBuf::from(value),
+        None => return None,
+    };
+    Some(Configured::Folder(folder))
+}
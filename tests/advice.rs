@@ -0,0 +1,51 @@
+mod common;
+
+use common::Fixture;
+use directory_cleaner::advice::advise;
+use directory_cleaner::logic::Options;
+use directory_cleaner::plan::plan;
+use std::path::PathBuf;
+
+fn files(fx: &Fixture, rels: &[&str]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = rels.iter().map(|r| fx.root().join(r)).collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn suggests_merging_tiny_folders() {
+    let fx = Fixture::new()
+        .file("a.xyz", "a")
+        .file("b.abc", "b")
+        .file("c.pdf", "c")
+        .file("d.pdf", "d")
+        .file("pdf/e.pdf", "e");
+    let rels = ["a.xyz", "b.abc", "c.pdf", "d.pdf", "pdf/e.pdf"];
+    let actions = plan(&files(&fx, &rels), fx.root(), &Options::default());
+
+    // pdf/ ends up with 3 files, so it's left out
+    assert_eq!(
+        advise(&actions, fx.root(), true),
+        vec!["abc/, xyz/ hold only 1-2 files each: merge them into one folder with --map \"abc,xyz=Misc\""]
+    );
+}
+
+#[test]
+fn points_out_folders_full_of_copies() {
+    let fx = Fixture::new()
+        .file("pdf/report.pdf", "1")
+        .file("old/report.pdf", "2")
+        .file("jpg/scan.jpg", "3")
+        .file("old/scan.jpg", "4")
+        .file("txt/notes.txt", "5")
+        .file("old/notes.txt", "6")
+        .file("old/song.mp3", "7");
+    let rels = fx.files();
+    let rels: Vec<&str> = rels.iter().map(|s| s.as_str()).collect();
+    let actions = plan(&files(&fx, &rels), fx.root(), &Options::default());
+
+    let advice = advise(&actions, fx.root(), true);
+    let hotspots: Vec<&String> = advice.iter().filter(|l| l.starts_with("old/")).collect();
+    assert_eq!(hotspots.len(), 1);
+    assert!(hotspots[0].starts_with("old/ had 3 files whose name was taken"));
+}
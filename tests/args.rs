@@ -0,0 +1,117 @@
+use clap::Parser;
+use directory_cleaner::args::{parse_duration, parse_rate, parse_time, Cli, Command};
+use directory_cleaner::target;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+// The only test in this file that touches the environment, so nothing else races with it
+#[test]
+fn environment_variables_fill_in_missing_flags() {
+    std::env::set_var("DIRCLEANER_PATH", "/from/env");
+    std::env::set_var("DIRCLEANER_DRY_RUN", "1");
+    std::env::set_var("DIRCLEANER_THREADS", "3");
+
+    let cli = Cli::try_parse_from(["directory-cleaner"]).unwrap();
+    assert_eq!(cli.path.as_deref(), Some("/from/env"));
+    assert!(cli.dry_run);
+    assert_eq!(cli.threads, NonZeroUsize::new(3));
+
+    // Flags on the command line win
+    let cli = Cli::try_parse_from([
+        "directory-cleaner",
+        "--path",
+        "/from/flag",
+        "--threads",
+        "1",
+    ])
+    .unwrap();
+    assert_eq!(cli.path.as_deref(), Some("/from/flag"));
+    assert_eq!(cli.threads, NonZeroUsize::new(1));
+
+    // Subcommands read them too
+    let cli = Cli::try_parse_from(["directory-cleaner", "undo"]).unwrap();
+    match cli.command {
+        Some(Command::Undo(undo)) => {
+            assert_eq!(undo.path, "/from/env");
+            assert!(undo.dry_run);
+        }
+        other => panic!("parsed {:?}", other),
+    }
+
+    std::env::set_var("DIRCLEANER_DRY_RUN", "false");
+    assert!(!Cli::try_parse_from(["directory-cleaner"]).unwrap().dry_run);
+    std::env::set_var("DIRCLEANER_THREADS", "0");
+    assert!(Cli::try_parse_from(["directory-cleaner"]).is_err());
+}
+
+#[test]
+fn path_with_a_pattern_is_split_into_folder_and_glob() {
+    let plain = target::parse("/home/me/Downloads").unwrap();
+    assert_eq!(plain.root, "/home/me/Downloads");
+    assert!(plain.matching.is_none());
+
+    let pdfs = target::parse("/home/me/Downloads/*.pdf").unwrap();
+    assert_eq!(pdfs.root, "/home/me/Downloads");
+    let matching = pdfs.matching.unwrap();
+    assert!(matching.is_match("report.pdf"));
+    assert!(!matching.is_match("report.txt"));
+    // * stays inside one folder
+    assert!(!matching.is_match("sub/report.pdf"));
+    assert_eq!(target::depth(&matching), Some(1));
+
+    let deep = target::parse("inbox/**/*.{jpg,png}").unwrap();
+    assert_eq!(deep.root, "inbox");
+    let matching = deep.matching.unwrap();
+    assert!(matching.is_match("2024/june/a.png"));
+    assert_eq!(target::depth(&matching), None);
+
+    assert_eq!(target::parse("*.pdf").unwrap().root, ".");
+    assert!(target::parse("/tmp/[oops").is_err());
+}
+
+#[test]
+fn folders_that_look_like_patterns_are_still_folders() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let photos = dir.path().join("Photos [2024]");
+    std::fs::create_dir(&photos).unwrap();
+    let photos = photos.to_str().unwrap();
+
+    let folder = target::parse(photos).unwrap();
+    assert_eq!(folder.root, photos);
+    assert!(folder.matching.is_none());
+
+    // A pattern inside it is still one
+    let jpgs = target::parse(&format!("{}/*.jpg", photos)).unwrap();
+    assert_eq!(jpgs.root, photos);
+    assert!(jpgs.matching.unwrap().is_match("beach.jpg"));
+}
+
+#[test]
+fn durations_need_a_unit() {
+    assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+    assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+    assert!(parse_duration("10").is_err());
+    assert!(parse_duration("0m").is_err());
+    assert!(parse_duration("ten minutes").is_err());
+}
+
+// A speed so slow that the wait for one piece wouldn't fit in a Duration is refused up front
+#[test]
+fn speeds_below_the_minimum_are_an_error() {
+    assert_eq!(parse_rate("0.5"), Ok(0.5));
+    assert_eq!(parse_rate("0.001"), Ok(0.001));
+    assert!(parse_rate("1e-300").is_err());
+    assert!(parse_rate("0").is_err());
+    assert!(parse_rate("inf").is_err());
+}
+
+#[test]
+fn times_too_long_ago_are_an_error() {
+    let day_ago = parse_time("1d").unwrap();
+    let age = chrono::Utc::now() - day_ago;
+    assert!((age.num_hours() - 24).abs() <= 1);
+    assert!(parse_time("99999999999999w").is_err());
+    assert!(parse_time("9223372036854775807s").is_err());
+    assert!(parse_time("3 days").is_err());
+}
@@ -0,0 +1,107 @@
+// External classifiers (--classifier): programs that decide where files go
+#![cfg(unix)]
+mod common;
+
+use common::Fixture;
+use directory_cleaner::classify::Classifier;
+use directory_cleaner::logic::{self, Options};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+// Writes the shell script `body` as dircleaner-classify-<name> into `dir`
+fn classifier(dir: &Path, name: &str, body: &str) -> Classifier {
+    let program = dir.join(format!("dircleaner-classify-{}", name));
+    fs::write(&program, format!("#!/bin/sh\n{}", body)).unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+    Classifier {
+        name: name.to_string(),
+        program,
+    }
+}
+
+// Sends every file with "invoice" in its path to Invoices/, and has no opinion about the rest
+const INVOICES: &str = r#"while IFS= read -r line; do
+  case "$line" in
+    *invoice*) echo '{"folder":"Invoices","category":"invoices"}' ;;
+    *) echo '{}' ;;
+  esac
+done
+"#;
+
+#[test]
+fn a_classifier_decides_where_its_files_go() {
+    let bin = TempDir::new().unwrap();
+    let fx = Fixture::new()
+        .file("invoice-0042.pdf", "i")
+        .file("report.pdf", "r")
+        .file("notes.txt", "n");
+
+    fx.organize(&Options {
+        classifiers: vec![classifier(bin.path(), "invoices", INVOICES)],
+        ..Options::default()
+    });
+
+    // No answer: the usual rules
+    assert_eq!(
+        fx.files(),
+        vec![
+            "Invoices/invoice-0042.pdf",
+            "pdf/report.pdf",
+            "txt/notes.txt"
+        ]
+    );
+}
+
+#[test]
+fn a_classifier_cannot_send_files_outside_the_folder() {
+    let bin = TempDir::new().unwrap();
+    let fx = Fixture::new().file("a.pdf", "a").file("b.pdf", "b");
+    let escape = classifier(
+        bin.path(),
+        "escape",
+        "while read -r line; do echo '{\"folder\":\"../elsewhere\"}'; done\n",
+    );
+
+    let options = Options {
+        classifiers: vec![escape],
+        state_dir: Some(fx.state_dir().to_path_buf()),
+        ..Options::default()
+    };
+    let error = logic::process_directory(fx.root().to_str().unwrap(), &options).unwrap_err();
+    assert!(format!("{:#}", error).contains("isn't inside the organized folder"));
+    assert_eq!(fx.files(), vec!["a.pdf", "b.pdf"]);
+}
+
+#[test]
+fn classifiers_are_found_on_path() {
+    let bin = TempDir::new().unwrap();
+    classifier(bin.path(), "invoices", INVOICES);
+    let fx = Fixture::new()
+        .file("invoice-0042.pdf", "i")
+        .file("report.pdf", "r");
+    let path = format!(
+        "{}:{}",
+        bin.path().display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let run = |name: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_directory-cleaner"))
+            .args(["--path", fx.root().to_str().unwrap(), "--classifier", name])
+            .args(["--state-dir", fx.state_dir().to_str().unwrap()])
+            .env("PATH", &path)
+            .output()
+            .unwrap()
+    };
+
+    let output = run("nope");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("found: invoices"));
+
+    assert!(run("invoices").status.success());
+    assert_eq!(
+        fx.files(),
+        vec!["Invoices/invoice-0042.pdf", "pdf/report.pdf"]
+    );
+}
@@ -0,0 +1,121 @@
+// Shared helpers for the integration tests: build a throwaway directory tree, run the organizer on it,
+// then look at what ended up where.
+#![allow(dead_code)] // each test file only uses some of these
+
+use directory_cleaner::journal;
+use directory_cleaner::logic::{self, Options};
+use directory_cleaner::repair::{self, RepairOptions, Repaired};
+use directory_cleaner::undo::{self, UndoOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+// A temporary directory that is deleted when the Fixture is dropped, with a second one as the
+// state folder (so the tests never write journals into the real ~/.local/state). Both are made in
+// the build's scratch folder, not the system's temp dir: on Windows that one is inside AppData,
+// which the organizer refuses to touch.
+pub struct Fixture {
+    dir: TempDir,
+    state: TempDir,
+}
+
+pub fn scratch_dir() -> TempDir {
+    TempDir::new_in(env!("CARGO_TARGET_TMPDIR")).expect("failed to create temp dir")
+}
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        Fixture {
+            dir: scratch_dir(),
+            state: scratch_dir(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        self.state.path()
+    }
+
+    // Creates a file (and its parent folders) at `rel`, containing `contents`
+    pub fn file(self, rel: &str, contents: &str) -> Fixture {
+        let path = self.root().join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        self
+    }
+
+    // Runs the organizer on the fixture's root (journals go to the fixture's state folder)
+    pub fn organize(&self, options: &Options) {
+        let options = Options {
+            state_dir: Some(self.state_dir().to_path_buf()),
+            ..options.clone()
+        };
+        logic::process_directory(self.root().to_str().unwrap(), &options)
+            .expect("process_directory failed");
+    }
+
+    // Undoes earlier runs on the fixture's root
+    pub fn undo(&self, options: &UndoOptions) {
+        let options = UndoOptions {
+            state_dir: Some(self.state_dir().to_path_buf()),
+            ..options.clone()
+        };
+        undo::undo(self.root().to_str().unwrap(), &options).expect("undo failed");
+    }
+
+    // Repairs what interrupted runs left behind in the fixture's root
+    pub fn repair(&self, options: &RepairOptions) -> Repaired {
+        let options = RepairOptions {
+            state_dir: Some(self.state_dir().to_path_buf()),
+            ..options.clone()
+        };
+        repair::repair(self.root().to_str().unwrap(), &options).expect("repair failed")
+    }
+
+    // The journals of the runs on root, oldest first
+    pub fn journals(&self) -> Vec<PathBuf> {
+        journal::list(self.root(), Some(self.state_dir())).unwrap()
+    }
+
+    // Every file below root as a sorted list of "/"-separated relative paths
+    // (the organizer's own journal folder is left out)
+    pub fn files(&self) -> Vec<String> {
+        files_below(self.root())
+            .iter()
+            .map(|p| {
+                let rel = p.strip_prefix(self.root()).unwrap();
+                let parts: Vec<String> = rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                parts.join("/")
+            })
+            .filter(|rel| !rel.starts_with(journal::STATE_DIR))
+            .collect()
+    }
+
+    pub fn read(&self, rel: &str) -> String {
+        fs::read_to_string(self.root().join(rel)).unwrap()
+    }
+}
+
+// A small recursive listing (symlinks are listed, not followed), sorted
+fn files_below(dir: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                stack.push(entry.path());
+            } else {
+                out.push(entry.path());
+            }
+        }
+    }
+    out.sort();
+    out
+}
@@ -0,0 +1,98 @@
+// Questions asked from several threads at once: each one gets its own answer, in one piece
+use directory_cleaner::confirm::ask_on;
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::{Barrier, Mutex};
+use std::thread;
+
+// A made-up terminal: what was printed and typed, in order, and the answers still to be typed
+struct Terminal {
+    transcript: Mutex<String>,
+    answers: Mutex<VecDeque<&'static str>>,
+}
+
+// Prints one character at a time, letting the other threads in between (the way a real
+// terminal could cut a question up)
+struct Screen<'a>(&'a Terminal);
+
+impl Write for Screen<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.0.transcript.lock().unwrap().push(byte as char);
+            thread::yield_now();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Types the next answer when it's read, and echoes it like a terminal does
+struct Keyboard<'a> {
+    terminal: &'a Terminal,
+    typed: Option<io::Cursor<Vec<u8>>>,
+}
+
+impl Read for Keyboard<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let terminal = self.terminal;
+        let typed = self.typed.get_or_insert_with(|| {
+            let answer = terminal.answers.lock().unwrap().pop_front().unwrap_or("");
+            let line = format!("{}\n", answer);
+            terminal.transcript.lock().unwrap().push_str(&line);
+            io::Cursor::new(line.into_bytes())
+        });
+        typed.read(buf)
+    }
+}
+
+#[test]
+fn questions_from_two_threads_keep_their_answers() {
+    let terminal = Terminal {
+        transcript: Mutex::new(String::new()),
+        answers: Mutex::new(VecDeque::from(["yes", "no"])),
+    };
+    let start = Barrier::new(2);
+    let ask = |question: &'static str| {
+        start.wait();
+        let answer = ask_on(question, || {
+            (
+                BufReader::new(Keyboard {
+                    terminal: &terminal,
+                    typed: None,
+                }),
+                Screen(&terminal),
+            )
+        })
+        .unwrap();
+        (question, answer)
+    };
+
+    let asked = thread::scope(|s| {
+        let first = s.spawn(|| ask("Trash 3 files? [y/N]"));
+        let second = s.spawn(|| ask("Replace 2 files with links? [y/N]"));
+        [first.join().unwrap(), second.join().unwrap()]
+    });
+
+    // Every line on the terminal is one whole question followed by the answer its thread got
+    let transcript = terminal.transcript.into_inner().unwrap();
+    let lines: Vec<&str> = transcript.lines().collect();
+    assert_eq!(lines.len(), 2, "{:?}", transcript);
+    for (question, answer) in asked {
+        let line = format!("{} {}", question, answer);
+        assert!(
+            lines.contains(&line.as_str()),
+            "{:?} in {:?}",
+            line,
+            transcript
+        );
+    }
+}
+
+#[test]
+fn the_answer_comes_without_the_line_break() {
+    let answer = ask_on("Continue? [y/N]", || (&b"y\r\n"[..], io::sink())).unwrap();
+    assert_eq!(answer, "y");
+}
@@ -0,0 +1,79 @@
+// The console: lines from many threads come out whole and complete, repeats are left out, and
+// --aggregate-moves prints one line per folder
+use directory_cleaner::console::Console;
+use directory_cleaner::logic::Options;
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// A writer the test can read back after the console is finished
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn lines(&self) -> Vec<String> {
+        let text = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+}
+
+// Moves 1000 files, 10 folders of 100, from every core
+fn run(options: &Options) -> (Vec<String>, Vec<String>) {
+    let (out, err) = (Buffer::default(), Buffer::default());
+    let console = Console::with_writers(options, Box::new(out.clone()), Box::new(err.clone()));
+    (0..1000).into_par_iter().for_each(|i| {
+        let from = PathBuf::from(format!("file{}.jpg", i));
+        let to = Path::new(&format!("folder{}", i % 10)).join(&from);
+        console.moved(&from, &to, 10, false);
+        console.error("Warning: the same for every file".to_string());
+    });
+    console.finish();
+    (out.lines(), err.lines())
+}
+
+#[test]
+fn lines_from_many_threads_come_out_whole() {
+    let (out, err) = run(&Options::default());
+    assert_eq!(out.len(), 1000);
+    for i in 0..1000 {
+        let line = format!(
+            "Moved \"file{}.jpg\" -> \"folder{}/file{}.jpg\"",
+            i,
+            i % 10,
+            i
+        );
+        assert!(out.contains(&line), "{:?} is missing", line);
+    }
+    assert_eq!(
+        err,
+        vec![
+            "Warning: the same for every file",
+            "(999 repeated messages left out)"
+        ]
+    );
+}
+
+#[test]
+fn aggregated_moves_are_one_line_per_folder() {
+    let (out, _) = run(&Options {
+        aggregate_moves: true,
+        dry_run: true,
+        ..Options::default()
+    });
+    let expected: Vec<String> = (0..10)
+        .map(|i| format!("[DRY RUN] Would move 100 files (1000 B) to \"folder{}\"", i))
+        .collect();
+    assert_eq!(out, expected);
+}
@@ -0,0 +1,407 @@
+// Finding identical files and similar-looking images in temp directories
+mod common;
+
+use common::Fixture;
+use directory_cleaner::args::parse_keep;
+use directory_cleaner::dedupe::{self, DedupeOptions, DuplicateGroup, Keep};
+use directory_cleaner::review::{self, ApplyOptions, Decision, GroupKind, ReviewGroup};
+use image::{imageops, GrayImage, Luma};
+use std::path::PathBuf;
+
+fn all_files(fx: &Fixture) -> Vec<PathBuf> {
+    fx.files().iter().map(|rel| fx.root().join(rel)).collect()
+}
+
+// A 64x64 picture with some structure: light on the left, dark on the right, plus a band
+fn picture() -> GrayImage {
+    GrayImage::from_fn(64, 64, |x, y| {
+        let band = if (20..40).contains(&y) { 60 } else { 0 };
+        Luma([(255 - x * 3).saturating_sub(band) as u8])
+    })
+}
+
+#[test]
+fn finds_identical_files() {
+    let fx = Fixture::new()
+        .file("a.txt", "hello")
+        .file("sub/b.txt", "hello")
+        .file("c.txt", "world")
+        .file("empty1", "")
+        .file("empty2", "");
+
+    let groups = dedupe::find_duplicates(&all_files(&fx), false);
+
+    assert_eq!(
+        groups,
+        vec![DuplicateGroup {
+            size: 5,
+            files: vec![fx.root().join("a.txt"), fx.root().join("sub/b.txt")],
+        }]
+    );
+}
+
+#[test]
+fn biggest_savings_come_first() {
+    let fx = Fixture::new()
+        .file("small1", "ab")
+        .file("small2", "ab")
+        .file("big1", "abcdef")
+        .file("big2", "abcdef");
+
+    let groups = dedupe::find_duplicates(&all_files(&fx), false);
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].size, 6);
+    assert_eq!(groups[0].wasted(), 6);
+}
+
+#[test]
+fn finds_resized_copies_of_an_image() {
+    let fx = Fixture::new();
+    picture().save(fx.root().join("photo.png")).unwrap();
+    std::fs::create_dir(fx.root().join("small")).unwrap();
+    imageops::resize(&picture(), 32, 32, imageops::FilterType::Triangle)
+        .save(fx.root().join("small/photo.jpg"))
+        .unwrap();
+    imageops::flip_horizontal(&picture())
+        .save(fx.root().join("other.png"))
+        .unwrap();
+
+    let groups = dedupe::find_similar_images(&all_files(&fx));
+
+    assert_eq!(groups.len(), 1);
+    let paths: Vec<PathBuf> = groups[0].iter().map(|image| image.path.clone()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            fx.root().join("photo.png"),
+            fx.root().join("small/photo.jpg")
+        ]
+    );
+    assert_eq!((groups[0][1].width, groups[0][1].height), (32, 32));
+}
+
+#[test]
+fn dedupe_only_reports() {
+    let fx = Fixture::new().file("a.txt", "same").file("b.txt", "same");
+
+    dedupe::dedupe(
+        &[fx.root().to_str().unwrap()],
+        &DedupeOptions {
+            perceptual: true,
+            ..DedupeOptions::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(fx.files(), vec!["a.txt", "b.txt"]);
+}
+
+#[test]
+fn review_file_lists_a_decision_for_every_file() {
+    let fx = Fixture::new()
+        .file("a.txt", "same")
+        .file("b.txt", "same")
+        .file("c.txt", "same");
+    let review_path = fx.root().join("review.txt");
+
+    dedupe::dedupe(
+        &[fx.root().to_str().unwrap()],
+        &DedupeOptions {
+            review: Some(review_path.clone()),
+            ..DedupeOptions::default()
+        },
+    )
+    .unwrap();
+
+    let groups = review::parse(&std::fs::read_to_string(&review_path).unwrap()).unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].kind, GroupKind::Identical);
+    let decisions: Vec<Decision> = groups[0].files.iter().map(|(d, _)| *d).collect();
+    assert_eq!(
+        decisions,
+        vec![Decision::Keep, Decision::Delete, Decision::Delete]
+    );
+    // Absolute paths, so the file can be applied from anywhere
+    assert!(groups[0].files.iter().all(|(_, path)| path.is_absolute()));
+}
+
+#[test]
+fn review_file_parsing() {
+    let text = "\
+# a comment
+== identical (2 files, 4 B each)
+keep   /x/a file.txt
+delete /x/b.txt
+
+== similar (2 images)
+keep /x/c.jpg
+keep /x/d.jpg
+";
+    let groups = review::parse(text).unwrap();
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(
+        groups[0].files,
+        vec![
+            (Decision::Keep, PathBuf::from("/x/a file.txt")),
+            (Decision::Delete, PathBuf::from("/x/b.txt")),
+        ]
+    );
+    assert_eq!(groups[1].kind, GroupKind::Similar);
+
+    assert!(review::parse("== identical\nremove /x/a.txt\n").is_err());
+    assert!(review::parse("keep /x/a.txt\n").is_err());
+}
+
+#[test]
+fn apply_dry_run_changes_nothing() {
+    let fx = Fixture::new().file("a.txt", "same").file("b.txt", "same");
+    let review_path = fx.root().join("review.txt");
+    review::write(
+        &review_path,
+        &[ReviewGroup {
+            kind: GroupKind::Identical,
+            files: vec![
+                (Decision::Keep, fx.root().join("a.txt")),
+                (Decision::Delete, fx.root().join("b.txt")),
+            ],
+        }],
+    )
+    .unwrap();
+
+    review::apply(
+        &review_path,
+        &ApplyOptions {
+            dry_run: true,
+            ..ApplyOptions::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(fx.files(), vec!["a.txt", "b.txt", "review.txt"]);
+}
+
+// Sets a file's modification time to `secs` seconds after the epoch
+fn set_mtime(path: &std::path::Path, secs: u64) {
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+        .unwrap();
+}
+
+#[test]
+fn keeper_strategies() {
+    let fx = Fixture::new()
+        .file("a/deep/er/copy.txt", "same")
+        .file("b/copy.txt", "same")
+        .file("c/copy.txt", "same");
+    let files = all_files(&fx);
+    set_mtime(&files[0], 3_000);
+    set_mtime(&files[1], 1_000);
+    set_mtime(&files[2], 2_000);
+
+    assert_eq!(dedupe::keeper(&files, &Keep::Newest), Some(0));
+    assert_eq!(dedupe::keeper(&files, &Keep::Oldest), Some(1));
+    // b/ and c/ are equally short, the first one wins
+    assert_eq!(dedupe::keeper(&files, &Keep::ShortestPath), Some(1));
+    assert_eq!(
+        dedupe::keeper(&files, &Keep::InDir(fx.root().join("c"))),
+        Some(2)
+    );
+    assert_eq!(
+        dedupe::keeper(&files, &Keep::InDir(fx.root().join("a/deep"))),
+        Some(0)
+    );
+}
+
+#[test]
+fn keep_in_dir_without_a_copy_there_keeps_everything() {
+    let fx = Fixture::new()
+        .file("a/copy.txt", "same")
+        .file("b/copy.txt", "same")
+        .file("elsewhere/other.txt", "different");
+    let group = DuplicateGroup {
+        size: 4,
+        files: vec![fx.root().join("a/copy.txt"), fx.root().join("b/copy.txt")],
+    };
+
+    let keep = Keep::InDir(fx.root().join("elsewhere"));
+    let groups = review::groups_for(&[group], &[], Some(&keep), Decision::Delete);
+
+    assert!(groups[0].files.iter().all(|(d, _)| *d == Decision::Keep));
+}
+
+#[test]
+fn keep_dry_run_changes_nothing() {
+    let fx = Fixture::new().file("a.txt", "same").file("b.txt", "same");
+
+    dedupe::dedupe(
+        &[fx.root().to_str().unwrap()],
+        &DedupeOptions {
+            keep: Some(Keep::Oldest),
+            dry_run: true,
+            ..DedupeOptions::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(fx.files(), vec!["a.txt", "b.txt"]);
+}
+
+#[test]
+fn keep_strategy_names() {
+    assert_eq!(parse_keep("newest"), Ok(Keep::Newest));
+    assert_eq!(parse_keep("shortest-path"), Ok(Keep::ShortestPath));
+    assert_eq!(
+        parse_keep("in-dir:/home/me/Pictures"),
+        Ok(Keep::InDir(PathBuf::from("/home/me/Pictures")))
+    );
+    assert!(parse_keep("in-dir:").is_err());
+    assert!(parse_keep("largest").is_err());
+}
+
+#[test]
+fn big_files_hash_the_same_in_pieces() {
+    use directory_cleaner::hashing::hash_files;
+
+    let fx = Fixture::new();
+    let mib = 1024 * 1024;
+    // Empty, small, exactly one piece, one byte more, several pieces
+    let sizes = [0, 10, mib, mib + 1, 3 * mib + mib / 2];
+    let mut files = Vec::new();
+    let mut expected = Vec::new();
+    for (i, size) in sizes.iter().enumerate() {
+        let contents: Vec<u8> = (0..*size).map(|b| (b * 7 + i) as u8).collect();
+        let path = fx.root().join(format!("file{}", i));
+        std::fs::write(&path, &contents).unwrap();
+        files.push(path);
+        expected.push(blake3::hash(&contents));
+    }
+    files.insert(2, fx.root().join("missing"));
+
+    let hashes = hash_files(&files);
+    assert!(hashes[2].is_err());
+    let hashes: Vec<blake3::Hash> = hashes.into_iter().filter_map(Result::ok).collect();
+    assert_eq!(hashes, expected);
+}
+
+#[test]
+fn quick_hash_finds_the_same_duplicates() {
+    use directory_cleaner::hashing::quick_hash;
+
+    let fx = Fixture::new().file("small1", "tiny").file("small2", "tiny");
+    let big: Vec<u8> = (0..1024 * 1024u32).map(|b| (b % 251) as u8).collect();
+    let changed = |at: usize| {
+        let mut copy = big.clone();
+        copy[at] ^= 0xFF;
+        copy
+    };
+    std::fs::write(fx.root().join("video.mp4"), &big).unwrap();
+    std::fs::write(fx.root().join("copy.mp4"), &big).unwrap();
+    // Differs in the middle sample: told apart by the quick hash
+    std::fs::write(fx.root().join("middle.mp4"), changed(512 * 1024)).unwrap();
+    // Differs between the samples: same quick hash, told apart by the full read
+    std::fs::write(fx.root().join("between.mp4"), changed(200 * 1024)).unwrap();
+    assert_eq!(
+        quick_hash(&fx.root().join("between.mp4")).unwrap(),
+        quick_hash(&fx.root().join("video.mp4")).unwrap()
+    );
+    assert_ne!(
+        quick_hash(&fx.root().join("middle.mp4")).unwrap(),
+        quick_hash(&fx.root().join("video.mp4")).unwrap()
+    );
+
+    let files = all_files(&fx);
+    let quick = dedupe::find_duplicates(&files, true);
+    assert_eq!(quick, dedupe::find_duplicates(&files, false));
+    let grouped: Vec<Vec<PathBuf>> = quick.into_iter().map(|g| g.files).collect();
+    assert_eq!(
+        grouped,
+        vec![
+            vec![fx.root().join("copy.mp4"), fx.root().join("video.mp4")],
+            vec![fx.root().join("small1"), fx.root().join("small2")],
+        ]
+    );
+}
+
+#[test]
+fn prefer_root_links_the_copies_elsewhere_to_the_curated_ones() {
+    let fx = Fixture::new()
+        .file("Downloads/beach.jpg", "photo")
+        .file("Downloads/notes.txt", "notes")
+        .file("Pictures/beach.jpg", "photo")
+        .file("Pictures/2024/beach.jpg", "photo");
+    let downloads = fx.root().join("Downloads");
+    let pictures = fx.root().join("Pictures");
+
+    dedupe::dedupe(
+        &[downloads.to_str().unwrap(), pictures.to_str().unwrap()],
+        &DedupeOptions {
+            keep: Some(Keep::PreferRoot(pictures.clone())),
+            link: true,
+            yes: true,
+            ..DedupeOptions::default()
+        },
+    )
+    .unwrap();
+
+    // Both copies in Pictures stay, the one in Downloads is now a link to one of them
+    assert_eq!(
+        fx.files(),
+        vec![
+            "Downloads/beach.jpg",
+            "Downloads/notes.txt",
+            "Pictures/2024/beach.jpg",
+            "Pictures/beach.jpg"
+        ]
+    );
+    assert_eq!(fx.read("Downloads/beach.jpg"), "photo");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let links = |rel: &str| std::fs::metadata(fx.root().join(rel)).unwrap().nlink();
+        assert_eq!(links("Downloads/beach.jpg"), 2);
+        assert_eq!(links("Downloads/notes.txt"), 1);
+    }
+}
+
+#[test]
+fn dedupe_roots_cannot_overlap() {
+    let fx = Fixture::new().file("Pictures/a.jpg", "a");
+    let pictures = fx.root().join("Pictures");
+    let error = dedupe::dedupe(
+        &[fx.root().to_str().unwrap(), pictures.to_str().unwrap()],
+        &DedupeOptions::default(),
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("overlap"));
+}
+
+#[test]
+fn deleting_copies_needs_a_yes() {
+    use std::io::IsTerminal;
+    // With a terminal the question would wait for an answer
+    if std::io::stdin().is_terminal() {
+        return;
+    }
+    let fx = Fixture::new().file("a.txt", "same").file("b.txt", "same");
+    let error = dedupe::dedupe(
+        &[fx.root().to_str().unwrap()],
+        &DedupeOptions {
+            keep: Some(Keep::Oldest),
+            link: true,
+            ..DedupeOptions::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(error.to_string().contains("--yes"), "{}", error);
+    assert!(error.to_string().contains("replaces 1 files with links"));
+    assert_eq!(fx.files(), vec!["a.txt", "b.txt"]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let links = |rel: &str| std::fs::metadata(fx.root().join(rel)).unwrap().nlink();
+        assert_eq!((links("a.txt"), links("b.txt")), (1, 1));
+    }
+}
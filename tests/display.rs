@@ -0,0 +1,70 @@
+// How paths are shown in the text output (display.rs)
+use directory_cleaner::display::{escape, fit, PathDisplay};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+
+// "a/b/c" with the separator of this OS
+fn native(path: &str) -> String {
+    path.replace('/', MAIN_SEPARATOR_STR)
+}
+
+#[test]
+fn paths_are_shown_relative_to_the_root() {
+    let display = PathDisplay {
+        root: Some(PathBuf::from("home/me/Downloads")),
+        ..PathDisplay::default()
+    };
+    let shown = display.path(Path::new("home/me/Downloads/pdf/a.pdf"), None);
+    assert_eq!(shown, format!("\"{}\"", native("pdf/a.pdf")));
+    // Outside of the root: whole
+    let shown = display.path(Path::new("elsewhere/a.pdf"), None);
+    assert_eq!(shown, format!("\"{}\"", native("elsewhere/a.pdf")));
+
+    let full = PathDisplay {
+        full: true,
+        ..display
+    };
+    let shown = full.path(Path::new("home/me/Downloads/pdf/a.pdf"), Some(10));
+    assert_eq!(
+        shown,
+        format!("\"{}\"", native("home/me/Downloads/pdf/a.pdf"))
+    );
+}
+
+#[test]
+fn long_paths_lose_folders_at_the_front_but_never_the_name() {
+    let path = PathBuf::from(native("inbox/2024/invoices/march/invoice-0042.pdf"));
+    assert_eq!(fit(&path, Some(200)), format!("\"{}\"", path.display()));
+    assert_eq!(
+        fit(&path, Some(30)),
+        format!("\"…{}\"", native("/march/invoice-0042.pdf"))
+    );
+    // No room at all: still the whole file name
+    assert_eq!(
+        fit(&path, Some(5)),
+        format!("\"…{}invoice-0042.pdf\"", MAIN_SEPARATOR)
+    );
+}
+
+#[test]
+fn lines_share_the_terminal_width() {
+    let display = PathDisplay {
+        width: Some(60),
+        ..PathDisplay::default()
+    };
+    let from = PathBuf::from(native("inbox/2024/invoices/march/invoice-0042.pdf"));
+    let to = PathBuf::from(native("pdf/inbox/2024/invoices/march/invoice-0042.pdf"));
+    let line = display.pair("Moved ", &from, &to, "");
+    assert!(line.chars().count() <= 60, "{}", line);
+    assert!(line.starts_with("Moved \"…"));
+    assert!(line.ends_with("invoice-0042.pdf\""));
+}
+
+#[test]
+fn control_characters_are_escaped() {
+    assert_eq!(escape("a\nb\tc"), "a\\nb\\tc");
+    assert_eq!(escape("red\u{1b}[31m.txt"), "red\\u{1b}[31m.txt");
+    // A right-to-left override would show "fdp.exe" backwards, as "exe.pdf"
+    assert_eq!(escape("invoice\u{202e}fdp.exe"), "invoice\\u{202e}fdp.exe");
+    assert_eq!(escape("say \"hi\".txt"), "say \\\"hi\\\".txt");
+    assert_eq!(escape("Résumé.pdf"), "Résumé.pdf");
+}
@@ -0,0 +1,94 @@
+// --stop-if-free-below with copies running at once: the bytes still being copied count as used
+use directory_cleaner::free_space::{FreeSpace, Room};
+use std::io;
+use std::path::Path;
+use std::sync::Barrier;
+use std::thread;
+
+// A drive with 10 000 bytes free that never changes (nothing is really copied here)
+fn ten_thousand_free(_: &Path) -> io::Result<u64> {
+    Ok(10_000)
+}
+
+fn unreadable(_: &Path) -> io::Result<u64> {
+    Err(io::Error::other("no such drive"))
+}
+
+// Each copy fits on its own, not both together: the second one has to see the first one's bytes
+#[test]
+fn two_copies_at_once_both_count() {
+    let space = FreeSpace::with_source(1_000, ten_thousand_free);
+    let first_reserved = Barrier::new(2);
+    let second_checked = Barrier::new(2);
+    let dest = Path::new("dest");
+
+    thread::scope(|s| {
+        let first = s.spawn(|| {
+            let room = space.reserve(6_000, dest);
+            assert!(matches!(room, Room::Enough(_)));
+            first_reserved.wait();
+            // Still copying while the other thread looks
+            second_checked.wait();
+            drop(room);
+        });
+        let second = s.spawn(|| {
+            first_reserved.wait();
+            let room = space.reserve(6_000, dest);
+            second_checked.wait();
+            room
+        });
+
+        first.join().unwrap();
+        match second.join().unwrap() {
+            Room::TooLittle { free, in_flight } => {
+                assert_eq!(free, 10_000);
+                assert_eq!(in_flight, 6_000);
+            }
+            other => panic!("expected too little room, got {:?}", other),
+        }
+    });
+    assert_eq!(space.in_flight(), 0);
+}
+
+#[test]
+fn a_finished_copy_gives_its_bytes_back() {
+    let space = FreeSpace::with_source(1_000, ten_thousand_free);
+    let dest = Path::new("dest");
+
+    let first = space.reserve(6_000, dest);
+    assert!(matches!(first, Room::Enough(_)));
+    assert_eq!(space.in_flight(), 6_000);
+    drop(first);
+
+    assert_eq!(space.in_flight(), 0);
+    assert!(matches!(space.reserve(6_000, dest), Room::Enough(_)));
+}
+
+// A copy that was refused doesn't hold space the others could use
+#[test]
+fn a_refused_copy_holds_nothing() {
+    let space = FreeSpace::with_source(1_000, ten_thousand_free);
+    let dest = Path::new("dest");
+
+    let room = space.reserve(9_500, dest);
+    assert!(matches!(
+        room,
+        Room::TooLittle {
+            free: 10_000,
+            in_flight: 0
+        }
+    ));
+    assert_eq!(space.in_flight(), 0);
+    assert!(matches!(space.reserve(9_000, dest), Room::Enough(_)));
+}
+
+// When the free space can't be read the copy goes ahead, and the others still count it
+#[test]
+fn an_unreadable_drive_still_counts_the_copy() {
+    let space = FreeSpace::with_source(1_000, unreadable);
+    let room = space.reserve(6_000, Path::new("dest"));
+    assert!(matches!(room, Room::Unknown(_, _)));
+    assert_eq!(space.in_flight(), 6_000);
+    drop(room);
+    assert_eq!(space.in_flight(), 0);
+}
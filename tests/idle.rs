@@ -0,0 +1,71 @@
+// --only-when-idle: the CPU times read from /proc, and how much of them other programs used
+use directory_cleaner::idle::{others_share, parse_times, Times};
+
+const STAT: &str = "cpu  100 20 30 800 50 5 5 0 0 0\ncpu0 50 10 15 400 25 2 3 0 0 0\nintr 12345\n";
+
+#[test]
+fn reads_the_machine_and_our_own_times() {
+    let own =
+        "4242 (directory-clean) S 1 4242 4242 0 -1 4194304 500 0 0 0 7 3 0 0 20 0 9 0 100 0 0";
+    assert_eq!(
+        parse_times(STAT, own),
+        Some(Times {
+            busy: 160,
+            total: 1010,
+            own: 10,
+        })
+    );
+}
+
+// The name is whatever the program calls itself, so only the last ')' ends it
+#[test]
+fn names_with_spaces_and_parentheses() {
+    let own = "77 (my (odd) ) name) R 1 77 77 0 -1 0 0 0 0 0 40 2 0 0 20 0 1 0 5 0 0";
+    assert_eq!(parse_times(STAT, own).map(|t| t.own), Some(42));
+}
+
+#[test]
+fn text_that_doesnt_look_right_is_none() {
+    let own = "1 (x) S 1 1 1 0 -1 0 0 0 0 0 1 1 0 0";
+    assert!(parse_times("", own).is_none());
+    assert!(parse_times("cpu  1 2 3", own).is_none());
+    assert!(parse_times(STAT, "1 no name here").is_none());
+    assert!(parse_times(STAT, "1 (cut short) S 1 1").is_none());
+}
+
+#[test]
+fn others_share_leaves_our_own_time_out() {
+    let before = Times {
+        busy: 1000,
+        total: 5000,
+        own: 100,
+    };
+    // 400 of 1000 ticks busy, 300 of them ours
+    let after = Times {
+        busy: 1400,
+        total: 6000,
+        own: 400,
+    };
+    assert!((others_share(&before, &after) - 0.1).abs() < 1e-9);
+
+    // Only we were busy
+    let ours = Times { own: 500, ..after };
+    assert_eq!(others_share(&before, &ours), 0.0);
+}
+
+#[test]
+fn others_share_of_no_time_is_zero() {
+    let sample = Times {
+        busy: 10,
+        total: 20,
+        own: 5,
+    };
+    assert_eq!(others_share(&sample, &sample), 0.0);
+    // Counters that went backwards (a CPU taken offline) don't make it negative or huge
+    let earlier = Times {
+        busy: 50,
+        total: 100,
+        own: 0,
+    };
+    assert_eq!(others_share(&earlier, &sample), 0.0);
+}
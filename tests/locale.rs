@@ -0,0 +1,86 @@
+// --lang: which language a locale means, and that every language names every category folder
+use directory_cleaner::args::parse_category_folder;
+use directory_cleaner::category;
+use directory_cleaner::locale::{self, Lang, LANGUAGES};
+
+// A made-up environment (the real one is shared by all tests)
+fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+    move |name| {
+        vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+#[test]
+fn locales_are_found_by_their_language() {
+    for name in ["de", "DE", "de_DE.UTF-8", "de_AT", "de-CH", "de_DE@euro"] {
+        assert_eq!(locale::find(name).map(|l| l.code), Some("de"), "{}", name);
+    }
+    assert_eq!(locale::find("pt_BR.UTF-8").map(|l| l.code), Some("pt"));
+    assert!(locale::find("C").is_none());
+    assert!(locale::find("POSIX").is_none());
+    assert!(locale::find("xx_YY").is_none());
+    assert!(locale::find("").is_none());
+}
+
+// The order every program reads them in: LC_ALL, then LC_MESSAGES, then LANG
+#[test]
+fn lc_all_wins_over_lang() {
+    let code = |vars: &[(&str, &str)]| locale::from_locale(env(vars)).map(|l| l.code);
+    assert_eq!(code(&[("LANG", "fr_FR.UTF-8")]), Some("fr"));
+    assert_eq!(
+        code(&[("LANG", "fr_FR.UTF-8"), ("LC_MESSAGES", "it_IT.UTF-8")]),
+        Some("it")
+    );
+    assert_eq!(
+        code(&[("LANG", "fr_FR.UTF-8"), ("LC_ALL", "nl_NL.UTF-8")]),
+        Some("nl")
+    );
+    // Set but empty is as if it wasn't set
+    assert_eq!(code(&[("LC_ALL", ""), ("LANG", "es_ES.UTF-8")]), Some("es"));
+    // "C" means no language in particular, even with LANG set
+    assert_eq!(code(&[("LC_ALL", "C"), ("LANG", "de_DE.UTF-8")]), None);
+    assert_eq!(code(&[]), None);
+
+    // --lang de doesn't look at the locale at all
+    let german = locale::find("de").unwrap();
+    assert_eq!(
+        Lang::Fixed(german).resolve(env(&[("LANG", "fr_FR.UTF-8")])),
+        Some(german)
+    );
+    assert_eq!(
+        Lang::Auto
+            .resolve(env(&[("LANG", "fr_FR.UTF-8")]))
+            .map(|l| l.code),
+        Some("fr")
+    );
+}
+
+// A category missing from one language would keep its English folder there, next to translated ones
+#[test]
+fn every_language_names_every_category() {
+    let names: Vec<&str> = category::CATEGORIES
+        .iter()
+        .map(|c| c.name)
+        .chain([category::JUNK_CATEGORY])
+        .collect();
+    for language in LANGUAGES.iter().filter(|l| !l.folders.is_empty()) {
+        for name in &names {
+            let folder = language
+                .folders
+                .iter()
+                .find(|(category, _)| category == name)
+                .map(|(_, folder)| folder);
+            let folder = folder.unwrap_or_else(|| panic!("{} has no {}", language.code, name));
+            // The same folders --category-folder would take
+            assert!(
+                parse_category_folder(&format!("{}={}", name, folder)).is_ok(),
+                "{}: {}",
+                language.code,
+                folder
+            );
+        }
+        assert_eq!(language.folders.len(), names.len(), "{}", language.code);
+    }
+}
@@ -0,0 +1,111 @@
+// Folding one organized folder into another
+mod common;
+
+use common::Fixture;
+use directory_cleaner::logic::{self, ConfigError, Options};
+use directory_cleaner::plan::DuplicateStrategy;
+use directory_cleaner::undo::UndoOptions;
+use std::num::NonZeroU64;
+
+#[test]
+fn merge_moves_files_to_the_same_place_and_handles_taken_names() {
+    let laptop = Fixture::new()
+        .file("pdf/report.pdf", "laptop")
+        .file("pdf/same.pdf", "same")
+        .file("jpg/sub/photo.jpg", "p");
+    let desktop = Fixture::new()
+        .file("pdf/report.pdf", "desktop")
+        .file("pdf/same.pdf", "same");
+
+    logic::merge(
+        laptop.root().to_str().unwrap(),
+        desktop.root().to_str().unwrap(),
+        &Options {
+            on_duplicate: DuplicateStrategy::Skip,
+            state_dir: Some(desktop.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        desktop.files(),
+        vec![
+            "jpg/sub/photo.jpg",
+            "pdf/report (1).pdf",
+            "pdf/report.pdf",
+            "pdf/same.pdf"
+        ]
+    );
+    assert_eq!(desktop.read("pdf/report (1).pdf"), "laptop");
+    // The identical copy stays behind
+    assert_eq!(laptop.files(), vec!["pdf/same.pdf"]);
+
+    // The merge is journaled with the destination, undo puts the files back
+    desktop.undo(&UndoOptions::default());
+    assert_eq!(
+        laptop.files(),
+        vec!["jpg/sub/photo.jpg", "pdf/report.pdf", "pdf/same.pdf"]
+    );
+    assert_eq!(laptop.read("pdf/report.pdf"), "laptop");
+    assert_eq!(desktop.files(), vec!["pdf/report.pdf", "pdf/same.pdf"]);
+}
+
+#[test]
+fn merge_refuses_nested_folders() {
+    let fx = Fixture::new().file("inner/a.pdf", "a");
+    let inner = fx.root().join("inner");
+
+    let result = logic::merge(
+        inner.to_str().unwrap(),
+        fx.root().to_str().unwrap(),
+        &Options {
+            dry_run: true,
+            ..Options::default()
+        },
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn merge_rejects_options_it_would_ignore() {
+    let laptop = Fixture::new().file("pdf/report.pdf", "laptop");
+    let desktop = Fixture::new();
+
+    let err = logic::merge(
+        laptop.root().to_str().unwrap(),
+        desktop.root().to_str().unwrap(),
+        &Options {
+            limit: NonZeroU64::new(10),
+            state_dir: Some(desktop.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err.downcast_ref::<ConfigError>(),
+        Some(&ConfigError::IgnoredByMerge("--limit"))
+    );
+
+    // Named the way it's typed on the command line
+    let err = logic::merge(
+        laptop.root().to_str().unwrap(),
+        desktop.root().to_str().unwrap(),
+        &Options {
+            html_report: Some(desktop.state_dir().join("report.html")),
+            state_dir: Some(desktop.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ConfigError>(),
+        Some(&ConfigError::IgnoredByMerge("--report html"))
+    );
+    assert_eq!(
+        err.to_string(),
+        "--report html can't be used with merge: a merge keeps every file's place"
+    );
+    assert_eq!(laptop.files(), vec!["pdf/report.pdf"]);
+}
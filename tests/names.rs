@@ -0,0 +1,129 @@
+// Weird real-world file names, organized for real: every one of them ends up in a folder with
+// its name and contents unchanged, a second run leaves them alone, and undo brings them back.
+// (The fuzz targets in fuzz/ try the same parsers with names nobody thought of.)
+mod common;
+
+use common::Fixture;
+use directory_cleaner::args::parse_map;
+use directory_cleaner::display;
+use directory_cleaner::logic::Options;
+use directory_cleaner::undo::UndoOptions;
+use std::path::{Component, Path};
+
+// Names any file system takes
+const NAMES: &[&str] = &[
+    "Rechnung März 2024.pdf",
+    "naïve café.txt",
+    "日本語のファイル.docx",
+    "مرحبا بالعالم.pdf",
+    "Привет.mp3",
+    "holiday 🏖️ 2024.jpg",
+    "👨‍👩‍👧 family.png",
+    "e\u{301}le\u{301}ment.txt",
+    "zero\u{200b}width.pdf",
+    "\u{202e}gpj.exe",
+    "no extension",
+    "archive.tar.gz",
+    "many.dots.in.the.name.csv",
+    "UPPER.PDF",
+    "-starts-with-a-dash.pdf",
+    "#hash & ampersand.txt",
+    "it's (1) [final] {v2}.docx",
+    "100% done!.xlsx",
+    "  leading spaces.txt",
+    "semi;colon,comma.txt",
+    "=equals=.txt",
+    "~tilde.txt",
+    "report (1).pdf",
+    "report -> copy.pdf",
+];
+
+// Names Windows doesn't allow, but Linux and macOS do
+#[cfg(unix)]
+const UNIX_NAMES: &[&str] = &[
+    "line\nbreak.pdf",
+    "tab\there.txt",
+    "carriage\rreturn.txt",
+    "escape \u{1b}[31mred\u{1b}[0m.txt",
+    "trailing space.pdf ",
+    "trailing dot.",
+    "back\\slash.txt",
+    "colon: yes.txt",
+    "question?.txt",
+    "star*.txt",
+    "pipe | and <angle>.txt",
+    "quote \"marks\".txt",
+    "é",
+];
+
+#[cfg(unix)]
+fn names() -> Vec<&'static str> {
+    NAMES.iter().chain(UNIX_NAMES).copied().collect()
+}
+
+#[cfg(not(unix))]
+fn names() -> Vec<&'static str> {
+    NAMES.to_vec()
+}
+
+fn file_name(rel: &str) -> &str {
+    rel.rsplit('/').next().unwrap()
+}
+
+#[test]
+fn weird_names_survive_a_run_and_its_undo() {
+    let mut fx = Fixture::new();
+    for (i, name) in names().iter().enumerate() {
+        fx = fx.file(&format!("inbox/{}", name), &i.to_string());
+    }
+    let before = fx.files();
+
+    fx.organize(&Options::default());
+    let after = fx.files();
+
+    assert_eq!(after.len(), names().len());
+    for (i, name) in names().iter().enumerate() {
+        let found: Vec<&String> = after.iter().filter(|rel| file_name(rel) == *name).collect();
+        assert_eq!(found.len(), 1, "{:?} is at {:?}", name, found);
+        assert_eq!(fx.read(found[0]), i.to_string(), "{:?}", name);
+        // Files without an extension are left where they are
+        let has_extension = Path::new(name).extension().is_some();
+        assert_eq!(
+            !found[0].starts_with("inbox/"),
+            has_extension,
+            "{:?} wasn't organized",
+            name
+        );
+    }
+
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), after);
+
+    fx.undo(&UndoOptions::default());
+    assert_eq!(fx.files(), before);
+}
+
+#[test]
+fn weird_names_are_shown_without_control_characters() {
+    for name in names() {
+        let shown = display::fit(Path::new(name), Some(20));
+        assert!(!shown.chars().any(|c| c.is_control()), "{:?}", shown);
+        // The name is never cut, only the folders in front of it
+        assert!(shown.ends_with(&format!("{}\"", display::escape(name))));
+    }
+}
+
+#[test]
+fn weird_names_as_map_folders_stay_inside_the_root() {
+    for name in names() {
+        if let Ok(mapping) = parse_map(&format!("pdf={}", name)) {
+            assert!(
+                Path::new(&mapping.folder)
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_))),
+                "{:?}",
+                mapping
+            );
+        }
+    }
+}
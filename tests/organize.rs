@@ -0,0 +1,1268 @@
+// End-to-end runs on real (temporary) directories
+mod common;
+
+use common::Fixture;
+use directory_cleaner::args::parse_category_folder;
+use directory_cleaner::gps;
+use directory_cleaner::journal::{self, Entry};
+use directory_cleaner::logic::{self, ConfigError, Options};
+use directory_cleaner::plan::{self, ConflictStrategy, DuplicateStrategy, Grouping, PlannedAction};
+use std::num::NonZeroUsize;
+
+#[test]
+fn organizes_files_by_extension() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("sub/script.py", "s")
+        .file("Photo.JPG", "p");
+
+    fx.organize(&Options::default());
+
+    assert_eq!(
+        fx.files(),
+        vec!["jpg/Photo.JPG", "pdf/report.pdf", "py/script.py"]
+    );
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("sub/notes.txt", "n");
+
+    fx.organize(&Options {
+        dry_run: true,
+        ..Options::default()
+    });
+
+    assert_eq!(fx.files(), vec!["report.pdf", "sub/notes.txt"]);
+    assert!(!fx.root().join("pdf").exists());
+}
+
+#[test]
+fn second_run_does_nothing() {
+    let fx = Fixture::new().file("a.pdf", "a").file("b/c.txt", "c");
+
+    fx.organize(&Options::default());
+    let after_first = fx.files();
+    fx.organize(&Options::default());
+
+    assert_eq!(fx.files(), after_first);
+}
+
+#[test]
+fn collisions_keep_every_file() {
+    let fx = Fixture::new()
+        .file("a/report.pdf", "from a")
+        .file("b/report.pdf", "from b")
+        .file("pdf/report.pdf", "already here");
+
+    fx.organize(&Options::default());
+
+    assert_eq!(
+        fx.files(),
+        vec!["pdf/report (1).pdf", "pdf/report (2).pdf", "pdf/report.pdf"]
+    );
+    assert_eq!(fx.read("pdf/report.pdf"), "already here");
+    assert_eq!(fx.read("pdf/report (1).pdf"), "from a");
+    assert_eq!(fx.read("pdf/report (2).pdf"), "from b");
+}
+
+#[test]
+fn collisions_can_be_skipped() {
+    let fx = Fixture::new()
+        .file("report.pdf", "new")
+        .file("pdf/report.pdf", "old");
+
+    fx.organize(&Options {
+        on_conflict: ConflictStrategy::Skip,
+        ..Options::default()
+    });
+
+    assert_eq!(fx.files(), vec!["pdf/report.pdf", "report.pdf"]);
+    assert_eq!(fx.read("pdf/report.pdf"), "old");
+}
+
+#[test]
+fn identical_copies_are_left_alone() {
+    let fx = Fixture::new()
+        .file("pdf/report.pdf", "same")
+        .file("a/report.pdf", "same")
+        .file("b/report.pdf", "different");
+
+    fx.organize(&Options {
+        on_duplicate: DuplicateStrategy::Skip,
+        ..Options::default()
+    });
+
+    assert_eq!(
+        fx.files(),
+        vec!["a/report.pdf", "pdf/report (1).pdf", "pdf/report.pdf"]
+    );
+    assert_eq!(fx.read("pdf/report (1).pdf"), "different");
+}
+
+#[test]
+fn dry_run_does_not_trash_duplicates() {
+    let fx = Fixture::new()
+        .file("pdf/report.pdf", "same")
+        .file("report.pdf", "same");
+
+    fx.organize(&Options {
+        on_duplicate: DuplicateStrategy::TrashSource,
+        dry_run: true,
+        ..Options::default()
+    });
+
+    assert_eq!(fx.files(), vec!["pdf/report.pdf", "report.pdf"]);
+}
+
+#[test]
+fn unicode_names() {
+    let fx = Fixture::new()
+        .file("Übersicht.pdf", "u")
+        .file("日本/写真.png", "j")
+        .file("emoji 🎉.txt", "e");
+
+    fx.organize(&Options::default());
+
+    assert_eq!(
+        fx.files(),
+        vec!["pdf/Übersicht.pdf", "png/写真.png", "txt/emoji 🎉.txt"]
+    );
+}
+
+#[test]
+fn file_blocking_a_folder_is_left_alone() {
+    let fx = Fixture::new()
+        .file("txt", "not a folder")
+        .file("notes.txt", "n");
+
+    fx.organize(&Options::default());
+
+    assert_eq!(fx.files(), vec!["notes.txt", "txt"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_moved_not_followed() {
+    use std::os::unix::fs::symlink;
+
+    let outside = Fixture::new().file("target.pdf", "real file");
+    let fx = Fixture::new();
+    symlink(
+        outside.root().join("target.pdf"),
+        fx.root().join("link.pdf"),
+    )
+    .unwrap();
+    // A link to a folder outside root must not be walked into
+    symlink(outside.root(), fx.root().join("linked_dir")).unwrap();
+
+    fx.organize(&Options::default());
+
+    assert_eq!(fx.files(), vec!["linked_dir", "pdf/link.pdf"]);
+    assert!(fx.root().join("pdf/link.pdf").is_symlink());
+    assert_eq!(outside.files(), vec!["target.pdf"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn fast_scan_finds_the_same_files() {
+    use std::os::unix::fs::symlink;
+
+    let outside = Fixture::new().file("target.pdf", "real file");
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("sub/notes.txt", "n");
+    symlink(
+        outside.root().join("target.pdf"),
+        fx.root().join("link.pdf"),
+    )
+    .unwrap();
+    symlink(outside.root(), fx.root().join("linked_dir")).unwrap();
+
+    fx.organize(&Options {
+        fast_scan: true,
+        ..Options::default()
+    });
+
+    assert_eq!(
+        fx.files(),
+        vec![
+            "linked_dir",
+            "pdf/link.pdf",
+            "pdf/report.pdf",
+            "txt/notes.txt"
+        ]
+    );
+}
+
+#[test]
+fn files_already_in_the_reference_folder_stay() {
+    let backup = Fixture::new()
+        .file("2023/report.pdf", "the report")
+        .file("notes.txt", "older notes");
+    let fx = Fixture::new()
+        .file("report.pdf", "the report")
+        .file("notes.txt", "newer notes");
+
+    fx.organize(&Options {
+        skip_if_in: Some(backup.root().to_path_buf()),
+        ..Options::default()
+    });
+
+    // Same name isn't enough, the contents have to match
+    assert_eq!(fx.files(), vec!["report.pdf", "txt/notes.txt"]);
+}
+
+#[test]
+fn deleting_backed_up_files_needs_a_yes() {
+    let backup = Fixture::new().file("report.pdf", "the report");
+    let fx = Fixture::new().file("report.pdf", "the report");
+
+    let err = logic::process_directory(
+        fx.root().to_str().unwrap(),
+        &Options {
+            skip_if_in: Some(backup.root().to_path_buf()),
+            delete_if_duplicated: true,
+            state_dir: Some(fx.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("moves 1 files to the trash"));
+    assert_eq!(fx.files(), vec!["report.pdf"]);
+}
+
+#[test]
+fn reference_folder_cannot_be_inside_the_organized_one() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("backup/report.pdf", "r");
+
+    let err = logic::process_directory(
+        fx.root().to_str().unwrap(),
+        &Options {
+            skip_if_in: Some(fx.root().join("backup")),
+            state_dir: Some(fx.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("overlap"));
+    assert_eq!(fx.files(), vec!["backup/report.pdf", "report.pdf"]);
+}
+
+#[test]
+fn options_that_make_no_sense_are_rejected_up_front() {
+    let fx = Fixture::new().file("report.pdf", "r");
+
+    for (options, error) in [
+        (
+            Options {
+                delete_if_duplicated: true,
+                ..Options::default()
+            },
+            ConfigError::DeleteWithoutReference,
+        ),
+        (
+            Options {
+                throttle: Some(0.0),
+                ..Options::default()
+            },
+            ConfigError::InvalidThrottle(0.0),
+        ),
+        (
+            Options {
+                into_user_dirs: true,
+                by: Grouping::Language,
+                ..Options::default()
+            },
+            ConfigError::NoUserDirsFor(Grouping::Language),
+        ),
+    ] {
+        let err = logic::process_directory(fx.root().to_str().unwrap(), &options).unwrap_err();
+        assert_eq!(err.downcast_ref::<ConfigError>(), Some(&error), "{}", err);
+    }
+    assert_eq!(fx.files(), vec!["report.pdf"]);
+}
+
+// The builder checks on the way out, so a program using the library gets the same errors as the CLI
+#[test]
+fn the_builder_refuses_what_the_checks_refuse() {
+    let err = Options::builder()
+        .delete_if_duplicated(true)
+        .build()
+        .unwrap_err();
+    assert_eq!(err, ConfigError::DeleteWithoutReference);
+    assert_eq!(
+        Options::builder()
+            .throttle(Some(f64::INFINITY))
+            .build()
+            .unwrap_err(),
+        ConfigError::InvalidThrottle(f64::INFINITY)
+    );
+    assert_eq!(
+        Options::builder()
+            .limit(std::num::NonZeroU64::new(5))
+            .build_merge()
+            .unwrap_err(),
+        ConfigError::IgnoredByMerge("--limit")
+    );
+
+    let options = Options::builder()
+        .dry_run(true)
+        .by(Grouping::Category)
+        .throttle(Some(0.5))
+        .min_group_size(NonZeroUsize::new(3))
+        .build()
+        .unwrap();
+    assert!(options.dry_run);
+    assert_eq!(options.by, Grouping::Category);
+    assert_eq!(options.throttle, Some(0.5));
+    assert_eq!(options.min_group_size, NonZeroUsize::new(3));
+}
+
+#[test]
+fn unfinished_copies_of_an_interrupted_run_are_removed() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("pdf/.dircleaner-partial-6f1c2a", "half of a");
+
+    fx.organize(&Options {
+        dry_run: true,
+        ..Options::default()
+    });
+    assert_eq!(
+        fx.files(),
+        vec!["pdf/.dircleaner-partial-6f1c2a", "report.pdf"]
+    );
+
+    // Never organized as a file of its own, gone after a real run
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), vec!["pdf/report.pdf"]);
+}
+
+#[test]
+fn journal_records_every_move() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("sub/notes.txt", "n");
+
+    fx.organize(&Options::default());
+
+    let journals = fx.journals();
+    assert_eq!(journals.len(), 1);
+    // Journals live in the state folder, not in the organized one
+    assert!(journals[0].starts_with(fx.state_dir()));
+    assert!(!fx.root().join(".directory-cleaner").exists());
+
+    let entries = journal::read(&journals[0]).unwrap();
+    let run_id = match &entries[0] {
+        Entry::Run { run_id, .. } => *run_id,
+        other => panic!("journal starts with {:?}", other),
+    };
+    assert!(journals[0].to_string_lossy().contains(&run_id.to_string()));
+
+    let mut moves: Vec<(String, String)> = entries
+        .iter()
+        .filter_map(|e| match e {
+            Entry::Move { from, to, .. } => Some((
+                from.to_string_lossy().replace('\\', "/"),
+                to.to_string_lossy().replace('\\', "/"),
+            )),
+            _ => None,
+        })
+        .collect();
+    moves.sort();
+    assert_eq!(
+        moves,
+        vec![
+            ("report.pdf".to_string(), "pdf/report.pdf".to_string()),
+            ("sub/notes.txt".to_string(), "txt/notes.txt".to_string()),
+        ]
+    );
+    assert!(matches!(entries.last(), Some(Entry::Done { moved: 2, .. })));
+}
+
+#[test]
+fn dry_run_writes_no_journal() {
+    let fx = Fixture::new().file("report.pdf", "r");
+
+    fx.organize(&Options {
+        dry_run: true,
+        ..Options::default()
+    });
+
+    assert!(fx.journals().is_empty());
+    assert!(!fx.root().join(".directory-cleaner").exists());
+}
+
+#[test]
+fn scripts_are_recognized_by_their_shebang() {
+    let fx = Fixture::new()
+        .file("deploy", "#!/usr/bin/env -S python3 -u\nprint('hi')\n")
+        .file("build", "#!/bin/sh\nmake\n")
+        .file("README", "just text");
+
+    fx.organize(&Options {
+        by: Grouping::Language,
+        ..Options::default()
+    });
+
+    assert_eq!(fx.files(), vec!["Python/deploy", "README", "Shell/build"]);
+}
+
+#[test]
+fn shortened_names_are_moved_and_journaled() {
+    let long = "a".repeat(250);
+    let fx = Fixture::new()
+        .file(&format!("{}.txt", long), "1")
+        .file(&format!("sub/{}.txt", long), "2");
+
+    fx.organize(&Options::default());
+
+    let files = fx.files();
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|f| f.starts_with("txt/")));
+
+    let journal = fx.journals().remove(0);
+    let shortened: Vec<bool> = journal::read(&journal)
+        .unwrap()
+        .into_iter()
+        .filter_map(|e| match e {
+            Entry::Move { shortened, .. } => Some(shortened),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(shortened.iter().filter(|s| **s).count(), 1);
+}
+
+#[test]
+fn ignore_files_use_gitignore_syntax() {
+    let fx = Fixture::new()
+        .file(
+            ".dircleanerignore",
+            "*.iso\n/top.pdf\nProjects/\n!keep/*.iso\n",
+        )
+        .file("big.iso", "i")
+        .file("keep/small.iso", "s")
+        .file("top.pdf", "t")
+        .file("sub/top.pdf", "u")
+        .file("Projects/plan.txt", "p")
+        .file("sub/.dircleanerignore", "notes.txt\n")
+        .file("sub/notes.txt", "n");
+
+    fx.organize(&Options::default());
+
+    assert_eq!(
+        fx.files(),
+        vec![
+            ".dircleanerignore",
+            "Projects/plan.txt",
+            "big.iso",
+            "iso/small.iso",
+            "pdf/top.pdf",
+            "sub/.dircleanerignore",
+            "sub/notes.txt",
+            "top.pdf",
+        ]
+    );
+}
+
+// Files the scan doesn't see (here: an ignored destination folder) still take their names, so
+// the plan, and a dry run, shows the renames and duplicates the moves would run into
+#[test]
+fn destinations_the_scan_left_out_are_looked_at() {
+    let fx = Fixture::new()
+        .file(".dircleanerignore", "pdf/\n")
+        .file("a.pdf", "new")
+        .file("pdf/a.pdf", "old")
+        .file("b.pdf", "same")
+        .file("pdf/b.pdf", "same");
+    let options = Options {
+        on_duplicate: DuplicateStrategy::Skip,
+        ..Options::default()
+    };
+    let files = vec![fx.root().join("a.pdf"), fx.root().join("b.pdf")];
+    let actions = plan::plan(&files, fx.root(), &options);
+    assert!(actions.contains(&PlannedAction::Move {
+        from: fx.root().join("a.pdf"),
+        to: fx.root().join("pdf/a (1).pdf"),
+        ext: "pdf".to_string(),
+        category: None,
+        shortened: false,
+    }));
+    assert!(actions.iter().any(|action| matches!(
+        action,
+        PlannedAction::Skip { path, reason }
+            if *path == fx.root().join("b.pdf") && reason.contains("identical")
+    )));
+
+    fx.organize(&options);
+    assert_eq!(
+        fx.files(),
+        vec![
+            ".dircleanerignore",
+            "b.pdf",
+            "pdf/a (1).pdf",
+            "pdf/a.pdf",
+            "pdf/b.pdf"
+        ]
+    );
+    assert_eq!(fx.read("pdf/a.pdf"), "old");
+}
+
+#[test]
+fn gitignore_is_only_honored_when_asked() {
+    let fx = Fixture::new()
+        .file(".gitignore", "*.log\n")
+        .file("a.log", "a")
+        .file("b.txt", "b");
+
+    fx.organize(&Options {
+        respect_gitignore: true,
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec![".gitignore", "a.log", "txt/b.txt"]);
+
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), vec![".gitignore", "log/a.log", "txt/b.txt"]);
+}
+
+#[test]
+fn html_report_lists_the_moves() {
+    let fx = Fixture::new()
+        .file("a&b.pdf", "12345")
+        .file("sub/notes.txt", "n")
+        .file("pdf", "blocks the pdf folder");
+    let out = tempfile::TempDir::new().unwrap();
+    let report = out.path().join("report.html");
+
+    fx.organize(&Options {
+        html_report: Some(report.clone()),
+        ..Options::default()
+    });
+
+    let html = std::fs::read_to_string(&report).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("Moved files (1)"));
+    assert!(html.contains("notes.txt"));
+    // The pdf couldn't be moved: the planner skipped it, names are escaped
+    assert!(html.contains("Skipped (1)"));
+    assert!(html.contains("a&amp;b.pdf"));
+    assert!(!html.contains("a&b.pdf"));
+}
+
+#[test]
+fn jsonl_output_is_one_event_per_line() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("notes.txt", "n");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_directory-cleaner"))
+        .args([
+            "--path",
+            fx.root().to_str().unwrap(),
+            "--output",
+            "jsonl",
+            "--dry-run",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Nothing but JSON on stdout
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|e| e["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, vec!["scan", "plan", "move", "move", "done"]);
+    assert_eq!(events[1]["moves"], 2);
+    assert_eq!(events[2]["dry_run"], true);
+    assert_eq!(events[4]["moved"], 2);
+    // A dry run has no run ID
+    assert!(events[4].get("run_id").is_none());
+    assert_eq!(fx.files(), vec!["notes.txt", "report.pdf"]);
+}
+
+#[test]
+fn warnings_are_counted_and_can_fail_the_run() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("pdf", "blocks the pdf folder")
+        .file("notes.txt", "n");
+    let run = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_directory-cleaner"))
+            .args(["--path", fx.root().to_str().unwrap(), "--output", "jsonl"])
+            .args(extra)
+            .output()
+            .unwrap()
+    };
+
+    // The skipped pdf is a warning: counted, but the run still succeeds
+    let output = run(&["--dry-run"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let done: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(done["event"], "done");
+    assert_eq!(done["warnings"], 1);
+    assert_eq!(done["problems"], 0);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning: Skipping"));
+
+    // ...unless warnings count as errors; the other files are moved all the same
+    let output = run(&["--warnings-as-errors"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 warning and 0 errors"));
+    assert_eq!(fx.files(), vec!["pdf", "report.pdf", "txt/notes.txt"]);
+}
+
+#[test]
+fn machine_readable_formats_carry_a_schema_version() {
+    let fx = Fixture::new().file("report.pdf", "r");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_directory-cleaner"))
+        .args(["--path", fx.root().to_str().unwrap(), "--print-plan"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        plan["schema_version"],
+        directory_cleaner::schema::SCHEMA_VERSION
+    );
+    assert_eq!(plan["actions"][0]["action"], "create_dir");
+    assert_eq!(plan["actions"][1]["action"], "move");
+    assert_eq!(plan["actions"][1]["ext"], "pdf");
+    // Printing the plan doesn't carry it out
+    assert_eq!(fx.files(), vec!["report.pdf"]);
+
+    // Journals written before the version was recorded still read as version 1
+    let old = fx.root().join("old.jsonl");
+    std::fs::write(
+        &old,
+        "{\"type\":\"run\",\"run_id\":\"6f1c3d2e-0000-4000-8000-000000000000\",\"started_at\":\"2024-06-01T10:33:12Z\",\"root\":\"/home/me\"}\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        journal::read(&old).unwrap()[0],
+        Entry::Run {
+            schema_version: 1,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn sample_shows_some_files_and_moves_nothing() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.txt", "b")
+        .file("c.zip", "c")
+        .file("sub/d.png", "d");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_directory-cleaner"))
+        .args(["--path", fx.root().to_str().unwrap(), "--sample", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Sample of 2 of 4 files"));
+    assert_eq!(stdout.matches(": move to ").count(), 2);
+    assert_eq!(fx.files(), vec!["a.pdf", "b.txt", "c.zip", "sub/d.png"]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn files_open_in_another_program_stay_put() {
+    let fx = Fixture::new()
+        .file("download.pdf", "still downloading")
+        .file("notes.txt", "n");
+
+    // `sleep` keeps the file open as its stdin until it's killed
+    let mut program = std::process::Command::new("sleep")
+        .arg("30")
+        .stdin(std::fs::File::open(fx.root().join("download.pdf")).unwrap())
+        .spawn()
+        .unwrap();
+    fx.organize(&Options::default());
+    program.kill().unwrap();
+    program.wait().unwrap();
+
+    assert_eq!(fx.files(), vec!["download.pdf", "txt/notes.txt"]);
+}
+
+#[test]
+fn adopt_existing_uses_the_folders_already_there() {
+    let fx = Fixture::new()
+        .file("pictures/old.png", "o")
+        .file("Music/song.flac", "s")
+        .file("photo.jpg", "p")
+        .file("song.mp3", "m")
+        .file("report.pdf", "r");
+
+    fx.organize(&Options {
+        adopt_existing: true,
+        ..Options::default()
+    });
+    assert_eq!(
+        fx.files(),
+        vec![
+            "Music/song.flac",
+            "Music/song.mp3",
+            "pdf/report.pdf",
+            "pictures/old.png",
+            "pictures/photo.jpg",
+        ]
+    );
+
+    // With --by category, and a --category-folder rule still wins over an adopted folder
+    let fx = Fixture::new()
+        .file("Pictures/old.png", "o")
+        .file("photo.jpg", "p")
+        .file("song.mp3", "m");
+    fx.organize(&Options {
+        adopt_existing: true,
+        by: Grouping::Category,
+        category_folders: vec![parse_category_folder("audio=Sounds").unwrap()],
+        ..Options::default()
+    });
+    assert_eq!(
+        fx.files(),
+        vec!["Pictures/old.png", "Pictures/photo.jpg", "Sounds/song.mp3"]
+    );
+}
+
+// (Windows can't open a folder with File::open to set its time up front)
+#[cfg(unix)]
+#[test]
+fn preserve_dir_mtime_restores_folder_times() {
+    let fx = Fixture::new()
+        .file("sub/report.pdf", "r")
+        .file("pdf/old.pdf", "o");
+    let long_ago =
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    let mtime = |rel: &str| {
+        std::fs::metadata(fx.root().join(rel))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+    for dir in ["", "sub", "pdf"] {
+        std::fs::File::open(fx.root().join(dir))
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+    }
+
+    fx.organize(&Options {
+        preserve_dir_mtime: true,
+        ..Options::default()
+    });
+
+    assert_eq!(fx.files(), vec!["pdf/old.pdf", "pdf/report.pdf"]);
+    assert_eq!(mtime(""), long_ago);
+    assert_eq!(mtime("sub"), long_ago);
+    assert_eq!(mtime("pdf"), long_ago);
+}
+
+#[cfg(unix)]
+#[test]
+fn owner_filter_leaves_other_users_files_alone() {
+    use directory_cleaner::owners::Owners;
+    use std::os::unix::fs::MetadataExt;
+
+    let fx = Fixture::new().file("report.pdf", "r");
+    let uid = std::fs::metadata(fx.root().join("report.pdf"))
+        .unwrap()
+        .uid();
+
+    // Somebody else's files: nothing to do, and nothing is reported as skipped
+    fx.organize(&Options {
+        owners: Owners::lookup(Some(&(uid + 1).to_string()), None).unwrap(),
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["report.pdf"]);
+
+    fx.organize(&Options {
+        owners: Owners::lookup(Some(&uid.to_string()), None).unwrap(),
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["pdf/report.pdf"]);
+
+    // Names are looked up in /etc/passwd and /etc/group
+    assert_eq!(
+        Owners::lookup(Some("root"), Some("root")).unwrap(),
+        Some(Owners {
+            uid: Some(0),
+            gid: Some(0)
+        })
+    );
+    assert!(Owners::lookup(Some("no-such-user-here"), None).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn files_in_read_only_folders_are_skipped_up_front() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // root may change any folder, so there's nothing to see
+    if rustix::process::geteuid().is_root() {
+        return;
+    }
+    let fx = Fixture::new()
+        .file("mine.pdf", "m")
+        .file("locked/theirs.pdf", "t");
+    let locked = fx.root().join("locked");
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+    let outcome = logic::process_directory(
+        fx.root().to_str().unwrap(),
+        &Options {
+            state_dir: Some(fx.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    );
+    // Unlocked again first, so the temp folder can be cleaned up even if the test fails
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    outcome.unwrap();
+    assert_eq!(fx.files(), vec!["locked/theirs.pdf", "pdf/mine.pdf"]);
+}
+
+#[test]
+fn a_pattern_in_path_organizes_only_the_matching_files() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.txt", "b")
+        .file("sub/c.pdf", "c");
+    let target =
+        directory_cleaner::target::parse(&format!("{}/*.pdf", fx.root().to_str().unwrap()))
+            .unwrap();
+
+    fx.organize(&Options {
+        matching: target.matching,
+        ..Options::default()
+    });
+
+    assert_eq!(fx.files(), vec!["b.txt", "pdf/a.pdf", "sub/c.pdf"]);
+}
+
+#[test]
+fn a_run_out_of_time_leaves_a_checkpoint_and_the_next_run_continues() {
+    let fx = Fixture::new().file("a.pdf", "a").file("b.txt", "b");
+
+    // Out of time before the first move
+    fx.organize(&Options {
+        deadline: Some(std::time::Instant::now()),
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["a.pdf", "b.txt"]);
+    let run_id = match journal::read(&fx.journals()[0]).unwrap()[0] {
+        Entry::Run { run_id, .. } => run_id,
+        ref other => panic!("journal starts with {:?}", other),
+    };
+    assert_eq!(
+        journal::checkpoint(fx.root(), Some(fx.state_dir())),
+        Some((run_id, 2))
+    );
+
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), vec!["pdf/a.pdf", "txt/b.txt"]);
+    // The second run finished, so there's nothing left to continue
+    assert_eq!(journal::checkpoint(fx.root(), Some(fx.state_dir())), None);
+}
+
+// A (picture-less) JPEG whose EXIF data says it was taken at 52°N: IFD0 points to a GPS folder
+// with a latitude reference ("N", fits in its entry) and a latitude (3 rationals, stored after it)
+fn jpeg_with_gps() -> Vec<u8> {
+    let mut tiff: Vec<u8> = b"II\x2a\x00".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    // IFD0 at 8: one entry, GPSInfo -> 26
+    tiff.extend(1u16.to_le_bytes());
+    tiff.extend([0x25, 0x88, 4, 0]);
+    tiff.extend(1u32.to_le_bytes());
+    tiff.extend(26u32.to_le_bytes());
+    tiff.extend(0u32.to_le_bytes());
+    // GPS folder at 26: GPSLatitudeRef "N", GPSLatitude at 56
+    tiff.extend(2u16.to_le_bytes());
+    tiff.extend([1, 0, 2, 0]);
+    tiff.extend(2u32.to_le_bytes());
+    tiff.extend(*b"N\0\0\0");
+    tiff.extend([2, 0, 5, 0]);
+    tiff.extend(3u32.to_le_bytes());
+    tiff.extend(56u32.to_le_bytes());
+    tiff.extend(0u32.to_le_bytes());
+    for (numerator, denominator) in [(52u32, 1u32), (31, 1), (0, 1)] {
+        tiff.extend(numerator.to_le_bytes());
+        tiff.extend(denominator.to_le_bytes());
+    }
+
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend(((2 + 6 + tiff.len()) as u16).to_be_bytes());
+    jpeg.extend(b"Exif\0\0");
+    jpeg.extend(tiff);
+    jpeg.extend([0xFF, 0xD9]);
+    jpeg
+}
+
+#[test]
+fn strip_exif_gps_removes_the_location_from_moved_photos() {
+    let fx = Fixture::new().file("holiday.jpg", "");
+    let photo = jpeg_with_gps();
+    std::fs::write(fx.root().join("holiday.jpg"), &photo).unwrap();
+    assert!(gps::has_gps(&fx.root().join("holiday.jpg")).unwrap());
+
+    // Undo can't put the location back, so that needs a yes like the trash does
+    let err = logic::process_directory(
+        fx.root().to_str().unwrap(),
+        &Options {
+            strip_exif_gps: true,
+            state_dir: Some(fx.state_dir().to_path_buf()),
+            ..Options::default()
+        },
+    )
+    .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("removes the GPS location from 1 photos"));
+    assert_eq!(fx.files(), vec!["holiday.jpg"]);
+
+    fx.organize(&Options {
+        strip_exif_gps: true,
+        yes: true,
+        ..Options::default()
+    });
+
+    let moved = fx.root().join("jpg/holiday.jpg");
+    assert!(!gps::has_gps(&moved).unwrap());
+    let scrubbed = std::fs::read(&moved).unwrap();
+    assert_eq!(scrubbed.len(), photo.len());
+    // The rest of the EXIF data is still there, the latitude isn't
+    assert_eq!(scrubbed[..30], photo[..30]);
+    assert!(!scrubbed.windows(4).any(|w| w == 52u32.to_le_bytes()));
+}
+
+#[cfg(unix)]
+#[test]
+fn link_policy_skips_linked_files_or_updates_the_links() {
+    use directory_cleaner::links::LinkPolicy;
+    use std::os::unix::fs::symlink;
+
+    let fx = Fixture::new()
+        .file("linked.pdf", "l")
+        .file("report.pdf", "r");
+    let desktop = Fixture::new();
+    let link = desktop.root().join("linked shortcut");
+    symlink(fx.root().join("linked.pdf"), &link).unwrap();
+    let link_dirs = vec![desktop.root().to_path_buf()];
+
+    // skip: the file the link points to stays
+    fx.organize(&Options {
+        link_policy: LinkPolicy::Skip,
+        link_dirs: link_dirs.clone(),
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["linked.pdf", "pdf/report.pdf"]);
+
+    // update: it moves, and the link follows it
+    fx.organize(&Options {
+        link_policy: LinkPolicy::Update,
+        link_dirs,
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["pdf/linked.pdf", "pdf/report.pdf"]);
+    assert_eq!(std::fs::read_to_string(&link).unwrap(), "l");
+
+    // Undo moves the file back and points the link at it again
+    fx.undo(&directory_cleaner::undo::UndoOptions::default());
+    assert_eq!(fx.files(), vec!["linked.pdf", "pdf/report.pdf"]);
+    assert_eq!(
+        std::fs::read_link(&link).unwrap(),
+        fx.root().join("linked.pdf")
+    );
+}
+
+// A Windows shortcut to `target` (ANSI link info), the way older versions of Windows write them:
+// with an ID list, a relative path, an environment-variable block and a property block
+fn shortcut(target: &str, relative: &str) -> Vec<u8> {
+    let mut data = vec![0u8; 0x4C];
+    data[0] = 0x4C;
+    // ID list + link info + relative path, no Unicode strings
+    data[0x14..0x18].copy_from_slice(&(1u32 | 2 | 8).to_le_bytes());
+    // The ID list: one made-up item, then the end
+    data.extend(8u16.to_le_bytes());
+    data.extend(6u16.to_le_bytes());
+    data.extend([0x1F, 0x50, 0xAA, 0xBB]);
+    data.extend(0u16.to_le_bytes());
+
+    let volume_id: Vec<u8> = [0x11u32, 3, 0x1234, 0x10]
+        .iter()
+        .flat_map(|f| f.to_le_bytes())
+        .chain([0])
+        .collect();
+    let base_at = 0x1C + volume_id.len();
+    let suffix_at = base_at + target.len() + 1;
+    let size = suffix_at + 1;
+    for field in [size, 0x1C, 1, 0x1C, base_at, 0, suffix_at] {
+        data.extend((field as u32).to_le_bytes());
+    }
+    data.extend(&volume_id);
+    data.extend(target.as_bytes());
+    data.extend([0, 0]);
+
+    data.extend((relative.len() as u16).to_le_bytes());
+    data.extend(relative.as_bytes());
+
+    for (size, signature) in [(0x314u32, 0xA000_0001u32), (12, 0xA000_0009)] {
+        data.extend(size.to_le_bytes());
+        data.extend(signature.to_le_bytes());
+        data.extend(vec![0x42; size as usize - 8]);
+    }
+    data.extend(0u32.to_le_bytes());
+    data
+}
+
+#[test]
+fn shortcuts_can_be_pointed_at_the_new_place() {
+    use directory_cleaner::lnk::{retarget, target};
+
+    let old = shortcut(
+        r"C:\Users\me\Downloads\report.pdf",
+        r"..\Downloads\report.pdf",
+    );
+    assert_eq!(
+        target(&old).as_deref(),
+        Some(r"C:\Users\me\Downloads\report.pdf")
+    );
+
+    let new = retarget(
+        &old,
+        r"C:\Users\me\Downloads\pdf\réport.pdf",
+        Some(r"..\Downloads\pdf\report.pdf"),
+    )
+    .unwrap();
+    assert_eq!(
+        target(&new).as_deref(),
+        Some(r"C:\Users\me\Downloads\pdf\réport.pdf")
+    );
+    // The ID list and the environment-variable block (both would still say the old place) are
+    // gone, the drive's serial number and the property block are kept
+    let flags = u32::from_le_bytes(new[0x14..0x18].try_into().unwrap());
+    assert_eq!(flags & 1, 0);
+    let has = |bytes: &[u8]| new.windows(bytes.len()).any(|w| w == bytes);
+    assert!(!has(&0xA000_0001u32.to_le_bytes()));
+    assert!(has(&0xA000_0009u32.to_le_bytes()));
+    assert!(has(&0x1234u32.to_le_bytes()));
+    assert!(has(br"..\Downloads\pdf\report.pdf"));
+
+    // And back again (what undo does)
+    let back = retarget(&new, r"C:\Users\me\Downloads\report.pdf", None).unwrap();
+    assert_eq!(
+        target(&back).as_deref(),
+        Some(r"C:\Users\me\Downloads\report.pdf")
+    );
+    assert!(target(b"not a shortcut").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_plan_runs_the_plan_as_edited() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.pdf", "b")
+        .file("notes.txt", "n");
+    // The "editor" removes b.pdf's line and sends notes.txt elsewhere (no sed -i, macOS's differs)
+    let editor = r#"edit() { grep -v b.pdf "$1" | sed 's|-> txt/|-> docs/|' > "$1.new" && mv "$1.new" "$1"; }; edit"#;
+    fx.organize(&Options {
+        edit_plan: Some(editor.to_string()),
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["b.pdf", "docs/notes.txt", "pdf/a.pdf"]);
+
+    // An editor that fails means nothing moves
+    let result = directory_cleaner::logic::process_directory(
+        fx.root().to_str().unwrap(),
+        &Options {
+            edit_plan: Some("false".to_string()),
+            ..Options::default()
+        },
+    );
+    assert!(result.is_err());
+    assert_eq!(fx.files(), vec!["b.pdf", "docs/notes.txt", "pdf/a.pdf"]);
+}
+
+#[test]
+fn check_rules_fails_for_a_rule_that_never_applies() {
+    use directory_cleaner::args::parse_rule;
+
+    let fx = Fixture::new()
+        .file("invoice-march.pdf", "i")
+        .file("manual.pdf", "m")
+        .file("notes.txt", "n");
+    let root = fx.root().to_str().unwrap();
+    let rules = |given: &[&str]| Options {
+        rules: plan::by_priority(given.iter().map(|r| parse_rule(r).unwrap()).collect()),
+        ..Options::default()
+    };
+
+    // "*.pdf" takes the invoices before "*invoice*.pdf" sees them
+    let shadowed = rules(&["*.pdf=Documents", "*invoice*.pdf=Finance"]);
+    let error = logic::check_rules(root, &shadowed).unwrap_err().to_string();
+    assert!(
+        error.contains("0:*invoice*.pdf=Finance never applies"),
+        "{}",
+        error
+    );
+
+    // A higher priority fixes that; a rule that matches nothing here is fine
+    let fixed = rules(&["*.pdf=Documents", "1:*invoice*.pdf=Finance", "*.mp3=Music"]);
+    logic::check_rules(root, &fixed).unwrap();
+    assert!(logic::check_rules(root, &Options::default()).is_err());
+
+    // Nothing was moved
+    assert_eq!(
+        fx.files(),
+        vec!["invoice-march.pdf", "manual.pdf", "notes.txt"]
+    );
+}
+
+#[test]
+fn since_last_run_only_looks_at_new_files() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("c.pdf", "old")
+        .file("pdf/c.pdf", "other");
+    let skip = Options {
+        on_conflict: ConflictStrategy::Skip,
+        since_last_run: true,
+        ..Options::default()
+    };
+    // No earlier run: every file is looked at (c.pdf is skipped, its name is taken)
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fx.organize(&skip);
+    assert_eq!(fx.files(), vec!["c.pdf", "pdf/a.pdf", "pdf/c.pdf"]);
+
+    // The next run only sees the file that came since; c.pdf would be renamed, but it's older
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    std::fs::write(fx.root().join("d.pdf"), "d").unwrap();
+    let options = Options {
+        since_last_run: true,
+        ..Options::default()
+    };
+    fx.organize(&options);
+    assert_eq!(
+        fx.files(),
+        vec!["c.pdf", "pdf/a.pdf", "pdf/c.pdf", "pdf/d.pdf"]
+    );
+
+    // Without it, everything again
+    fx.organize(&Options::default());
+    assert_eq!(
+        fx.files(),
+        vec!["pdf/a.pdf", "pdf/c (1).pdf", "pdf/c.pdf", "pdf/d.pdf"]
+    );
+}
+
+#[test]
+fn min_group_size_counts_the_files_the_scan_left_out() {
+    let fx = Fixture::new()
+        .file("pdf/a.pdf", "a")
+        .file("pdf/b.pdf", "b")
+        .file("pdf/c.pdf", "c")
+        .file("one.jpg", "1")
+        .file("two.jpg", "2");
+    fx.organize(&Options::default());
+    std::thread::sleep(std::time::Duration::from_millis(20));
+
+    // The scan only sees new.pdf (and the photos moved last time), but pdf/ has three already
+    std::fs::write(fx.root().join("new.pdf"), "n").unwrap();
+    let options = Options {
+        since_last_run: true,
+        min_group_size: NonZeroUsize::new(2),
+        ..Options::default()
+    };
+    fx.organize(&options);
+    assert_eq!(
+        fx.files(),
+        vec![
+            "jpg/one.jpg",
+            "jpg/two.jpg",
+            "pdf/a.pdf",
+            "pdf/b.pdf",
+            "pdf/c.pdf",
+            "pdf/new.pdf"
+        ]
+    );
+
+    // The same for a --path pattern that only sees the files right in the root
+    std::fs::write(fx.root().join("late.pdf"), "l").unwrap();
+    let target =
+        directory_cleaner::target::parse(&format!("{}/*.pdf", fx.root().to_str().unwrap()))
+            .unwrap();
+    let options = Options {
+        min_group_size: NonZeroUsize::new(2),
+        matching: target.matching,
+        ..Options::default()
+    };
+    fx.organize(&options);
+    assert!(fx.root().join("pdf/late.pdf").exists());
+}
+
+#[test]
+fn protected_folders_are_refused_unless_allow_system() {
+    let fx = Fixture::new().file("AppData/Local/settings.json", "{}");
+    let app_data = fx.root().join("AppData/Local");
+    let options = Options {
+        state_dir: Some(fx.state_dir().to_path_buf()),
+        ..Options::default()
+    };
+
+    let err = logic::process_directory(app_data.to_str().unwrap(), &options).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("Refusing to organize protected system directory"));
+    assert!(app_data.join("settings.json").exists());
+
+    logic::process_directory(
+        app_data.to_str().unwrap(),
+        &Options {
+            allow_system: true,
+            ..options
+        },
+    )
+    .unwrap();
+    assert!(app_data.join("json/settings.json").exists());
+}
+
+#[test]
+fn protected_folders_are_skipped_when_a_parent_is_organized() {
+    let fx = Fixture::new()
+        .file("AppData/settings.json", "{}")
+        .file("report.pdf", "r");
+
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), vec!["AppData/settings.json", "pdf/report.pdf"]);
+
+    fx.organize(&Options {
+        allow_system: true,
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["json/settings.json", "pdf/report.pdf"]);
+}
+
+#[test]
+fn the_root_of_the_file_system_is_refused() {
+    let err = logic::process_directory("/", &Options::default()).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("Refusing to organize protected system directory"));
+}
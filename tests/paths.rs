@@ -0,0 +1,75 @@
+// Telling whether two paths are the same place, and the paths typed on the command line
+use directory_cleaner::paths::{key, normalize, same, starts_with};
+use std::path::Path;
+
+#[test]
+fn the_same_place_written_differently() {
+    assert!(same(
+        Path::new("/home/me/Downloads/"),
+        Path::new("/home/me/Downloads")
+    ));
+    assert!(same(
+        Path::new("/home/me/./Downloads"),
+        Path::new("/home/me/Downloads")
+    ));
+    assert!(same(Path::new("a//b"), Path::new("a/b")));
+    assert!(same(Path::new("a/b/../c"), Path::new("a/c")));
+    assert!(same(
+        Path::new("/home/me/../me/pdf"),
+        Path::new("/home/me/pdf")
+    ));
+    assert!(same(Path::new("/../home"), Path::new("/home")));
+    // Above where a relative path starts stays above it
+    assert!(same(Path::new("../a/../b"), Path::new("../b")));
+    assert!(!same(Path::new("../b"), Path::new("b")));
+    assert!(!same(Path::new("a/b"), Path::new("a/c")));
+    assert!(starts_with(Path::new("pdf/sub/a.pdf"), Path::new("pdf")));
+    assert!(!starts_with(Path::new("pdfs/a.pdf"), Path::new("pdf")));
+}
+
+#[test]
+fn case_matters_only_where_the_file_system_says_so() {
+    let differ = key(Path::new("PDF/Report.pdf")) != key(Path::new("pdf/report.pdf"));
+    assert_eq!(differ, !cfg!(any(windows, target_os = "macos")));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn paths_outside_windows_are_left_alone() {
+    assert_eq!(normalize("./Downloads/inbox"), "./Downloads/inbox");
+    assert_eq!(normalize(r"back\slash"), r"back\slash");
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_paths_are_the_same_place_however_they_are_written() {
+    assert!(same(Path::new(r"C:\Users\me"), Path::new("C:/Users/me")));
+    assert!(same(Path::new(r"C:\Users\me"), Path::new(r"c:\users\ME")));
+    assert!(same(
+        Path::new(r"\\?\C:\Users\me"),
+        Path::new(r"C:\Users\me")
+    ));
+    assert!(same(
+        Path::new(r"\\server\share\inbox"),
+        Path::new("//server/share/inbox")
+    ));
+    assert!(same(
+        Path::new(r"\\?\UNC\server\share\inbox"),
+        Path::new(r"\\server\share\inbox")
+    ));
+    // Relative to the current folder of C:, which isn't C:\
+    assert!(!same(Path::new("C:Users"), Path::new(r"C:\Users")));
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_paths_from_the_command_line_are_normalized() {
+    assert_eq!(normalize("//server/share/inbox"), r"\\server\share\inbox");
+    assert_eq!(normalize("C:/Users/me/Downloads"), r"C:\Users\me\Downloads");
+
+    let here = std::env::current_dir().unwrap();
+    let drive = &here.to_string_lossy()[..2];
+    let normalized = normalize(&format!("{}inbox", drive));
+    assert!(Path::new(&normalized).is_absolute(), "{}", normalized);
+    assert!(same(Path::new(&normalized), &here.join("inbox")));
+}
@@ -0,0 +1,1178 @@
+// The planner never touches the disk, so these tests use made-up paths
+use directory_cleaner::args::{parse_category_folder, parse_compound_ext, parse_map, parse_rule};
+use directory_cleaner::locale;
+use directory_cleaner::logic::Options;
+use directory_cleaner::plan::{
+    by_priority, explain, limit, plan, plan_with, second_run, shuffle, skip_open, skip_unmovable,
+    trash_where, ConflictStrategy, Contents, DuplicateStrategy, Grouping, JunkStrategy,
+    PlannedAction, SmallGroups,
+};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+fn root() -> PathBuf {
+    PathBuf::from("/fake/root")
+}
+
+fn paths(rels: &[&str]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = rels.iter().map(|r| root().join(r)).collect();
+    files.sort();
+    files
+}
+
+// Made-up file contents for plan_with()
+struct Fake<F: Fn(&Path, &Path) -> bool> {
+    same: F,
+    // Files without an extension, and the program in their "#!" line
+    scripts: Vec<(PathBuf, &'static str)>,
+}
+
+impl<F: Fn(&Path, &Path) -> bool> Contents for Fake<F> {
+    fn same(&self, a: &Path, b: &Path) -> bool {
+        (self.same)(a, b)
+    }
+
+    fn interpreter(&self, file: &Path) -> Option<String> {
+        self.scripts
+            .iter()
+            .find(|(path, _)| path == file)
+            .map(|(_, program)| program.to_string())
+    }
+}
+
+// Contents where two files are the same if `same` says so
+fn same_if<F: Fn(&Path, &Path) -> bool>(same: F) -> Fake<F> {
+    Fake {
+        same,
+        scripts: Vec::new(),
+    }
+}
+
+fn moves(actions: &[PlannedAction]) -> Vec<(PathBuf, PathBuf)> {
+    actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::Move { from, to, .. } => Some((from.clone(), to.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn skips(actions: &[PlannedAction]) -> Vec<&Path> {
+    actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::Skip { path, .. } => Some(path.as_path()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn moves_files_into_extension_folders() {
+    let actions = plan(
+        &paths(&["report.pdf", "sub/photo.JPG"]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("report.pdf"), root().join("pdf/report.pdf")),
+            (root().join("sub/photo.JPG"), root().join("jpg/photo.JPG")),
+        ]
+    );
+}
+
+#[test]
+fn compound_extensions_are_one_extension() {
+    let files = paths(&[
+        "backup.tar.gz",
+        "pdf/backup.tar.gz",
+        "tar.gz/backup.tar.gz",
+        "brain.NII.GZ",
+        "notes.txt.gz",
+        ".tar.gz",
+        "scan.dcm.gz",
+    ]);
+    let actions = plan(&files, &root(), &Options::default());
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join(".tar.gz"), root().join("gz/.tar.gz")),
+            (
+                root().join("backup.tar.gz"),
+                root().join("tar.gz/backup (1).tar.gz")
+            ),
+            (
+                root().join("brain.NII.GZ"),
+                root().join("nii.gz/brain.NII.GZ")
+            ),
+            (root().join("notes.txt.gz"), root().join("gz/notes.txt.gz")),
+            (
+                root().join("pdf/backup.tar.gz"),
+                root().join("tar.gz/backup (2).tar.gz")
+            ),
+            (root().join("scan.dcm.gz"), root().join("gz/scan.dcm.gz")),
+        ]
+    );
+
+    // More of them, and by category
+    let actions = plan(
+        &files,
+        &root(),
+        &Options {
+            by: Grouping::Category,
+            compound_extensions: vec![parse_compound_ext(".DCM.gz").unwrap()],
+            map: vec![parse_map("nii.gz=Scans").unwrap()],
+            ..Options::default()
+        },
+    );
+    let moved = moves(&actions);
+    assert!(moved.contains(&(
+        root().join("backup.tar.gz"),
+        root().join("Archives/backup.tar.gz")
+    )));
+    assert!(moved.contains(&(
+        root().join("brain.NII.GZ"),
+        root().join("Scans/brain.NII.GZ")
+    )));
+    assert!(moved.contains(&(
+        root().join("scan.dcm.gz"),
+        root().join("dcm.gz/scan.dcm.gz")
+    )));
+
+    assert!(parse_compound_ext("gz").is_err());
+    assert!(parse_compound_ext("tar..gz").is_err());
+    assert!(parse_compound_ext("a/b.gz").is_err());
+}
+
+#[test]
+fn creates_each_folder_once_before_any_move() {
+    let actions = plan(
+        &paths(&["a.pdf", "b.pdf", "c.txt"]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert_eq!(
+        &actions[..2],
+        &[
+            PlannedAction::CreateDir {
+                path: root().join("pdf")
+            },
+            PlannedAction::CreateDir {
+                path: root().join("txt")
+            },
+        ]
+    );
+    assert_eq!(moves(&actions).len(), 3);
+}
+
+#[test]
+fn leaves_files_without_extension_and_already_organized_files_alone() {
+    let actions = plan(
+        &paths(&["README", "pdf/done.pdf"]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert!(moves(&actions).is_empty());
+}
+
+#[test]
+fn renames_colliding_destinations_in_order() {
+    let actions = plan(
+        &paths(&["a/report.pdf", "b/report.pdf", "pdf/report.pdf"]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (
+                root().join("a/report.pdf"),
+                root().join("pdf/report (1).pdf")
+            ),
+            (
+                root().join("b/report.pdf"),
+                root().join("pdf/report (2).pdf")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn rename_skips_numbers_that_are_already_taken() {
+    let actions = plan(
+        &paths(&["report.pdf", "pdf/report.pdf", "pdf/report (1).pdf"]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![(root().join("report.pdf"), root().join("pdf/report (2).pdf"))]
+    );
+}
+
+#[test]
+fn skip_strategy_keeps_the_first_file_only() {
+    let actions = plan(
+        &paths(&["a/report.pdf", "b/report.pdf"]),
+        &root(),
+        &Options {
+            on_conflict: ConflictStrategy::Skip,
+            ..Options::default()
+        },
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![(root().join("a/report.pdf"), root().join("pdf/report.pdf"))]
+    );
+    assert_eq!(skips(&actions), vec![root().join("b/report.pdf")]);
+}
+
+#[test]
+fn file_in_the_way_of_a_folder_skips_its_moves() {
+    // "txt" has no extension, so it stays, and blocks the "txt" folder
+    let actions = plan(&paths(&["txt", "notes.txt"]), &root(), &Options::default());
+
+    assert!(moves(&actions).is_empty());
+    assert_eq!(skips(&actions), vec![root().join("notes.txt")]);
+}
+
+#[test]
+fn planning_is_deterministic() {
+    let files = paths(&["x/a.pdf", "y/a.pdf", "z/a.pdf", "b.txt"]);
+    assert_eq!(
+        plan(&files, &root(), &Options::default()),
+        plan(&files, &root(), &Options::default())
+    );
+}
+
+#[test]
+fn groups_by_category() {
+    let actions = plan(
+        &paths(&["report.pdf", "notes.TXT", "photo.jpg", "data.xyz"]),
+        &root(),
+        &Options {
+            by: Grouping::Category,
+            ..Options::default()
+        },
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("data.xyz"), root().join("xyz/data.xyz")),
+            (root().join("notes.TXT"), root().join("Documents/notes.TXT")),
+            (root().join("photo.jpg"), root().join("Images/photo.jpg")),
+            (
+                root().join("report.pdf"),
+                root().join("Documents/report.pdf")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn keep_both_never_compares_contents() {
+    let actions = plan_with(
+        &paths(&["a/report.pdf", "b/report.pdf"]),
+        &root(),
+        &Options::default(),
+        &same_if(|_, _| panic!("contents compared")),
+    );
+
+    assert_eq!(moves(&actions).len(), 2);
+}
+
+#[test]
+fn identical_copies_are_skipped() {
+    let actions = plan_with(
+        &paths(&["a/report.pdf", "b/report.pdf"]),
+        &root(),
+        &Options {
+            on_duplicate: DuplicateStrategy::Skip,
+            ..Options::default()
+        },
+        &same_if(|_, _| true),
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![(root().join("a/report.pdf"), root().join("pdf/report.pdf"))]
+    );
+    assert_eq!(skips(&actions), vec![root().join("b/report.pdf")]);
+}
+
+#[test]
+fn different_files_fall_back_to_the_conflict_strategy() {
+    let actions = plan_with(
+        &paths(&["a/report.pdf", "b/report.pdf"]),
+        &root(),
+        &Options {
+            on_duplicate: DuplicateStrategy::Skip,
+            ..Options::default()
+        },
+        &same_if(|_, _| false),
+    );
+
+    assert_eq!(
+        moves(&actions)[1],
+        (
+            root().join("b/report.pdf"),
+            root().join("pdf/report (1).pdf")
+        )
+    );
+}
+
+#[test]
+fn duplicates_of_numbered_copies_are_trashed() {
+    // Only the earlier "report (1).pdf" has the same contents
+    let existing = root().join("pdf/report (1).pdf");
+    let actions = plan_with(
+        &paths(&["pdf/report.pdf", "pdf/report (1).pdf", "report.pdf"]),
+        &root(),
+        &Options {
+            on_duplicate: DuplicateStrategy::TrashSource,
+            ..Options::default()
+        },
+        &same_if(|_, other| other == existing),
+    );
+
+    assert!(moves(&actions).is_empty());
+    assert_eq!(
+        actions,
+        vec![
+            PlannedAction::CreateDir {
+                path: root().join("pdf")
+            },
+            PlannedAction::Trash {
+                path: root().join("report.pdf"),
+                duplicate_of: Some(existing),
+            },
+        ]
+    );
+}
+
+#[test]
+fn backed_up_files_can_be_trashed_instead_of_moved() {
+    let backup = PathBuf::from("/fake/backup/report.pdf");
+    let actions = trash_where(
+        plan(
+            &paths(&["report.pdf", "notes.txt"]),
+            &root(),
+            &Options::default(),
+        ),
+        |file| (file == root().join("report.pdf")).then(|| backup.clone()),
+    );
+
+    // pdf/ isn't needed anymore
+    assert_eq!(
+        actions,
+        vec![
+            PlannedAction::CreateDir {
+                path: root().join("txt")
+            },
+            PlannedAction::Move {
+                from: root().join("notes.txt"),
+                to: root().join("txt/notes.txt"),
+                ext: "txt".to_string(),
+                category: None,
+                shortened: false,
+            },
+            PlannedAction::Trash {
+                path: root().join("report.pdf"),
+                duplicate_of: Some(backup),
+            },
+        ]
+    );
+}
+
+// On a case-insensitive file system "PDF" is the "pdf" folder, so files in it are where they belong
+#[cfg(any(windows, target_os = "macos"))]
+#[test]
+fn folders_that_differ_only_in_case_are_the_same_folder() {
+    let actions = plan(
+        &paths(&["PDF/Report.PDF", "report.pdf"]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![(root().join("report.pdf"), root().join("pdf/report (1).pdf"))]
+    );
+}
+
+#[test]
+fn junk_is_collected_in_temp() {
+    let actions = plan(
+        &paths(&[
+            "~$report.docx",
+            "old/notes.BAK",
+            "mail/ATT00001.htm",
+            "report.docx",
+        ]),
+        &root(),
+        &Options::default(),
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (
+                root().join("mail/ATT00001.htm"),
+                root().join("Temp/ATT00001.htm")
+            ),
+            (root().join("old/notes.BAK"), root().join("Temp/notes.BAK")),
+            (root().join("report.docx"), root().join("docx/report.docx")),
+            (
+                root().join("~$report.docx"),
+                root().join("Temp/~$report.docx")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn junk_can_be_trashed_or_organized_normally() {
+    let files = paths(&["a.tmp", "Temp/b.tmp"]);
+
+    let trashed = plan(
+        &files,
+        &root(),
+        &Options {
+            junk: JunkStrategy::Trash,
+            ..Options::default()
+        },
+    );
+    assert_eq!(
+        trashed,
+        vec![
+            PlannedAction::Trash {
+                path: root().join("Temp/b.tmp"),
+                duplicate_of: None,
+            },
+            PlannedAction::Trash {
+                path: root().join("a.tmp"),
+                duplicate_of: None,
+            },
+        ]
+    );
+
+    let organized = plan(
+        &files,
+        &root(),
+        &Options {
+            junk: JunkStrategy::Organize,
+            ..Options::default()
+        },
+    );
+    assert_eq!(moves(&organized).len(), 2);
+    assert!(moves(&organized)
+        .iter()
+        .all(|(_, to)| to.parent() == Some(&root().join("tmp"))));
+}
+
+#[test]
+fn junk_patterns() {
+    use directory_cleaner::category::is_junk;
+    use std::ffi::OsStr;
+
+    for name in [
+        "~$Budget.xlsx",
+        ".~lock.notes.odt#",
+        "x.TMP",
+        "winmail.dat",
+        "image003.png",
+    ] {
+        assert!(is_junk(OsStr::new(name)), "{} should be junk", name);
+    }
+    for name in [
+        "report.pdf",
+        "template.docx",
+        "image.png",
+        "my image001.png",
+    ] {
+        assert!(!is_junk(OsStr::new(name)), "{} should not be junk", name);
+    }
+}
+
+#[test]
+fn groups_code_by_language() {
+    let files = paths(&["main.rs", "tool.PY", "backup", "notes", "report.pdf"]);
+    let contents = Fake {
+        same: |_: &Path, _: &Path| false,
+        scripts: vec![(root().join("backup"), "bash")],
+    };
+
+    let actions = plan_with(
+        &files,
+        &root(),
+        &Options {
+            by: Grouping::Language,
+            ..Options::default()
+        },
+        &contents,
+    );
+
+    // "notes" has no extension and no "#!" line, so it stays
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("backup"), root().join("Shell/backup")),
+            (root().join("main.rs"), root().join("Rust/main.rs")),
+            (root().join("report.pdf"), root().join("pdf/report.pdf")),
+            (root().join("tool.PY"), root().join("Python/tool.PY")),
+        ]
+    );
+}
+
+#[test]
+fn groups_by_mime_supertype() {
+    let actions = plan(
+        &paths(&[
+            "photo.JPG",
+            "clip.mp4",
+            "notes.txt",
+            "data.zip",
+            "thing.xyz123",
+        ]),
+        &root(),
+        &Options {
+            by: Grouping::Mime,
+            ..Options::default()
+        },
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("clip.mp4"), root().join("video/clip.mp4")),
+            (root().join("data.zip"), root().join("application/data.zip")),
+            (root().join("notes.txt"), root().join("text/notes.txt")),
+            (root().join("photo.JPG"), root().join("image/photo.JPG")),
+            (
+                root().join("thing.xyz123"),
+                root().join("xyz123/thing.xyz123")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn preserve_structure_keeps_subfolders() {
+    let actions = plan(
+        &paths(&["sub/dir/report.pdf", "top.pdf", "pdf/old/kept.pdf"]),
+        &root(),
+        &Options {
+            preserve_structure: true,
+            ..Options::default()
+        },
+    );
+
+    // "pdf/old/kept.pdf" was organized before, it stays
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (
+                root().join("sub/dir/report.pdf"),
+                root().join("pdf/sub/dir/report.pdf")
+            ),
+            (root().join("top.pdf"), root().join("pdf/top.pdf")),
+        ]
+    );
+}
+
+#[test]
+fn names_too_long_for_the_os_are_shortened() {
+    // 250 + ".pdf" fits in 255 bytes, "... (1).pdf" doesn't
+    let long = "a".repeat(250);
+    let actions = plan(
+        &paths(&[&format!("{}.pdf", long), &format!("sub/{}.pdf", long)]),
+        &root(),
+        &Options::default(),
+    );
+
+    let moved: Vec<(&Path, bool)> = actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::Move { to, shortened, .. } => Some((to.as_path(), *shortened)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(moved.len(), 2);
+    assert_eq!(
+        moved[0],
+        (root().join(format!("pdf/{}.pdf", long)).as_path(), false)
+    );
+
+    let (short, shortened) = moved[1];
+    let name = short.file_name().unwrap().to_str().unwrap();
+    assert!(shortened);
+    assert!(name.len() <= 255);
+    assert!(name.starts_with("aaaa") && name.ends_with(".pdf"));
+    assert!(name.contains('~'));
+}
+
+#[test]
+fn paths_too_long_for_the_os_are_shortened_or_skipped() {
+    // With --preserve-structure "pdf/" pushes these over the path length limit
+    let deep = vec!["d".repeat(99); 40].join("/");
+    let deeper = vec!["d".repeat(99); 41].join("/");
+    let fits = format!("{}/{}.pdf", deep, "n".repeat(96));
+    let too_deep = format!("{}/report.pdf", deeper);
+    let actions = plan(
+        &paths(&[&fits, &too_deep]),
+        &root(),
+        &Options {
+            preserve_structure: true,
+            ..Options::default()
+        },
+    );
+
+    let moved = moves(&actions);
+    assert_eq!(moved.len(), 1);
+    assert!(moved[0].1.as_os_str().len() <= 4095);
+    assert!(moved[0].1.starts_with(root().join("pdf").join(&deep)));
+    assert_eq!(skips(&actions), vec![root().join(&too_deep).as_path()]);
+}
+
+#[test]
+fn map_overrides_grouping() {
+    let options = Options {
+        by: Grouping::Category,
+        map: vec![
+            parse_map("pdf=Documents").unwrap(),
+            parse_map(".JPG, png=Pictures/Phone").unwrap(),
+            // Later flags win
+            parse_map("pdf=Papers").unwrap(),
+            parse_map("bak=Backups").unwrap(),
+        ],
+        ..Options::default()
+    };
+    let actions = plan(
+        &paths(&["a.pdf", "b.jpg", "c.png", "d.mp3", "e.bak"]),
+        &root(),
+        &options,
+    );
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("a.pdf"), root().join("Papers/a.pdf")),
+            (root().join("b.jpg"), root().join("Pictures/Phone/b.jpg")),
+            (root().join("c.png"), root().join("Pictures/Phone/c.png")),
+            (root().join("d.mp3"), root().join("Audio/d.mp3")),
+            // A --map rule beats the junk patterns
+            (root().join("e.bak"), root().join("Backups/e.bak")),
+        ]
+    );
+
+    // With --preserve-structure, files already in a nested mapped folder stay put
+    let actions = plan(
+        &paths(&["Pictures/Phone/old/b.jpg"]),
+        &root(),
+        &Options {
+            preserve_structure: true,
+            ..options
+        },
+    );
+    assert!(moves(&actions).is_empty());
+}
+
+#[test]
+fn map_rejects_folders_outside_the_root() {
+    assert_eq!(
+        parse_map("pdf=Documents/"),
+        Ok(directory_cleaner::plan::Mapping {
+            exts: vec!["pdf".to_string()],
+            folder: "Documents".to_string(),
+        })
+    );
+    assert!(parse_map("pdf").is_err());
+    assert!(parse_map("=Documents").is_err());
+    assert!(parse_map("pdf,=Documents").is_err());
+    assert!(parse_map("pdf=").is_err());
+    assert!(parse_map("pdf=../Documents").is_err());
+    assert!(parse_map("pdf=/tmp").is_err());
+    assert!(parse_map("pdf=.directory-cleaner/x").is_err());
+}
+
+#[test]
+fn category_folders_rename_only_the_folder() {
+    let options = Options {
+        by: Grouping::Category,
+        category_folders: vec![
+            parse_category_folder("Images=Pictures/").unwrap(),
+            parse_category_folder("temp=Junk").unwrap(),
+        ],
+        ..Options::default()
+    };
+    let actions = plan(&paths(&["a.jpg", "b.pdf", "c.tmp"]), &root(), &options);
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("a.jpg"), root().join("Pictures/a.jpg")),
+            (root().join("b.pdf"), root().join("Documents/b.pdf")),
+            (root().join("c.tmp"), root().join("Junk/c.tmp")),
+        ]
+    );
+    // The summary still counts them as images, documents and temp
+    let categories: Vec<_> = actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::Move { category, .. } => category.as_deref(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(categories, vec!["images", "documents", "temp"]);
+
+    assert!(parse_category_folder("pictures=Images").is_err());
+    assert!(parse_category_folder("images").is_err());
+    assert!(parse_category_folder("images=../Pictures").is_err());
+}
+
+// --lang names the folders, and a --category-folder rule still wins over it
+#[test]
+fn lang_folders_lose_to_category_folders() {
+    let options = Options {
+        by: Grouping::Category,
+        lang_folders: locale::category_folders(locale::find("de").unwrap()),
+        category_folders: vec![parse_category_folder("documents=Papers").unwrap()],
+        ..Options::default()
+    };
+    let actions = plan(&paths(&["a.jpg", "b.pdf", "c.tmp"]), &root(), &options);
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("a.jpg"), root().join("Bilder/a.jpg")),
+            (root().join("b.pdf"), root().join("Papers/b.pdf")),
+            (root().join("c.tmp"), root().join("Temporär/c.tmp")),
+        ]
+    );
+}
+
+#[test]
+fn the_first_matching_rule_wins() {
+    let rules = |given: &[&str]| {
+        let rules = given.iter().map(|rule| parse_rule(rule).unwrap()).collect();
+        Options {
+            by: Grouping::Category,
+            rules: by_priority(rules),
+            map: vec![parse_map("pdf=Papers").unwrap()],
+            ..Options::default()
+        }
+    };
+    let files = paths(&[
+        "Invoice-2024.PDF",
+        "manual.pdf",
+        "sub/invoice.pdf.txt",
+        "README",
+    ]);
+    let expected = vec![
+        (
+            root().join("Invoice-2024.PDF"),
+            root().join("Finance/Invoice-2024.PDF"),
+        ),
+        // Files without an extension can have rules too
+        (root().join("README"), root().join("Documents/README")),
+        (
+            root().join("manual.pdf"),
+            root().join("Documents/manual.pdf"),
+        ),
+        (
+            root().join("sub/invoice.pdf.txt"),
+            root().join("Documents/invoice.pdf.txt"),
+        ),
+    ];
+
+    // In the order given...
+    let options = rules(&["*invoice*.pdf=Finance", "*.pdf=Documents", "*=Documents"]);
+    assert_eq!(moves(&plan(&files, &root(), &options)), expected);
+    // ...or by priority, the highest first; the same priority keeps the order given
+    let options = rules(&["*.pdf=Documents", "*=Documents", "5:*invoice*.pdf=Finance"]);
+    assert_eq!(moves(&plan(&files, &root(), &options)), expected);
+    let order: Vec<_> = options
+        .rules
+        .iter()
+        .map(|rule| rule.glob.as_str())
+        .collect();
+    assert_eq!(order, vec!["*invoice*.pdf", "*.pdf", "*"]);
+
+    // Without a priority the invoices go with the other PDFs, and the rule before --map wins
+    let options = rules(&["*.pdf=Documents", "*invoice*.pdf=Finance"]);
+    let moved = moves(&plan(&files, &root(), &options));
+    assert!(moved.contains(&(
+        root().join("Invoice-2024.PDF"),
+        root().join("Documents/Invoice-2024.PDF")
+    )));
+
+    // With a "/" the pattern is for the path below the root
+    let options = rules(&["sub/*=Old"]);
+    let moved = moves(&plan(&files, &root(), &options));
+    assert!(moved.contains(&(
+        root().join("sub/invoice.pdf.txt"),
+        root().join("Old/invoice.pdf.txt")
+    )));
+    assert!(moved.contains(&(root().join("manual.pdf"), root().join("Papers/manual.pdf"))));
+
+    let lines = explain(
+        &root().join("manual.pdf"),
+        &root(),
+        &rules(&["*invoice*=Finance", "*.PDF=Documents", "*=Other"]),
+        &same_if(|_, _| false),
+    );
+    assert_eq!(
+        lines,
+        vec![
+            "extension: \"pdf\"".to_string(),
+            "--rule 0:*invoice*=Finance: no match".to_string(),
+            "--rule 0:*.PDF=Documents: matches".to_string(),
+            "--rule 0:*=Other: not tried, an earlier rule matched".to_string(),
+            "decision by --rule: folder Documents/".to_string(),
+            format!("destination: {:?}", root().join("Documents/manual.pdf")),
+        ]
+    );
+
+    let rule = parse_rule("-3:reports/**/*.csv=Data/Reports/").unwrap();
+    assert_eq!(
+        (rule.priority, rule.glob.as_str(), rule.folder.as_str()),
+        (-3, "reports/**/*.csv", "Data/Reports")
+    );
+    // Not a priority: part of the pattern
+    assert_eq!(parse_rule("a:b=C").unwrap().glob, "a:b");
+    assert!(parse_rule("*.pdf").is_err());
+    assert!(parse_rule("=Documents").is_err());
+    assert!(parse_rule("10:=Documents").is_err());
+    assert!(parse_rule("*.pdf=").is_err());
+    assert!(parse_rule("*.pdf=../Documents").is_err());
+    assert!(parse_rule("[*.pdf=Documents").is_err());
+}
+
+#[test]
+fn small_groups_get_no_folder_of_their_own() {
+    let files = paths(&[
+        "a.pdf",
+        "b.pdf",
+        "c.pdf",
+        "one.xyz",
+        "pic.jpg",
+        "jpg/old.jpg",
+        "Misc/seen.rar",
+        "notes.txt",
+    ]);
+    let options = Options {
+        min_group_size: NonZeroUsize::new(2),
+        map: vec![parse_map("txt=Notes").unwrap()],
+        ..Options::default()
+    };
+    assert_eq!(
+        moves(&plan(&files, &root(), &options)),
+        vec![
+            (root().join("a.pdf"), root().join("pdf/a.pdf")),
+            (root().join("b.pdf"), root().join("pdf/b.pdf")),
+            (root().join("c.pdf"), root().join("pdf/c.pdf")),
+            // A --map folder is wanted, however few files it gets
+            (root().join("notes.txt"), root().join("Notes/notes.txt")),
+            (root().join("one.xyz"), root().join("Misc/one.xyz")),
+            // The file already in jpg/ counts too
+            (root().join("pic.jpg"), root().join("jpg/pic.jpg")),
+        ]
+    );
+
+    // By category, the categories are the groups
+    let options = Options {
+        by: Grouping::Category,
+        min_group_size: NonZeroUsize::new(3),
+        small_groups: SmallGroups::Stay,
+        ..Options::default()
+    };
+    let moved = moves(&plan(&files, &root(), &options));
+    assert!(moved.contains(&(root().join("a.pdf"), root().join("Documents/a.pdf"))));
+    assert!(moved.contains(&(root().join("notes.txt"), root().join("Documents/notes.txt"))));
+    assert!(!moved.iter().any(|(from, _)| from.ends_with("pic.jpg")));
+    assert!(!moved
+        .iter()
+        .any(|(_, to)| to.starts_with(root().join("Misc"))));
+}
+
+#[test]
+fn a_second_run_finds_what_rules_leave_unsettled() {
+    let fake = same_if(|_, _| false);
+    let files = paths(&[
+        "a.pdf",
+        "inbox/b.pdf",
+        "pdf/c.pdf",
+        "report.pdf",
+        "Temp/x.tmp",
+    ]);
+    let second = |options: &Options| {
+        let first = plan_with(&files, &root(), options, &fake);
+        moves(&second_run(&files, &root(), options, &fake, &first))
+    };
+
+    // The built-in grouping, junk and renames settle in one run
+    let options = Options {
+        by: Grouping::Category,
+        ..Options::default()
+    };
+    assert_eq!(second(&options), vec![]);
+
+    // Out of inbox/ into Sorted/, and from there on into pdf/
+    let options = Options {
+        rules: vec![parse_rule("inbox/*=Sorted").unwrap()],
+        ..Options::default()
+    };
+    assert_eq!(
+        second(&options),
+        vec![(root().join("Sorted/b.pdf"), root().join("pdf/b.pdf"))]
+    );
+
+    // Back and forth between pdf/ and Docs/: c.pdf comes back, the others go on
+    let options = Options {
+        rules: vec![
+            parse_rule("pdf/*=Docs").unwrap(),
+            parse_rule("Docs/*=pdf").unwrap(),
+        ],
+        ..Options::default()
+    };
+    assert_eq!(
+        second(&options),
+        vec![
+            (root().join("Docs/c.pdf"), root().join("pdf/c.pdf")),
+            (root().join("pdf/a.pdf"), root().join("Docs/a.pdf")),
+            (root().join("pdf/b.pdf"), root().join("Docs/b.pdf")),
+            (
+                root().join("pdf/report.pdf"),
+                root().join("Docs/report.pdf")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn explain_lists_the_rules_in_order() {
+    let options = Options {
+        by: Grouping::Category,
+        map: vec![parse_map("bak=Backups").unwrap()],
+        ..Options::default()
+    };
+    let fake = same_if(|_, _| false);
+
+    assert_eq!(
+        explain(&root().join("sub/song.mp3"), &root(), &options, &fake),
+        vec![
+            "extension: \"mp3\"".to_string(),
+            "--map bak=Backups: no match".to_string(),
+            "junk patterns (--junk temp): no match".to_string(),
+            "--by category: audio -> Audio/".to_string(),
+            format!("destination: {:?}", root().join("Audio/song.mp3")),
+        ]
+    );
+
+    let lines = explain(&root().join("old.bak"), &root(), &options, &fake);
+    assert_eq!(lines[1], "--map bak=Backups: matches");
+    assert_eq!(
+        lines[2],
+        "junk patterns: match, but a --map rule comes first"
+    );
+    assert_eq!(
+        lines.last().unwrap(),
+        &format!("destination: {:?}", root().join("Backups/old.bak"))
+    );
+
+    let lines = explain(&root().join("Audio/song.mp3"), &root(), &options, &fake);
+    assert_eq!(
+        lines.last().unwrap(),
+        "destination: none, it's already in the right place"
+    );
+}
+
+#[test]
+fn limit_keeps_the_first_moves_of_the_plan() {
+    let files = paths(&["a.pdf", "b.pdf", "c.txt", "d.zip"]);
+    let size = |file: &Path| match file.file_name().unwrap().to_str().unwrap() {
+        "a.pdf" => 10,
+        "b.pdf" => 20,
+        _ => 5,
+    };
+
+    let (actions, left_out) = limit(
+        plan(&files, &root(), &Options::default()),
+        Some(2),
+        None,
+        size,
+    );
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("a.pdf"), root().join("pdf/a.pdf")),
+            (root().join("b.pdf"), root().join("pdf/b.pdf")),
+        ]
+    );
+    assert_eq!(left_out, 2);
+    // txt/ and zip/ get no files now, so they aren't created either
+    let folders: Vec<_> = actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::CreateDir { path } => Some(path.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(folders, vec![root().join("pdf")]);
+
+    // 10 + 20 > 25: the run ends at b.pdf, even though the smaller files after it would fit
+    let (actions, left_out) = limit(
+        plan(&files, &root(), &Options::default()),
+        None,
+        Some(25),
+        size,
+    );
+    assert_eq!(moves(&actions).len(), 1);
+    assert_eq!(left_out, 3);
+}
+
+#[test]
+fn shuffled_plans_keep_their_folders_first_and_limit_a_random_sample() {
+    let rels: Vec<String> = (0..40)
+        .map(|i| format!("d{}/f{}.{}", i % 4, i, ["pdf", "txt"][i % 2]))
+        .collect();
+    let files = paths(&rels.iter().map(String::as_str).collect::<Vec<_>>());
+    let planned = plan(&files, &root(), &Options::default());
+
+    let shuffled = shuffle(planned.clone(), &mut fastrand::Rng::with_seed(7));
+    assert_ne!(shuffled, planned);
+    assert!(matches!(shuffled[0], PlannedAction::CreateDir { .. }));
+    assert!(matches!(shuffled[1], PlannedAction::CreateDir { .. }));
+    // The same moves, in another order
+    let mut sorted = moves(&shuffled);
+    sorted.sort();
+    assert_eq!(sorted, moves(&planned));
+
+    // The first 5 of the plan all come from d0/, a shuffled 5 are from anywhere
+    let (sample, _) = limit(shuffled, Some(5), None, |_| 1);
+    let sample = moves(&sample);
+    assert_eq!(sample.len(), 5);
+    assert_ne!(sample, moves(&planned)[..5]);
+}
+
+#[test]
+fn open_files_are_skipped() {
+    let actions = plan(&paths(&["a.pdf", "b.txt"]), &root(), &Options::default());
+    let open = root().join("b.txt");
+
+    let actions = skip_open(actions, |file| {
+        (file == open).then(|| "vim (pid 42)".to_string())
+    });
+
+    assert_eq!(
+        moves(&actions),
+        vec![(root().join("a.pdf"), root().join("pdf/a.pdf"))]
+    );
+    assert_eq!(skips(&actions), vec![open.as_path()]);
+    // txt/ isn't needed anymore
+    assert!(!actions
+        .iter()
+        .any(|a| matches!(a, PlannedAction::CreateDir { path } if path.ends_with("txt"))));
+}
+
+#[test]
+fn files_we_cannot_move_become_skips() {
+    let actions = plan(
+        &paths(&["a.pdf", "theirs/b.txt"]),
+        &root(),
+        &Options::default(),
+    );
+    let theirs = root().join("theirs");
+
+    let mut asked = Vec::new();
+    let actions = skip_unmovable(actions, |file, to| {
+        asked.push(to.map(Path::to_path_buf));
+        file.starts_with(&theirs)
+            .then(|| "you can't change its folder".to_string())
+    });
+
+    // The checker sees where each file would go
+    assert!(asked.contains(&Some(root().join("pdf/a.pdf"))));
+    assert_eq!(
+        moves(&actions),
+        vec![(root().join("a.pdf"), root().join("pdf/a.pdf"))]
+    );
+    assert_eq!(skips(&actions), vec![theirs.join("b.txt").as_path()]);
+}
+
+#[test]
+fn an_edited_plan_leaves_removed_files_and_sends_others_elsewhere() {
+    use directory_cleaner::edit_plan::{apply, plan_text};
+
+    let actions = plan(
+        &paths(&["a.pdf", "b.pdf", "notes.txt"]),
+        &root(),
+        &Options::default(),
+    );
+    let text = plan_text(&actions, &root());
+    assert!(text.contains("move  a.pdf -> pdf/a.pdf\n"));
+
+    // b.pdf's line removed, notes.txt sent to "docs"
+    let edited: String = text
+        .lines()
+        .filter(|line| !line.contains("b.pdf"))
+        .map(|line| line.replace("-> txt/notes.txt", "-> docs/notes.txt") + "\n")
+        .collect();
+    let actions = apply(actions, &edited, &root()).unwrap();
+
+    assert_eq!(
+        moves(&actions),
+        vec![
+            (root().join("a.pdf"), root().join("pdf/a.pdf")),
+            (root().join("notes.txt"), root().join("docs/notes.txt")),
+        ]
+    );
+    assert_eq!(skips(&actions), vec![root().join("b.pdf").as_path()]);
+    // The new folder is created, the one nothing goes into anymore isn't
+    let dirs: Vec<&Path> = actions
+        .iter()
+        .filter_map(|a| match a {
+            PlannedAction::CreateDir { path } => Some(path.as_path()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        dirs,
+        vec![root().join("docs").as_path(), root().join("pdf").as_path()]
+    );
+}
+
+#[test]
+fn edited_destinations_are_checked() {
+    use directory_cleaner::edit_plan::apply;
+
+    let actions = || plan(&paths(&["a.pdf", "b.pdf"]), &root(), &Options::default());
+    for (edit, problem) in [
+        (
+            "move  a.pdf -> ../a.pdf",
+            "stay inside the organized folder",
+        ),
+        (
+            "move  a.pdf -> /tmp/a.pdf",
+            "stay inside the organized folder",
+        ),
+        ("move  a.pdf -> .directory-cleaner/a.pdf", "journals"),
+        ("move  a.pdf -> x.pdf\nmove  b.pdf -> x.pdf", "two files"),
+        ("move  other.pdf -> pdf/other.pdf", "file of the plan"),
+        ("trash a.pdf", "between \"move\" and \"trash\""),
+        ("copy  a.pdf", "expected \"move\" or \"trash\""),
+    ] {
+        let error = apply(actions(), edit, &root()).unwrap_err().to_string();
+        assert!(error.contains(problem), "{:?}: {}", edit, error);
+    }
+}
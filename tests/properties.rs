@@ -0,0 +1,132 @@
+// Property-based tests: generate random directory trees and check that organizing them
+// never loses a file, puts everything in the right folder, and is stable on a second run.
+mod common;
+
+use common::Fixture;
+use directory_cleaner::logic::Options;
+use directory_cleaner::plan::{plan, PlannedAction};
+use proptest::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// A random tree: up to 20 relative paths like "d1/d2/ab.PDF" or "noext_x"
+fn tree() -> impl Strategy<Value = Vec<String>> {
+    let dir = prop::sample::select(vec!["", "d1/", "d2/", "d1/d3/", "ü/"]);
+    let name = "[a-zé]{1,4}";
+    let ext = prop::sample::select(vec![".pdf", ".PDF", ".txt", ".rs", ".tar.gz", ""]);
+
+    prop::collection::vec((dir, name, ext), 0..20).prop_map(|entries| {
+        // Drop paths that would be the same file on a case-insensitive file system
+        let mut seen = HashSet::new();
+        entries
+            .into_iter()
+            .map(|(dir, name, ext)| {
+                let prefix = if ext.is_empty() { "noext_" } else { "" };
+                format!("{}{}{}{}", dir, prefix, name, ext)
+            })
+            .filter(|path| seen.insert(path.to_lowercase()))
+            .collect()
+    })
+}
+
+// How the file system compares names (case-insensitive on Windows and macOS)
+fn key(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    if cfg!(any(windows, target_os = "macos")) {
+        s.to_lowercase()
+    } else {
+        s.into_owned()
+    }
+}
+
+// The folder a file named `name` belongs in: its extension, both parts of "tar.gz"
+fn folder_for(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    if name.len() > ".tar.gz".len() && name.ends_with(".tar.gz") {
+        return Some("tar.gz".to_string());
+    }
+    name.rsplit_once('.').map(|(_, ext)| ext.to_string())
+}
+
+proptest! {
+    // No regression files: they'd be written next to src/, not tests/
+    #![proptest_config(ProptestConfig {
+        cases: 64,
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn planned_destinations_are_unique(rels in tree()) {
+        let root = PathBuf::from("/fake/root");
+        let mut files: Vec<PathBuf> = rels.iter().map(|r| root.join(r)).collect();
+        files.sort();
+
+        let mut seen = HashSet::new();
+        for action in plan(&files, &root, &Options::default()) {
+            if let PlannedAction::Move { to, .. } = action {
+                prop_assert!(seen.insert(key(&to)));
+                prop_assert!(!files.contains(&to));
+            }
+        }
+    }
+
+    #[test]
+    fn organizing_keeps_every_file_and_is_idempotent(rels in tree()) {
+        let mut fx = Fixture::new();
+        for (i, rel) in rels.iter().enumerate() {
+            fx = fx.file(rel, &i.to_string());
+        }
+
+        fx.organize(&Options::default());
+        let after_first = fx.files();
+
+        // Same contents as before (each file holds its unique index)
+        let mut before: Vec<String> = (0..rels.len()).map(|i| i.to_string()).collect();
+        let mut after: Vec<String> = after_first.iter().map(|f| fx.read(f)).collect();
+        before.sort();
+        after.sort();
+        prop_assert_eq!(before, after);
+
+        // Files with an extension sit in the folder named after it, the rest didn't move
+        for file in &after_first {
+            let (folder, name) = file.rsplit_once('/').unwrap_or(("", file.as_str()));
+            match folder_for(name) {
+                Some(ext) => prop_assert_eq!(folder, ext),
+                None => prop_assert!(rels.contains(file)),
+            }
+        }
+
+        fx.organize(&Options::default());
+        prop_assert_eq!(fx.files(), after_first);
+    }
+
+    #[test]
+    fn preserving_structure_keeps_every_path_below_its_folder(rels in tree()) {
+        let mut fx = Fixture::new();
+        for (i, rel) in rels.iter().enumerate() {
+            fx = fx.file(rel, &i.to_string());
+        }
+        let options = Options {
+            preserve_structure: true,
+            ..Options::default()
+        };
+
+        fx.organize(&options);
+        let after_first = fx.files();
+
+        // "d1/d3/ab.PDF" ends up as "pdf/d1/d3/ab.PDF", with the same contents
+        for (i, rel) in rels.iter().enumerate() {
+            let name = rel.rsplit('/').next().unwrap();
+            let expected = match folder_for(name) {
+                Some(ext) => format!("{}/{}", ext, rel),
+                None => rel.clone(),
+            };
+            prop_assert_eq!(fx.read(&expected), i.to_string());
+        }
+        prop_assert_eq!(after_first.len(), rels.len());
+
+        fx.organize(&options);
+        prop_assert_eq!(fx.files(), after_first);
+    }
+}
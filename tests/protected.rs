@@ -0,0 +1,36 @@
+// The denylist: which folders count as the system's or an application's
+use directory_cleaner::protected::is_protected;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn the_root_is_protected() {
+    assert!(is_protected(Path::new("/")));
+    if cfg!(windows) {
+        assert!(is_protected(Path::new(r"C:\")));
+    }
+}
+
+// The Unix version of AppData: settings, data, state and caches below the home folder
+#[cfg(unix)]
+#[test]
+fn app_data_in_the_home_folder_is_protected() {
+    let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+    for dir in [
+        ".config",
+        ".config/git/config",
+        ".local/share",
+        ".local/state/directory-cleaner",
+        ".cache",
+    ] {
+        assert!(is_protected(&home.join(dir)), "{} isn't protected", dir);
+    }
+    assert!(!is_protected(&home.join("Downloads")));
+    assert!(!is_protected(&home.join(".local-notes")));
+}
+
+#[test]
+fn app_data_anywhere_is_protected() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    assert!(is_protected(&dir.join("AppData").join("Roaming")));
+    assert!(!is_protected(&dir.join("Downloads")));
+}
@@ -0,0 +1,89 @@
+// Searching the journals of earlier runs: query and locate
+mod common;
+
+use common::Fixture;
+use directory_cleaner::locate::{trails, with_hash};
+use directory_cleaner::logic::Options;
+use directory_cleaner::query::{find, QueryOptions};
+use directory_cleaner::undo::UndoOptions;
+use std::path::PathBuf;
+
+#[test]
+fn query_finds_moves_by_extension_and_marks_undone_ones() {
+    let fx = Fixture::new()
+        .file("inbox/invoice.pdf", "i")
+        .file("notes.txt", "n");
+    fx.organize(&Options::default());
+    fx.undo(&UndoOptions {
+        exts: vec!["txt".to_string()],
+        ..UndoOptions::default()
+    });
+
+    let query = |options: QueryOptions| {
+        find(
+            fx.root(),
+            &QueryOptions {
+                state_dir: Some(fx.state_dir().to_path_buf()),
+                ..options
+            },
+        )
+        .unwrap()
+    };
+
+    let all = query(QueryOptions::default());
+    assert_eq!(all.len(), 2);
+    let notes = all.iter().find(|f| f.from.ends_with("notes.txt")).unwrap();
+    assert!(notes.undone);
+
+    let pdfs = query(QueryOptions {
+        exts: vec!["PDF".to_string()],
+        ..QueryOptions::default()
+    });
+    assert_eq!(pdfs.len(), 1);
+    assert_eq!(pdfs[0].from, PathBuf::from("inbox/invoice.pdf"));
+    assert_eq!(pdfs[0].to, Some(PathBuf::from("pdf/invoice.pdf")));
+    assert!(!pdfs[0].undone);
+
+    let none = query(QueryOptions {
+        run: Some("no-such-run".to_string()),
+        ..QueryOptions::default()
+    });
+    assert!(none.is_empty());
+}
+
+#[test]
+fn locate_follows_a_file_through_several_runs() {
+    let fx = Fixture::new().file("inbox/report.pdf", "r");
+    let state = Some(fx.state_dir());
+
+    fx.organize(&Options::default());
+    fx.undo(&UndoOptions::default());
+    fx.organize(&Options::default());
+
+    let found = trails(fx.root(), "report.pdf", state).unwrap();
+    // One file, found in inbox/ before the first run (the second run found it there again
+    // only because of the undo, so that's the same trail)
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].original, PathBuf::from("inbox/report.pdf"));
+    let undos: Vec<bool> = found[0].steps.iter().map(|s| s.undo).collect();
+    assert_eq!(undos, vec![false, true, false]);
+    assert_eq!(
+        found[0].now(),
+        Some(PathBuf::from("pdf/report.pdf").as_path())
+    );
+
+    assert!(trails(fx.root(), "other.pdf", state).unwrap().is_empty());
+}
+
+#[test]
+fn locate_by_hash_reads_the_tree() {
+    let fx = Fixture::new()
+        .file("renamed by hand.pdf", "contents")
+        .file("other.pdf", "something else");
+    let hash = blake3::hash(b"contents").to_hex();
+
+    let found = with_hash(fx.root(), &hash[..10].to_uppercase()).unwrap();
+    assert_eq!(found, vec![fx.root().join("renamed by hand.pdf")]);
+
+    assert!(with_hash(fx.root(), "abc").is_err());
+}
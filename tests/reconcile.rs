@@ -0,0 +1,78 @@
+// Plan vs. result, when the folder changes between planning and moving
+mod common;
+
+use common::Fixture;
+use directory_cleaner::logic::{self, Options};
+use directory_cleaner::plan;
+use directory_cleaner::reconcile::{Estimate, Reconciliation};
+use std::fs;
+
+#[test]
+fn changes_after_the_plan_are_accounted_for() {
+    let fx = Fixture::new()
+        .file("gone.pdf", "deleted before its turn")
+        .file("taken.pdf", "someone else got there first")
+        .file("growing.txt", "a download that")
+        .file("steady.txt", "stays the same");
+    let files: Vec<_> = fx.files().iter().map(|f| fx.root().join(f)).collect();
+    let options = Options::default();
+    let actions = plan::plan(&files, fx.root(), &options);
+    let estimate = Estimate::of(&actions);
+
+    // Meanwhile, in the folder
+    fs::remove_file(fx.root().join("gone.pdf")).unwrap();
+    fs::create_dir(fx.root().join("pdf")).unwrap();
+    fs::write(fx.root().join("pdf/taken.pdf"), "another program's file").unwrap();
+    fs::write(fx.root().join("growing.txt"), "a download that finished").unwrap();
+
+    let outcome = logic::execute(&actions, &options, None);
+    let reconciliation = Reconciliation::new(&estimate, &outcome);
+
+    assert_eq!(reconciliation.planned.files, 4);
+    assert_eq!(reconciliation.moved.files, 2);
+    assert_eq!(reconciliation.vanished, 1);
+    assert_eq!(reconciliation.late_conflicts, 1);
+    assert_eq!(reconciliation.failed, 0);
+    assert_eq!(reconciliation.resized, 1);
+    assert_eq!(reconciliation.resized_bytes, " finished".len() as i64);
+    assert!(!reconciliation.as_planned());
+    // The file that appeared is left alone
+    assert_eq!(fx.read("pdf/taken.pdf"), "another program's file");
+    assert_eq!(fx.read("taken.pdf"), "someone else got there first");
+}
+
+#[test]
+fn an_undisturbed_run_goes_as_planned() {
+    let fx = Fixture::new().file("a.pdf", "a").file("b/c.txt", "c");
+    let files: Vec<_> = fx.files().iter().map(|f| fx.root().join(f)).collect();
+    let options = Options::default();
+    let actions = plan::plan(&files, fx.root(), &options);
+    let estimate = Estimate::of(&actions);
+
+    let outcome = logic::execute(&actions, &options, None);
+    let reconciliation = Reconciliation::new(&estimate, &outcome);
+
+    assert!(reconciliation.as_planned());
+    assert_eq!(reconciliation.lines()[1], "Moved   : 2 files, 2 B");
+}
+
+// A destination that is the file itself, reached through a symlinked folder (or on Windows and
+// macOS through other case), isn't a conflict: the file is where it belongs already
+#[cfg(unix)]
+#[test]
+fn a_destination_that_is_the_file_itself_is_left_alone() {
+    let fx = Fixture::new().file("docs/a.pdf", "a");
+    std::os::unix::fs::symlink(fx.root().join("docs"), fx.root().join("pdf")).unwrap();
+    let files = vec![fx.root().join("docs/a.pdf")];
+    let options = Options::default();
+    let actions = plan::plan(&files, fx.root(), &options);
+    let estimate = Estimate::of(&actions);
+
+    let outcome = logic::execute(&actions, &options, None);
+    let reconciliation = Reconciliation::new(&estimate, &outcome);
+
+    assert_eq!(reconciliation.in_place, 1);
+    assert_eq!(reconciliation.late_conflicts, 0);
+    assert_eq!(reconciliation.failed, 0);
+    assert_eq!(fx.read("docs/a.pdf"), "a");
+}
@@ -0,0 +1,149 @@
+// repair: what interrupted runs leave behind (moves nobody recorded, unfinished copies, the
+// folders they created) is cleaned up, and then undo works as if the run had ended normally
+mod common;
+
+use common::Fixture;
+use directory_cleaner::journal::{self, Integrity, Journal};
+use directory_cleaner::logic::PARTIAL_PREFIX;
+use directory_cleaner::repair::{RepairOptions, Repaired};
+use directory_cleaner::undo::UndoOptions;
+use std::fs;
+
+// A run that was killed: a.pdf was moved right after its intent was written, b.txt never was,
+// and c.mp3 was copied to its destination but the original is still there. (Dropping the journal
+// closes it without a "done" line, like a killed process does.)
+fn interrupted(fx: &Fixture) {
+    let root = fx.root();
+    let run = Journal::create(root, root, Some(fx.state_dir())).unwrap();
+    for (from, folder) in [("a.pdf", "pdf"), ("b.txt", "txt"), ("c.mp3", "mp3")] {
+        fs::create_dir(root.join(folder)).unwrap();
+        run.record_create_dir(&root.join(folder)).unwrap();
+        run.record_intent(&root.join(from), &root.join(folder).join(from), 1)
+            .unwrap();
+    }
+    fs::rename(root.join("a.pdf"), root.join("pdf/a.pdf")).unwrap();
+    fs::copy(root.join("c.mp3"), root.join("mp3/c.mp3")).unwrap();
+    drop(run);
+}
+
+#[test]
+fn repair_finishes_what_an_interrupted_run_started() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.txt", "b")
+        .file("c.mp3", "c");
+    interrupted(&fx);
+
+    let repaired = fx.repair(&RepairOptions::default());
+    assert_eq!(
+        repaired,
+        Repaired {
+            completed: 2,
+            folders: 1,
+            ..Repaired::default()
+        }
+    );
+    // The empty txt/ folder is gone, the copy of c.mp3 is the only one now
+    assert_eq!(fx.files(), vec!["b.txt", "mp3/c.mp3", "pdf/a.pdf"]);
+    assert!(!fx.root().join("txt").exists());
+    assert_eq!(
+        journal::verify(&fx.journals()[0]).unwrap(),
+        Integrity::Unfinished
+    );
+
+    // A second repair has nothing left to do
+    assert_eq!(fx.repair(&RepairOptions::default()), Repaired::default());
+
+    fx.undo(&UndoOptions::default());
+    assert_eq!(fx.files(), vec!["a.pdf", "b.txt", "c.mp3"]);
+}
+
+#[test]
+fn repair_dry_run_changes_nothing() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.txt", "b")
+        .file("c.mp3", "c")
+        .file(&format!("inbox/{}1234", PARTIAL_PREFIX), "half");
+    interrupted(&fx);
+    let before = fx.files();
+    let journal = fs::read_to_string(&fx.journals()[0]).unwrap();
+
+    let repaired = fx.repair(&RepairOptions {
+        dry_run: true,
+        ..RepairOptions::default()
+    });
+    assert_eq!(repaired.completed, 2);
+    assert_eq!(repaired.partials, 1);
+    assert_eq!(fx.files(), before);
+    assert!(fx.root().join("txt").is_dir());
+    assert_eq!(fs::read_to_string(&fx.journals()[0]).unwrap(), journal);
+}
+
+#[test]
+fn repair_removes_unfinished_copies() {
+    let partial = format!("pdf/{}6f1c", PARTIAL_PREFIX);
+    let fx = Fixture::new().file("a.pdf", "a").file(&partial, "hal");
+
+    let repaired = fx.repair(&RepairOptions::default());
+    assert_eq!(repaired.partials, 1);
+    assert_eq!(fx.files(), vec!["a.pdf"]);
+}
+
+#[test]
+fn repair_cuts_off_a_half_written_last_line() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.txt", "b")
+        .file("c.mp3", "c");
+    interrupted(&fx);
+    let path = fx.journals()[0].clone();
+    let mut text = fs::read_to_string(&path).unwrap();
+    text.push_str("{\"type\":\"move\",\"fro");
+    fs::write(&path, text).unwrap();
+    assert!(matches!(
+        journal::verify(&path).unwrap(),
+        Integrity::Damaged { .. }
+    ));
+
+    let repaired = fx.repair(&RepairOptions::default());
+    assert_eq!(repaired.truncated, 1);
+    assert_eq!(repaired.completed, 2);
+    assert_eq!(journal::verify(&path).unwrap(), Integrity::Unfinished);
+}
+
+#[test]
+fn repair_leaves_changed_journals_alone() {
+    let fx = Fixture::new()
+        .file("a.pdf", "a")
+        .file("b.txt", "b")
+        .file("c.mp3", "c");
+    interrupted(&fx);
+    let path = fx.journals()[0].clone();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::write(&path, text.replacen("a.pdf", "x.pdf", 1)).unwrap();
+
+    let repaired = fx.repair(&RepairOptions::default());
+    assert_eq!(repaired.left_alone, 1);
+    assert_eq!(repaired.completed, 0);
+    assert!(fx.root().join("txt").is_dir());
+}
+
+#[test]
+fn repair_waits_for_a_run_that_is_still_going() {
+    let fx = Fixture::new().file(&format!("{}1", PARTIAL_PREFIX), "busy");
+    let root = fx.root();
+    let run = Journal::create(root, root, Some(fx.state_dir())).unwrap();
+
+    let options = RepairOptions {
+        state_dir: Some(fx.state_dir().to_path_buf()),
+        ..RepairOptions::default()
+    };
+    let result = directory_cleaner::repair::repair(root.to_str().unwrap(), &options);
+    assert!(result.unwrap_err().to_string().contains("still going"));
+    assert!(root.join(format!("{}1", PARTIAL_PREFIX)).exists());
+
+    run.finish(0, 0).unwrap();
+    assert!(!journal::lock_path(&fx.journals()[0]).exists());
+    assert_eq!(fx.repair(&RepairOptions::default()).partials, 1);
+}
@@ -0,0 +1,114 @@
+// The stats subcommand: files per category, and how old they are
+mod common;
+
+use common::Fixture;
+use directory_cleaner::stats::{age_group, age_stats, AGES};
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[test]
+fn age_groups_go_by_days() {
+    let now = SystemTime::now();
+    assert_eq!(age_group(now, now), 0);
+    assert_eq!(age_group(now - 29 * DAY, now), 0);
+    assert_eq!(age_group(now - 31 * DAY, now), 1);
+    assert_eq!(age_group(now - 200 * DAY, now), 2);
+    assert_eq!(age_group(now - 400 * DAY, now), AGES.len() - 1);
+    // A clock that was off: a file from the future is new
+    assert_eq!(age_group(now + DAY, now), 0);
+}
+
+#[test]
+fn files_are_counted_by_category_and_age() {
+    let fx = Fixture::new()
+        .file("new.jpg", "12")
+        .file("old.jpg", "1234")
+        .file("old.pdf", "1")
+        .file("no-category.xyz", "1");
+    let now = SystemTime::now();
+    for (name, age) in [("old.jpg", 400), ("old.pdf", 60)] {
+        let file = File::options()
+            .write(true)
+            .open(fx.root().join(name))
+            .unwrap();
+        file.set_modified(now - age * DAY).unwrap();
+    }
+
+    let files: Vec<PathBuf> = fx.files().iter().map(|f| fx.root().join(f)).collect();
+    let stats = age_stats(&files, now);
+
+    let images = &stats["images"];
+    assert_eq!((images[0].files, images[0].bytes), (1, 2));
+    assert_eq!((images[3].files, images[3].bytes), (1, 4));
+    assert_eq!(stats["documents"][1].files, 1);
+    assert_eq!(stats["other"][0].files, 1);
+    assert_eq!(stats.len(), 3);
+}
+
+#[test]
+fn files_inside_archives_are_counted_without_unpacking() {
+    use directory_cleaner::archives::{can_peek, peek};
+    use directory_cleaner::stats::archive_stats;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let fx = Fixture::new().file("broken.zip", "not a zip");
+    let now = SystemTime::now();
+
+    let mut zip = zip::ZipWriter::new(File::create(fx.root().join("photos.zip")).unwrap());
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.add_directory("2024/", stored).unwrap();
+    zip.start_file("2024/beach.jpg", stored).unwrap();
+    zip.write_all(b"12345").unwrap();
+    zip.start_file("notes.pdf", stored).unwrap();
+    zip.write_all(b"12").unwrap();
+    zip.finish().unwrap();
+
+    let gz = flate2::write::GzEncoder::new(
+        File::create(fx.root().join("old.tar.gz")).unwrap(),
+        flate2::Compression::default(),
+    );
+    let mut tar = tar::Builder::new(gz);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(3);
+    header.set_mode(0o644);
+    header.set_mtime(
+        (now - 400 * DAY)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    );
+    header.set_cksum();
+    tar.append_data(&mut header, "song.mp3", &b"abc"[..])
+        .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+
+    assert!(can_peek(&fx.root().join("old.tar.gz")));
+    assert!(!can_peek(&fx.root().join("song.mp3")));
+    let inside = peek(&fx.root().join("photos.zip")).unwrap();
+    assert_eq!(
+        inside
+            .iter()
+            .map(|f| (f.path.clone(), f.size))
+            .collect::<Vec<_>>(),
+        vec![
+            (PathBuf::from("2024/beach.jpg"), 5),
+            (PathBuf::from("notes.pdf"), 2)
+        ]
+    );
+
+    let archives: Vec<PathBuf> = ["broken.zip", "old.tar.gz", "photos.zip"]
+        .iter()
+        .map(|name| fx.root().join(name))
+        .collect();
+    let (stats, peeked) = archive_stats(&archives, now);
+    // The broken one is reported and left out
+    assert_eq!(peeked, 2);
+    assert_eq!((stats["images"][0].files, stats["images"][0].bytes), (1, 5));
+    assert_eq!(stats["documents"][0].files, 1);
+    assert_eq!((stats["audio"][3].files, stats["audio"][3].bytes), (1, 3));
+    assert_eq!(stats.len(), 3);
+}
@@ -0,0 +1,401 @@
+// Organize, then undo (all or part of) the run
+mod common;
+
+use chrono::{Duration, Utc};
+use common::Fixture;
+use directory_cleaner::journal::{self, Integrity, Journal};
+use directory_cleaner::logic::{self, Options};
+use directory_cleaner::undo::{self, UndoOptions};
+
+fn organized() -> Fixture {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("sub/scan.PDF", "s")
+        .file("sub/notes.txt", "n")
+        .file("photo.png", "p");
+    fx.organize(&Options::default());
+    fx
+}
+
+#[test]
+fn undo_restores_everything() {
+    let fx = organized();
+
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(
+        fx.files(),
+        vec!["photo.png", "report.pdf", "sub/notes.txt", "sub/scan.PDF"]
+    );
+    // The folders the run created are gone again
+    assert!(!fx.root().join("pdf").exists());
+}
+
+#[test]
+fn undo_leaves_the_folders_that_were_there_before() {
+    let fx = Fixture::new()
+        .file("report.pdf", "r")
+        .file("notes.txt", "n");
+    // Empty before the run, which only added report.pdf to it
+    std::fs::create_dir(fx.root().join("pdf")).unwrap();
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), vec!["pdf/report.pdf", "txt/notes.txt"]);
+
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(fx.files(), vec!["notes.txt", "report.pdf"]);
+    assert!(fx.root().join("pdf").is_dir());
+    assert!(!fx.root().join("txt").exists());
+}
+
+#[test]
+fn undo_by_extension_keeps_the_rest() {
+    let fx = organized();
+
+    fx.undo(&UndoOptions {
+        exts: vec!["pdf".to_string()],
+        ..UndoOptions::default()
+    });
+
+    assert_eq!(
+        fx.files(),
+        vec![
+            "png/photo.png",
+            "report.pdf",
+            "sub/scan.PDF",
+            "txt/notes.txt"
+        ]
+    );
+}
+
+#[test]
+fn undo_by_glob() {
+    let fx = organized();
+
+    fx.undo(&UndoOptions {
+        patterns: vec!["sub/*".to_string()],
+        ..UndoOptions::default()
+    });
+
+    assert_eq!(
+        fx.files(),
+        vec![
+            "pdf/report.pdf",
+            "png/photo.png",
+            "sub/notes.txt",
+            "sub/scan.PDF"
+        ]
+    );
+}
+
+#[test]
+fn undo_since_the_future_restores_nothing() {
+    let fx = organized();
+    let before = fx.files();
+
+    fx.undo(&UndoOptions {
+        since: Some(Utc::now() + Duration::hours(1)),
+        ..UndoOptions::default()
+    });
+
+    assert_eq!(fx.files(), before);
+}
+
+#[test]
+fn undo_by_compound_extension() {
+    let fx = Fixture::new()
+        .file("backup.tar.gz", "b")
+        .file("notes.txt.gz", "n");
+    fx.organize(&Options::default());
+    assert_eq!(fx.files(), vec!["gz/notes.txt.gz", "tar.gz/backup.tar.gz"]);
+
+    fx.undo(&UndoOptions {
+        exts: vec![".tar.gz".to_string()],
+        ..UndoOptions::default()
+    });
+    assert_eq!(fx.files(), vec!["backup.tar.gz", "gz/notes.txt.gz"]);
+
+    // "gz" is the last part of both
+    fx.undo(&UndoOptions {
+        exts: vec!["gz".to_string()],
+        ..UndoOptions::default()
+    });
+    assert_eq!(fx.files(), vec!["backup.tar.gz", "notes.txt.gz"]);
+}
+
+#[test]
+fn partial_undos_add_up() {
+    let fx = organized();
+
+    fx.undo(&UndoOptions {
+        exts: vec!["txt".to_string()],
+        ..UndoOptions::default()
+    });
+    fx.undo(&UndoOptions::default());
+    // Nothing left to undo, this one changes nothing
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(
+        fx.files(),
+        vec!["photo.png", "report.pdf", "sub/notes.txt", "sub/scan.PDF"]
+    );
+}
+
+#[test]
+fn undo_never_overwrites() {
+    let fx = organized();
+    std::fs::write(fx.root().join("report.pdf"), "new file").unwrap();
+
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(fx.read("report.pdf"), "new file");
+    assert_eq!(fx.read("pdf/report.pdf"), "r");
+}
+
+#[test]
+fn undo_skips_files_changed_since_the_run() {
+    let fx = organized();
+    std::fs::write(fx.root().join("pdf/report.pdf"), "edited later").unwrap();
+
+    fx.undo(&UndoOptions::default());
+
+    // The edited file stays where the run put it, the others go back
+    assert_eq!(fx.read("pdf/report.pdf"), "edited later");
+    assert_eq!(
+        fx.files(),
+        vec![
+            "pdf/report.pdf",
+            "photo.png",
+            "sub/notes.txt",
+            "sub/scan.PDF"
+        ]
+    );
+}
+
+#[test]
+fn undo_notices_replaced_files_of_the_same_size() {
+    let fx = organized();
+    let path = fx.root().join("png/photo.png");
+    std::fs::write(&path, "q").unwrap();
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(fx.read("png/photo.png"), "q");
+}
+
+#[test]
+fn force_moves_changed_files_back() {
+    let fx = organized();
+    std::fs::write(fx.root().join("pdf/report.pdf"), "edited later").unwrap();
+
+    fx.undo(&UndoOptions {
+        force: true,
+        ..UndoOptions::default()
+    });
+
+    assert_eq!(fx.read("report.pdf"), "edited later");
+    assert!(!fx.root().join("pdf").exists());
+}
+
+#[test]
+fn undo_removes_nested_folders_it_created() {
+    let fx = Fixture::new().file("a/b/report.pdf", "r");
+    fx.organize(&Options {
+        preserve_structure: true,
+        ..Options::default()
+    });
+    assert_eq!(fx.files(), vec!["pdf/a/b/report.pdf"]);
+
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(fx.files(), vec!["a/b/report.pdf"]);
+    assert!(!fx.root().join("pdf").exists());
+}
+
+#[test]
+fn undo_finds_journals_older_versions_kept_in_the_folder() {
+    let fx = organized();
+    // Older versions wrote the journal to <root>/.directory-cleaner/journal
+    let legacy = fx.root().join(".directory-cleaner/journal");
+    std::fs::create_dir_all(&legacy).unwrap();
+    for journal in fx.journals() {
+        std::fs::rename(&journal, legacy.join(journal.file_name().unwrap())).unwrap();
+    }
+
+    fx.undo(&UndoOptions::default());
+
+    assert_eq!(
+        fx.files(),
+        vec!["photo.png", "report.pdf", "sub/notes.txt", "sub/scan.PDF"]
+    );
+}
+
+#[test]
+fn journals_are_checksummed_and_damage_is_noticed() {
+    let fx = organized();
+    let path = fx.journals().remove(0);
+    assert_eq!(journal::verify(&path).unwrap(), Integrity::Intact);
+
+    // A crash in the middle of writing the last line
+    let text = std::fs::read_to_string(&path).unwrap();
+    let cut = text.trim_end().rfind('\n').unwrap() + 10;
+    std::fs::write(&path, &text[..cut]).unwrap();
+    let lines = text.lines().count();
+    assert!(matches!(
+        journal::verify(&path).unwrap(),
+        Integrity::Damaged { line, .. } if line == lines
+    ));
+
+    // undo refuses, unless forced to use the lines before the damage
+    let undo = |force| {
+        directory_cleaner::undo::undo(
+            fx.root().to_str().unwrap(),
+            &UndoOptions {
+                force,
+                state_dir: Some(fx.state_dir().to_path_buf()),
+                ..UndoOptions::default()
+            },
+        )
+    };
+    assert!(undo(false).is_err());
+    undo(true).unwrap();
+    assert_eq!(
+        fx.files(),
+        vec!["photo.png", "report.pdf", "sub/notes.txt", "sub/scan.PDF"]
+    );
+
+    // Changing a line breaks its checksum
+    let fx = organized();
+    let path = fx.journals().remove(0);
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, text.replacen("\"size\":1", "\"size\":2", 1)).unwrap();
+    // (the first line with a size, after the run and create_dir lines)
+    let changed = text.lines().position(|l| l.contains("\"size\":1")).unwrap() + 1;
+    assert!(matches!(
+        journal::verify(&path).unwrap(),
+        Integrity::Damaged { line, .. } if line == changed
+    ));
+}
+
+#[test]
+fn undo_keeps_the_checksums_going() {
+    let fx = organized();
+    fx.undo(&UndoOptions {
+        exts: vec!["pdf".to_string()],
+        ..UndoOptions::default()
+    });
+
+    assert_eq!(
+        journal::verify(&fx.journals()[0]).unwrap(),
+        Integrity::Intact
+    );
+}
+
+// The checksum is of the line as it's written: the same JSON with a space added is a change too
+#[test]
+fn the_checksum_covers_the_bytes_of_the_line() {
+    let fx = organized();
+    let path = fx.journals().remove(0);
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text
+        .lines()
+        .all(|line| line.contains(",\"checksum\":\"") && line.ends_with("\"}")));
+
+    std::fs::write(&path, text.replacen("{\"type\"", "{ \"type\"", 1)).unwrap();
+    assert!(matches!(
+        journal::verify(&path).unwrap(),
+        Integrity::Damaged { line: 1, .. }
+    ));
+}
+
+// tests/data/journal-sorted-keys.jsonl was written by the version before, which hashed every line
+// with its keys in alphabetical order
+#[test]
+fn journals_of_the_version_before_still_verify() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("journal.jsonl");
+    let old = include_str!("data/journal-sorted-keys.jsonl");
+    std::fs::write(&path, old).unwrap();
+    assert_eq!(journal::verify(&path).unwrap(), Integrity::Intact);
+
+    // Lines added now (by undo, or repair) continue its checksums the new way
+    journal::append(
+        &path,
+        &journal::Entry::Undo {
+            from: "pdf/report.pdf".into(),
+            to: "report.pdf".into(),
+            at: Utc::now(),
+        },
+    )
+    .unwrap();
+    assert_eq!(journal::verify(&path).unwrap(), Integrity::Intact);
+
+    // And a change is still noticed
+    std::fs::write(&path, old.replacen("\"moved\":4", "\"moved\":5", 1)).unwrap();
+    assert!(matches!(
+        journal::verify(&path).unwrap(),
+        Integrity::Damaged { line, .. } if line == old.lines().count()
+    ));
+}
+
+#[test]
+fn undo_after_a_crash_uses_the_intents() {
+    let fx = Fixture::new().file("a.pdf", "a").file("b.txt", "b");
+    let root = fx.root();
+
+    // A run that crashed: a.pdf was moved right after its intent was written, b.txt never was
+    let run = Journal::create(root, root, Some(fx.state_dir())).unwrap();
+    run.record_intent(&root.join("a.pdf"), &root.join("pdf/a.pdf"), 1)
+        .unwrap();
+    std::fs::create_dir(root.join("pdf")).unwrap();
+    std::fs::rename(root.join("a.pdf"), root.join("pdf/a.pdf")).unwrap();
+    run.record_intent(&root.join("b.txt"), &root.join("txt/b.txt"), 1)
+        .unwrap();
+    // No move lines, no "done" line: the process is gone
+    std::mem::forget(run);
+
+    assert_eq!(
+        journal::verify(&fx.journals()[0]).unwrap(),
+        Integrity::Unfinished
+    );
+    fx.undo(&UndoOptions::default());
+    assert_eq!(fx.files(), vec!["a.pdf", "b.txt"]);
+}
+
+// Organizing ~ walks through ~/.local/state, where the journals are: they stay where they are,
+// so a later run can't move them into jsonl/ and undo still finds them
+#[test]
+fn journals_kept_inside_root_are_left_alone() {
+    let fx = Fixture::new().file("report.pdf", "r");
+    let state = fx.root().join(".local/state/directory-cleaner");
+    let in_root = |options: Options| Options {
+        state_dir: Some(state.clone()),
+        ..options
+    };
+    let root = fx.root().to_str().unwrap();
+
+    logic::process_directory(root, &in_root(Options::default())).unwrap();
+    std::fs::write(fx.root().join("notes.txt"), "n").unwrap();
+    logic::process_directory(root, &in_root(Options::default())).unwrap();
+    assert!(!fx.root().join("jsonl").exists());
+
+    for _ in 0..2 {
+        undo::undo(
+            root,
+            &UndoOptions {
+                state_dir: Some(state.clone()),
+                ..UndoOptions::default()
+            },
+        )
+        .unwrap();
+    }
+    let left: Vec<String> = fx
+        .files()
+        .into_iter()
+        .filter(|file| !file.starts_with(".local/"))
+        .collect();
+    assert_eq!(left, vec!["notes.txt", "report.pdf"]);
+}
@@ -0,0 +1,124 @@
+// --into-user-dirs: categories into the platform's Pictures, Music, ... folders
+mod common;
+
+use directory_cleaner::logic::{ConfigError, Options};
+use directory_cleaner::plan::Grouping;
+use directory_cleaner::user_dirs::{from_user_dirs, Configured};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn user_dirs_files_are_read_like_the_shell_reads_them() {
+    let home = Path::new("/home/me");
+    let text = r#"
+# This file is written by xdg-user-dirs-update
+XDG_DESKTOP_DIR="$HOME/Desktop"
+XDG_PICTURES_DIR="$HOME/Bilder"
+XDG_MUSIC_DIR="/mnt/music"
+XDG_VIDEOS_DIR="$HOME/"
+XDG_DOCUMENTS_DIR="relative/Docs"
+XDG_PICTURES_DIR="$HOME/Fotos"
+"#;
+    let folder = |name| from_user_dirs(text, name, home);
+    // The last line for a folder wins
+    assert_eq!(
+        folder("PICTURES"),
+        Some(Configured::Folder(PathBuf::from("/home/me/Fotos")))
+    );
+    assert_eq!(
+        folder("MUSIC"),
+        Some(Configured::Folder(PathBuf::from("/mnt/music")))
+    );
+    // The home folder itself turns the folder off
+    assert_eq!(folder("VIDEOS"), Some(Configured::Off));
+    // Not allowed there, so as if it wasn't there
+    assert_eq!(folder("DOCUMENTS"), None);
+    assert_eq!(folder("DOWNLOAD"), None);
+}
+
+#[test]
+fn into_user_dirs_needs_folders_to_put_things_in() {
+    let options = Options {
+        into_user_dirs: true,
+        by: Grouping::Mime,
+        ..Options::default()
+    };
+    assert_eq!(
+        options.check(),
+        Err(ConfigError::NoUserDirsFor(Grouping::Mime))
+    );
+    assert_eq!(
+        Options {
+            into_user_dirs: true,
+            ..Options::default()
+        }
+        .check_merge(),
+        Err(ConfigError::IgnoredByMerge("--into-user-dirs"))
+    );
+}
+
+// The only test in this file that touches the environment, so nothing else races with it
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn categories_go_into_the_users_own_folders() {
+    use common::Fixture;
+    use directory_cleaner::plan::Grouping;
+    use directory_cleaner::undo::UndoOptions;
+    use std::fs;
+
+    let home = tempfile::TempDir::new().unwrap();
+    fs::create_dir_all(home.path().join("Pictures")).unwrap();
+    fs::create_dir_all(home.path().join("Musik")).unwrap();
+    fs::create_dir_all(home.path().join("Documents")).unwrap();
+    fs::create_dir_all(home.path().join(".config")).unwrap();
+    fs::write(
+        home.path().join(".config/user-dirs.dirs"),
+        "XDG_MUSIC_DIR=\"$HOME/Musik\"\nXDG_DOCUMENTS_DIR=\"$HOME/\"\n",
+    )
+    .unwrap();
+    std::env::set_var("HOME", home.path());
+    std::env::remove_var("XDG_CONFIG_HOME");
+
+    let fx = Fixture::new()
+        .file("photo.jpg", "p")
+        .file("song.mp3", "s")
+        .file("report.pdf", "r")
+        .file("clip.mp4", "c")
+        .file("backup.zip", "b");
+    let options = Options {
+        by: Grouping::Category,
+        into_user_dirs: true,
+        ..Options::default()
+    };
+    fx.organize(&options);
+
+    assert_eq!(
+        fs::read_to_string(home.path().join("Pictures/photo.jpg")).unwrap(),
+        "p"
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("Musik/song.mp3")).unwrap(),
+        "s"
+    );
+    // Documents are turned off and there's no Videos folder, so those stay below the root
+    assert_eq!(
+        fx.files(),
+        vec![
+            "Archives/backup.zip",
+            "Documents/report.pdf",
+            "Videos/clip.mp4"
+        ]
+    );
+
+    fx.undo(&UndoOptions::default());
+    assert_eq!(
+        fx.files(),
+        vec![
+            "backup.zip",
+            "clip.mp4",
+            "photo.jpg",
+            "report.pdf",
+            "song.mp3"
+        ]
+    );
+    assert!(home.path().join("Pictures").is_dir());
+}